@@ -0,0 +1,153 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+#[allow(dead_code)]
+#[path = "../src/geometry.rs"]
+mod geometry;
+#[allow(dead_code)]
+#[path = "../src/raster.rs"]
+mod raster;
+
+use raster::{Canvas, ImageRef, Rect};
+
+const TILE_SIZE: usize = 16;
+const TILES_X: usize = 80;
+const TILES_Y: usize = 45;
+const WIDTH: usize = TILES_X * TILE_SIZE;
+const HEIGHT: usize = TILES_Y * TILE_SIZE;
+
+/// A 16x16 tile with every pixel set, and a sprite where half of the pixels are transparent.
+fn test_images() -> (Vec<u8>, Vec<u8>) {
+    let tile = (0..TILE_SIZE * TILE_SIZE)
+        .flat_map(|i| [i as u8, 0x40, 0x80, 0xff])
+        .collect();
+    let sprite = (0..TILE_SIZE * TILE_SIZE)
+        .flat_map(|i| [0xff, i as u8, 0x20, if i % 2 == 0 { 0xff } else { 0x00 }])
+        .collect();
+    (tile, sprite)
+}
+
+fn full_rect() -> Rect {
+    Rect {
+        x: 0,
+        y: 0,
+        width: TILE_SIZE,
+        height: TILE_SIZE,
+    }
+}
+
+fn bench_raster(c: &mut Criterion) {
+    let mut frame = vec![0u8; WIDTH * HEIGHT * 4];
+    let (tile, sprite) = test_images();
+    let tile = ImageRef::new(&tile, TILE_SIZE, TILE_SIZE);
+    let sprite = ImageRef::new(&sprite, TILE_SIZE, TILE_SIZE);
+
+    c.bench_function("clear 1280x720", |b| {
+        b.iter(|| Canvas::new(&mut frame, WIDTH, HEIGHT).clear(black_box([0, 0, 0, 0xff])))
+    });
+
+    c.bench_function("fill 80x45 tiles", |b| {
+        b.iter(|| {
+            let mut canvas = Canvas::new(&mut frame, WIDTH, HEIGHT);
+            for y in 0..TILES_Y {
+                for x in 0..TILES_X {
+                    canvas.fill_rect(
+                        (x * TILE_SIZE) as i32,
+                        (y * TILE_SIZE) as i32,
+                        TILE_SIZE as i32,
+                        TILE_SIZE as i32,
+                        black_box([0x20, 0x1c, 0x24, 0xff]),
+                    );
+                }
+            }
+        })
+    });
+
+    c.bench_function("blit 80x45 opaque tiles", |b| {
+        b.iter(|| {
+            let mut canvas = Canvas::new(&mut frame, WIDTH, HEIGHT);
+            for y in 0..TILES_Y {
+                for x in 0..TILES_X {
+                    canvas.blit(
+                        (x * TILE_SIZE) as i32,
+                        (y * TILE_SIZE) as i32,
+                        black_box(tile),
+                        full_rect(),
+                        1,
+                    );
+                }
+            }
+        })
+    });
+
+    c.bench_function("blit 80x45 transparent sprites", |b| {
+        b.iter(|| {
+            let mut canvas = Canvas::new(&mut frame, WIDTH, HEIGHT);
+            for y in 0..TILES_Y {
+                for x in 0..TILES_X {
+                    canvas.blit(
+                        (x * TILE_SIZE) as i32,
+                        (y * TILE_SIZE) as i32,
+                        black_box(sprite),
+                        full_rect(),
+                        1,
+                    );
+                }
+            }
+        })
+    });
+
+    c.bench_function("blit 40x22 sprites at 2x scale", |b| {
+        b.iter(|| {
+            let mut canvas = Canvas::new(&mut frame, WIDTH, HEIGHT);
+            for y in 0..TILES_Y / 2 {
+                for x in 0..TILES_X / 2 {
+                    canvas.blit(
+                        (x * TILE_SIZE * 2) as i32,
+                        (y * TILE_SIZE * 2) as i32,
+                        black_box(sprite),
+                        full_rect(),
+                        2,
+                    );
+                }
+            }
+        })
+    });
+
+    c.bench_function("blit 80x45 rotated sprites", |b| {
+        let rotation = glam::Affine2::from_angle(0.3);
+        b.iter(|| {
+            let mut canvas = Canvas::new(&mut frame, WIDTH, HEIGHT);
+            for y in 0..TILES_Y {
+                for x in 0..TILES_X {
+                    let pos = glam::Vec2::new((x * TILE_SIZE) as f32, (y * TILE_SIZE) as f32);
+                    canvas.blit_transformed(
+                        black_box(sprite),
+                        full_rect(),
+                        glam::Affine2::from_translation(pos) * rotation,
+                    );
+                }
+            }
+        })
+    });
+
+    let glyph = vec![0x80u8; 8 * 8];
+    c.bench_function("blend 160x90 glyphs", |b| {
+        b.iter(|| {
+            let mut canvas = Canvas::new(&mut frame, WIDTH, HEIGHT);
+            for y in 0..HEIGHT / 8 {
+                for x in 0..WIDTH / 8 {
+                    canvas.blend_mask(
+                        (x * 8) as i32,
+                        (y * 8) as i32,
+                        black_box(&glyph),
+                        8,
+                        [0xff, 0xff, 0xff, 0xff],
+                    );
+                }
+            }
+        })
+    });
+}
+
+criterion_group!(benches, bench_raster);
+criterion_main!(benches);
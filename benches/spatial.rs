@@ -0,0 +1,114 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use glam::IVec2;
+
+#[allow(dead_code)]
+#[path = "../src/spatial.rs"]
+mod spatial;
+
+use spatial::SpatialIndex;
+
+const MAP_SIZE: i32 = 200;
+const ENTITIES: usize = 5000;
+
+/// Positions spread over the map with a small linear congruential generator, so every run
+/// uses the same layout.
+fn positions() -> Vec<IVec2> {
+    let mut state = 0x2545_f491_u64;
+    (0..ENTITIES)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let x = (state >> 33) as i32 % MAP_SIZE;
+            let y = (state >> 45) as i32 % MAP_SIZE;
+            IVec2::new(x, y)
+        })
+        .collect()
+}
+
+fn build(positions: &[IVec2]) -> SpatialIndex<usize> {
+    let mut index = SpatialIndex::new();
+    for (id, &pos) in positions.iter().enumerate() {
+        index.insert(id, pos);
+    }
+    index
+}
+
+fn bench_spatial(c: &mut Criterion) {
+    let positions = positions();
+    let index = build(&positions);
+    let probes: Vec<_> = positions.iter().step_by(50).copied().collect();
+
+    c.bench_function("insert 5000 entities", |b| {
+        b.iter(|| build(black_box(&positions)))
+    });
+
+    c.bench_function("move 5000 entities one tile", |b| {
+        let mut index = build(&positions);
+        let mut current = positions.clone();
+        b.iter(|| {
+            for (id, pos) in current.iter_mut().enumerate() {
+                let to = IVec2::new((pos.x + 1) % MAP_SIZE, pos.y);
+                index.update(id, *pos, to);
+                *pos = to;
+            }
+        })
+    });
+
+    c.bench_function("100 tile lookups among 5000", |b| {
+        b.iter(|| {
+            probes
+                .iter()
+                .map(|&pos| index.at(black_box(pos)).count())
+                .sum::<usize>()
+        })
+    });
+
+    c.bench_function("100 tile lookups among 5000, linear scan", |b| {
+        b.iter(|| {
+            probes
+                .iter()
+                .map(|&pos| positions.iter().filter(|&&p| p == black_box(pos)).count())
+                .sum::<usize>()
+        })
+    });
+
+    c.bench_function("100 radius 6 queries among 5000", |b| {
+        b.iter(|| {
+            probes
+                .iter()
+                .map(|&pos| index.in_radius(black_box(pos), 6).count())
+                .sum::<usize>()
+        })
+    });
+
+    c.bench_function("100 radius 6 queries among 5000, linear scan", |b| {
+        b.iter(|| {
+            probes
+                .iter()
+                .map(|&pos| {
+                    positions
+                        .iter()
+                        .filter(|&&p| {
+                            let d = p - black_box(pos);
+                            d.x * d.x + d.y * d.y <= 36
+                        })
+                        .count()
+                })
+                .sum::<usize>()
+        })
+    });
+
+    c.bench_function("100 40x22 rect queries among 5000", |b| {
+        let size = IVec2::new(40, 22);
+        b.iter(|| {
+            probes
+                .iter()
+                .map(|&pos| index.in_rect(black_box(pos), pos + size).count())
+                .sum::<usize>()
+        })
+    });
+}
+
+criterion_group!(benches, bench_spatial);
+criterion_main!(benches);
@@ -0,0 +1,112 @@
+// Full-screen post-processing passes. Every pass samples the previous pass' output and shares
+// the same parameters; each effect has its own fragment entry point.
+
+struct Params {
+    flash_color: vec4<f32>,
+    // x: scanline period in screen pixels, which is also the scale of the frame,
+    // y: scanline strength, z: vignette strength, w: number of palette colours.
+    settings: vec4<f32>,
+    // x: damage flash amount, y: desaturation amount,
+    // z: 1.0 when the textures are sRGB, so samples are linear.
+    state: vec4<f32>,
+    // Where the scaled-up frame is drawn inside the letterbox: x, y, width and height in
+    // screen pixels.
+    frame: vec4<f32>,
+}
+
+@group(0) @binding(0) var t_input: texture_2d<f32>;
+@group(0) @binding(1) var s_input: sampler;
+@group(0) @binding(2) var<uniform> params: Params;
+// Colours of the palette, in sRGB.
+@group(0) @binding(3) var<storage, read> palette: array<vec4<f32>>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One full-screen triangle.
+    let pos = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(pos * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(pos.x, 1.0 - pos.y);
+    return out;
+}
+
+@fragment
+fn fs_scanlines(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+    // Darken the lower half of every scaled-up pixel row.
+    if (fract((in.position.y - params.frame.y) / params.settings.x) > 0.5) {
+        return vec4<f32>(color.rgb * (1.0 - params.settings.y), color.a);
+    }
+    return color;
+}
+
+// Position of the centre of the frame pixel under `position`, from 0.0 to 1.0 across the
+// frame. The same coordinates the CPU fallback works in, with the letterbox left out.
+fn frame_uv(position: vec2<f32>) -> vec2<f32> {
+    let scale = params.settings.x;
+    let size = params.frame.zw / scale;
+    return (floor((position - params.frame.xy) / scale) + 0.5) / size;
+}
+
+fn to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_vignette(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+    let distance = length(frame_uv(in.position.xy) - vec2<f32>(0.5, 0.5)) * 1.41421356;
+    let darken = params.settings.z * smoothstep(0.4, 1.0, distance);
+    return vec4<f32>(color.rgb * (1.0 - darken), color.a);
+}
+
+// Snap to the nearest palette colour by squared distance in sRGB, like `Palette::nearest`.
+@fragment
+fn fs_quantize(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+    let srgb = params.state.z > 0.5;
+    var rgb = color.rgb;
+    if (srgb) {
+        rgb = to_srgb(rgb);
+    }
+    var nearest = rgb;
+    var nearest_distance = 4.0;
+    for (var i = 0u; i < u32(params.settings.w); i = i + 1u) {
+        let d = palette[i].rgb - rgb;
+        let distance = dot(d, d);
+        if (distance < nearest_distance) {
+            nearest_distance = distance;
+            nearest = palette[i].rgb;
+        }
+    }
+    if (srgb) {
+        nearest = to_linear(nearest);
+    }
+    return vec4<f32>(nearest, color.a);
+}
+
+@fragment
+fn fs_damage_flash(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+    return vec4<f32>(mix(color.rgb, params.flash_color.rgb, params.state.x), color.a);
+}
+
+@fragment
+fn fs_desaturate(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+    let luma = dot(color.rgb, vec3<f32>(0.299, 0.587, 0.114));
+    return vec4<f32>(mix(color.rgb, vec3<f32>(luma), params.state.y), color.a);
+}
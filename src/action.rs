@@ -0,0 +1,150 @@
+use crate::entity::Slot;
+use glam::IVec2;
+use std::fmt;
+use std::str::FromStr;
+
+/// Something the player asked the game to do. Input is turned into actions before it
+/// reaches the game, so actions can be recorded and replayed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Action {
+    /// Step one tile in a direction, bumping into whatever is in the way.
+    Move(IVec2),
+    /// Pass a turn.
+    Wait,
+    /// Describe what is on a map tile.
+    LookAt(IVec2),
+    /// Shoot the equipped ranged weapon towards a map tile.
+    Fire(IVec2),
+    /// Pick up the items the player is standing on.
+    PickUp,
+    /// Drop the inventory slot with this index.
+    Drop(usize),
+    /// Use up one item of an inventory slot, e.g. drink a potion.
+    Use(usize),
+    /// Throw one item of an inventory slot at a map tile.
+    Throw(usize, IVec2),
+    /// Equip the item in an inventory slot.
+    Equip(usize),
+    /// Put the item in an equipment slot back into the inventory.
+    Unequip(Slot),
+    /// Take the stairs the player is standing on down, or up.
+    Descend,
+    Ascend,
+    /// Open the inventory or equipment screen.
+    OpenInventory,
+    OpenEquipment,
+    /// Close the open screen, or stop aiming.
+    CloseMenu,
+    /// Move the selection in a screen, or the target while aiming.
+    MoveCursor(IVec2),
+    /// Pick a target for throwing the item in an inventory slot.
+    Aim(usize),
+    /// Switch between the tile and ASCII presentation.
+    TogglePresentation,
+    /// Scroll the message log back (positive) or forward (negative) by some lines.
+    ScrollLog(i32),
+    ScrollLogToBottom,
+}
+
+impl Action {
+    /// Whether the action may take the player's turn. It only does if it succeeds, e.g.
+    /// picking up from an empty floor does not.
+    pub(crate) fn takes_turn(&self) -> bool {
+        matches!(
+            self,
+            Action::Move(_)
+                | Action::Wait
+                | Action::Fire(_)
+                | Action::PickUp
+                | Action::Drop(_)
+                | Action::Use(_)
+                | Action::Throw(..)
+                | Action::Equip(_)
+                | Action::Unequip(_)
+                | Action::Descend
+                | Action::Ascend
+        )
+    }
+}
+
+/// The text form used by replay files, e.g. `move 1 0` or `look 3 4`.
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Move(dir) => write!(f, "move {} {}", dir.x, dir.y),
+            Action::Wait => write!(f, "wait"),
+            Action::LookAt(pos) => write!(f, "look {} {}", pos.x, pos.y),
+            Action::Fire(pos) => write!(f, "fire {} {}", pos.x, pos.y),
+            Action::PickUp => write!(f, "pick_up"),
+            Action::Drop(index) => write!(f, "drop {}", index),
+            Action::Use(index) => write!(f, "use {}", index),
+            Action::Throw(index, pos) => write!(f, "throw {} {} {}", index, pos.x, pos.y),
+            Action::Equip(index) => write!(f, "equip {}", index),
+            Action::Unequip(slot) => write!(f, "unequip {}", *slot as usize),
+            Action::Descend => write!(f, "descend"),
+            Action::Ascend => write!(f, "ascend"),
+            Action::OpenInventory => write!(f, "inventory"),
+            Action::OpenEquipment => write!(f, "equipment"),
+            Action::CloseMenu => write!(f, "close_menu"),
+            Action::MoveCursor(dir) => write!(f, "cursor {} {}", dir.x, dir.y),
+            Action::Aim(index) => write!(f, "aim {}", index),
+            Action::TogglePresentation => write!(f, "toggle_presentation"),
+            Action::ScrollLog(lines) => write!(f, "scroll_log {}", lines),
+            Action::ScrollLogToBottom => write!(f, "scroll_log_to_bottom"),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = ParseActionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseActionError(s.to_string());
+        let mut parts = s.split_whitespace();
+        let name = parts.next().ok_or_else(error)?;
+        let args = parts
+            .map(|arg| arg.parse::<i32>().map_err(|_| error()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let action = match (name, args.as_slice()) {
+            ("move", &[x, y]) => Action::Move(IVec2::new(x, y)),
+            ("wait", []) => Action::Wait,
+            ("look", &[x, y]) => Action::LookAt(IVec2::new(x, y)),
+            ("fire", &[x, y]) => Action::Fire(IVec2::new(x, y)),
+            ("pick_up", []) => Action::PickUp,
+            ("drop", &[index]) => Action::Drop(index.try_into().map_err(|_| error())?),
+            ("use", &[index]) => Action::Use(index.try_into().map_err(|_| error())?),
+            ("throw", &[index, x, y]) => {
+                Action::Throw(index.try_into().map_err(|_| error())?, IVec2::new(x, y))
+            }
+            ("equip", &[index]) => Action::Equip(index.try_into().map_err(|_| error())?),
+            ("unequip", &[slot]) => {
+                let slot = usize::try_from(slot).map_err(|_| error())?;
+                Action::Unequip(*Slot::ALL.get(slot).ok_or_else(error)?)
+            }
+            ("descend", []) => Action::Descend,
+            ("ascend", []) => Action::Ascend,
+            ("inventory", []) => Action::OpenInventory,
+            ("equipment", []) => Action::OpenEquipment,
+            ("close_menu", []) => Action::CloseMenu,
+            ("cursor", &[x, y]) => Action::MoveCursor(IVec2::new(x, y)),
+            ("aim", &[index]) => Action::Aim(index.try_into().map_err(|_| error())?),
+            ("toggle_presentation", []) => Action::TogglePresentation,
+            ("scroll_log", &[lines]) => Action::ScrollLog(lines),
+            ("scroll_log_to_bottom", []) => Action::ScrollLogToBottom,
+            _ => return Err(error()),
+        };
+        Ok(action)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct ParseActionError(String);
+
+impl fmt::Display for ParseActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` is not an action", self.0)
+    }
+}
+
+impl std::error::Error for ParseActionError {}
@@ -0,0 +1,383 @@
+use crate::combat;
+use crate::entity::{AiKind, Entity, EntityId, EntityStore};
+use crate::fov::compute_fov;
+use crate::map::Map;
+use crate::pathfinding::{self, DIRECTIONS};
+use crate::raws::Raws;
+use crate::rng::Rng;
+use crate::status::StatusKind;
+use glam::IVec2;
+use serde::{Deserialize, Serialize};
+
+/// Sight radius of monsters that do not define one.
+pub(crate) const DEFAULT_SIGHT: i32 = 8;
+/// Game turns a scent trail can still be followed.
+const SCENT_DURATION: u64 = 20;
+/// Tiles expanded at most when looking for a path.
+const MAX_PATH_NODES: usize = 400;
+/// Extra cost of a path through another creature, which may move out of the way.
+const CROWDED_COST: i32 = 5;
+/// Guards chase intruders at most this far from their post.
+const GUARD_RADIUS: i32 = 5;
+/// Ranged monsters back away from the player when it is this close.
+const KITE_DISTANCE: i32 = 2;
+/// Chance that a wandering monster stays put for a turn.
+const WANDER_REST_CHANCE: f32 = 0.5;
+
+/// What a monster is currently doing, shown in the entity inspector.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub(crate) enum AiState {
+    Idle,
+    Wandering,
+    /// Sees the player and goes after it.
+    Hunting,
+    /// Lost sight of the player and heads to where it was last noticed.
+    Searching,
+    Fleeing,
+    /// Walking back to its post.
+    Returning,
+}
+
+/// Memory of a monster, kept between turns.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Brain {
+    pub state: AiState,
+    /// Where the player was last seen, heard or smelled.
+    pub target: Option<IVec2>,
+    /// Post of a guard, where it was spawned.
+    pub home: IVec2,
+}
+
+impl Brain {
+    pub(crate) fn new(home: IVec2) -> Self {
+        Self {
+            state: AiState::Idle,
+            target: None,
+            home,
+        }
+    }
+
+    /// Notice a noise at `pos`. Monsters that can see the player ignore it.
+    pub(crate) fn hear(&mut self, pos: IVec2) {
+        if self.state != AiState::Hunting {
+            self.target = Some(pos);
+            self.state = AiState::Searching;
+        }
+    }
+}
+
+/// What a monster does with its action.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Decision {
+    Wait,
+    /// Step in a direction.
+    Move(IVec2),
+    /// Melee attack a creature on a neighbouring tile.
+    Attack(EntityId),
+    /// Shoot the ranged weapon at a tile.
+    Fire(IVec2),
+}
+
+/// A sound that monsters within `radius` tiles hear.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Noise {
+    pub pos: IVec2,
+    pub radius: i32,
+}
+
+/// Trail the player leaves behind: the game turn each tile was last walked on.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Scent {
+    width: i32,
+    height: i32,
+    trail: Vec<u64>,
+    /// Game turns since the trail was started.
+    now: u64,
+}
+
+impl Scent {
+    pub(crate) fn new(width: i32, height: i32) -> Self {
+        Self {
+            width,
+            height,
+            trail: vec![0; (width * height).max(0) as usize],
+            now: 0,
+        }
+    }
+
+    /// Advance a game turn with the player standing on `pos`.
+    pub(crate) fn update(&mut self, pos: IVec2) {
+        self.now += 1;
+        if let Some(index) = self.index(pos) {
+            self.trail[index] = self.now;
+        }
+    }
+
+    fn index(&self, pos: IVec2) -> Option<usize> {
+        let inside = pos.x >= 0 && pos.y >= 0 && pos.x < self.width && pos.y < self.height;
+        inside.then(|| (pos.y * self.width + pos.x) as usize)
+    }
+
+    /// Game turn `pos` was last walked on, if the scent there is still fresh.
+    pub(crate) fn at(&self, pos: IVec2) -> Option<u64> {
+        let walked = self.trail[self.index(pos)?];
+        (walked > 0 && self.now - walked < SCENT_DURATION).then_some(walked)
+    }
+
+    /// Neighbour of `pos` with a fresher scent than `pos` itself, leading along the trail.
+    pub(crate) fn follow(&self, pos: IVec2) -> Option<IVec2> {
+        let here = self.at(pos).unwrap_or(0);
+        DIRECTIONS
+            .iter()
+            .map(|&direction| pos + direction)
+            .filter_map(|next| Some((next, self.at(next)?)))
+            .filter(|&(_, walked)| walked > here)
+            .max_by_key(|&(_, walked)| walked)
+            .map(|(next, _)| next)
+    }
+}
+
+/// The world as a monster perceives it while deciding.
+pub(crate) struct AiContext<'a> {
+    pub map: &'a Map,
+    pub entities: &'a EntityStore,
+    pub raws: &'a Raws,
+    pub scent: &'a Scent,
+    pub player: EntityId,
+}
+
+impl AiContext<'_> {
+    /// Whether `from` sees `target` within `sight` tiles. Walls block the view, and invisible
+    /// creatures cannot be seen.
+    fn can_see(&self, from: IVec2, sight: i32, target: EntityId) -> bool {
+        if !self.entities.in_radius(from, sight).contains(&target) {
+            return false;
+        }
+        let Some(target) = self.entities.get(target) else {
+            return false;
+        };
+        if target.effects.has(StatusKind::Invisibility) {
+            return false;
+        }
+        // Only cast as far as the target, tiles further away cannot hide it.
+        let d = target.pos() - from;
+        let reach = ((d.x * d.x + d.y * d.y) as f32).sqrt().ceil() as i32;
+        let mut seen = false;
+        compute_fov(
+            from,
+            reach,
+            |pos| self.map.is_blocked(pos),
+            |pos| seen |= pos == target.pos(),
+        );
+        seen
+    }
+
+    fn is_free(&self, pos: IVec2) -> bool {
+        !self.map.is_blocked(pos) && self.entities.blocking_at(pos).is_none()
+    }
+
+    /// First step from `from` towards `goal`, going around walls and other creatures. `None`
+    /// if there is no way, or `from` is the goal already.
+    fn step_towards(&self, from: IVec2, goal: IVec2) -> Option<IVec2> {
+        let path = pathfinding::astar(from, goal, MAX_PATH_NODES, |pos| {
+            if self.map.is_blocked(pos) {
+                None
+            } else if self.entities.blocking_at(pos).is_some() {
+                Some(1 + CROWDED_COST)
+            } else {
+                Some(1)
+            }
+        })?;
+        path.first().copied()
+    }
+}
+
+/// Decide what monster `id` does this turn, updating its memory in `brain`.
+pub(crate) fn think(ctx: &AiContext, id: EntityId, brain: &mut Brain, rng: &mut Rng) -> Decision {
+    let (Some(entity), Some(player)) = (ctx.entities.get(id), ctx.entities.get(ctx.player)) else {
+        return Decision::Wait;
+    };
+    let Some(kind) = entity.ai else {
+        return Decision::Wait;
+    };
+    let player_alive = player.stats.as_ref().is_some_and(|stats| !stats.is_dead());
+    let def = ctx.raws.monster(&entity.name);
+    let sight = def.map_or(DEFAULT_SIGHT, |def| def.sight);
+    let sees = player_alive && ctx.can_see(entity.pos(), sight, ctx.player);
+    if sees {
+        brain.target = Some(player.pos());
+    }
+
+    // Badly hurt monsters run for their lives.
+    let flee_below = def.map_or(0.0, |def| def.flee_below);
+    let hurt = entity
+        .stats
+        .as_ref()
+        .is_some_and(|stats| (stats.hp as f32) < stats.max_hp as f32 * flee_below);
+
+    let monster = Monster {
+        ctx,
+        id,
+        entity,
+        player,
+        sees,
+    };
+    match kind {
+        AiKind::Idle => {
+            brain.state = AiState::Idle;
+            Decision::Wait
+        }
+        AiKind::Wander => monster
+            .attack_adjacent()
+            .unwrap_or_else(|| monster.wander(brain, rng)),
+        AiKind::Flee if sees => monster.flee(brain),
+        AiKind::Flee => monster.wander(brain, rng),
+        _ if hurt && sees => monster.flee(brain),
+        AiKind::Melee => monster.hunt(brain, rng),
+        AiKind::Guard => monster.guard(brain),
+        AiKind::Kite => monster.kite(brain, rng),
+    }
+}
+
+/// A monster deciding on its action.
+struct Monster<'a> {
+    ctx: &'a AiContext<'a>,
+    id: EntityId,
+    entity: &'a Entity,
+    player: &'a Entity,
+    /// Whether the monster sees the player this turn.
+    sees: bool,
+}
+
+impl Monster<'_> {
+    fn distance_to_player(&self) -> IVec2 {
+        (self.player.pos() - self.entity.pos()).abs()
+    }
+
+    fn attack_adjacent(&self) -> Option<Decision> {
+        let d = self.distance_to_player();
+        let alive = self.player.stats.as_ref().is_some_and(|s| !s.is_dead());
+        (alive && d.x + d.y == 1).then_some(Decision::Attack(self.ctx.player))
+    }
+
+    /// Step towards `goal`, attacking the player if it is in the way.
+    fn approach(&self, goal: IVec2) -> Decision {
+        match self.ctx.step_towards(self.entity.pos(), goal) {
+            Some(next) if next == self.player.pos() => Decision::Attack(self.ctx.player),
+            Some(next) if self.ctx.is_free(next) => Decision::Move(next - self.entity.pos()),
+            _ => Decision::Wait,
+        }
+    }
+
+    fn wander(&self, brain: &mut Brain, rng: &mut Rng) -> Decision {
+        brain.state = AiState::Wandering;
+        if rng.chance(WANDER_REST_CHANCE) {
+            return Decision::Wait;
+        }
+        let Some(&direction) = rng.pick(&DIRECTIONS) else {
+            return Decision::Wait;
+        };
+        if self.ctx.is_free(self.entity.pos() + direction) {
+            Decision::Move(direction)
+        } else {
+            Decision::Wait
+        }
+    }
+
+    /// Chase the player while it is in sight, then search where it was last noticed and
+    /// follow its scent from there.
+    fn hunt(&self, brain: &mut Brain, rng: &mut Rng) -> Decision {
+        if self.sees {
+            brain.state = AiState::Hunting;
+            return self.approach(self.player.pos());
+        }
+        if brain.target == Some(self.entity.pos()) {
+            brain.target = self.ctx.scent.follow(self.entity.pos());
+        }
+        match brain.target {
+            Some(target) => {
+                brain.state = AiState::Searching;
+                match self.approach(target) {
+                    // Someone is in the way, there is no point in waiting for them.
+                    Decision::Wait => {
+                        brain.target = None;
+                        self.wander(brain, rng)
+                    }
+                    decision => decision,
+                }
+            }
+            None => self.wander(brain, rng),
+        }
+    }
+
+    /// Step to the free neighbouring tile furthest from the player. Cornered monsters fight.
+    fn flee(&self, brain: &mut Brain) -> Decision {
+        brain.state = AiState::Fleeing;
+        let from = self.player.pos();
+        let distance = |pos: IVec2| {
+            let d = pos - from;
+            d.x * d.x + d.y * d.y
+        };
+        let best = DIRECTIONS
+            .iter()
+            .map(|&direction| self.entity.pos() + direction)
+            .filter(|&pos| self.ctx.is_free(pos))
+            .max_by_key(|&pos| distance(pos))
+            .filter(|&pos| distance(pos) > distance(self.entity.pos()));
+        match best {
+            Some(pos) => Decision::Move(pos - self.entity.pos()),
+            None => self.attack_adjacent().unwrap_or(Decision::Wait),
+        }
+    }
+
+    /// Chase intruders near the post, and walk back to it otherwise.
+    fn guard(&self, brain: &mut Brain) -> Decision {
+        let near_post = (self.player.pos() - brain.home).abs().max_element() <= GUARD_RADIUS;
+        if self.sees && near_post {
+            brain.state = AiState::Hunting;
+            return self.approach(self.player.pos());
+        }
+        brain.target = None;
+        if self.entity.pos() == brain.home {
+            brain.state = AiState::Idle;
+            return self.attack_adjacent().unwrap_or(Decision::Wait);
+        }
+        brain.state = AiState::Returning;
+        self.approach(brain.home)
+    }
+
+    /// Keep some distance and shoot. Without a clear shot, close in like a melee monster.
+    fn kite(&self, brain: &mut Brain, rng: &mut Rng) -> Decision {
+        let attack = combat::ranged_attack(self.entity, self.ctx.raws);
+        let Some(attack) = attack.filter(|_| self.sees) else {
+            return self.hunt(brain, rng);
+        };
+        brain.state = AiState::Hunting;
+        let distance = self.distance_to_player().max_element();
+        if distance <= KITE_DISTANCE {
+            if let Decision::Move(direction) = self.flee(brain) {
+                brain.state = AiState::Hunting;
+                return Decision::Move(direction);
+            }
+        }
+        if distance <= attack.range.unwrap_or(0) && self.clear_shot() {
+            return Decision::Fire(self.player.pos());
+        }
+        self.approach(self.player.pos())
+    }
+
+    /// Whether nothing stands between the monster and the player.
+    fn clear_shot(&self) -> bool {
+        crate::geometry::line(self.entity.pos(), self.player.pos())
+            .skip(1)
+            .take_while(|&pos| pos != self.player.pos())
+            .all(|pos| {
+                !self.ctx.map.is_blocked(pos)
+                    && self
+                        .ctx
+                        .entities
+                        .blocking_at(pos)
+                        .is_none_or(|other| other == self.id)
+            })
+    }
+}
@@ -0,0 +1,84 @@
+use crate::color::Color;
+use crate::easing;
+use crate::entity::EntityId;
+use glam::Vec2;
+use std::collections::HashMap;
+
+/// Seconds a lunge towards an attacked tile takes, there and back.
+const LUNGE_TIME: f32 = 0.2;
+/// Pixels an entity moves towards the tile it attacks.
+const LUNGE_DISTANCE: f32 = 5.0;
+/// Seconds an entity is tinted after being hurt.
+const HURT_TIME: f32 = 0.25;
+const HURT_TINT: Color = Color::rgb(0xff, 0x40, 0x40);
+
+#[derive(Default)]
+struct EntityAnimation {
+    /// Unit vector towards the attacked tile.
+    lunge_dir: Vec2,
+    lunge_time: f32,
+    hurt_time: f32,
+}
+
+/// Short cosmetic animations of entities, such as lunging at an enemy. They change how an
+/// entity is drawn, never the game state.
+#[derive(Default)]
+pub(crate) struct Animations {
+    entities: HashMap<EntityId, EntityAnimation>,
+}
+
+impl Animations {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move `id` briefly towards `direction` and back.
+    pub(crate) fn lunge(&mut self, id: EntityId, direction: Vec2) {
+        let animation = self.entities.entry(id).or_default();
+        animation.lunge_dir = direction.normalize_or_zero();
+        animation.lunge_time = LUNGE_TIME;
+    }
+
+    /// Flash `id` red.
+    pub(crate) fn hurt(&mut self, id: EntityId) {
+        self.entities.entry(id).or_default().hurt_time = HURT_TIME;
+    }
+
+    pub(crate) fn remove(&mut self, id: EntityId) {
+        self.entities.remove(&id);
+    }
+
+    pub(crate) fn update(&mut self, dt: f32) {
+        for animation in self.entities.values_mut() {
+            animation.lunge_time = (animation.lunge_time - dt).max(0.0);
+            animation.hurt_time = (animation.hurt_time - dt).max(0.0);
+        }
+        self.entities
+            .retain(|_, a| a.lunge_time > 0.0 || a.hurt_time > 0.0);
+    }
+
+    /// Offset in pixels to draw `id` at.
+    pub(crate) fn offset(&self, id: EntityId) -> Vec2 {
+        let Some(animation) = self.entities.get(&id) else {
+            return Vec2::ZERO;
+        };
+        // Out quickly, back slowly.
+        let t = 1.0 - animation.lunge_time / LUNGE_TIME;
+        let reach = if t < 0.3 {
+            easing::ease_out_quad(t / 0.3)
+        } else {
+            1.0 - easing::ease_in_out_quad((t - 0.3) / 0.7)
+        };
+        animation.lunge_dir * reach * LUNGE_DISTANCE
+    }
+
+    /// Colour to multiply `tint` with while `id` is hurt.
+    pub(crate) fn tint(&self, id: EntityId, tint: Color) -> Color {
+        match self.entities.get(&id) {
+            Some(animation) if animation.hurt_time > 0.0 => {
+                tint.lerp(HURT_TINT, animation.hurt_time / HURT_TIME)
+            }
+            _ => tint,
+        }
+    }
+}
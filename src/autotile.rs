@@ -0,0 +1,228 @@
+use crate::map::Map;
+use crate::raws::Raws;
+use glam::IVec2;
+use serde::Deserialize;
+
+/// How the neighbours of a tile pick its frame.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub(crate) enum Autotile {
+    /// 16 frames, one for every combination of the four edge neighbours. The frame is the
+    /// sum of 1 for north, 2 for east, 4 for south and 8 for west when that neighbour is
+    /// alike.
+    FourBit,
+    /// 47 frames, from all eight neighbours. A corner only counts when both edges beside it
+    /// are alike, which leaves 47 distinct masks: 1 for north, 2 for north-east, 4 for east
+    /// and so on clockwise up to 128 for north-west. The frames are in ascending mask order.
+    Blob,
+}
+
+impl Autotile {
+    /// Frames the sprite sheet needs.
+    pub(crate) fn frames(self) -> u32 {
+        match self {
+            Autotile::FourBit => 16,
+            Autotile::Blob => BLOB_MASKS.len() as u32,
+        }
+    }
+
+    /// Frame of a tile, given which of its neighbours are alike.
+    pub(crate) fn frame(self, alike: impl Fn(IVec2) -> bool) -> u32 {
+        match self {
+            Autotile::FourBit => EDGES
+                .iter()
+                .enumerate()
+                .filter(|&(_, &offset)| alike(offset))
+                .map(|(bit, _)| 1 << bit)
+                .sum(),
+            Autotile::Blob => {
+                let mask = NEIGHBOURS
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &offset)| alike(offset))
+                    .map(|(bit, _)| 1 << bit)
+                    .sum();
+                let mask = reduce(mask);
+                BLOB_MASKS.binary_search(&mask).unwrap_or(0) as u32
+            }
+        }
+    }
+}
+
+/// North, east, south and west.
+const EDGES: [IVec2; 4] = [
+    IVec2::new(0, -1),
+    IVec2::new(1, 0),
+    IVec2::new(0, 1),
+    IVec2::new(-1, 0),
+];
+
+/// Clockwise from north.
+const NEIGHBOURS: [IVec2; 8] = [
+    IVec2::new(0, -1),
+    IVec2::new(1, -1),
+    IVec2::new(1, 0),
+    IVec2::new(1, 1),
+    IVec2::new(0, 1),
+    IVec2::new(-1, 1),
+    IVec2::new(-1, 0),
+    IVec2::new(-1, -1),
+];
+
+/// Clear the corners of an eight neighbour mask whose two edges are not both set.
+const fn reduce(mask: u8) -> u8 {
+    let mut reduced = mask & 0b0101_0101;
+    let mut corner = 1;
+    while corner < 8 {
+        let before = 1 << (corner - 1);
+        let after = 1 << ((corner + 1) % 8);
+        if mask & (1 << corner) != 0 && mask & before != 0 && mask & after != 0 {
+            reduced |= 1 << corner;
+        }
+        corner += 2;
+    }
+    reduced
+}
+
+/// Every mask that `reduce` leaves unchanged, in ascending order. The index of a mask is
+/// its blob frame.
+const BLOB_MASKS: [u8; 47] = {
+    let mut masks = [0; 47];
+    let mut count = 0;
+    let mut mask = 0;
+    while mask < 256 {
+        if reduce(mask as u8) == mask as u8 {
+            masks[count] = mask as u8;
+            count += 1;
+        }
+        mask += 1;
+    }
+    masks
+};
+
+/// The autotile frame of every tile of a map. Build it when a map is generated or loaded,
+/// and update it whenever a tile changes.
+pub(crate) struct TileFrames {
+    width: i32,
+    height: i32,
+    frames: Vec<u32>,
+}
+
+impl TileFrames {
+    pub(crate) fn build(map: &Map, raws: &Raws) -> Self {
+        let frames = (0..map.height)
+            .flat_map(|y| (0..map.width).map(move |x| IVec2::new(x, y)))
+            .map(|pos| frame(map, raws, pos))
+            .collect();
+        Self {
+            width: map.width,
+            height: map.height,
+            frames,
+        }
+    }
+
+    /// Pick the frames around `pos` again after the tile there changed.
+    pub(crate) fn update(&mut self, map: &Map, raws: &Raws, pos: IVec2) {
+        for offset in NEIGHBOURS.iter().chain([&IVec2::ZERO]) {
+            let tile = pos + *offset;
+            if map.in_bounds(tile) {
+                self.frames[(tile.y * self.width + tile.x) as usize] = frame(map, raws, tile);
+            }
+        }
+    }
+
+    /// Frame of the tile at `pos`, 0 for tiles that are not autotiled.
+    pub(crate) fn get(&self, pos: IVec2) -> u32 {
+        let inside = pos.cmpge(IVec2::ZERO).all() && pos.x < self.width && pos.y < self.height;
+        if inside {
+            self.frames[(pos.y * self.width + pos.x) as usize]
+        } else {
+            0
+        }
+    }
+}
+
+fn frame(map: &Map, raws: &Raws, pos: IVec2) -> u32 {
+    let tile = map.get(pos);
+    let Some(rule) = raws.autotile(tile) else {
+        return 0;
+    };
+    let alike = |offset: IVec2| {
+        let other = map.get(pos + offset);
+        other == tile || rule.joins.contains(&other)
+    };
+    rule.mask.frame(alike)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::TileType;
+    use crate::palette::Palette;
+
+    const NORTH: IVec2 = IVec2::new(0, -1);
+    const NORTH_EAST: IVec2 = IVec2::new(1, -1);
+    const EAST: IVec2 = IVec2::new(1, 0);
+    const WEST: IVec2 = IVec2::new(-1, 0);
+
+    fn alike(offsets: &[IVec2]) -> impl Fn(IVec2) -> bool + '_ {
+        move |offset| offsets.contains(&offset)
+    }
+
+    #[test]
+    fn blob_masks() {
+        assert!(BLOB_MASKS.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(BLOB_MASKS.iter().all(|&mask| reduce(mask) == mask));
+        for mask in 0..=255 {
+            assert!(BLOB_MASKS.contains(&reduce(mask)));
+        }
+    }
+
+    #[test]
+    fn isolated_and_surrounded() {
+        assert_eq!(Autotile::Blob.frame(|_| false), 0);
+        assert_eq!(Autotile::Blob.frame(|_| true), 46);
+        assert_eq!(Autotile::FourBit.frame(|_| false), 0);
+        assert_eq!(Autotile::FourBit.frame(|_| true), 15);
+    }
+
+    #[test]
+    fn lone_corners_are_ignored() {
+        let north = Autotile::Blob.frame(alike(&[NORTH]));
+        assert_eq!(north, 1);
+        assert_eq!(Autotile::Blob.frame(alike(&[NORTH, NORTH_EAST])), north);
+        assert_eq!(Autotile::Blob.frame(alike(&[NORTH_EAST])), 0);
+        let corner = Autotile::Blob.frame(alike(&[NORTH, NORTH_EAST, EAST]));
+        assert_ne!(corner, Autotile::Blob.frame(alike(&[NORTH, EAST])));
+        assert_eq!(BLOB_MASKS[corner as usize], 0b0000_0111);
+    }
+
+    #[test]
+    fn four_bit() {
+        assert_eq!(Autotile::FourBit.frame(alike(&[NORTH, WEST])), 9);
+        assert_eq!(Autotile::FourBit.frame(alike(&[NORTH, NORTH_EAST])), 1);
+    }
+
+    #[test]
+    fn update_repicks_the_neighbours() {
+        let palette = Palette::load("assets/palettes/dungeon.gpl").unwrap();
+        let raws = Raws::load("assets/raws", &palette).unwrap();
+        let mut map = Map::new(7, 7, TileType::Floor);
+        for y in 2..=4 {
+            for x in 2..=4 {
+                map.set(IVec2::new(x, y), TileType::Wall);
+            }
+        }
+        let mut frames = TileFrames::build(&map, &raws);
+        let before: Vec<_> = frames.frames.clone();
+
+        let center = IVec2::new(3, 3);
+        map.set(center, TileType::Floor);
+        frames.update(&map, &raws, center);
+
+        assert_eq!(frames.frames, TileFrames::build(&map, &raws).frames);
+        for offset in NEIGHBOURS {
+            let pos = ((center + offset).y * 7 + (center + offset).x) as usize;
+            assert_ne!(frames.frames[pos], before[pos], "{}", offset);
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+use log::{error, info};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Speed of the GIF colour quantizer, from 1 (best quality) to 30 (fastest).
+const GIF_QUANTIZER_SPEED: i32 = 10;
+
+/// Work for the encoder thread.
+enum Job {
+    Screenshot { path: PathBuf, frame: RgbaImage },
+    StartRecording { path: PathBuf },
+    RecordFrame { frame: RgbaImage, delay_ms: u32 },
+    StopRecording,
+}
+
+struct Recording {
+    /// Seconds left until the recording stops by itself.
+    remaining: f32,
+    /// Seconds since the last captured frame.
+    since_frame: f32,
+}
+
+/// Saves screenshots and records animated GIFs of the frame buffer.
+///
+/// Frames are copied on the game thread, and encoded and written on a background thread so
+/// capturing does not hitch the game loop.
+pub(crate) struct FrameCapture {
+    width: u32,
+    height: u32,
+    dir: PathBuf,
+    /// Seconds between recorded frames.
+    frame_interval: f32,
+    recording: Option<Recording>,
+    sender: Option<Sender<Job>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl FrameCapture {
+    /// Capture frames of `width` x `height` pixels into `dir`, recording at up to `fps`
+    /// frames per second.
+    pub(crate) fn new(width: u32, height: u32, dir: impl Into<PathBuf>, fps: u32) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let worker = thread::Builder::new()
+            .name("frame-capture".to_string())
+            .spawn(move || encode_jobs(receiver))
+            .map_err(|e| error!("Could not start the frame capture thread: {}", e))
+            .ok();
+
+        Self {
+            width,
+            height,
+            dir: dir.into(),
+            frame_interval: 1.0 / fps.max(1) as f32,
+            recording: None,
+            sender: worker.as_ref().map(|_| sender),
+            worker,
+        }
+    }
+
+    /// Save `frame` as a timestamped PNG, returns the path it is written to.
+    pub(crate) fn screenshot(&self, frame: &[u8]) -> Option<PathBuf> {
+        let path = self.new_path("screenshot", "png");
+        let frame = self.to_image(frame)?;
+        self.send(Job::Screenshot {
+            path: path.clone(),
+            frame,
+        })
+        .then_some(path)
+    }
+
+    /// Start recording a timestamped GIF that stops after `seconds`, returns its path.
+    pub(crate) fn start_recording(&mut self, seconds: f32) -> Option<PathBuf> {
+        self.stop_recording();
+
+        let path = self.new_path("recording", "gif");
+        if !self.send(Job::StartRecording { path: path.clone() }) {
+            return None;
+        }
+        self.recording = Some(Recording {
+            remaining: seconds,
+            // Capture the first frame right away.
+            since_frame: self.frame_interval,
+        });
+        Some(path)
+    }
+
+    pub(crate) fn stop_recording(&mut self) {
+        if self.recording.take().is_some() {
+            self.send(Job::StopRecording);
+        }
+    }
+
+    pub(crate) fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Feed the frame that was just drawn to the recording, if there is one. Frames are
+    /// skipped to keep to the recording frame rate.
+    pub(crate) fn capture_frame(&mut self, frame: &[u8], dt: f32) {
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+        recording.remaining -= dt;
+        recording.since_frame += dt;
+        if recording.remaining <= 0.0 {
+            self.stop_recording();
+            return;
+        }
+        if recording.since_frame < self.frame_interval {
+            return;
+        }
+
+        let delay_ms = (recording.since_frame * 1000.0).round() as u32;
+        recording.since_frame = 0.0;
+        if let Some(frame) = self.to_image(frame) {
+            self.send(Job::RecordFrame { frame, delay_ms });
+        }
+    }
+
+    /// Finish the current recording and wait until everything is written to disk.
+    pub(crate) fn shutdown(&mut self) {
+        self.stop_recording();
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            if worker.join().is_err() {
+                error!("The frame capture thread panicked");
+            }
+        }
+    }
+
+    fn send(&self, job: Job) -> bool {
+        match &self.sender {
+            Some(sender) => sender.send(job).is_ok(),
+            None => false,
+        }
+    }
+
+    fn to_image(&self, frame: &[u8]) -> Option<RgbaImage> {
+        RgbaImage::from_raw(self.width, self.height, frame.to_vec())
+    }
+
+    /// A path in the capture directory that does not exist yet.
+    fn new_path(&self, prefix: &str, extension: &str) -> PathBuf {
+        let stamp = timestamp();
+        let mut path = self.dir.join(format!("{}_{}.{}", prefix, stamp, extension));
+        let mut n = 2;
+        while path.exists() {
+            path = self
+                .dir
+                .join(format!("{}_{}_{}.{}", prefix, stamp, n, extension));
+            n += 1;
+        }
+        path
+    }
+}
+
+impl Drop for FrameCapture {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Runs on the capture thread until the sender is dropped.
+fn encode_jobs(receiver: Receiver<Job>) {
+    let mut gif: Option<(PathBuf, GifEncoder<BufWriter<File>>)> = None;
+
+    for job in receiver {
+        match job {
+            Job::Screenshot { path, frame } => {
+                let result = match create_parent(&path) {
+                    Ok(()) => frame.save(&path).map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                match result {
+                    Ok(()) => info!("Saved screenshot {}", path.display()),
+                    Err(e) => error!("Could not save screenshot {}: {}", path.display(), e),
+                }
+            }
+            Job::StartRecording { path } => {
+                let file = create_parent(&path).and_then(|_| File::create(&path));
+                gif = match file {
+                    Ok(file) => {
+                        let mut encoder =
+                            GifEncoder::new_with_speed(BufWriter::new(file), GIF_QUANTIZER_SPEED);
+                        if let Err(e) = encoder.set_repeat(Repeat::Infinite) {
+                            error!("Could not write {}: {}", path.display(), e);
+                        }
+                        Some((path, encoder))
+                    }
+                    Err(e) => {
+                        error!("Could not create {}: {}", path.display(), e);
+                        None
+                    }
+                };
+            }
+            Job::RecordFrame { frame, delay_ms } => {
+                if let Some((path, encoder)) = &mut gif {
+                    let delay = Delay::from_numer_denom_ms(delay_ms, 1);
+                    if let Err(e) = encoder.encode_frame(Frame::from_parts(frame, 0, 0, delay)) {
+                        error!("Could not write {}: {}", path.display(), e);
+                        gif = None;
+                    }
+                }
+            }
+            Job::StopRecording => {
+                // Dropping the encoder writes the GIF trailer.
+                if let Some((path, encoder)) = gif.take() {
+                    drop(encoder);
+                    info!("Saved recording {}", path.display());
+                }
+            }
+        }
+    }
+}
+
+fn create_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+/// Current UTC time as `YYYY-MM-DD_HH-MM-SS`.
+pub(crate) fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // Civil date from days since 1970-01-01, after Howard Hinnant's `civil_from_days`.
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
@@ -0,0 +1,308 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// An 8-bit per channel RGBA colour, not premultiplied.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug, Serialize, Deserialize)]
+pub(crate) struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub(crate) const TRANSPARENT: Color = Color::rgba(0x00, 0x00, 0x00, 0x00);
+    pub(crate) const BLACK: Color = Color::rgb(0x00, 0x00, 0x00);
+    pub(crate) const WHITE: Color = Color::rgb(0xff, 0xff, 0xff);
+    pub(crate) const GRAY: Color = Color::rgb(0x80, 0x80, 0x80);
+    pub(crate) const DARK_GRAY: Color = Color::rgb(0x40, 0x40, 0x40);
+    pub(crate) const RED: Color = Color::rgb(0xe0, 0x40, 0x40);
+    pub(crate) const GREEN: Color = Color::rgb(0x40, 0xe0, 0x60);
+    pub(crate) const BLUE: Color = Color::rgb(0x60, 0xa0, 0xff);
+    pub(crate) const YELLOW: Color = Color::rgb(0xff, 0xe0, 0x40);
+    pub(crate) const ORANGE: Color = Color::rgb(0xff, 0xa0, 0x30);
+    pub(crate) const CYAN: Color = Color::rgb(0x40, 0xe0, 0xe0);
+    pub(crate) const MAGENTA: Color = Color::rgb(0xe0, 0x40, 0xe0);
+
+    /// Named constants, as understood by [`Color::named`].
+    const NAMED: [(&'static str, Color); 12] = [
+        ("transparent", Color::TRANSPARENT),
+        ("black", Color::BLACK),
+        ("white", Color::WHITE),
+        ("gray", Color::GRAY),
+        ("dark_gray", Color::DARK_GRAY),
+        ("red", Color::RED),
+        ("green", Color::GREEN),
+        ("blue", Color::BLUE),
+        ("yellow", Color::YELLOW),
+        ("orange", Color::ORANGE),
+        ("cyan", Color::CYAN),
+        ("magenta", Color::MAGENTA),
+    ];
+
+    pub(crate) const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 0xff }
+    }
+
+    pub(crate) const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+
+    pub(crate) const fn with_alpha(self, a: u8) -> Self {
+        Self { a, ..self }
+    }
+
+    /// Multiply the alpha by `opacity`, from 0.0 to 1.0.
+    pub(crate) fn fade(self, opacity: f32) -> Self {
+        self.with_alpha((self.a as f32 * opacity.clamp(0.0, 1.0)).round() as u8)
+    }
+
+    pub(crate) const fn to_array(self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+
+    /// Channels scaled to 0.0 - 1.0.
+    pub(crate) fn to_f32(self) -> [f32; 4] {
+        self.to_array().map(|c| c as f32 / 255.0)
+    }
+
+    /// Look up one of the named constants, e.g. `"red"`.
+    pub(crate) fn named(name: &str) -> Option<Self> {
+        Self::NAMED
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, color)| *color)
+    }
+
+    /// Parse `rgb`, `rrggbb` or `rrggbbaa` hex digits, with or without a leading `#`.
+    pub(crate) fn from_hex(hex: &str) -> Result<Self, ParseColorError> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ParseColorError(hex.to_string()));
+        }
+        let channel = |i: usize| u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).unwrap();
+
+        match digits.len() {
+            3 => {
+                let short = |i: usize| u8::from_str_radix(&digits[i..i + 1], 16).unwrap() * 0x11;
+                Ok(Self::rgb(short(0), short(1), short(2)))
+            }
+            6 => Ok(Self::rgb(channel(0), channel(1), channel(2))),
+            8 => Ok(Self::rgba(channel(0), channel(1), channel(2), channel(3))),
+            _ => Err(ParseColorError(hex.to_string())),
+        }
+    }
+
+    /// `#rrggbb`, or `#rrggbbaa` for translucent colours.
+    pub(crate) fn to_hex(self) -> String {
+        if self.a == 0xff {
+            format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, self.a)
+        }
+    }
+
+    /// Linear interpolation of all four channels, `t` is clamped to 0.0 - 1.0.
+    pub(crate) fn lerp(self, other: Color, t: f32) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Self::rgba(
+            channel(self.r, other.r),
+            channel(self.g, other.g),
+            channel(self.b, other.b),
+            channel(self.a, other.a),
+        )
+    }
+
+    /// Multiply the colour channels by `factors`, leaving alpha alone.
+    pub(crate) fn scale_rgb(self, factors: [f32; 3]) -> Self {
+        let channel = |c: u8, f: f32| (c as f32 * f).round().clamp(0.0, 255.0) as u8;
+        Self::rgba(
+            channel(self.r, factors[0]),
+            channel(self.g, factors[1]),
+            channel(self.b, factors[2]),
+            self.a,
+        )
+    }
+
+    /// Hue in degrees (0.0 - 360.0), saturation and value from 0.0 to 1.0.
+    pub(crate) fn from_hsv(hue: f32, saturation: f32, value: f32) -> Self {
+        let chroma = value * saturation;
+        Self::from_hue_chroma(hue, chroma, value - chroma)
+    }
+
+    /// Hue in degrees (0.0 - 360.0), saturation and value from 0.0 to 1.0.
+    pub(crate) fn to_hsv(self) -> (f32, f32, f32) {
+        let (hue, min, max) = self.hue_min_max();
+        let saturation = if max > 0.0 { (max - min) / max } else { 0.0 };
+        (hue, saturation, max)
+    }
+
+    /// Hue in degrees (0.0 - 360.0), saturation and lightness from 0.0 to 1.0.
+    pub(crate) fn from_hsl(hue: f32, saturation: f32, lightness: f32) -> Self {
+        let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+        Self::from_hue_chroma(hue, chroma, lightness - chroma / 2.0)
+    }
+
+    /// Hue in degrees (0.0 - 360.0), saturation and lightness from 0.0 to 1.0.
+    pub(crate) fn to_hsl(self) -> (f32, f32, f32) {
+        let (hue, min, max) = self.hue_min_max();
+        let lightness = (max + min) / 2.0;
+        let saturation = if max == min {
+            0.0
+        } else {
+            (max - min) / (1.0 - (2.0 * lightness - 1.0).abs())
+        };
+        (hue, saturation, lightness)
+    }
+
+    /// Shared part of the HSV and HSL conversions. `min` is added to every channel.
+    fn from_hue_chroma(hue: f32, chroma: f32, min: f32) -> Self {
+        let sector = hue.rem_euclid(360.0) / 60.0;
+        let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+        let (r, g, b) = match sector as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let channel = |c: f32| ((c + min) * 255.0).round().clamp(0.0, 255.0) as u8;
+        Self::rgb(channel(r), channel(g), channel(b))
+    }
+
+    /// Hue in degrees and the smallest and largest channel, from 0.0 to 1.0.
+    fn hue_min_max(self) -> (f32, f32, f32) {
+        let [r, g, b, _] = self.to_f32();
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        (hue, min, max)
+    }
+}
+
+impl From<[u8; 4]> for Color {
+    fn from([r, g, b, a]: [u8; 4]) -> Self {
+        Self::rgba(r, g, b, a)
+    }
+}
+
+impl From<Color> for [u8; 4] {
+    fn from(color: Color) -> Self {
+        color.to_array()
+    }
+}
+
+/// Accepts hex colours and the names of the constants.
+impl FromStr for Color {
+    type Err = ParseColorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::named(s).map_or_else(|| Self::from_hex(s), Ok)
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct ParseColorError(String);
+
+impl fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` is not a colour", self.0)
+    }
+}
+
+impl std::error::Error for ParseColorError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIMARIES: [(Color, f32); 6] = [
+        (Color::rgb(0xff, 0x00, 0x00), 0.0),
+        (Color::rgb(0xff, 0xff, 0x00), 60.0),
+        (Color::rgb(0x00, 0xff, 0x00), 120.0),
+        (Color::rgb(0x00, 0xff, 0xff), 180.0),
+        (Color::rgb(0x00, 0x00, 0xff), 240.0),
+        (Color::rgb(0xff, 0x00, 0xff), 300.0),
+    ];
+
+    #[test]
+    fn parse_hex() {
+        assert_eq!("#f80".parse(), Ok(Color::rgb(0xff, 0x88, 0x00)));
+        assert_eq!("#ff8000".parse(), Ok(Color::rgb(0xff, 0x80, 0x00)));
+        assert_eq!("ff8000".parse(), Ok(Color::rgb(0xff, 0x80, 0x00)));
+        assert_eq!("#FF800080".parse(), Ok(Color::rgba(0xff, 0x80, 0x00, 0x80)));
+        assert_eq!("red".parse(), Ok(Color::RED));
+    }
+
+    #[test]
+    fn reject_hex() {
+        for text in [
+            "", "#", "#ff", "#ff80", "#ff800", "#ff80000", "#gg8000", "#ffé", "#ééé",
+        ] {
+            assert_eq!(
+                text.parse::<Color>(),
+                Err(ParseColorError(text.to_string())),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        for color in [Color::ORANGE, Color::TRANSPARENT, Color::rgba(1, 2, 3, 4)] {
+            assert_eq!(Color::from_hex(&color.to_hex()), Ok(color));
+        }
+        assert_eq!(Color::ORANGE.to_hex(), "#ffa030");
+    }
+
+    #[test]
+    fn hsv_primaries() {
+        for (color, hue) in PRIMARIES {
+            assert_eq!(color.to_hsv(), (hue, 1.0, 1.0));
+            assert_eq!(Color::from_hsv(hue, 1.0, 1.0), color);
+        }
+        assert_eq!(Color::from_hsv(360.0, 1.0, 1.0), PRIMARIES[0].0);
+        assert_eq!(Color::from_hsv(0.0, 0.0, 0.0), Color::BLACK);
+        assert_eq!(Color::WHITE.to_hsv(), (0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn hsl_primaries() {
+        for (color, hue) in PRIMARIES {
+            assert_eq!(color.to_hsl(), (hue, 1.0, 0.5));
+            assert_eq!(Color::from_hsl(hue, 1.0, 0.5), color);
+        }
+        assert_eq!(Color::from_hsl(0.0, 0.0, 1.0), Color::WHITE);
+        assert_eq!(Color::BLACK.to_hsl(), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn hsv_and_hsl_round_trip() {
+        for (_, color) in Color::NAMED.into_iter().skip(1) {
+            let (h, s, v) = color.to_hsv();
+            assert_eq!(Color::from_hsv(h, s, v), color);
+            let (h, s, l) = color.to_hsl();
+            assert_eq!(Color::from_hsl(h, s, l), color);
+        }
+    }
+}
@@ -0,0 +1,462 @@
+use crate::entity::{Entity, EntityId, EntityStore, Slot};
+use crate::event::{Combatant, GameEvent};
+use crate::raws::{ItemDef, Raws};
+use crate::rng::{Dice, Rng};
+use crate::status::{self, StatusEffect, StatusKind};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Attack rolls are a d20 plus the attacker's bonus against 10 plus the target's defense.
+const BASE_DIFFICULTY: i32 = 10;
+/// A natural 20 always hits, and is a critical hit.
+const CRITICAL_ROLL: i32 = 20;
+/// A natural 1 always misses.
+const FUMBLE_ROLL: i32 = 1;
+/// Tiles a thrown item flies.
+pub(crate) const THROW_RANGE: i32 = 6;
+/// Attack roll penalty against targets that cannot be seen.
+const INVISIBLE_DEFENSE: i32 = 5;
+/// Damage of thrown items that are not weapons.
+const THROWN_DAMAGE: Dice = Dice {
+    count: 1,
+    sides: 2,
+    modifier: 0,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub(crate) enum DamageType {
+    Slashing,
+    Piercing,
+    Blunt,
+    Fire,
+    Cold,
+    Poison,
+}
+
+impl fmt::Display for DamageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DamageType::Slashing => "slashing",
+            DamageType::Piercing => "piercing",
+            DamageType::Blunt => "blunt",
+            DamageType::Fire => "fire",
+            DamageType::Cold => "cold",
+            DamageType::Poison => "poison",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Percentage of damage of each type that is ignored. Negative values are weaknesses.
+pub(crate) type Resistances = BTreeMap<DamageType, i32>;
+
+/// A single way of dealing damage, from a weapon or an entity's natural attack.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct Attack {
+    /// Added to the attack roll.
+    pub to_hit: i32,
+    pub damage: Dice,
+    pub damage_type: DamageType,
+    /// Tiles a ranged attack reaches, `None` for melee attacks.
+    pub range: Option<i32>,
+    /// Status effect a hit may cause.
+    pub inflict: Option<Inflict>,
+}
+
+/// A status effect that hits cause with some chance.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct Inflict {
+    pub effect: StatusEffect,
+    pub chance: f32,
+}
+
+/// Everything that protects an entity, from its own stats and its armour.
+#[derive(Clone, Default, Debug)]
+pub(crate) struct Defense {
+    /// Added to the difficulty of attack rolls against the entity.
+    pub defense: i32,
+    /// Taken off the damage of every hit.
+    pub armour: i32,
+    pub resistances: Resistances,
+}
+
+/// Melee attack of `entity`: its weapon, or its natural attack without one.
+pub(crate) fn melee_attack(entity: &Entity, raws: &Raws) -> Option<Attack> {
+    let stats = entity.stats.as_ref()?;
+    let weapon = entity
+        .equipment
+        .get(Slot::Weapon)
+        .and_then(|name| raws.item(name))
+        .and_then(|item| item.weapon.as_ref());
+    Some(match weapon {
+        Some(weapon) => Attack {
+            to_hit: stats.to_hit + weapon.to_hit + rings_to_hit(entity, raws),
+            damage: weapon.damage,
+            damage_type: weapon.damage_type,
+            range: None,
+            inflict: weapon.inflict.as_ref().map(|def| def.to_inflict()),
+        },
+        None => Attack {
+            to_hit: stats.to_hit + rings_to_hit(entity, raws),
+            damage: stats.attack,
+            damage_type: stats.damage_type,
+            range: None,
+            inflict: raws
+                .monster(&entity.name)
+                .and_then(|def| def.stats.inflict.as_ref())
+                .map(|def| def.to_inflict()),
+        },
+    })
+}
+
+/// Ranged attack of `entity`, if it has a ranged weapon equipped.
+pub(crate) fn ranged_attack(entity: &Entity, raws: &Raws) -> Option<Attack> {
+    let stats = entity.stats.as_ref()?;
+    let weapon = raws
+        .item(entity.equipment.get(Slot::Ranged)?)?
+        .weapon
+        .as_ref()?;
+    Some(Attack {
+        to_hit: stats.to_hit + weapon.to_hit + rings_to_hit(entity, raws),
+        damage: weapon.damage,
+        damage_type: weapon.damage_type,
+        range: weapon.range,
+        inflict: weapon.inflict.as_ref().map(|def| def.to_inflict()),
+    })
+}
+
+/// Attack of `entity` throwing `item`. Weapons hit as hard as they do in hand, anything else
+/// deals a little blunt damage.
+pub(crate) fn thrown_attack(entity: &Entity, item: &ItemDef, raws: &Raws) -> Option<Attack> {
+    let stats = entity.stats.as_ref()?;
+    let to_hit = stats.to_hit + rings_to_hit(entity, raws);
+    Some(match &item.weapon {
+        Some(weapon) => Attack {
+            to_hit: to_hit + weapon.to_hit,
+            damage: weapon.damage,
+            damage_type: weapon.damage_type,
+            range: Some(THROW_RANGE),
+            inflict: weapon.inflict.as_ref().map(|def| def.to_inflict()),
+        },
+        None => Attack {
+            to_hit,
+            damage: THROWN_DAMAGE,
+            damage_type: DamageType::Blunt,
+            range: Some(THROW_RANGE),
+            inflict: None,
+        },
+    })
+}
+
+/// Bonus to attack rolls from the rings `entity` wears.
+fn rings_to_hit(entity: &Entity, raws: &Raws) -> i32 {
+    entity
+        .equipment
+        .items()
+        .filter_map(|name| raws.item(name)?.ring.as_ref())
+        .map(|ring| ring.to_hit)
+        .sum()
+}
+
+/// Protection of `entity`, adding up its stats and everything it wears.
+pub(crate) fn defense(entity: &Entity, raws: &Raws) -> Defense {
+    let Some(stats) = &entity.stats else {
+        return Defense::default();
+    };
+    let mut defense = Defense {
+        defense: stats.defense,
+        armour: stats.armour,
+        resistances: stats.resistances.clone(),
+    };
+    if entity.effects.has(StatusKind::Invisibility) {
+        defense.defense += INVISIBLE_DEFENSE;
+    }
+    for item in entity.equipment.items().filter_map(|name| raws.item(name)) {
+        let resist = if let Some(armour) = &item.armour {
+            defense.armour += armour.armour;
+            &armour.resist
+        } else if let Some(ring) = &item.ring {
+            defense.defense += ring.defense;
+            &ring.resist
+        } else {
+            continue;
+        };
+        for (&damage_type, &percent) in resist {
+            *defense.resistances.entry(damage_type).or_default() += percent;
+        }
+    }
+    defense
+}
+
+/// Damage left after armour and resistances. Any hit deals at least 1 damage unless the
+/// target is immune to its type.
+pub(crate) fn mitigate(damage: i32, damage_type: DamageType, defense: &Defense) -> i32 {
+    resist((damage - defense.armour).max(1), damage_type, defense)
+}
+
+/// Damage left after resistances alone, at least 1 unless the target is immune. Armour
+/// does not help against harm from within, such as poison.
+pub(crate) fn resist(damage: i32, damage_type: DamageType, defense: &Defense) -> i32 {
+    let resist = defense
+        .resistances
+        .get(&damage_type)
+        .copied()
+        .unwrap_or(0)
+        .min(100);
+    if resist >= 100 || damage <= 0 {
+        return 0;
+    }
+    (damage * (100 - resist) / 100).max(1)
+}
+
+/// Whether a d20 `roll` with the attacker's `to_hit` bonus hits a target with `defense`.
+fn hits(roll: i32, to_hit: i32, defense: i32) -> bool {
+    roll != FUMBLE_ROLL && (roll == CRITICAL_ROLL || roll + to_hit >= BASE_DIFFICULTY + defense)
+}
+
+/// Damage before mitigation. Critical hits roll the dice twice, but add the modifier once.
+fn roll_damage(dice: Dice, critical: bool, rng: &mut Rng) -> i32 {
+    let damage = dice.roll(rng);
+    if critical {
+        damage + dice.roll(rng) - dice.modifier
+    } else {
+        damage
+    }
+}
+
+/// Roll `attack` from `attacker` against `target` and apply the damage. Returns an event
+/// for the hit or miss, and for the death of the target. Killed entities stay in the store
+/// with no health left, for the caller to clean up. Returns nothing if either entity is
+/// gone or the target cannot be hurt any more.
+pub(crate) fn resolve(
+    entities: &mut EntityStore,
+    raws: &Raws,
+    rng: &mut Rng,
+    player: EntityId,
+    attacker: EntityId,
+    target: EntityId,
+    attack: Attack,
+) -> Vec<GameEvent> {
+    let mut events = Vec::new();
+    let (Some(attacker_entity), Some(target_entity)) =
+        (entities.get(attacker), entities.get(target))
+    else {
+        return events;
+    };
+    if target_entity
+        .stats
+        .as_ref()
+        .is_none_or(|stats| stats.is_dead())
+    {
+        return events;
+    }
+    let attacker = Combatant::new(attacker_entity, attacker, player);
+    let victim = Combatant::new(target_entity, target, player);
+    let defense = defense(target_entity, raws);
+    let ranged = attack.range.is_some();
+
+    let roll = rng.range(1, 20);
+    if !hits(roll, attack.to_hit, defense.defense) {
+        events.push(GameEvent::Missed {
+            attacker,
+            target: victim,
+            ranged,
+        });
+        return events;
+    }
+
+    let critical = roll == CRITICAL_ROLL;
+    let damage = roll_damage(attack.damage, critical, rng);
+    let damage = mitigate(damage, attack.damage_type, &defense);
+
+    let Some(stats) = entities.get_mut(target).and_then(|e| e.stats.as_mut()) else {
+        return events;
+    };
+    stats.hp -= damage;
+    let killed = stats.hp <= 0;
+    events.push(GameEvent::Attacked {
+        attacker,
+        target: victim.clone(),
+        damage,
+        damage_type: attack.damage_type,
+        critical,
+        ranged,
+    });
+    if killed {
+        events.push(GameEvent::Died { victim });
+    } else if let Some(inflict) = attack.inflict.filter(|i| rng.chance(i.chance)) {
+        events.extend(status::apply(
+            entities,
+            raws,
+            player,
+            target,
+            inflict.effect,
+        ));
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Palette;
+    use glam::IVec2;
+
+    fn raws() -> Raws {
+        let palette = Palette::load("assets/palettes/dungeon.gpl").unwrap();
+        Raws::load("assets/raws", &palette).unwrap()
+    }
+
+    fn protection(armour: i32, resistances: &[(DamageType, i32)]) -> Defense {
+        Defense {
+            defense: 0,
+            armour,
+            resistances: resistances.iter().copied().collect(),
+        }
+    }
+
+    #[test]
+    fn attacks_hit_ten_plus_defense() {
+        assert!(hits(10, 0, 0));
+        assert!(!hits(9, 0, 0));
+        assert!(hits(8, 2, 0));
+        assert!(!hits(12, 2, 5));
+        assert!(hits(13, 2, 5));
+        // Natural rolls win over any bonus.
+        assert!(hits(CRITICAL_ROLL, -50, 50));
+        assert!(!hits(FUMBLE_ROLL, 50, 0));
+    }
+
+    #[test]
+    fn critical_hits_roll_the_dice_twice() {
+        let mut rng = Rng::seed_from_u64(1);
+        let dice = Dice {
+            count: 2,
+            sides: 1,
+            modifier: 3,
+        };
+        assert_eq!(roll_damage(dice, false, &mut rng), 5);
+        assert_eq!(roll_damage(dice, true, &mut rng), 7);
+
+        let dice: Dice = "1d6".parse().unwrap();
+        for _ in 0..100 {
+            assert!((2..=12).contains(&roll_damage(dice, true, &mut rng)));
+        }
+    }
+
+    #[test]
+    fn armour_applies_before_resistances() {
+        let defense = protection(2, &[(DamageType::Fire, 50), (DamageType::Cold, -50)]);
+        // (10 - 2) halved, not 10 halved less 2.
+        assert_eq!(mitigate(10, DamageType::Fire, &defense), 4);
+        assert_eq!(mitigate(10, DamageType::Cold, &defense), 12);
+        assert_eq!(mitigate(10, DamageType::Slashing, &defense), 8);
+        // Poison and other harm from within skips the armour.
+        assert_eq!(resist(10, DamageType::Slashing, &defense), 10);
+    }
+
+    #[test]
+    fn hits_deal_at_least_one_unless_immune() {
+        let defense = protection(20, &[(DamageType::Fire, 90), (DamageType::Poison, 100)]);
+        assert_eq!(mitigate(3, DamageType::Slashing, &defense), 1);
+        assert_eq!(mitigate(30, DamageType::Fire, &defense), 1);
+        assert_eq!(mitigate(30, DamageType::Poison, &defense), 0);
+        let defense = protection(0, &[(DamageType::Poison, 150)]);
+        assert_eq!(mitigate(30, DamageType::Poison, &defense), 0);
+    }
+
+    #[test]
+    fn resolve_applies_the_rolled_damage() {
+        let raws = raws();
+        let mut entities = EntityStore::new();
+        let player = raws.spawn_monster("player", IVec2::ZERO).unwrap();
+        let player = entities.spawn(player);
+        let mut goblin = raws.spawn_monster("goblin", IVec2::X).unwrap();
+        // Tough enough to take a few misses before it goes down.
+        goblin.stats.as_mut().unwrap().hp = 100;
+        let goblin = entities.spawn(goblin);
+        let attack = melee_attack(entities.get(player).unwrap(), &raws).unwrap();
+        let mut rng = Rng::seed_from_u64(7);
+
+        let hp = |entities: &EntityStore| entities.get(goblin).unwrap().stats.as_ref().unwrap().hp;
+        let (mut hits, mut misses) = (0, 0);
+        while hp(&entities) > 0 {
+            let before = hp(&entities);
+            let events = resolve(
+                &mut entities,
+                &raws,
+                &mut rng,
+                player,
+                player,
+                goblin,
+                attack,
+            );
+            match events.as_slice() {
+                [GameEvent::Missed { .. }] => {
+                    assert_eq!(hp(&entities), before);
+                    misses += 1;
+                }
+                [GameEvent::Attacked { damage, .. }, rest @ ..] => {
+                    assert!(*damage >= 1);
+                    assert_eq!(hp(&entities), before - damage);
+                    assert_eq!(rest.len(), usize::from(hp(&entities) <= 0));
+                    hits += 1;
+                }
+                events => panic!("unexpected {} events", events.len()),
+            }
+        }
+        assert!(hits > 0 && misses > 0, "{} hits, {} misses", hits, misses);
+        // The dead cannot be hurt any more.
+        let events = resolve(
+            &mut entities,
+            &raws,
+            &mut rng,
+            player,
+            player,
+            goblin,
+            attack,
+        );
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn equipment_changes_stats() {
+        let raws = raws();
+        let mut entity = raws.spawn_monster("goblin", IVec2::ZERO).unwrap();
+        let natural = melee_attack(&entity, &raws).unwrap();
+        assert_eq!(natural.damage, "1d6+1".parse().unwrap());
+        assert_eq!(natural.to_hit, 1);
+        assert_eq!(defense(&entity, &raws).armour, 1);
+
+        entity
+            .equipment
+            .set(Slot::Weapon, Some("sword".to_string()));
+        entity
+            .equipment
+            .set(Slot::Armour, Some("leather armour".to_string()));
+        entity
+            .equipment
+            .set(Slot::LeftRing, Some("ring of accuracy".to_string()));
+        entity
+            .equipment
+            .set(Slot::RightRing, Some("ring of fire resistance".to_string()));
+        let armed = melee_attack(&entity, &raws).unwrap();
+        assert_eq!(armed.damage, "1d8".parse().unwrap());
+        assert_eq!(armed.to_hit, 1 + 1 + 2);
+        let worn = defense(&entity, &raws);
+        assert_eq!(worn.armour, 2);
+        assert_eq!(worn.resistances[&DamageType::Fire], 50);
+        assert!(ranged_attack(&entity, &raws).is_none());
+
+        // Taking it all off again restores the natural stats.
+        for slot in Slot::ALL {
+            entity.equipment.set(slot, None);
+        }
+        assert_eq!(melee_attack(&entity, &raws), Some(natural));
+        assert_eq!(defense(&entity, &raws).armour, 1);
+        assert!(!defense(&entity, &raws)
+            .resistances
+            .contains_key(&DamageType::Fire));
+    }
+}
@@ -0,0 +1,188 @@
+use log::warn;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Engine settings, read from a simple `key = value` file.
+pub(crate) struct Config {
+    /// Logical resolution of the frame buffer. The GPU scales it up by an integer factor
+    /// and letterboxes the rest of the window.
+    pub width: u32,
+    pub height: u32,
+    /// Initial window size as a multiple of the logical resolution.
+    pub window_scale: u32,
+    /// Palette file (`.gpl` or `.hex`) that re-themes the game's named colours.
+    pub palette: Option<PathBuf>,
+    /// Directory screenshots and recordings are saved to.
+    pub capture_dir: PathBuf,
+    /// Length and frame rate of GIF recordings.
+    pub record_seconds: u32,
+    pub record_fps: u32,
+    /// Where the game is saved on quit and resumed from on start.
+    pub save_path: PathBuf,
+    /// Delete the save when it is loaded, so every run has a single life.
+    pub permadeath: bool,
+    /// Seed for new games, picked at random when not set.
+    pub seed: Option<u64>,
+    /// Record the actions of every new game, to replay them with `--replay <file>`.
+    pub record_replays: bool,
+    pub replay_dir: PathBuf,
+    /// Directory with the monster, item and tile definitions.
+    pub raws_dir: PathBuf,
+    pub postfx: PostFxConfig,
+}
+
+/// Which post-processing effects run and how strong they are.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct PostFxConfig {
+    pub scanlines: bool,
+    pub vignette: bool,
+    /// Snap every pixel to the nearest colour of the palette.
+    pub quantize: bool,
+    pub damage_flash: bool,
+    pub desaturate: bool,
+    /// How much the scanlines darken, from 0.0 to 1.0.
+    pub scanline_strength: f32,
+    /// How much the corners darken, from 0.0 to 1.0.
+    pub vignette_strength: f32,
+    /// Apply the effects to the frame buffer on the CPU instead of running the GPU passes.
+    pub cpu_fallback: bool,
+}
+
+impl Default for PostFxConfig {
+    fn default() -> Self {
+        Self {
+            scanlines: false,
+            vignette: true,
+            quantize: false,
+            damage_flash: true,
+            desaturate: true,
+            scanline_strength: 0.25,
+            vignette_strength: 0.4,
+            cpu_fallback: false,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            width: 320,
+            height: 180,
+            window_scale: 4,
+            palette: Some(PathBuf::from("assets/palettes/dungeon.gpl")),
+            capture_dir: PathBuf::from("captures"),
+            record_seconds: 10,
+            record_fps: 20,
+            save_path: PathBuf::from("savegame.sav"),
+            permadeath: true,
+            seed: None,
+            record_replays: true,
+            replay_dir: PathBuf::from("replays"),
+            raws_dir: PathBuf::from("assets/raws"),
+            postfx: PostFxConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Load the config at `path`, falling back to the defaults for missing or invalid entries.
+    pub(crate) fn load(path: impl AsRef<Path>) -> Self {
+        let mut config = Self::default();
+        let path = path.as_ref();
+
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return config,
+        };
+
+        for (line_num, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                warn!(
+                    "{}:{}: expected `key = value`",
+                    path.display(),
+                    line_num + 1
+                );
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            if !config.set(key, value) {
+                warn!(
+                    "{}:{}: invalid setting `{}` = `{}`",
+                    path.display(),
+                    line_num + 1,
+                    key,
+                    value
+                );
+            }
+        }
+
+        config
+    }
+
+    /// Apply a single setting, returns false if the key is unknown or the value is invalid.
+    fn set(&mut self, key: &str, value: &str) -> bool {
+        fn parse_nonzero(value: &str) -> Option<u32> {
+            value.parse().ok().filter(|v| *v > 0)
+        }
+        fn parse_unit(value: &str) -> Option<f32> {
+            value.parse().ok().filter(|v| (0.0..=1.0).contains(v))
+        }
+
+        match key {
+            "width" => parse_nonzero(value).map(|v| self.width = v).is_some(),
+            "height" => parse_nonzero(value).map(|v| self.height = v).is_some(),
+            "window_scale" => parse_nonzero(value)
+                .map(|v| self.window_scale = v)
+                .is_some(),
+            "palette" => {
+                self.palette = Some(PathBuf::from(value));
+                true
+            }
+            "capture_dir" => {
+                self.capture_dir = PathBuf::from(value);
+                true
+            }
+            "record_seconds" => parse_nonzero(value)
+                .map(|v| self.record_seconds = v)
+                .is_some(),
+            "record_fps" => parse_nonzero(value)
+                .filter(|v| *v <= 100)
+                .map(|v| self.record_fps = v)
+                .is_some(),
+            "save_path" => {
+                self.save_path = PathBuf::from(value);
+                true
+            }
+            "permadeath" => value.parse().map(|v| self.permadeath = v).is_ok(),
+            "seed" => value.parse().map(|v| self.seed = Some(v)).is_ok(),
+            "record_replays" => value.parse().map(|v| self.record_replays = v).is_ok(),
+            "replay_dir" => {
+                self.replay_dir = PathBuf::from(value);
+                true
+            }
+            "raws_dir" => {
+                self.raws_dir = PathBuf::from(value);
+                true
+            }
+            "postfx_scanlines" => value.parse().map(|v| self.postfx.scanlines = v).is_ok(),
+            "postfx_vignette" => value.parse().map(|v| self.postfx.vignette = v).is_ok(),
+            "postfx_quantize" => value.parse().map(|v| self.postfx.quantize = v).is_ok(),
+            "postfx_damage_flash" => value.parse().map(|v| self.postfx.damage_flash = v).is_ok(),
+            "postfx_desaturate" => value.parse().map(|v| self.postfx.desaturate = v).is_ok(),
+            "postfx_scanline_strength" => parse_unit(value)
+                .map(|v| self.postfx.scanline_strength = v)
+                .is_some(),
+            "postfx_vignette_strength" => parse_unit(value)
+                .map(|v| self.postfx.vignette_strength = v)
+                .is_some(),
+            "postfx_cpu" => value.parse().map(|v| self.postfx.cpu_fallback = v).is_ok(),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::color::Color;
+use crate::renderer::Renderer;
+use glam::Vec2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct Cell {
+    pub glyph: char,
+    pub fg: Color,
+    pub bg: Color,
+}
+
+impl Cell {
+    pub(crate) const EMPTY: Cell = Cell {
+        glyph: ' ',
+        fg: Color::WHITE,
+        bg: Color::rgba(0x00, 0x00, 0x00, 0x00),
+    };
+}
+
+/// A fixed grid of glyph cells, drawn through the renderer's font.
+/// Cells with a transparent background leave whatever was drawn underneath.
+pub(crate) struct Console {
+    width: u32,
+    height: u32,
+    cell_size: u32,
+    cells: Vec<Cell>,
+}
+
+impl Console {
+    pub(crate) fn new(width: u32, height: u32, cell_size: u32) -> Self {
+        Self {
+            width,
+            height,
+            cell_size,
+            cells: vec![Cell::EMPTY; (width * height) as usize],
+        }
+    }
+
+    pub(crate) fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub(crate) fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.cells.fill(Cell::EMPTY);
+    }
+
+    pub(crate) fn set(&mut self, x: u32, y: u32, glyph: char, fg: Color, bg: Color) {
+        if x < self.width && y < self.height {
+            self.cells[(y * self.width + x) as usize] = Cell { glyph, fg, bg };
+        }
+    }
+
+    /// Change the glyph of a cell while keeping its background.
+    pub(crate) fn set_glyph(&mut self, x: u32, y: u32, glyph: char, fg: Color) {
+        if x < self.width && y < self.height {
+            let cell = &mut self.cells[(y * self.width + x) as usize];
+            cell.glyph = glyph;
+            cell.fg = fg;
+        }
+    }
+
+    /// Write `text` starting at the given cell. Text running off the right edge is clipped.
+    pub(crate) fn print(&mut self, x: u32, y: u32, text: &str, fg: Color) {
+        for (i, char) in text.chars().enumerate() {
+            self.set_glyph(x + i as u32, y, char, fg);
+        }
+    }
+
+    pub(crate) fn draw(&self, renderer: &mut Renderer, pos: Vec2) {
+        let cell_size = self.cell_size as f32;
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let cell = &self.cells[(y * self.width + x) as usize];
+                let cell_pos = Vec2 {
+                    x: pos.x + x as f32 * cell_size,
+                    y: pos.y + y as f32 * cell_size,
+                };
+
+                if cell.bg.a > 0 {
+                    renderer.draw_square(cell_pos, Vec2::splat(cell_size), cell.bg);
+                }
+                if cell.glyph != ' ' {
+                    renderer.draw_glyph(cell_pos, cell_size, cell.glyph, cell.fg);
+                }
+            }
+        }
+    }
+}
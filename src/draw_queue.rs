@@ -0,0 +1,301 @@
+use crate::color::Color;
+use crate::renderer::DrawParams;
+use crate::sprite::Sprite;
+use glam::Vec2;
+
+/// Draw layers, drawn back to front in declaration order.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub(crate) enum Layer {
+    Floor,
+    Items,
+    Actors,
+    Effects,
+    Ui,
+}
+
+impl Layer {
+    pub(crate) const COUNT: usize = 5;
+}
+
+pub(crate) enum DrawCommand<'a> {
+    Sprite {
+        pos: Vec2,
+        sprite: &'a Sprite,
+        frame: u32,
+        params: DrawParams,
+    },
+    Text {
+        pos: Vec2,
+        text: String,
+        size: f32,
+        spacing: f32,
+        color: Color,
+    },
+    Rect {
+        pos: Vec2,
+        size: Vec2,
+        color: Color,
+    },
+    RectOutline {
+        pos: Vec2,
+        size: Vec2,
+        color: Color,
+    },
+    Line {
+        start: Vec2,
+        end: Vec2,
+        color: Color,
+    },
+    Circle {
+        center: Vec2,
+        radius: f32,
+        color: Color,
+        filled: bool,
+    },
+    /// Outline of a closed polygon.
+    Polygon {
+        points: Vec<Vec2>,
+        color: Color,
+    },
+    FillPolygon {
+        points: Vec<Vec2>,
+        color: Color,
+    },
+}
+
+pub(crate) struct DrawEntry<'a> {
+    pub layer: Layer,
+    /// Order within the layer, e.g. the y position of an actor. Lower keys are drawn first.
+    pub sort_key: i32,
+    pub command: DrawCommand<'a>,
+}
+
+/// Settings applied to every command on a layer when the queue is flushed.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LayerSettings {
+    pub visible: bool,
+    /// Added to the position of every command, e.g. a camera offset for world layers.
+    pub offset: Vec2,
+}
+
+impl Default for LayerSettings {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            offset: Vec2::ZERO,
+        }
+    }
+}
+
+/// Draw commands collected over a frame, sorted by layer and sort key when flushed by the
+/// `Renderer`. Commands with equal layer and key keep their submission order.
+#[derive(Default)]
+pub(crate) struct DrawQueue<'a> {
+    entries: Vec<DrawEntry<'a>>,
+    layers: [LayerSettings; Layer::COUNT],
+}
+
+impl<'a> DrawQueue<'a> {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn push(&mut self, layer: Layer, sort_key: i32, command: DrawCommand<'a>) {
+        self.entries.push(DrawEntry {
+            layer,
+            sort_key,
+            command,
+        });
+    }
+
+    pub(crate) fn sprite(
+        &mut self,
+        layer: Layer,
+        sort_key: i32,
+        pos: Vec2,
+        sprite: &'a Sprite,
+        frame: u32,
+        params: DrawParams,
+    ) {
+        self.push(
+            layer,
+            sort_key,
+            DrawCommand::Sprite {
+                pos,
+                sprite,
+                frame,
+                params,
+            },
+        );
+    }
+
+    /// Queue text with a sort key of 0. Use `push` to order text within its layer.
+    pub(crate) fn text(
+        &mut self,
+        layer: Layer,
+        pos: Vec2,
+        text: &str,
+        size: f32,
+        spacing: f32,
+        color: Color,
+    ) {
+        self.push(
+            layer,
+            0,
+            DrawCommand::Text {
+                pos,
+                text: text.to_string(),
+                size,
+                spacing,
+                color,
+            },
+        );
+    }
+
+    pub(crate) fn rect(
+        &mut self,
+        layer: Layer,
+        sort_key: i32,
+        pos: Vec2,
+        size: Vec2,
+        color: Color,
+    ) {
+        self.push(layer, sort_key, DrawCommand::Rect { pos, size, color });
+    }
+
+    /// Outline of a rectangle, one pixel wide.
+    pub(crate) fn rect_outline(
+        &mut self,
+        layer: Layer,
+        sort_key: i32,
+        pos: Vec2,
+        size: Vec2,
+        color: Color,
+    ) {
+        self.push(
+            layer,
+            sort_key,
+            DrawCommand::RectOutline { pos, size, color },
+        );
+    }
+
+    pub(crate) fn line(
+        &mut self,
+        layer: Layer,
+        sort_key: i32,
+        start: Vec2,
+        end: Vec2,
+        color: Color,
+    ) {
+        self.push(layer, sort_key, DrawCommand::Line { start, end, color });
+    }
+
+    pub(crate) fn circle(
+        &mut self,
+        layer: Layer,
+        sort_key: i32,
+        center: Vec2,
+        radius: f32,
+        color: Color,
+        filled: bool,
+    ) {
+        self.push(
+            layer,
+            sort_key,
+            DrawCommand::Circle {
+                center,
+                radius,
+                color,
+                filled,
+            },
+        );
+    }
+
+    /// Outline of the closed polygon through `points`.
+    pub(crate) fn polygon(&mut self, layer: Layer, sort_key: i32, points: &[Vec2], color: Color) {
+        self.push(
+            layer,
+            sort_key,
+            DrawCommand::Polygon {
+                points: points.to_vec(),
+                color,
+            },
+        );
+    }
+
+    pub(crate) fn fill_polygon(
+        &mut self,
+        layer: Layer,
+        sort_key: i32,
+        points: &[Vec2],
+        color: Color,
+    ) {
+        self.push(
+            layer,
+            sort_key,
+            DrawCommand::FillPolygon {
+                points: points.to_vec(),
+                color,
+            },
+        );
+    }
+
+    /// Settings of every layer, indexed by `Layer as usize`.
+    pub(crate) fn layers(&self) -> &[LayerSettings; Layer::COUNT] {
+        &self.layers
+    }
+
+    pub(crate) fn layer_mut(&mut self, layer: Layer) -> &mut LayerSettings {
+        &mut self.layers[layer as usize]
+    }
+
+    /// Sort the commands into draw order and remove them from the queue.
+    pub(crate) fn drain_sorted(&mut self) -> std::vec::Drain<'_, DrawEntry<'a>> {
+        // A stable sort keeps submission order for equal keys.
+        self.entries
+            .sort_by_key(|entry| (entry.layer, entry.sort_key));
+        self.entries.drain(..)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primitives_are_sorted_by_layer_then_key() {
+        let mut queue = DrawQueue::new();
+        let color = Color::WHITE;
+        queue.circle(Layer::Ui, 0, Vec2::ZERO, 2.0, color, true);
+        queue.polygon(Layer::Floor, 5, &[Vec2::ZERO, Vec2::X, Vec2::Y], color);
+        queue.line(Layer::Effects, -1, Vec2::ZERO, Vec2::ONE, color);
+        queue.fill_polygon(Layer::Floor, 1, &[Vec2::ZERO, Vec2::X, Vec2::Y], color);
+        queue.rect_outline(Layer::Effects, -1, Vec2::ZERO, Vec2::ONE, color);
+        queue.rect(Layer::Actors, 3, Vec2::ZERO, Vec2::ONE, color);
+
+        let order: Vec<_> = queue
+            .drain_sorted()
+            .map(|entry| match entry.command {
+                DrawCommand::FillPolygon { .. } => "fill polygon",
+                DrawCommand::Polygon { .. } => "polygon",
+                DrawCommand::Rect { .. } => "rect",
+                DrawCommand::Line { .. } => "line",
+                DrawCommand::RectOutline { .. } => "rect outline",
+                DrawCommand::Circle { .. } => "circle",
+                DrawCommand::Sprite { .. } | DrawCommand::Text { .. } => "other",
+            })
+            .collect();
+        // Equal layers and keys keep the order they were queued in.
+        assert_eq!(
+            order,
+            [
+                "fill polygon",
+                "polygon",
+                "rect",
+                "line",
+                "rect outline",
+                "circle"
+            ]
+        );
+    }
+}
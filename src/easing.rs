@@ -1,4 +1,5 @@
 #![allow(dead_code)]
+use serde::{Deserialize, Serialize};
 
 pub fn ease_in_sine(val: f32) -> f32 {
     1.0 - ((val * std::f32::consts::PI) / 2.0).cos()
@@ -9,7 +10,7 @@ pub fn ease_out_sin(val: f32) -> f32 {
 }
 
 pub fn ease_in_out_sin(val: f32) -> f32 {
-    -((std::f32::consts::PI * val) - 1.0).cos() / 2.0
+    -((std::f32::consts::PI * val).cos() - 1.0) / 2.0
 }
 
 pub fn ease_in_quad(val: f32) -> f32 {
@@ -97,12 +98,10 @@ pub fn ease_in_out_expo(val: f32) -> f32 {
         0.0
     } else if val == 1.0 {
         1.0
+    } else if val < 0.5 {
+        2.0_f32.powf(20.0 * val - 10.0) / 2.0
     } else {
-        if val < 0.5 {
-            2.0_f32.powf(20.0 * val - 20.0) / 2.0
-        } else {
-            2.0 - 2.0_f32.powf(-20.0 * val - 20.0) / 2.0
-        }
+        (2.0 - 2.0_f32.powf(-20.0 * val + 10.0)) / 2.0
     }
 }
 
@@ -118,7 +117,7 @@ pub fn ease_in_out_circ(val: f32) -> f32 {
     if val < 0.5 {
         (1.0 - (1.0 - (2.0 * val).powf(2.0)).sqrt()) / 2.0
     } else {
-        ((1.0 - (-2.0 * val + 2.0).powf(2.0)) + 1.0).sqrt() / 2.0
+        ((1.0 - (-2.0 * val + 2.0).powf(2.0)).sqrt() + 1.0) / 2.0
     }
 }
 
@@ -143,7 +142,7 @@ pub fn ease_in_out_back(val: f32) -> f32 {
     if val < 0.5 {
         ((2.0 * val).powf(2.0) * ((c2 + 1.0) * 2.0 * val - c2)) / 2.0
     } else {
-        ((2.0 * val).powf(2.0) * ((c2 + 1.0) * 2.0 * val - c2)) / 2.0
+        ((2.0 * val - 2.0).powf(2.0) * ((c2 + 1.0) * (val * 2.0 - 2.0) + c2) + 2.0) / 2.0
     }
 }
 
@@ -155,7 +154,7 @@ pub fn ease_in_elastic(val: f32) -> f32 {
     } else if val == 1.0 {
         1.0
     } else {
-        -(2.0_f32).powf(10.0 * val * -10.0) * ((val * 10.0 - 10.75) * c4).sin()
+        -(2.0_f32).powf(10.0 * val - 10.0) * ((val * 10.0 - 10.75) * c4).sin()
     }
 }
 
@@ -179,7 +178,7 @@ pub fn ease_in_out_elastic(val: f32) -> f32 {
     } else if val < 0.5 {
         -(2.0_f32).powf(20.0 * val - 10.0) * ((20.0 * val - 11.125) * c5).sin() / 2.0
     } else {
-        (2.0_f32).powf(-20.0 * val + 10.0) * ((20.0 * val - 11.125) * c5).sin() / 2.0
+        (2.0_f32).powf(-20.0 * val + 10.0) * ((20.0 * val - 11.125) * c5).sin() / 2.0 + 1.0
     }
 }
 
@@ -194,11 +193,11 @@ pub fn ease_out_bounce(val: f32) -> f32 {
     if val < 1.0 / d1 {
         n1 * val * val
     } else if val < 2.0 / d1 {
-        n1 * (val - 1.5 / d1) * val + 0.75
+        n1 * (val - 1.5 / d1).powf(2.0) + 0.75
     } else if val < 2.5 / d1 {
-        n1 * (val - 2.25 / d1) * val + 0.9375
+        n1 * (val - 2.25 / d1).powf(2.0) + 0.9375
     } else {
-        n1 * (val - 2.625 / d1) * val + 0.984375
+        n1 * (val - 2.625 / d1).powf(2.0) + 0.984375
     }
 }
 
@@ -209,3 +208,179 @@ pub fn ease_in_out_bounce(val: f32) -> f32 {
         (1.0 + ease_out_bounce(2.0 * val - 1.0)) / 2.0
     }
 }
+
+/// An easing curve that, unlike a function pointer, can be compared and serialized.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Easing {
+    Linear,
+    InSine,
+    OutSine,
+    InOutSine,
+    InQuad,
+    OutQuad,
+    InOutQuad,
+    InCubic,
+    OutCubic,
+    InOutCubic,
+    InQuart,
+    OutQuart,
+    InOutQuart,
+    InQuint,
+    OutQuint,
+    InOutQuint,
+    InExpo,
+    OutExpo,
+    InOutExpo,
+    InCirc,
+    OutCirc,
+    InOutCirc,
+    InBack,
+    OutBack,
+    InOutBack,
+    InElastic,
+    OutElastic,
+    InOutElastic,
+    InBounce,
+    OutBounce,
+    InOutBounce,
+}
+
+impl Easing {
+    pub fn apply(self, val: f32) -> f32 {
+        match self {
+            Easing::Linear => val,
+            Easing::InSine => ease_in_sine(val),
+            Easing::OutSine => ease_out_sin(val),
+            Easing::InOutSine => ease_in_out_sin(val),
+            Easing::InQuad => ease_in_quad(val),
+            Easing::OutQuad => ease_out_quad(val),
+            Easing::InOutQuad => ease_in_out_quad(val),
+            Easing::InCubic => ease_in_cubic(val),
+            Easing::OutCubic => ease_out_cubic(val),
+            Easing::InOutCubic => ease_in_out_cubic(val),
+            Easing::InQuart => ease_in_quart(val),
+            Easing::OutQuart => ease_out_quart(val),
+            Easing::InOutQuart => ease_in_out_quart(val),
+            Easing::InQuint => ease_in_quint(val),
+            Easing::OutQuint => ease_out_quint(val),
+            Easing::InOutQuint => ease_in_out_quint(val),
+            Easing::InExpo => ease_in_expo(val),
+            Easing::OutExpo => ease_out_expo(val),
+            Easing::InOutExpo => ease_in_out_expo(val),
+            Easing::InCirc => ease_in_circ(val),
+            Easing::OutCirc => ease_out_circ(val),
+            Easing::InOutCirc => ease_in_out_circ(val),
+            Easing::InBack => ease_in_back(val),
+            Easing::OutBack => ease_out_back(val),
+            Easing::InOutBack => ease_in_out_back(val),
+            Easing::InElastic => ease_in_elastic(val),
+            Easing::OutElastic => ease_out_elastic(val),
+            Easing::InOutElastic => ease_in_out_elastic(val),
+            Easing::InBounce => ease_in_bounce(val),
+            Easing::OutBounce => ease_out_bounce(val),
+            Easing::InOutBounce => ease_in_out_bounce(val),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Easing; 31] = [
+        Easing::Linear,
+        Easing::InSine,
+        Easing::OutSine,
+        Easing::InOutSine,
+        Easing::InQuad,
+        Easing::OutQuad,
+        Easing::InOutQuad,
+        Easing::InCubic,
+        Easing::OutCubic,
+        Easing::InOutCubic,
+        Easing::InQuart,
+        Easing::OutQuart,
+        Easing::InOutQuart,
+        Easing::InQuint,
+        Easing::OutQuint,
+        Easing::InOutQuint,
+        Easing::InExpo,
+        Easing::OutExpo,
+        Easing::InOutExpo,
+        Easing::InCirc,
+        Easing::OutCirc,
+        Easing::InOutCirc,
+        Easing::InBack,
+        Easing::OutBack,
+        Easing::InOutBack,
+        Easing::InElastic,
+        Easing::OutElastic,
+        Easing::InOutElastic,
+        Easing::InBounce,
+        Easing::OutBounce,
+        Easing::InOutBounce,
+    ];
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn curves_start_at_0_and_end_at_1() {
+        for easing in ALL {
+            assert!(close(easing.apply(0.0), 0.0), "{:?}", easing);
+            assert!(close(easing.apply(1.0), 1.0), "{:?}", easing);
+        }
+    }
+
+    #[test]
+    fn in_out_curves_pass_through_the_middle() {
+        for easing in [
+            Easing::InOutSine,
+            Easing::InOutQuad,
+            Easing::InOutCubic,
+            Easing::InOutQuart,
+            Easing::InOutQuint,
+            Easing::InOutExpo,
+            Easing::InOutCirc,
+            Easing::InOutBack,
+            Easing::InOutElastic,
+            Easing::InOutBounce,
+        ] {
+            assert!(close(easing.apply(0.5), 0.5), "{:?}", easing);
+        }
+    }
+
+    #[test]
+    fn curves_without_overshoot_stay_in_range() {
+        let overshoot = |e: &Easing| {
+            matches!(
+                e,
+                Easing::InBack
+                    | Easing::OutBack
+                    | Easing::InOutBack
+                    | Easing::InElastic
+                    | Easing::OutElastic
+                    | Easing::InOutElastic
+            )
+        };
+        for easing in ALL.iter().filter(|e| !overshoot(e)) {
+            for i in 0..=100 {
+                let value = easing.apply(i as f32 / 100.0);
+                assert!((-1e-3..=1.0 + 1e-3).contains(&value), "{:?}", easing);
+            }
+        }
+    }
+
+    #[test]
+    fn bounce_is_continuous() {
+        for i in 0..1000 {
+            let (a, b) = (i as f32 / 1000.0, (i + 1) as f32 / 1000.0);
+            assert!(
+                (ease_out_bounce(b) - ease_out_bounce(a)).abs() < 0.05,
+                "{}",
+                a
+            );
+        }
+    }
+}
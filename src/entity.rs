@@ -0,0 +1,315 @@
+use crate::ai::Brain;
+use crate::color::Color;
+use crate::combat::{DamageType, Resistances};
+use crate::inventory::{Inventory, ItemStack};
+use crate::lighting::Light;
+use crate::rng::Dice;
+use crate::spatial::SpatialIndex;
+use crate::status::StatusEffects;
+use glam::IVec2;
+use serde::{Deserialize, Serialize};
+
+/// Ids order entities by their slot in the store.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub(crate) struct EntityId(usize);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Entity {
+    pub name: String,
+    /// Only changed by `EntityStore::move_to`, which keeps the spatial index in sync.
+    pos: IVec2,
+    /// Glyph and colour used by the ASCII presentation.
+    pub glyph: char,
+    pub color: Color,
+    /// Key of the sprite used by the tile presentation.
+    pub sprite: String,
+    /// Mirror the sprite horizontally, e.g. after moving left.
+    pub facing_left: bool,
+    /// Whether other entities can share a tile with this one.
+    pub blocks: bool,
+    /// Light the entity emits, if any.
+    pub light: Option<Light>,
+    /// Entities without stats cannot fight or be hurt.
+    pub stats: Option<Stats>,
+    /// How the entity decides what to do. The player has no brain.
+    pub ai: Option<AiKind>,
+    /// What the entity remembers between turns, for entities with an `ai`.
+    pub brain: Option<Brain>,
+    /// Loot table rolled when the entity dies.
+    pub loot: Option<String>,
+    /// Names of the items the entity fights with and wears.
+    pub equipment: Equipment,
+    /// Items carried. Entities without an inventory cannot pick anything up.
+    pub inventory: Option<Inventory>,
+    /// Set for items lying on the floor.
+    pub item: Option<ItemStack>,
+    pub effects: StatusEffects,
+    /// Spent on actions and regained every game turn, see `scheduler`.
+    pub energy: i32,
+}
+
+impl Entity {
+    /// An entity at `pos` that does nothing and lets others pass. The remaining fields are
+    /// filled in by whoever spawns it.
+    pub(crate) fn new(name: String, pos: IVec2) -> Self {
+        Self {
+            name,
+            pos,
+            glyph: '?',
+            color: Color::WHITE,
+            sprite: String::new(),
+            facing_left: false,
+            blocks: false,
+            light: None,
+            stats: None,
+            ai: None,
+            brain: None,
+            loot: None,
+            equipment: Equipment::default(),
+            inventory: None,
+            item: None,
+            effects: StatusEffects::default(),
+            energy: 0,
+        }
+    }
+
+    pub(crate) fn pos(&self) -> IVec2 {
+        self.pos
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Stats {
+    pub hp: i32,
+    pub max_hp: i32,
+    /// Added to attack rolls.
+    pub to_hit: i32,
+    /// Damage dealt by an unarmed attack.
+    pub attack: Dice,
+    pub damage_type: DamageType,
+    /// Added to the difficulty of attack rolls against the entity.
+    pub defense: i32,
+    /// Taken off the damage of every hit.
+    pub armour: i32,
+    pub resistances: Resistances,
+}
+
+impl Stats {
+    pub(crate) fn is_dead(&self) -> bool {
+        self.hp <= 0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Slot {
+    /// Melee weapon.
+    Weapon,
+    /// Weapon fired with `Action::Fire`.
+    Ranged,
+    Armour,
+    LeftRing,
+    RightRing,
+}
+
+impl Slot {
+    pub(crate) const COUNT: usize = 5;
+    pub(crate) const ALL: [Slot; Slot::COUNT] = [
+        Slot::Weapon,
+        Slot::Ranged,
+        Slot::Armour,
+        Slot::LeftRing,
+        Slot::RightRing,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Slot::Weapon => "Weapon",
+            Slot::Ranged => "Ranged",
+            Slot::Armour => "Armour",
+            Slot::LeftRing => "Left ring",
+            Slot::RightRing => "Right ring",
+        }
+    }
+}
+
+/// Equipped items by slot, named by their definition in the raws.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub(crate) struct Equipment {
+    slots: [Option<String>; Slot::COUNT],
+}
+
+impl Equipment {
+    pub(crate) fn get(&self, slot: Slot) -> Option<&str> {
+        self.slots[slot as usize].as_deref()
+    }
+
+    /// Put `item` into `slot`, returning what was there before.
+    pub(crate) fn set(&mut self, slot: Slot, item: Option<String>) -> Option<String> {
+        std::mem::replace(&mut self.slots[slot as usize], item)
+    }
+
+    /// Slot an item meant for `slot` goes into: the right hand for a second ring.
+    pub(crate) fn free_slot(&self, slot: Slot) -> Slot {
+        match slot {
+            Slot::LeftRing
+                if self.get(Slot::LeftRing).is_some() && self.get(Slot::RightRing).is_none() =>
+            {
+                Slot::RightRing
+            }
+            slot => slot,
+        }
+    }
+
+    /// Every equipped item.
+    pub(crate) fn items(&self) -> impl Iterator<Item = &str> {
+        self.slots.iter().filter_map(|item| item.as_deref())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub(crate) enum AiKind {
+    /// Stands still.
+    Idle,
+    /// Moves around at random, and attacks the player when next to it.
+    Wander,
+    /// Chases the player on sight, then searches where it was last noticed and follows its
+    /// scent.
+    Melee,
+    /// Stays near its post and chases only intruders close to it.
+    Guard,
+    /// Keeps its distance and shoots with its ranged weapon.
+    Kite,
+    /// Runs away from the player.
+    Flee,
+}
+
+/// Owns every entity in the world. Ids stay valid until the entity is removed.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(from = "EntitySlots")]
+pub(crate) struct EntityStore {
+    entities: Vec<Option<Entity>>,
+    /// Where every entity is. Not saved, but rebuilt from the entities when loading.
+    #[serde(skip)]
+    index: SpatialIndex<EntityId>,
+}
+
+/// Saved form of an `EntityStore`.
+#[derive(Deserialize)]
+struct EntitySlots {
+    entities: Vec<Option<Entity>>,
+}
+
+impl From<EntitySlots> for EntityStore {
+    fn from(slots: EntitySlots) -> Self {
+        Self::from_slots(slots.entities)
+    }
+}
+
+impl EntityStore {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// A store with the given slots, `None` for removed entities. Used when upgrading saves.
+    pub(crate) fn from_slots(entities: Vec<Option<Entity>>) -> Self {
+        let mut store = Self {
+            entities,
+            index: SpatialIndex::new(),
+        };
+        let positions: Vec<_> = store.iter().map(|(id, e)| (id, e.pos)).collect();
+        for (id, pos) in positions {
+            store.index.insert(id, pos);
+        }
+        store
+    }
+
+    pub(crate) fn spawn(&mut self, entity: Entity) -> EntityId {
+        let pos = entity.pos;
+        let id = if let Some(index) = self.entities.iter().position(|e| e.is_none()) {
+            self.entities[index] = Some(entity);
+            EntityId(index)
+        } else {
+            self.entities.push(Some(entity));
+            EntityId(self.entities.len() - 1)
+        };
+        self.index.insert(id, pos);
+        id
+    }
+
+    /// Add `entity` on `pos`, e.g. when it arrives from another level.
+    pub(crate) fn spawn_at(&mut self, mut entity: Entity, pos: IVec2) -> EntityId {
+        entity.pos = pos;
+        self.spawn(entity)
+    }
+
+    pub(crate) fn remove(&mut self, id: EntityId) -> Option<Entity> {
+        let entity = self.entities.get_mut(id.0).and_then(|e| e.take())?;
+        self.index.remove(id, entity.pos);
+        Some(entity)
+    }
+
+    /// Put entity `id` on `pos`.
+    pub(crate) fn move_to(&mut self, id: EntityId, pos: IVec2) {
+        let Some(entity) = self.entities.get_mut(id.0).and_then(|e| e.as_mut()) else {
+            return;
+        };
+        self.index.update(id, entity.pos, pos);
+        entity.pos = pos;
+    }
+
+    pub(crate) fn get(&self, id: EntityId) -> Option<&Entity> {
+        self.entities.get(id.0).and_then(|e| e.as_ref())
+    }
+
+    pub(crate) fn get_mut(&mut self, id: EntityId) -> Option<&mut Entity> {
+        self.entities.get_mut(id.0).and_then(|e| e.as_mut())
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (EntityId, &Entity)> {
+        self.entities
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| e.as_ref().map(|e| (EntityId(i), e)))
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut Entity)> {
+        self.entities
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, e)| e.as_mut().map(|e| (EntityId(i), e)))
+    }
+
+    /// First entity at `pos` that blocks movement.
+    pub(crate) fn blocking_at(&self, pos: IVec2) -> Option<EntityId> {
+        self.index
+            .at(pos)
+            .filter(|&id| self.get(id).is_some_and(|e| e.blocks))
+            .min()
+    }
+
+    /// Entities on `pos`, in the order of their ids.
+    pub(crate) fn at(&self, pos: IVec2) -> Vec<EntityId> {
+        let mut ids: Vec<_> = self.index.at(pos).collect();
+        ids.sort();
+        ids
+    }
+
+    /// Entities on tiles from `min` to `max`, both inclusive, in the order of their ids.
+    pub(crate) fn in_rect(&self, min: IVec2, max: IVec2) -> Vec<EntityId> {
+        let mut ids: Vec<_> = self.index.in_rect(min, max).map(|(id, _)| id).collect();
+        ids.sort();
+        ids
+    }
+
+    /// Entities within `radius` tiles of `center` in a straight line, in the order of their
+    /// ids.
+    pub(crate) fn in_radius(&self, center: IVec2, radius: i32) -> Vec<EntityId> {
+        let mut ids: Vec<_> = self
+            .index
+            .in_radius(center, radius)
+            .map(|(id, _)| id)
+            .collect();
+        ids.sort();
+        ids
+    }
+}
@@ -0,0 +1,106 @@
+use crate::combat::DamageType;
+use crate::entity::{Entity, EntityId};
+use crate::message_log::MessageCategory;
+use crate::status::StatusKind;
+use glam::IVec2;
+
+/// Something that happened in the game world. Game logic pushes these and subsystems
+/// (such as the message log) consume them once per update.
+pub(crate) enum GameEvent {
+    Message {
+        text: String,
+        category: MessageCategory,
+    },
+    Waited,
+    Bumped {
+        name: String,
+        pos: IVec2,
+    },
+    /// A projectile flew from one tile to another.
+    Shot {
+        from: IVec2,
+        to: IVec2,
+    },
+    Attacked {
+        attacker: Combatant,
+        target: Combatant,
+        /// Damage dealt after armour and resistances.
+        damage: i32,
+        damage_type: DamageType,
+        critical: bool,
+        ranged: bool,
+    },
+    Missed {
+        attacker: Combatant,
+        target: Combatant,
+        ranged: bool,
+    },
+    Died {
+        victim: Combatant,
+    },
+    StatusStarted {
+        target: Combatant,
+        kind: StatusKind,
+    },
+    /// An effect ran out, or was cancelled by its opposite.
+    StatusEnded {
+        target: Combatant,
+        kind: StatusKind,
+    },
+    /// Damage dealt by an effect at the start of a turn.
+    StatusDamage {
+        target: Combatant,
+        kind: StatusKind,
+        damage: i32,
+        damage_type: DamageType,
+    },
+}
+
+/// An entity taking part in a fight, as it was when the event happened.
+#[derive(Clone, Debug)]
+pub(crate) struct Combatant {
+    pub id: EntityId,
+    pub name: String,
+    pub pos: IVec2,
+    pub is_player: bool,
+}
+
+impl Combatant {
+    pub(crate) fn new(entity: &Entity, id: EntityId, player: EntityId) -> Self {
+        Self {
+            id,
+            name: entity.name.clone(),
+            pos: entity.pos(),
+            is_player: id == player,
+        }
+    }
+
+    /// How the combatant is named at the start of a sentence.
+    pub(crate) fn subject(&self) -> String {
+        if self.is_player {
+            "You".to_string()
+        } else {
+            format!("The {}", self.name)
+        }
+    }
+
+    /// How the combatant is named elsewhere in a sentence.
+    pub(crate) fn object(&self) -> String {
+        if self.is_player {
+            "you".to_string()
+        } else {
+            format!("the {}", self.name)
+        }
+    }
+
+    /// `verb` conjugated for the combatant, e.g. "hit" or "hits".
+    pub(crate) fn verb(&self, verb: &str) -> String {
+        match (self.is_player, verb) {
+            (true, "be") => "are".to_string(),
+            (false, "be") => "is".to_string(),
+            (true, _) => verb.to_string(),
+            (false, "miss") => "misses".to_string(),
+            (false, _) => format!("{}s", verb),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use glam::IVec2;
+
+/// Transforms mapping each octant onto the first one, as `[xx, xy, yx, yy]`.
+const OCTANTS: [[i32; 4]; 8] = [
+    [1, 0, 0, 1],
+    [0, 1, 1, 0],
+    [0, -1, 1, 0],
+    [-1, 0, 0, 1],
+    [-1, 0, 0, -1],
+    [0, -1, -1, 0],
+    [0, 1, -1, 0],
+    [1, 0, 0, -1],
+];
+
+/// Field of view using recursive shadowcasting.
+///
+/// Calls `visit` for every tile within `radius` of `origin` that can be seen from it,
+/// including the opaque tiles that block the view. Tiles may be visited more than once.
+pub(crate) fn compute_fov(
+    origin: IVec2,
+    radius: i32,
+    is_opaque: impl Fn(IVec2) -> bool,
+    mut visit: impl FnMut(IVec2),
+) {
+    visit(origin);
+    for [xx, xy, yx, yy] in OCTANTS {
+        cast_light(
+            &Octant {
+                origin,
+                radius,
+                xx,
+                xy,
+                yx,
+                yy,
+            },
+            1,
+            1.0,
+            0.0,
+            &is_opaque,
+            &mut visit,
+        );
+    }
+}
+
+struct Octant {
+    origin: IVec2,
+    radius: i32,
+    xx: i32,
+    xy: i32,
+    yx: i32,
+    yy: i32,
+}
+
+fn cast_light(
+    octant: &Octant,
+    row: i32,
+    mut start: f32,
+    end: f32,
+    is_opaque: &impl Fn(IVec2) -> bool,
+    visit: &mut impl FnMut(IVec2),
+) {
+    if start < end {
+        return;
+    }
+
+    let radius_squared = octant.radius * octant.radius;
+    let mut new_start = 0.0;
+
+    for distance in row..=octant.radius {
+        let dy = -distance;
+        let mut blocked = false;
+
+        for dx in -distance..=0 {
+            let pos = octant.origin
+                + IVec2::new(
+                    dx * octant.xx + dy * octant.xy,
+                    dx * octant.yx + dy * octant.yy,
+                );
+            let left_slope = (dx as f32 - 0.5) / (dy as f32 + 0.5);
+            let right_slope = (dx as f32 + 0.5) / (dy as f32 - 0.5);
+
+            if start < right_slope {
+                continue;
+            } else if end > left_slope {
+                break;
+            }
+
+            if dx * dx + dy * dy <= radius_squared {
+                visit(pos);
+            }
+
+            if blocked {
+                if is_opaque(pos) {
+                    new_start = right_slope;
+                } else {
+                    blocked = false;
+                    start = new_start;
+                }
+            } else if is_opaque(pos) && distance < octant.radius {
+                // Scan the part of the next row that is still visible past this blocker.
+                blocked = true;
+                cast_light(octant, distance + 1, start, left_slope, is_opaque, visit);
+                new_start = right_slope;
+            }
+        }
+
+        if blocked {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Tiles seen from `origin` in a 15x15 room, with walls where `is_wall` says.
+    fn visible(origin: IVec2, radius: i32, is_wall: impl Fn(IVec2) -> bool) -> HashSet<IVec2> {
+        let mut seen = HashSet::new();
+        compute_fov(
+            origin,
+            radius,
+            |pos| is_wall(pos) || pos.min_element() < 0 || pos.max_element() > 14,
+            |pos| {
+                seen.insert(pos);
+            },
+        );
+        seen
+    }
+
+    #[test]
+    fn origin_is_always_visible() {
+        let origin = IVec2::new(7, 7);
+        assert_eq!(visible(origin, 0, |_| false), HashSet::from([origin]));
+        assert!(visible(origin, 5, |_| true).contains(&origin));
+    }
+
+    #[test]
+    fn open_ground_is_seen_up_to_the_radius() {
+        let origin = IVec2::new(7, 7);
+        let seen = visible(origin, 5, |_| false);
+        for y in 0..15 {
+            for x in 0..15 {
+                let pos = IVec2::new(x, y);
+                let offset = pos - origin;
+                let inside = offset.x * offset.x + offset.y * offset.y <= 25;
+                assert_eq!(seen.contains(&pos), inside, "{}", pos);
+            }
+        }
+    }
+
+    #[test]
+    fn walls_block_the_view_behind_them() {
+        let origin = IVec2::new(3, 7);
+        let seen = visible(origin, 10, |pos| pos.x == 6);
+        // The wall is seen, nothing past it is.
+        assert!(seen.contains(&IVec2::new(6, 7)));
+        assert!(seen.contains(&IVec2::new(6, 9)));
+        assert!(seen.iter().all(|pos| pos.x <= 6));
+
+        // A single pillar hides the tiles straight behind it, but not those beside them.
+        let seen = visible(origin, 10, |pos| pos == IVec2::new(5, 7));
+        assert!(seen.contains(&IVec2::new(5, 7)));
+        assert!(!seen.contains(&IVec2::new(6, 7)));
+        assert!(!seen.contains(&IVec2::new(9, 7)));
+        assert!(seen.contains(&IVec2::new(9, 5)));
+    }
+}
@@ -1,43 +1,1767 @@
-use crate::renderer::Renderer;
+use crate::action::Action;
+use crate::ai::{self, AiContext, Brain, Decision, Noise};
+use crate::animation::Animations;
+use crate::autotile::TileFrames;
+use crate::color::Color;
+use crate::combat;
+use crate::console::Console;
+use crate::draw_queue::{DrawCommand, DrawQueue, Layer};
+use crate::entity::{Entity, EntityId, EntityStore, Slot, Stats};
+use crate::event::{Combatant, GameEvent};
+use crate::geometry;
+use crate::inventory::{Inventory, ItemStack};
+use crate::level::Level;
+use crate::lighting::{LightMap, LightSource};
+use crate::map::TileType;
+use crate::menu::{Menu, MenuView};
+use crate::message_log::{MessageCategory, MessageLog};
+use crate::particles::{Emitter, EmitterConfig, EmitterId, ParticleSystem};
+use crate::pathfinding;
+use crate::postfx::ScreenEffects;
+use crate::raws::{Raws, UseEffect};
+use crate::renderer::{DrawParams, Renderer};
+use crate::rng::{self, GameRng};
+use crate::save::SaveData;
+use crate::scheduler;
 use crate::sprite::Sprite;
-use glam::Vec2;
+use crate::status::{self, StatusEffect, StatusKind};
+use glam::{IVec2, Vec2};
+use std::collections::HashMap;
+use winit::event::VirtualKeyCode;
 use winit_input_helper::WinitInputHelper;
 
-pub const WIDTH: u32 = 640;
-pub const HEIGHT: u32 = 480;
+const TILE_SIZE: u32 = 16;
+const CELL_SIZE: u32 = 8;
+/// Console rows above the map in the ASCII presentation, holding the status line.
+const STATUS_ROWS: u32 = 1;
+
+/// Chance that a confused entity moves in a random direction.
+const CONFUSED_STUMBLE_CHANCE: f32 = 0.5;
+
+/// How far the player's actions can be heard, in tiles.
+const FOOTSTEP_NOISE: i32 = 3;
+const MELEE_NOISE: i32 = 8;
+const SHOT_NOISE: i32 = 6;
+const IMPACT_NOISE: i32 = 6;
+
+/// Seconds it takes the screen to lose its colour after the player dies.
+const DEATH_FADE_TIME: f32 = 2.0;
+
+/// Light every tile receives, even without light sources nearby.
+const AMBIENT_LIGHT: [f32; 3] = [0.15, 0.15, 0.22];
+
+const MAX_PARTICLES: usize = 2048;
+const MAX_PARTICLE_SPAWNS_PER_FRAME: usize = 256;
+
+/// Colour the invisible player fades towards. Invisible monsters are not drawn at all.
+const INVISIBLE_TINT: Color = Color::rgb(0x30, 0x40, 0x60);
+/// Pixels between the status effect dots above an entity.
+const STATUS_ICON_SPACING: f32 = 4.0;
+
+const LOG_LINES: usize = 5;
+const LOG_TEXT_SIZE: f32 = 8.0;
+
+/// How the world is presented on screen. Both draw the same game state.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Presentation {
+    Tiles,
+    Ascii,
+}
 
 pub struct Game {
-    slime: Sprite,
+    /// Logical resolution of the frame buffer.
+    width: u32,
+    height: u32,
+    /// The level the player is on.
+    level: Level,
+    /// Levels the player has left, frozen until they return.
+    levels: Vec<Level>,
+    player: EntityId,
+    /// Player turns taken since the start of the game.
+    turn: u64,
+    light_map: LightMap,
+    /// Autotile frame of every tile of the level.
+    tile_frames: TileFrames,
+    particles: ParticleSystem,
+    /// Ambient particle emitters that follow an entity.
+    emitters: HashMap<EntityId, EmitterId>,
+    animations: Animations,
+    sprites: HashMap<String, Sprite>,
+    /// Monster, item and tile definitions.
+    raws: Raws,
+    presentation: Presentation,
+    console: Console,
     time_passed: f32,
+    /// Cursor position in frame buffer pixels.
+    mouse: Option<Vec2>,
+    pub(crate) message_log: MessageLog,
+    /// Open inventory or equipment screen, or throw targeting.
+    menu: Option<Menu>,
+    /// Sounds made since the last game turn, heard by monsters at the start of the next.
+    noises: Vec<Noise>,
+    events: Vec<GameEvent>,
+    /// Full-screen effects for the post-processing passes.
+    screen_effects: ScreenEffects,
+    /// Gameplay randomness, split into streams such as `rng::COMBAT`.
+    rng: GameRng,
 }
 
 impl Game {
-    pub(crate) fn new() -> Self {
-        Self {
-            slime: Sprite::from_image_animated("assets/slime_idle_spritesheet.png", 6, None),
+    /// Start a new game. Everything random about it follows from `seed`.
+    pub(crate) fn new(width: u32, height: u32, seed: u64, raws: Raws) -> Self {
+        let rng = GameRng::new(seed);
+        let mut level = Level::generate(1, &raws, &mut level_rng(&rng, 1));
+        let start = level.arrival(true);
+        let player = level.entities.spawn(
+            raws.spawn_monster("player", start)
+                .expect("the raws define the player"),
+        );
+
+        let mut game = Self::from_save(
+            width,
+            height,
+            SaveData {
+                player,
+                turn: 0,
+                message_log: MessageLog::new(256),
+                rng,
+                level,
+                levels: Vec::new(),
+            },
+            raws,
+        );
+        game.events.push(GameEvent::Message {
+            text: "Welcome to the [gold]dungeon[/]!".to_string(),
+            category: MessageCategory::Info,
+        });
+        game
+    }
+
+    /// Resume a game from the world state in a save.
+    pub(crate) fn from_save(width: u32, height: u32, save: SaveData, raws: Raws) -> Self {
+        let mut game = Self {
+            width,
+            height,
+            light_map: LightMap::new(save.level.map.width, save.level.map.height, AMBIENT_LIGHT),
+            tile_frames: TileFrames::build(&save.level.map, &raws),
+            level: save.level,
+            levels: save.levels,
+            player: save.player,
+            turn: save.turn,
+            particles: ParticleSystem::new(
+                MAX_PARTICLES,
+                MAX_PARTICLE_SPAWNS_PER_FRAME,
+                save.rng.fork("particles"),
+            ),
+            emitters: HashMap::new(),
+            animations: Animations::new(),
+            sprites: raws.load_sprites(),
+            raws,
+            presentation: Presentation::Tiles,
+            console: Console::new(width / CELL_SIZE, height / CELL_SIZE, CELL_SIZE),
             time_passed: 0.0,
+            mouse: None,
+            message_log: save.message_log,
+            menu: None,
+            noises: Vec::new(),
+            events: Vec::new(),
+            screen_effects: ScreenEffects::default(),
+            rng: save.rng,
+        };
+
+        // Entities from older saves pick up what they are missing from their definition.
+        for (_, entity) in game.level.entities.iter_mut() {
+            let def = game.raws.monster(&entity.name);
+            let Some(def) = def.filter(|_| entity.item.is_none()) else {
+                continue;
+            };
+            if entity.stats.is_none() {
+                entity.stats = Some(game.raws.stats(def));
+                entity.ai = def.ai;
+                entity.loot = def.loot.clone();
+                entity.equipment = game.raws.equipment(def);
+            }
+            if let (None, Some(inventory)) = (&entity.inventory, &def.inventory) {
+                entity.inventory = Some(Inventory::new(inventory.capacity));
+            }
+            if entity.ai.is_some() && entity.brain.is_none() {
+                entity.brain = Some(Brain::new(entity.pos()));
+            }
+        }
+
+        game.enter_level();
+        game
+    }
+
+    /// Reset what belongs to the level the player was on before, and set up the new one.
+    fn enter_level(&mut self) {
+        self.emitters.clear();
+        self.particles.clear();
+        self.animations = Animations::new();
+        self.noises.clear();
+        self.menu = None;
+        self.light_map = LightMap::new(self.level.map.width, self.level.map.height, AMBIENT_LIGHT);
+        self.tile_frames = TileFrames::build(&self.level.map, &self.raws);
+
+        // Slimes give off a faint glowing haze.
+        let slimes: Vec<_> = self
+            .level
+            .entities
+            .iter()
+            .filter(|(_, e)| e.name == "slime")
+            .map(|(id, e)| (id, e.pos()))
+            .collect();
+        for (id, pos) in slimes {
+            let emitter = self.particles.add_emitter(Emitter::new(
+                EmitterConfig::magic_trail(),
+                (pos.as_vec2() + 0.5) * TILE_SIZE as f32,
+                6.0,
+            ));
+            self.emitters.insert(id, emitter);
+        }
+    }
+
+    /// Move the ambient emitters onto the entities they belong to, which may have moved or
+    /// be in the middle of a step.
+    fn follow_emitters(&mut self) {
+        for (&id, &emitter) in &self.emitters {
+            let (Some(entity), Some(emitter)) = (
+                self.level.entities.get(id),
+                self.particles.emitter_mut(emitter),
+            ) else {
+                continue;
+            };
+            emitter.pos =
+                (entity.pos().as_vec2() + 0.5) * TILE_SIZE as f32 + self.animations.offset(id);
+        }
+    }
+
+    /// World state to write to a save file.
+    pub(crate) fn to_save(&self) -> SaveData {
+        SaveData {
+            player: self.player,
+            turn: self.turn,
+            message_log: self.message_log.clone(),
+            rng: self.rng.clone(),
+            level: self.level.clone(),
+            levels: self.levels.clone(),
+        }
+    }
+
+    /// Player turns taken since the start of the game.
+    pub(crate) fn turn(&self) -> u64 {
+        self.turn
+    }
+
+    pub(crate) fn player_stats(&self) -> Option<&Stats> {
+        self.level
+            .entities
+            .get(self.player)
+            .and_then(|e| e.stats.as_ref())
+    }
+
+    /// Dungeon level the player is on, 1 for the first.
+    pub(crate) fn depth(&self) -> u32 {
+        self.level.depth
+    }
+
+    pub(crate) fn raws(&self) -> &Raws {
+        &self.raws
+    }
+
+    pub(crate) fn entities(&self) -> &EntityStore {
+        &self.level.entities
+    }
+
+    pub(crate) fn player(&self) -> EntityId {
+        self.player
+    }
+
+    /// Whether the player has died. A dead player's game is over and is not saved.
+    pub(crate) fn is_player_dead(&self) -> bool {
+        self.player_stats().is_some_and(|stats| stats.is_dead())
+    }
+
+    /// Whether a screen is open on top of the map. Escape closes it instead of quitting.
+    pub(crate) fn is_menu_open(&self) -> bool {
+        self.menu.is_some()
+    }
+
+    /// Seed the game was started with, to reproduce a run.
+    pub(crate) fn seed(&self) -> u64 {
+        self.rng.seed()
+    }
+
+    /// Turn this frame's input into actions. `mouse` is the cursor position in frame buffer
+    /// pixels, if it is over the frame.
+    pub(crate) fn read_input(&self, input: &WinitInputHelper, mouse: Option<Vec2>) -> Vec<Action> {
+        let mut actions = Vec::new();
+
+        if let (true, Some(mouse)) = (input.mouse_pressed(0), mouse) {
+            actions.push(Action::LookAt(self.screen_to_tile(mouse)));
+        }
+        if let (true, Some(mouse), None) = (input.mouse_pressed(1), mouse, self.menu) {
+            actions.push(Action::Fire(self.screen_to_tile(mouse)));
+        }
+
+        if input.key_pressed(VirtualKeyCode::Tab) {
+            actions.push(Action::TogglePresentation);
+        }
+
+        let direction = if input.key_pressed(VirtualKeyCode::Left) {
+            Some(IVec2::new(-1, 0))
+        } else if input.key_pressed(VirtualKeyCode::Right) {
+            Some(IVec2::new(1, 0))
+        } else if input.key_pressed(VirtualKeyCode::Up) {
+            Some(IVec2::new(0, -1))
+        } else if input.key_pressed(VirtualKeyCode::Down) {
+            Some(IVec2::new(0, 1))
+        } else {
+            None
+        };
+        let confirm = input.key_pressed(VirtualKeyCode::Return);
+
+        // Keys mean different things on each screen.
+        match self.menu {
+            None => {
+                if let Some(direction) = direction {
+                    actions.push(Action::Move(direction));
+                } else if input.key_pressed(VirtualKeyCode::Space) {
+                    actions.push(Action::Wait);
+                } else if input.key_pressed(VirtualKeyCode::G) {
+                    actions.push(Action::PickUp);
+                } else if input.key_pressed(VirtualKeyCode::I) {
+                    actions.push(Action::OpenInventory);
+                } else if input.key_pressed(VirtualKeyCode::E) {
+                    actions.push(Action::OpenEquipment);
+                } else if input.key_pressed(VirtualKeyCode::Period) && input.held_shift() {
+                    actions.push(Action::Descend);
+                } else if input.key_pressed(VirtualKeyCode::Comma) && input.held_shift() {
+                    actions.push(Action::Ascend);
+                }
+            }
+            Some(Menu::Inventory { cursor }) => {
+                if let Some(direction) = direction {
+                    actions.push(Action::MoveCursor(direction));
+                } else if confirm || input.key_pressed(VirtualKeyCode::U) {
+                    actions.push(Action::Use(cursor));
+                } else if input.key_pressed(VirtualKeyCode::E) {
+                    actions.push(Action::Equip(cursor));
+                } else if input.key_pressed(VirtualKeyCode::D) {
+                    actions.push(Action::Drop(cursor));
+                } else if input.key_pressed(VirtualKeyCode::T) {
+                    actions.push(Action::Aim(cursor));
+                }
+            }
+            Some(Menu::Equipment { cursor }) => {
+                if let Some(direction) = direction {
+                    actions.push(Action::MoveCursor(direction));
+                } else if let (true, Some(&slot)) = (confirm, Slot::ALL.get(cursor)) {
+                    actions.push(Action::Unequip(slot));
+                }
+            }
+            Some(Menu::Throw { item, target }) => {
+                if let Some(direction) = direction {
+                    actions.push(Action::MoveCursor(direction));
+                } else if confirm {
+                    actions.push(Action::Throw(item, target));
+                }
+            }
+        }
+        if self.menu.is_some()
+            && (input.key_pressed(VirtualKeyCode::Escape) || input.key_pressed(VirtualKeyCode::I))
+        {
+            actions.push(Action::CloseMenu);
+        }
+
+        if input.key_pressed(VirtualKeyCode::PageUp) {
+            actions.push(Action::ScrollLog(LOG_LINES as i32));
+        }
+        if input.key_pressed(VirtualKeyCode::PageDown) {
+            actions.push(Action::ScrollLog(-(LOG_LINES as i32)));
         }
+        if input.key_pressed(VirtualKeyCode::End) {
+            actions.push(Action::ScrollLogToBottom);
+        }
+
+        actions
     }
 
-    pub(crate) fn update(&mut self, _input: &mut WinitInputHelper, dt: f32) {
+    /// Carry out `actions` and advance the simulation by `dt` seconds. `mouse` is the cursor
+    /// position in frame buffer pixels, if it is over the frame.
+    pub(crate) fn update(&mut self, actions: &[Action], mouse: Option<Vec2>, dt: f32) {
         self.time_passed += dt;
+        self.mouse = mouse;
+        self.particles.begin_frame();
+
+        for action in actions {
+            self.apply(*action);
+        }
+
+        // Let all subsystems react to what happened this update.
+        let events = std::mem::take(&mut self.events);
+        for event in &events {
+            self.message_log.handle_event(event);
+            self.spawn_particles(event);
+            self.animate(event);
+        }
+        self.message_log.update(dt);
+        self.follow_emitters();
+        self.particles.update(dt);
+        self.animations.update(dt);
+        self.screen_effects.update(dt);
+        if self.is_player_dead() {
+            self.screen_effects.desaturation =
+                (self.screen_effects.desaturation + dt / DEATH_FADE_TIME).min(1.0);
+        }
+
+        let lights: Vec<_> = self
+            .level
+            .entities
+            .iter()
+            .filter_map(|(_, e)| {
+                e.light.map(|light| LightSource {
+                    pos: e.pos(),
+                    light,
+                })
+            })
+            .collect();
+        self.light_map
+            .compute(&self.level.map, &lights, self.time_passed);
+    }
+
+    fn apply(&mut self, action: Action) {
+        // The dead can only look around.
+        if action.takes_turn() && self.is_player_dead() {
+            return;
+        }
+        let acted = match action {
+            Action::Move(direction) => self.move_player(direction),
+            Action::Wait => {
+                self.events.push(GameEvent::Waited);
+                true
+            }
+            Action::Fire(target) => self.fire(target),
+            Action::PickUp => self.pick_up(),
+            Action::Drop(index) => self.drop_item(index),
+            Action::Use(index) => self.use_item(index),
+            Action::Throw(index, target) => self.throw(index, target),
+            Action::Equip(index) => self.equip(index),
+            Action::Unequip(slot) => self.unequip(slot),
+            Action::Descend => self.take_stairs(true),
+            Action::Ascend => self.take_stairs(false),
+            _ => {
+                self.apply_interface(action);
+                false
+            }
+        };
+        if acted {
+            self.end_player_turn();
+            self.turn += 1;
+        }
+        self.clamp_cursor();
+    }
+
+    /// Apply an action that only changes what is shown, and never takes a turn.
+    fn apply_interface(&mut self, action: Action) {
+        match action {
+            Action::LookAt(pos) => self.look_at(pos),
+            Action::OpenInventory => {
+                let has_inventory = self
+                    .level
+                    .entities
+                    .get(self.player)
+                    .is_some_and(|e| e.inventory.is_some());
+                if has_inventory {
+                    self.menu = Some(Menu::Inventory { cursor: 0 });
+                }
+            }
+            Action::OpenEquipment => self.menu = Some(Menu::Equipment { cursor: 0 }),
+            Action::CloseMenu => self.menu = None,
+            Action::MoveCursor(step) => self.move_cursor(step),
+            Action::Aim(index) => {
+                self.menu = Some(Menu::Throw {
+                    item: index,
+                    target: self.player_pos(),
+                })
+            }
+            Action::TogglePresentation => {
+                self.presentation = match self.presentation {
+                    Presentation::Tiles => Presentation::Ascii,
+                    Presentation::Ascii => Presentation::Tiles,
+                };
+            }
+            Action::ScrollLog(lines) if lines >= 0 => self.message_log.scroll_up(lines as usize),
+            Action::ScrollLog(lines) => self.message_log.scroll_down(lines.unsigned_abs() as usize),
+            Action::ScrollLogToBottom => self.message_log.scroll_to_bottom(),
+            _ => (),
+        }
+    }
+
+    /// Number of carried item stacks.
+    fn inventory_len(&self) -> usize {
+        self.level
+            .entities
+            .get(self.player)
+            .and_then(|e| e.inventory.as_ref())
+            .map_or(0, |inventory| inventory.len())
+    }
+
+    fn move_cursor(&mut self, step: IVec2) {
+        let len = self.inventory_len();
+        self.menu = match self.menu {
+            Some(Menu::Inventory { cursor }) => Some(Menu::Inventory {
+                cursor: Menu::step_cursor(cursor, step.y, len),
+            }),
+            Some(Menu::Equipment { cursor }) => Some(Menu::Equipment {
+                cursor: Menu::step_cursor(cursor, step.y, Slot::COUNT),
+            }),
+            Some(Menu::Throw { item, target }) => {
+                // Keep the target on the map and within reach of a throw.
+                let player = self.player_pos();
+                let reach = IVec2::splat(combat::THROW_RANGE);
+                let map = &self.level.map;
+                let target = (target + step)
+                    .clamp(player - reach, player + reach)
+                    .clamp(IVec2::ZERO, IVec2::new(map.width - 1, map.height - 1));
+                Some(Menu::Throw { item, target })
+            }
+            None => None,
+        };
+    }
+
+    /// Keep the inventory cursor on an item after items were used up or dropped.
+    fn clamp_cursor(&mut self) {
+        let last = self.inventory_len().saturating_sub(1);
+        if let Some(Menu::Inventory { cursor }) = &mut self.menu {
+            *cursor = (*cursor).min(last);
+        }
+    }
+
+    /// Remove monsters killed this turn and scatter their loot.
+    fn bury_dead(&mut self) {
+        let dead: Vec<_> = self
+            .level
+            .entities
+            .iter()
+            .filter(|&(id, e)| id != self.player && e.stats.as_ref().is_some_and(|s| s.is_dead()))
+            .map(|(id, _)| id)
+            .collect();
+        for id in dead {
+            let Some(entity) = self.level.entities.remove(id) else {
+                continue;
+            };
+            self.animations.remove(id);
+            if let Some(emitter) = self.emitters.remove(&id) {
+                self.particles.remove_emitter(emitter);
+            }
+            let loot = entity
+                .loot
+                .as_ref()
+                .and_then(|table| self.raws.roll_loot(table, self.rng.stream(rng::LOOT)));
+            if let Some(stack) = loot {
+                self.place_item(stack, entity.pos());
+            }
+        }
+    }
+
+    /// Items lying on `pos`.
+    fn items_at(&self, pos: IVec2) -> Vec<EntityId> {
+        let mut items = self.level.entities.at(pos);
+        items.retain(|&id| {
+            self.level
+                .entities
+                .get(id)
+                .is_some_and(|e| e.item.is_some())
+        });
+        items
+    }
+
+    /// Put items on the floor, on top of a pile of the same kind if there is one.
+    fn place_item(&mut self, stack: ItemStack, pos: IVec2) {
+        let pile = self.level.entities.at(pos).into_iter().find(|&id| {
+            self.level
+                .entities
+                .get(id)
+                .and_then(|e| e.item.as_ref())
+                .is_some_and(|item| item.kind == stack.kind)
+        });
+        if let Some(pile) = pile.and_then(|id| self.level.entities.get_mut(id)?.item.as_mut()) {
+            pile.count += stack.count;
+        } else if let Some(entity) = self.raws.spawn_item(stack, pos) {
+            self.level.entities.spawn(entity);
+        }
+    }
+
+    /// `count` of the item named `kind`, e.g. "a sword" or "2 healing potions".
+    fn describe_item(&self, kind: &str, count: u32) -> String {
+        self.raws
+            .item(kind)
+            .map_or_else(|| kind.to_string(), |def| def.describe(count))
+    }
+
+    fn message(&mut self, text: String) {
+        self.events.push(GameEvent::Message {
+            text,
+            category: MessageCategory::General,
+        });
     }
 
-    pub(crate) fn draw(&self, renderer: &mut Renderer) {
-        renderer.draw_text(
-            Vec2 { x: 32.0, y: 32.0 },
-            "Hello World!",
-            32.0,
-            24.0,
-            [0xff, 0xff, 0xff, 0xff],
+    /// Move the items on the player's tile into their inventory, as far as they fit.
+    /// Returns whether anything was picked up.
+    fn pick_up(&mut self) -> bool {
+        let items = self.items_at(self.player_pos());
+        if items.is_empty() {
+            self.message("There is nothing here to pick up.".to_string());
+            return false;
+        }
+        let mut picked_up = false;
+        for id in items {
+            let Some(stack) = self.level.entities.get_mut(id).and_then(|e| e.item.take()) else {
+                continue;
+            };
+            let Some(inventory) = self
+                .level
+                .entities
+                .get_mut(self.player)
+                .and_then(|e| e.inventory.as_mut())
+            else {
+                return picked_up;
+            };
+            let (kind, count) = (stack.kind.clone(), stack.count);
+            let left = inventory.add(stack, &self.raws);
+            let taken = count - left.as_ref().map_or(0, |left| left.count);
+            match left {
+                Some(left) => {
+                    if let Some(entity) = self.level.entities.get_mut(id) {
+                        entity.item = Some(left);
+                    }
+                }
+                None => {
+                    self.level.entities.remove(id);
+                }
+            }
+            let text = if taken > 0 {
+                picked_up = true;
+                format!("You pick up {}.", self.describe_item(&kind, taken))
+            } else {
+                format!("You have no room for {}.", self.describe_item(&kind, count))
+            };
+            self.message(text);
+        }
+        picked_up
+    }
+
+    /// Take up to `count` items out of the player's inventory slot `index`.
+    fn take_item(&mut self, index: usize, count: u32) -> Option<ItemStack> {
+        self.level
+            .entities
+            .get_mut(self.player)?
+            .inventory
+            .as_mut()?
+            .take(index, count)
+    }
+
+    /// Add items to the player's inventory. What does not fit falls to the floor.
+    fn give_item(&mut self, stack: ItemStack) {
+        let pos = self.player_pos();
+        let left = match self
+            .level
+            .entities
+            .get_mut(self.player)
+            .and_then(|e| e.inventory.as_mut())
+        {
+            Some(inventory) => inventory.add(stack, &self.raws),
+            None => Some(stack),
+        };
+        if let Some(left) = left {
+            self.message(format!(
+                "You drop {}.",
+                self.describe_item(&left.kind, left.count)
+            ));
+            self.place_item(left, pos);
+        }
+    }
+
+    fn drop_item(&mut self, index: usize) -> bool {
+        let Some(stack) = self.take_item(index, u32::MAX) else {
+            return false;
+        };
+        self.message(format!(
+            "You drop {}.",
+            self.describe_item(&stack.kind, stack.count)
+        ));
+        self.place_item(stack, self.player_pos());
+        true
+    }
+
+    /// Use up one item of the player's inventory slot `index`. Returns whether it was used.
+    fn use_item(&mut self, index: usize) -> bool {
+        let Some(def) = self
+            .level
+            .entities
+            .get(self.player)
+            .and_then(|e| e.inventory.as_ref()?.get(index))
+            .and_then(|stack| self.raws.item(&stack.kind))
+        else {
+            return false;
+        };
+        let name = def.name.clone();
+        let Some(effect) = def.use_effect else {
+            self.message(format!("You cannot use the {}.", name));
+            return false;
+        };
+        self.take_item(index, 1);
+        self.message(format!("You use the {}.", name));
+        self.use_effect(self.player, effect);
+        true
+    }
+
+    /// Give `target` the effect of an item used on it.
+    fn use_effect(&mut self, target: EntityId, effect: UseEffect) {
+        match effect {
+            UseEffect::Heal(dice) => {
+                let amount = dice.roll(self.rng.stream(rng::COMBAT)).max(0);
+                let Some(entity) = self.level.entities.get_mut(target) else {
+                    return;
+                };
+                let Some(stats) = entity.stats.as_mut() else {
+                    return;
+                };
+                let healed = amount.min(stats.max_hp - stats.hp).max(0);
+                stats.hp += healed;
+                let target = Combatant::new(entity, target, self.player);
+                self.message(format!(
+                    "{} {} {} health.",
+                    target.subject(),
+                    target.verb("recover"),
+                    healed
+                ));
+            }
+            UseEffect::Status {
+                status,
+                turns,
+                potency,
+            } => {
+                let effect = StatusEffect {
+                    kind: status,
+                    turns,
+                    potency,
+                };
+                let events = status::apply(
+                    &mut self.level.entities,
+                    &self.raws,
+                    self.player,
+                    target,
+                    effect,
+                );
+                self.events.extend(events);
+            }
+            UseEffect::Shatter { radius } => {
+                let Some(center) = self.level.entities.get(target).map(|e| e.pos()) else {
+                    return;
+                };
+                let map = &self.level.map;
+                let inner = |pos: IVec2| {
+                    pos.cmpgt(IVec2::ZERO).all() && pos.x < map.width - 1 && pos.y < map.height - 1
+                };
+                let walls: Vec<_> = (-radius..=radius)
+                    .flat_map(|y| (-radius..=radius).map(move |x| center + IVec2::new(x, y)))
+                    .filter(|&pos| inner(pos) && map.get(pos) == TileType::Wall)
+                    .collect();
+                for &pos in &walls {
+                    self.set_tile(pos, TileType::Floor);
+                }
+                if walls.is_empty() {
+                    self.message("Nothing happens.".to_string());
+                } else {
+                    self.message("The walls crumble to dust.".to_string());
+                }
+            }
+        }
+    }
+
+    /// Change the tile at `pos`, and pick the tile art around it again.
+    fn set_tile(&mut self, pos: IVec2, tile: TileType) {
+        self.level.map.set(pos, tile);
+        self.tile_frames.update(&self.level.map, &self.raws, pos);
+    }
+
+    /// Wear or wield one item of the player's inventory slot `index`, putting away what was
+    /// in its equipment slot. Returns whether it was equipped.
+    fn equip(&mut self, index: usize) -> bool {
+        let Some(stack) = self
+            .level
+            .entities
+            .get(self.player)
+            .and_then(|e| e.inventory.as_ref()?.get(index))
+        else {
+            return false;
+        };
+        let kind = stack.kind.clone();
+        let Some(slot) = self.raws.item(&kind).and_then(|def| def.slot()) else {
+            self.message(format!("You cannot equip the {}.", kind));
+            return false;
+        };
+        self.take_item(index, 1);
+        let Some(player) = self.level.entities.get_mut(self.player) else {
+            return false;
+        };
+        let slot = player.equipment.free_slot(slot);
+        let previous = player.equipment.set(slot, Some(kind.clone()));
+
+        if let Some(previous) = previous {
+            self.message(format!("You take off the {}.", previous));
+            self.give_item(ItemStack::new(&previous, 1));
+        }
+        self.message(format!("You equip the {}.", kind));
+        true
+    }
+
+    /// Returns whether anything was taken off.
+    fn unequip(&mut self, slot: Slot) -> bool {
+        let previous = self
+            .level
+            .entities
+            .get_mut(self.player)
+            .and_then(|e| e.equipment.set(slot, None));
+        match previous {
+            Some(previous) => {
+                self.message(format!("You take off the {}.", previous));
+                self.give_item(ItemStack::new(&previous, 1));
+                true
+            }
+            None => {
+                self.message(format!(
+                    "You have nothing in the {} slot.",
+                    slot.name().to_lowercase()
+                ));
+                false
+            }
+        }
+    }
+
+    /// Throw one item of the player's inventory slot `index` towards `target`. It hits the
+    /// first creature in the way and lands where it stops. Returns whether it was thrown.
+    fn throw(&mut self, index: usize, target: IVec2) -> bool {
+        self.menu = None;
+        let path = self.projectile_path(self.player_pos(), target);
+        let range = (combat::THROW_RANGE as usize).min(path.len());
+        if range == 0 {
+            return false;
+        }
+        let Some(player) = self.level.entities.get(self.player) else {
+            return false;
+        };
+        let Some(def) = player
+            .inventory
+            .as_ref()
+            .and_then(|inventory| inventory.get(index))
+            .and_then(|stack| self.raws.item(&stack.kind))
+        else {
+            return false;
+        };
+        let attack = combat::thrown_attack(player, def, &self.raws);
+        let effect = def.use_effect;
+        let Some(stack) = self.take_item(index, 1) else {
+            return false;
+        };
+
+        let end = path[range - 1];
+        self.message(format!("You throw {}.", self.describe_item(&stack.kind, 1)));
+        self.noises.push(Noise {
+            pos: end,
+            radius: IMPACT_NOISE,
+        });
+        self.events.push(GameEvent::Shot {
+            from: self.player_pos(),
+            to: end,
+        });
+        let victim = self
+            .level
+            .entities
+            .blocking_at(end)
+            .filter(|&id| id != self.player);
+        // Potions and the like break on whoever they hit.
+        if let (Some(victim), Some(effect)) = (victim, effect) {
+            self.message(format!("The {} shatters!", stack.kind));
+            self.use_effect(victim, effect);
+            return true;
+        }
+        if let (Some(victim), Some(attack)) = (victim, attack) {
+            let events = combat::resolve(
+                &mut self.level.entities,
+                &self.raws,
+                self.rng.stream(rng::COMBAT),
+                self.player,
+                self.player,
+                victim,
+                attack,
+            );
+            self.events.extend(events);
+        }
+
+        // Items bounce off walls onto the tile in front of them.
+        let landing = if self.level.map.is_blocked(end) {
+            range
+                .checked_sub(2)
+                .map_or(self.player_pos(), |before| path[before])
+        } else {
+            end
+        };
+        self.place_item(stack, landing);
+        true
+    }
+
+    /// Take the stairs the player stands on to the level below, or above. Levels are
+    /// generated the first time they are reached, and the level left behind is frozen.
+    /// Returns whether the player changed levels.
+    fn take_stairs(&mut self, down: bool) -> bool {
+        let (stairs, depth) = if down {
+            (TileType::StairsDown, self.level.depth + 1)
+        } else {
+            (TileType::StairsUp, self.level.depth.saturating_sub(1))
+        };
+        if self.level.map.get(self.player_pos()) != stairs {
+            let direction = if down { "down" } else { "up" };
+            self.message(format!("There are no stairs {} here.", direction));
+            return false;
+        }
+        if depth == 0 {
+            self.message("The way out is blocked. There is no turning back.".to_string());
+            return false;
+        }
+
+        let Some(player) = self.level.entities.remove(self.player) else {
+            return false;
+        };
+        let next = match self.levels.iter().position(|level| level.depth == depth) {
+            Some(index) => self.levels.swap_remove(index),
+            None => Level::generate(depth, &self.raws, &mut level_rng(&self.rng, depth)),
+        };
+        let previous = std::mem::replace(&mut self.level, next);
+        self.levels.push(previous);
+
+        let pos = self.level.arrival(down);
+        self.player = self.level.entities.spawn_at(player, pos);
+        self.enter_level();
+        let verb = if down { "descend" } else { "climb up" };
+        self.message(format!("You {} to level {}.", verb, depth));
+        true
+    }
+
+    /// The player spent their action. Game turns pass until they have the energy for the
+    /// next one, so a hasted player may act again at once and a slowed one waits two turns.
+    fn end_player_turn(&mut self) {
+        if let Some(player) = self.level.entities.get_mut(self.player) {
+            scheduler::spend(player);
+        }
+        self.bury_dead();
+        while !self.is_player_dead()
+            && !self
+                .level
+                .entities
+                .get(self.player)
+                .is_some_and(scheduler::is_ready)
+        {
+            self.game_turn();
+        }
+    }
+
+    /// Monsters with enough energy act, status effects tick and everyone regains energy.
+    fn game_turn(&mut self) {
+        self.level.scent.update(self.player_pos());
+        for noise in std::mem::take(&mut self.noises) {
+            let extent = IVec2::splat(noise.radius);
+            for id in self
+                .level
+                .entities
+                .in_rect(noise.pos - extent, noise.pos + extent)
+            {
+                if let Some(brain) = self
+                    .level
+                    .entities
+                    .get_mut(id)
+                    .and_then(|e| e.brain.as_mut())
+                {
+                    brain.hear(noise.pos);
+                }
+            }
+        }
+
+        let actors: Vec<_> = self
+            .level
+            .entities
+            .iter()
+            .filter(|&(id, e)| id != self.player && e.ai.is_some())
+            .map(|(id, _)| id)
+            .collect();
+        for id in actors {
+            while self.is_alive(id) && !self.is_player_dead() {
+                let Some(entity) = self
+                    .level
+                    .entities
+                    .get_mut(id)
+                    .filter(|e| scheduler::is_ready(e))
+                else {
+                    break;
+                };
+                scheduler::spend(entity);
+                self.monster_turn(id);
+            }
+        }
+
+        let affected: Vec<_> = self
+            .level
+            .entities
+            .iter()
+            .filter(|(_, e)| !e.effects.is_empty())
+            .map(|(id, _)| id)
+            .collect();
+        for id in affected {
+            let events = status::tick(&mut self.level.entities, &self.raws, self.player, id);
+            self.events.extend(events);
+        }
+
+        for (_, entity) in self.level.entities.iter_mut() {
+            if entity.stats.is_some() {
+                scheduler::recharge(entity);
+            }
+        }
+        self.bury_dead();
+    }
+
+    /// Monster `id` decides what to do and does it. Confused monsters may stumble around
+    /// instead.
+    fn monster_turn(&mut self, id: EntityId) {
+        let Some(entity) = self.level.entities.get(id) else {
+            return;
+        };
+        let (pos, Some(mut brain)) = (entity.pos(), entity.brain.clone()) else {
+            return;
+        };
+        let decision = match self.stumble(id) {
+            Some(direction) => Decision::Move(direction),
+            None => {
+                let ctx = AiContext {
+                    map: &self.level.map,
+                    entities: &self.level.entities,
+                    raws: &self.raws,
+                    scent: &self.level.scent,
+                    player: self.player,
+                };
+                ai::think(&ctx, id, &mut brain, self.rng.stream(rng::AI))
+            }
+        };
+        if let Some(entity) = self.level.entities.get_mut(id) {
+            entity.brain = Some(brain);
+        }
+
+        match decision {
+            Decision::Wait => {}
+            Decision::Move(direction) => {
+                let target = pos + direction;
+                if self.level.map.is_blocked(target)
+                    || self.level.entities.blocking_at(target).is_some()
+                {
+                    return;
+                }
+                self.level.entities.move_to(id, target);
+                if let Some(entity) = self.level.entities.get_mut(id) {
+                    if direction.x != 0 {
+                        entity.facing_left = direction.x < 0;
+                    }
+                }
+            }
+            Decision::Attack(target) => self.melee(id, target),
+            Decision::Fire(target) => {
+                if let Some(entity) = self.level.entities.get(id) {
+                    if let Some(attack) = combat::ranged_attack(entity, &self.raws) {
+                        let path = self.projectile_path(entity.pos(), target);
+                        self.shoot(id, attack, &path);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Random direction a confused entity moves in instead of where it meant to go.
+    fn stumble(&mut self, id: EntityId) -> Option<IVec2> {
+        let confused = self
+            .level
+            .entities
+            .get(id)
+            .is_some_and(|e| e.effects.has(StatusKind::Confusion));
+        let rng = self.rng.stream(rng::STATUS);
+        if !confused || !rng.chance(CONFUSED_STUMBLE_CHANCE) {
+            return None;
+        }
+        rng.pick(&pathfinding::DIRECTIONS).copied()
+    }
+
+    /// `attacker` attacks `target` on a neighbouring tile.
+    fn melee(&mut self, attacker: EntityId, target: EntityId) {
+        let Some(attack) = self
+            .level
+            .entities
+            .get(attacker)
+            .and_then(|e| combat::melee_attack(e, &self.raws))
+        else {
+            return;
+        };
+        if attacker == self.player {
+            self.noises.push(Noise {
+                pos: self.player_pos(),
+                radius: MELEE_NOISE,
+            });
+        }
+        let events = combat::resolve(
+            &mut self.level.entities,
+            &self.raws,
+            self.rng.stream(rng::COMBAT),
+            self.player,
+            attacker,
+            target,
+            attack,
         );
+        self.events.extend(events);
+    }
 
-        renderer.draw_sprite_animated(
-            Vec2 { x: 50.0, y: 50.0 },
-            &self.slime,
-            ((self.time_passed * 8.0) % 5.0).round() as u32,
+    /// Shoot the player's ranged weapon towards `target`, hitting the first thing in the way.
+    /// Returns whether the player had anything to shoot with.
+    fn fire(&mut self, target: IVec2) -> bool {
+        let Some(attack) = self
+            .level
+            .entities
+            .get(self.player)
+            .and_then(|e| combat::ranged_attack(e, &self.raws))
+        else {
+            self.events.push(GameEvent::Message {
+                text: "You have nothing to shoot with.".to_string(),
+                category: MessageCategory::General,
+            });
+            return false;
+        };
+        self.noises.push(Noise {
+            pos: self.player_pos(),
+            radius: SHOT_NOISE,
+        });
+        let path = self.projectile_path(self.player_pos(), target);
+        let hit = self.shoot(self.player, attack, &path);
+        if !hit && path.len() > attack.range.unwrap_or(0) as usize {
+            self.events.push(GameEvent::Message {
+                text: "Your shot falls short.".to_string(),
+                category: MessageCategory::General,
+            });
+        }
+        true
+    }
+
+    /// `shooter` makes a ranged `attack` along `path`, from `projectile_path`, hitting the
+    /// first creature in the way. Returns whether anything was hit.
+    fn shoot(&mut self, shooter: EntityId, attack: combat::Attack, path: &[IVec2]) -> bool {
+        let Some(from) = self.level.entities.get(shooter).map(|e| e.pos()) else {
+            return false;
+        };
+        let range = attack.range.unwrap_or(0) as usize;
+        let Some(&end) = path.get(range.min(path.len()).saturating_sub(1)) else {
+            return false;
+        };
+        self.events.push(GameEvent::Shot { from, to: end });
+
+        let Some(victim) = self
+            .level
+            .entities
+            .blocking_at(end)
+            .filter(|&id| id != shooter)
+        else {
+            return false;
+        };
+        let events = combat::resolve(
+            &mut self.level.entities,
+            &self.raws,
+            self.rng.stream(rng::COMBAT),
+            self.player,
+            shooter,
+            victim,
+            attack,
         );
+        self.events.extend(events);
+        true
+    }
 
-        renderer.draw_sprite(Vec2 { x: 100.0, y: 150.0 }, &Sprite::from_image("assets/goblin_idle_anim_f0.png", Some(8.0)))
+    pub(crate) fn screen_effects(&self) -> &ScreenEffects {
+        &self.screen_effects
     }
+
+    /// Visual feedback for an event.
+    fn spawn_particles(&mut self, event: &GameEvent) {
+        let tile_size = TILE_SIZE as f32;
+        match event {
+            GameEvent::Bumped { name, pos } => {
+                // Effects appear on the edge of the tile the player bumped into.
+                let center = (pos.as_vec2() + 0.5) * tile_size;
+                let player = (self.player_pos().as_vec2() + 0.5) * tile_size;
+                let contact = (center + player) / 2.0;
+                if name == "wall" {
+                    self.particles.burst(&EmitterConfig::dust(), contact, 6);
+                } else if name == "slime" {
+                    self.particles
+                        .burst(&EmitterConfig::slime_splat(), contact, 3);
+                } else {
+                    self.particles.burst(&EmitterConfig::blood(), contact, 10);
+                }
+            }
+            GameEvent::Waited => {
+                let player = (self.player_pos().as_vec2() + 0.5) * tile_size;
+                self.particles.burst(&EmitterConfig::sparks(), player, 12);
+            }
+            GameEvent::Shot { from, to } => {
+                for pos in geometry::line(*from, *to).skip(1) {
+                    let center = (pos.as_vec2() + 0.5) * tile_size;
+                    self.particles.burst(&EmitterConfig::dust(), center, 1);
+                }
+            }
+            GameEvent::Attacked {
+                attacker,
+                target,
+                critical,
+                ..
+            } => {
+                // Hits appear on the side of the target facing the attacker.
+                let center = (target.pos.as_vec2() + 0.5) * tile_size;
+                let from = (attacker.pos.as_vec2() + 0.5) * tile_size;
+                let contact = center + (from - center).normalize_or_zero() * tile_size * 0.25;
+                if target.name == "slime" {
+                    self.particles
+                        .burst(&EmitterConfig::slime_splat(), contact, 3);
+                } else {
+                    self.particles.burst(&EmitterConfig::blood(), contact, 10);
+                }
+                if *critical {
+                    self.particles.burst(&EmitterConfig::sparks(), contact, 16);
+                }
+            }
+            GameEvent::Died { victim } => {
+                let center = (victim.pos.as_vec2() + 0.5) * tile_size;
+                if victim.name == "slime" {
+                    self.particles
+                        .burst(&EmitterConfig::slime_splat(), center, 8);
+                } else {
+                    self.particles.burst(&EmitterConfig::blood(), center, 30);
+                }
+            }
+            GameEvent::StatusDamage { target, kind, .. } => {
+                let center = (target.pos.as_vec2() + 0.5) * tile_size;
+                match kind {
+                    StatusKind::Burning => {
+                        self.particles.burst(&EmitterConfig::sparks(), center, 8)
+                    }
+                    _ => self
+                        .particles
+                        .burst(&EmitterConfig::slime_splat(), center, 3),
+                }
+            }
+            GameEvent::Message { .. }
+            | GameEvent::Missed { .. }
+            | GameEvent::StatusStarted { .. }
+            | GameEvent::StatusEnded { .. } => {}
+        }
+    }
+
+    /// Animations and screen effects for an event.
+    fn animate(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::Attacked {
+                attacker,
+                target,
+                damage,
+                ranged,
+                ..
+            } => {
+                if !ranged && self.is_alive(attacker.id) {
+                    let direction = (target.pos - attacker.pos).as_vec2();
+                    self.animations.lunge(attacker.id, direction);
+                }
+                self.hurt(target, *damage);
+            }
+            GameEvent::StatusDamage { target, damage, .. } => self.hurt(target, *damage),
+            GameEvent::Missed {
+                attacker,
+                target,
+                ranged: false,
+            } if self.is_alive(attacker.id) => {
+                let direction = (target.pos - attacker.pos).as_vec2();
+                self.animations.lunge(attacker.id, direction);
+            }
+            _ => {}
+        }
+    }
+
+    /// Flash an entity that took damage, and the screen if it is the player.
+    fn hurt(&mut self, target: &Combatant, damage: i32) {
+        if self.is_alive(target.id) {
+            self.animations.hurt(target.id);
+        }
+        if target.is_player {
+            let max_hp = self
+                .level
+                .entities
+                .get(target.id)
+                .and_then(|e| e.stats.as_ref())
+                .map_or(1, |stats| stats.max_hp.max(1));
+            let amount = 0.3 + 0.7 * (damage as f32 / max_hp as f32);
+            self.screen_effects.flash(Color::RED, amount);
+        }
+    }
+
+    /// Whether `id` is a living creature. The dead are buried before their events are
+    /// handled, and their ids may be reused by the loot they dropped.
+    fn is_alive(&self, id: EntityId) -> bool {
+        self.level
+            .entities
+            .get(id)
+            .and_then(|e| e.stats.as_ref())
+            .is_some_and(|stats| !stats.is_dead())
+    }
+
+    /// Step, or attack whoever is in the way. Returns whether it took a turn: bumping into a
+    /// wall is free, unless the player stumbled into it.
+    fn move_player(&mut self, direction: IVec2) -> bool {
+        let stumbled = self.stumble(self.player);
+        let direction = stumbled.unwrap_or(direction);
+        let target = self.player_pos() + direction;
+        let mut acted = true;
+
+        if self.level.map.is_blocked(target) {
+            self.events.push(GameEvent::Bumped {
+                name: self.level.map.get(target).name().to_string(),
+                pos: target,
+            });
+            acted = stumbled.is_some();
+        } else if let Some(other) = self.level.entities.blocking_at(target) {
+            let other_entity = self.level.entities.get(other).unwrap();
+            if other_entity.stats.is_some() {
+                self.melee(self.player, other);
+            } else {
+                let name = other_entity.name.clone();
+                self.events.push(GameEvent::Bumped { name, pos: target });
+                acted = stumbled.is_some();
+            }
+        } else {
+            self.level.entities.move_to(self.player, target);
+            self.noises.push(Noise {
+                pos: target,
+                radius: FOOTSTEP_NOISE,
+            });
+            let items: Vec<_> = self
+                .items_at(target)
+                .into_iter()
+                .filter_map(|id| self.level.entities.get(id)?.item.as_ref())
+                .map(|stack| self.describe_item(&stack.kind, stack.count))
+                .collect();
+            if !items.is_empty() {
+                self.message(format!("You see {} here.", items.join(", ")));
+            }
+        }
+
+        if direction.x != 0 {
+            if let Some(player) = self.level.entities.get_mut(self.player) {
+                player.facing_left = direction.x < 0;
+            }
+        }
+        acted
+    }
+
+    /// Describe what is on the given tile in the message log.
+    fn look_at(&mut self, pos: IVec2) {
+        // Creatures stand on top of items.
+        let entity = self
+            .level
+            .entities
+            .at(pos)
+            .into_iter()
+            .filter_map(|id| self.level.entities.get(id))
+            .min_by_key(|e| e.item.is_some());
+        let name = match entity {
+            Some(entity) => match &entity.item {
+                Some(stack) => self.describe_item(&stack.kind, stack.count),
+                None => format!("a {}", entity.name),
+            },
+            None if self.level.map.in_bounds(pos) => {
+                format!("a {}", self.level.map.get(pos).name())
+            }
+            None => return,
+        };
+        self.events.push(GameEvent::Message {
+            text: format!("You see {}.", name),
+            category: MessageCategory::Info,
+        });
+    }
+
+    /// Size of the visible part of the map, in tiles or console cells.
+    fn view_size(&self) -> IVec2 {
+        match self.presentation {
+            Presentation::Tiles => IVec2::new(
+                (self.width / TILE_SIZE) as i32,
+                (self.height / TILE_SIZE) as i32,
+            ),
+            Presentation::Ascii => IVec2::new(
+                self.console.width() as i32,
+                self.console.height() as i32 - STATUS_ROWS as i32,
+            ),
+        }
+    }
+
+    /// Map tile under a frame buffer pixel in the current presentation.
+    fn screen_to_tile(&self, pixel: Vec2) -> IVec2 {
+        let (unit, top) = match self.presentation {
+            Presentation::Tiles => (TILE_SIZE, 0),
+            Presentation::Ascii => (CELL_SIZE, STATUS_ROWS),
+        };
+        let cell = (pixel / unit as f32).floor().as_ivec2();
+        self.camera(self.view_size()) + cell - IVec2::new(0, top as i32)
+    }
+
+    fn player_pos(&self) -> IVec2 {
+        self.level
+            .entities
+            .get(self.player)
+            .map_or(IVec2::ZERO, |e| e.pos())
+    }
+
+    /// Top-left tile of a view of `view_size` tiles centered on the player.
+    fn camera(&self, view_size: IVec2) -> IVec2 {
+        let map_size = IVec2::new(self.level.map.width, self.level.map.height);
+        (self.player_pos() - view_size / 2)
+            .clamp(IVec2::ZERO, (map_size - view_size).max(IVec2::ZERO))
+    }
+
+    pub(crate) fn draw(&mut self, renderer: &mut Renderer) {
+        renderer.clear_frame(Color::BLACK);
+
+        let mut queue = DrawQueue::new();
+        match self.presentation {
+            Presentation::Tiles => self.draw_tiles(&mut queue),
+            Presentation::Ascii => self.draw_ascii(renderer),
+        }
+
+        self.message_log.draw(
+            &mut queue,
+            Vec2 {
+                x: 8.0,
+                y: self.height as f32 - LOG_TEXT_SIZE * (LOG_LINES as f32 + 0.5),
+            },
+            LOG_LINES,
+            LOG_TEXT_SIZE,
+        );
+
+        if let (Some(menu), Some(player)) = (self.menu, self.level.entities.get(self.player)) {
+            let view = MenuView {
+                entity: player,
+                raws: &self.raws,
+                sprites: &self.sprites,
+                time: self.time_passed,
+            };
+            view.draw(&mut queue, menu);
+        }
+
+        renderer.flush(&mut queue);
+    }
+
+    fn draw_tiles<'a>(&'a self, queue: &mut DrawQueue<'a>) {
+        let view_size = self.view_size();
+        let camera = self.camera(view_size);
+        let tile_size = TILE_SIZE as f32;
+
+        // Everything but the UI scrolls with the camera.
+        for layer in [Layer::Floor, Layer::Items, Layer::Actors, Layer::Effects] {
+            queue.layer_mut(layer).offset = -camera.as_vec2() * tile_size;
+        }
+
+        for y in camera.y..camera.y + view_size.y {
+            for x in camera.x..camera.x + view_size.x {
+                let pos = IVec2::new(x, y);
+                let tile = self.level.map.get(pos);
+                let style = self.raws.tile(tile);
+                let color = self.light_map.modulate(pos, style.color);
+                let art = self
+                    .raws
+                    .autotile(tile)
+                    .and_then(|autotile| self.sprites.get(&autotile.sprite));
+                match art {
+                    Some(sprite) => queue.sprite(
+                        Layer::Floor,
+                        0,
+                        pos.as_vec2() * tile_size,
+                        sprite,
+                        self.tile_frames.get(pos),
+                        DrawParams {
+                            tint: color,
+                            ..Default::default()
+                        },
+                    ),
+                    None => queue.rect(
+                        Layer::Floor,
+                        0,
+                        pos.as_vec2() * tile_size,
+                        Vec2::splat(tile_size),
+                        color,
+                    ),
+                }
+                // There are no stair sprites, so they show their glyph.
+                if matches!(tile, TileType::StairsDown | TileType::StairsUp) {
+                    queue.push(
+                        Layer::Floor,
+                        1,
+                        DrawCommand::Text {
+                            pos: pos.as_vec2() * tile_size + Vec2::new(5.0, 4.0),
+                            text: style.glyph.to_string(),
+                            size: CELL_SIZE as f32,
+                            spacing: 0.0,
+                            color: self.light_map.modulate(pos, style.glyph_color),
+                        },
+                    );
+                }
+            }
+        }
+
+        for (id, entity) in self.level.entities.iter() {
+            let on_screen =
+                entity.pos().cmpge(camera).all() && entity.pos().cmplt(camera + view_size).all();
+            if !on_screen {
+                continue;
+            }
+            if entity.item.is_some() {
+                self.draw_item(queue, entity);
+                continue;
+            }
+            let invisible = entity.effects.has(StatusKind::Invisibility);
+            if invisible && id != self.player {
+                continue;
+            }
+            let pos = entity.pos().as_vec2() * tile_size + self.animations.offset(id);
+            let mut tint = self
+                .animations
+                .tint(id, self.light_map.modulate(entity.pos(), Color::WHITE));
+            if invisible {
+                tint = tint.lerp(INVISIBLE_TINT, 0.6);
+            }
+            if let Some(sprite) = self.sprites.get(&entity.sprite) {
+                queue.sprite(
+                    Layer::Actors,
+                    entity.pos().y,
+                    pos,
+                    sprite,
+                    sprite.frame_at(self.time_passed),
+                    DrawParams {
+                        flip_x: entity.facing_left,
+                        tint,
+                        ..Default::default()
+                    },
+                );
+            }
+
+            // The melee weapon is held in front of the entity.
+            let weapon = entity
+                .equipment
+                .get(Slot::Weapon)
+                .and_then(|name| self.raws.item(name))
+                .and_then(|item| item.sprite.as_ref())
+                .and_then(|sprite| self.sprites.get(sprite));
+            if let Some(sprite) = weapon {
+                let hand = Vec2::new(if entity.facing_left { -7.0 } else { 7.0 }, 1.0);
+                queue.sprite(
+                    Layer::Actors,
+                    entity.pos().y,
+                    pos + hand,
+                    sprite,
+                    sprite.frame_at(self.time_passed),
+                    DrawParams {
+                        flip_x: entity.facing_left,
+                        tint,
+                        ..Default::default()
+                    },
+                );
+            }
+            self.draw_status_icons(queue, entity, pos);
+        }
+
+        self.particles.draw(queue, &self.sprites);
+        self.draw_targeting(queue);
+    }
+
+    /// A dot per status effect above an entity drawn at `pos`, in the colour of the effect.
+    fn draw_status_icons(&self, queue: &mut DrawQueue, entity: &Entity, pos: Vec2) {
+        let count = entity.effects.iter().count() as f32;
+        let left = pos.x + (TILE_SIZE as f32 - (count - 1.0) * STATUS_ICON_SPACING) / 2.0;
+        for (i, effect) in entity.effects.iter().enumerate() {
+            let center = Vec2::new(left + i as f32 * STATUS_ICON_SPACING, pos.y - 2.0);
+            queue.circle(
+                Layer::Effects,
+                entity.pos().y,
+                center,
+                1.5,
+                effect.kind.color(),
+                true,
+            );
+        }
+    }
+
+    /// Item lying on the floor: its sprite, or its glyph for items without one.
+    fn draw_item<'a>(&'a self, queue: &mut DrawQueue<'a>, entity: &Entity) {
+        let pos = entity.pos().as_vec2() * TILE_SIZE as f32;
+        let tint = self.light_map.modulate(entity.pos(), Color::WHITE);
+        match self.sprites.get(&entity.sprite) {
+            Some(sprite) => queue.sprite(
+                Layer::Items,
+                entity.pos().y,
+                pos,
+                sprite,
+                sprite.frame_at(self.time_passed),
+                DrawParams {
+                    tint,
+                    ..Default::default()
+                },
+            ),
+            None => queue.push(
+                Layer::Items,
+                entity.pos().y,
+                DrawCommand::Text {
+                    pos: pos + Vec2::new(5.0, 4.0),
+                    text: entity.glyph.to_string(),
+                    size: CELL_SIZE as f32,
+                    spacing: 0.0,
+                    color: self.light_map.modulate(entity.pos(), entity.color),
+                },
+            ),
+        }
+    }
+
+    /// Tiles a projectile would pass from `from` towards `target`, up to and including the
+    /// first tile that blocks it.
+    fn projectile_path(&self, from: IVec2, target: IVec2) -> Vec<IVec2> {
+        let mut path = Vec::new();
+        for pos in geometry::line(from, target).skip(1) {
+            path.push(pos);
+            if self.level.map.is_blocked(pos) || self.level.entities.blocking_at(pos).is_some() {
+                break;
+            }
+        }
+        path
+    }
+
+    /// Preview of the projectile path towards the tile under the cursor.
+    fn draw_targeting(&self, queue: &mut DrawQueue) {
+        let target = match (self.menu, self.mouse) {
+            (Some(Menu::Throw { target, .. }), _) => target,
+            (None, Some(mouse)) => self.screen_to_tile(mouse),
+            _ => return,
+        };
+        if !self.level.map.in_bounds(target) {
+            return;
+        }
+
+        let tile_size = TILE_SIZE as f32;
+        let path = self.projectile_path(self.player_pos(), target);
+        for pos in &path {
+            queue.rect(
+                Layer::Effects,
+                0,
+                pos.as_vec2() * tile_size,
+                Vec2::splat(tile_size),
+                Color::rgba(0xff, 0xe0, 0x40, 0x40),
+            );
+        }
+        // A line to where the projectile lands, marked with a diamond.
+        if let Some(landing) = path.last() {
+            let center = |pos: IVec2| (pos.as_vec2() + 0.5) * tile_size;
+            let end = center(*landing);
+            queue.line(
+                Layer::Effects,
+                1,
+                center(self.player_pos()),
+                end,
+                Color::rgba(0xff, 0xe0, 0x40, 0x80),
+            );
+            let diamond = [Vec2::X, Vec2::Y, -Vec2::X, -Vec2::Y].map(|d| end + d * 3.0);
+            queue.fill_polygon(
+                Layer::Effects,
+                2,
+                &diamond,
+                Color::rgba(0xff, 0xe0, 0x40, 0x80),
+            );
+            queue.polygon(
+                Layer::Effects,
+                2,
+                &diamond,
+                Color::rgba(0xff, 0xe0, 0x40, 0xff),
+            );
+        }
+        queue.rect_outline(
+            Layer::Effects,
+            1,
+            target.as_vec2() * tile_size,
+            Vec2::splat(tile_size),
+            Color::rgba(0xff, 0xe0, 0x40, 0xc0),
+        );
+    }
+
+    fn draw_ascii(&mut self, renderer: &mut Renderer) {
+        let view_size = self.view_size();
+        let camera = self.camera(view_size);
+
+        self.console.clear();
+        for y in 0..view_size.y {
+            for x in 0..view_size.x {
+                let pos = camera + IVec2::new(x, y);
+                let style = self.raws.tile(self.level.map.get(pos));
+                let color = self.light_map.modulate(pos, style.glyph_color);
+                self.console
+                    .set_glyph(x as u32, y as u32 + STATUS_ROWS, style.glyph, color);
+            }
+        }
+        // Items first, so creatures standing on them are drawn on top.
+        let mut entities: Vec<_> = self
+            .level
+            .entities
+            .iter()
+            .filter(|&(id, e)| id == self.player || !e.effects.has(StatusKind::Invisibility))
+            .map(|(_, e)| e)
+            .collect();
+        entities.sort_by_key(|e| e.item.is_none());
+        for entity in entities {
+            let pos = entity.pos() - camera;
+            if pos.cmpge(IVec2::ZERO).all() && pos.cmplt(view_size).all() {
+                let (x, y) = (pos.x as u32, pos.y as u32 + STATUS_ROWS);
+                let color = self.light_map.modulate(entity.pos(), entity.color);
+                // The newest effect tints the cell behind an affected entity.
+                match entity.effects.iter().last() {
+                    Some(effect) => {
+                        let (hue, saturation, lightness) = effect.kind.color().to_hsl();
+                        let background = Color::from_hsl(hue, saturation, lightness * 0.4);
+                        self.console.set(x, y, entity.glyph, color, background);
+                    }
+                    None => self.console.set_glyph(x, y, entity.glyph, color),
+                }
+            }
+        }
+        if let Some(stats) = self.player_stats() {
+            let status = format!(
+                "Depth {}  HP {}/{}",
+                self.level.depth,
+                stats.hp.max(0),
+                stats.max_hp
+            );
+            self.console.print(0, 0, &status, Color::WHITE);
+        }
+
+        self.console.draw(renderer, Vec2::ZERO);
+    }
+}
+
+/// Generator for dungeon level `depth`. Each level has its own, so a level looks the same
+/// for a seed no matter when the player first reaches it.
+fn level_rng(rng: &GameRng, depth: u32) -> rng::Rng {
+    rng.fork(&format!("{} {}", rng::MAPGEN, depth))
 }
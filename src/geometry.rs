@@ -0,0 +1,103 @@
+use glam::IVec2;
+
+/// Iterator over the grid cells on a Bresenham line, including both end points.
+///
+/// Used for drawing lines as well as for paths on the tile grid, such as projectiles.
+pub(crate) struct Line {
+    current: IVec2,
+    end: IVec2,
+    delta: IVec2,
+    step: IVec2,
+    error: i32,
+    done: bool,
+}
+
+impl Line {
+    pub(crate) fn new(start: IVec2, end: IVec2) -> Self {
+        let delta = IVec2::new((end.x - start.x).abs(), -(end.y - start.y).abs());
+        Self {
+            current: start,
+            end,
+            delta,
+            step: IVec2::new((end.x - start.x).signum(), (end.y - start.y).signum()),
+            error: delta.x + delta.y,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for Line {
+    type Item = IVec2;
+
+    fn next(&mut self) -> Option<IVec2> {
+        if self.done {
+            return None;
+        }
+
+        let point = self.current;
+        if point == self.end {
+            self.done = true;
+            return Some(point);
+        }
+
+        let error = self.error * 2;
+        if error >= self.delta.y {
+            self.error += self.delta.y;
+            self.current.x += self.step.x;
+        }
+        if error <= self.delta.x {
+            self.error += self.delta.x;
+            self.current.y += self.step.y;
+        }
+
+        Some(point)
+    }
+}
+
+/// Bresenham line between two grid cells.
+pub(crate) fn line(start: IVec2, end: IVec2) -> Line {
+    Line::new(start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(start: IVec2, end: IVec2) -> Vec<IVec2> {
+        line(start, end).collect()
+    }
+
+    #[test]
+    fn lines_hit_both_end_points() {
+        let start = IVec2::new(3, -2);
+        for end in [
+            IVec2::new(8, 0),
+            IVec2::new(5, 3),
+            IVec2::new(1, 7),
+            IVec2::new(-4, 1),
+            IVec2::new(-3, -6),
+            IVec2::new(0, -9),
+            IVec2::new(9, -5),
+            IVec2::new(3, 4),
+            start,
+        ] {
+            let points = points(start, end);
+            let delta = (end - start).abs();
+            assert_eq!(points.first(), Some(&start));
+            assert_eq!(points.last(), Some(&end));
+            assert_eq!(points.len() as i32, delta.x.max(delta.y) + 1, "to {}", end);
+            // Every step moves to one of the eight neighbours.
+            for pair in points.windows(2) {
+                let step = (pair[1] - pair[0]).abs();
+                assert!(step.max_element() == 1, "{} to {}", pair[0], pair[1]);
+            }
+        }
+    }
+
+    #[test]
+    fn lines_are_symmetric_on_diagonals() {
+        let points = points(IVec2::ZERO, IVec2::new(-4, 4));
+        let expected: Vec<_> = (0..=4).map(|i| IVec2::new(-i, i)).collect();
+        assert_eq!(points, expected);
+    }
+}
@@ -1,4 +1,12 @@
-use egui::{ClippedPrimitive, Context, TexturesDelta};
+use crate::color::Color;
+use crate::game::Game;
+use crate::mapgen::LevelSample;
+use crate::markup;
+use crate::palette::Palette;
+use crate::rng::{DiceError, GameRng};
+use crate::scheduler;
+use crate::wfc::{Step, Wfc};
+use egui::{ClippedPrimitive, Color32, Context, RichText, TexturesDelta};
 use egui_wgpu::renderer::{RenderPass, ScreenDescriptor};
 use pixels::{wgpu, PixelsContext};
 use winit::event_loop::EventLoopWindowTarget;
@@ -22,8 +30,44 @@ pub(crate) struct Framework {
 struct Gui {
     /// Only show the egui window when true.
     window_open: bool,
+    /// Show the message log history window when true.
+    message_log_open: bool,
+    /// Show the entity inspector window when true.
+    inspector_open: bool,
+    /// Show the wave function collapse debugger window when true.
+    wfc_open: bool,
+    wfc: WfcDebugger,
+    /// Show the dice roller window when true.
+    dice_open: bool,
+    dice: DiceRoller,
 }
 
+/// Rolls dice notation on a generator seeded like the game, to check specs for the raws.
+struct DiceRoller {
+    spec: String,
+    /// Seed the generator was created with, to start over when the game changes.
+    seed: Option<u64>,
+    rng: GameRng,
+    last_roll: Option<Result<i32, DiceError>>,
+}
+
+/// Steps through the wave function collapse of a level sample, to see how it settles.
+struct WfcDebugger {
+    /// Index of the sample in the raws.
+    sample: usize,
+    seed: u64,
+    width: i32,
+    height: i32,
+    wfc: Option<Wfc>,
+    /// Keep stepping every frame.
+    running: bool,
+    steps_per_frame: u32,
+    last_step: Option<Step>,
+}
+
+/// Size of a cell of the collapse in the debugger, in points.
+const WFC_CELL_SIZE: f32 = 6.0;
+
 impl Framework {
     /// Create egui.
     pub(crate) fn new<T>(
@@ -76,12 +120,12 @@ impl Framework {
     }
 
     /// Prepare egui.
-    pub(crate) fn prepare(&mut self, window: &Window) {
+    pub(crate) fn prepare(&mut self, window: &Window, game: &Game, palette: &Palette) {
         // Run the egui frame and create all paint jobs to prepare for rendering.
         let raw_input = self.egui_state.take_egui_input(window);
         let output = self.egui_ctx.run(raw_input, |egui_ctx| {
             // Draw the demo application.
-            self.gui.ui(egui_ctx);
+            self.gui.ui(egui_ctx, game, palette);
         });
 
         self.textures.append(output.textures_delta);
@@ -129,11 +173,33 @@ impl Framework {
 impl Gui {
     /// Create a `Gui`.
     fn new() -> Self {
-        Self { window_open: false }
+        Self {
+            window_open: false,
+            message_log_open: false,
+            inspector_open: false,
+            wfc_open: false,
+            wfc: WfcDebugger {
+                sample: 0,
+                seed: 0,
+                width: 48,
+                height: 32,
+                wfc: None,
+                running: false,
+                steps_per_frame: 10,
+                last_step: None,
+            },
+            dice_open: false,
+            dice: DiceRoller {
+                spec: "2d6+1".to_string(),
+                seed: None,
+                rng: GameRng::new(0),
+                last_roll: None,
+            },
+        }
     }
 
     /// Create the UI using egui.
-    fn ui(&mut self, ctx: &Context) {
+    fn ui(&mut self, ctx: &Context, game: &Game, palette: &Palette) {
         egui::TopBottomPanel::top("menubar_container").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
@@ -144,7 +210,43 @@ impl Gui {
                     if ui.button("Pause").clicked() {
                         println!("Hello World!");
                     }
-                })
+                });
+                ui.menu_button("View", |ui| {
+                    if ui.button("Message Log").clicked() {
+                        self.message_log_open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Inspector").clicked() {
+                        self.inspector_open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("WFC Debugger").clicked() {
+                        self.wfc_open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Dice Roller").clicked() {
+                        self.dice_open = true;
+                        ui.close_menu();
+                    }
+                });
+                ui.separator();
+                ui.label(format!("Turn {}", game.turn()));
+                ui.separator();
+                ui.label(format!("Depth {}", game.depth()));
+                ui.separator();
+                ui.label(format!("Seed {}", game.seed()));
+                if let Some(stats) = game.player_stats() {
+                    ui.separator();
+                    // Fades from green at full health to red near death.
+                    let health = stats.hp.max(0) as f32 / stats.max_hp.max(1) as f32;
+                    let (green, saturation, value) = Color::GREEN.to_hsv();
+                    let [r, g, b, _] =
+                        Color::from_hsv(green * health, saturation, value).to_array();
+                    ui.label(
+                        RichText::new(format!("HP {}/{}", stats.hp.max(0), stats.max_hp))
+                            .color(Color32::from_rgb(r, g, b)),
+                    );
+                }
             });
         });
 
@@ -162,5 +264,256 @@ impl Gui {
                     ui.hyperlink("https://docs.rs/egui");
                 });
             });
+
+        egui::Window::new("Inspector")
+            .open(&mut self.inspector_open)
+            .default_height(240.0)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| inspector(ui, game));
+            });
+
+        egui::Window::new("WFC Debugger")
+            .open(&mut self.wfc_open)
+            .show(ctx, |ui| self.wfc.ui(ui, game.raws().samples()));
+
+        egui::Window::new("Dice Roller")
+            .open(&mut self.dice_open)
+            .show(ctx, |ui| self.dice.ui(ui, game.seed()));
+
+        egui::Window::new("Message Log")
+            .open(&mut self.message_log_open)
+            .default_height(240.0)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for message in game.message_log.messages() {
+                            let text = message.display_text();
+                            ui.horizontal_wrapped(|ui| {
+                                ui.spacing_mut().item_spacing.x = 0.0;
+                                for span in markup::parse(&text, message.color, palette) {
+                                    let Color { r, g, b, a } = span.color;
+                                    ui.label(
+                                        RichText::new(span.text)
+                                            .color(Color32::from_rgba_unmultiplied(r, g, b, a)),
+                                    );
+                                }
+                            });
+                        }
+                    });
+            });
+    }
+}
+
+impl DiceRoller {
+    /// Stream the roller draws from, apart from the streams the game itself uses.
+    const STREAM: &'static str = "dice roller";
+
+    fn ui(&mut self, ui: &mut egui::Ui, seed: u64) {
+        if self.seed != Some(seed) {
+            self.seed = Some(seed);
+            self.rng = GameRng::new(seed);
+            self.last_roll = None;
+        }
+        ui.horizontal(|ui| {
+            ui.label("Dice");
+            ui.text_edit_singleline(&mut self.spec);
+            if ui.button("Roll").clicked() {
+                self.last_roll = Some(self.rng.roll(Self::STREAM, &self.spec));
+            }
+        });
+        match &self.last_roll {
+            Some(Ok(roll)) => ui.label(format!("Rolled {}", roll)),
+            Some(Err(e)) => ui.label(e.to_string()),
+            None => ui.label("Not rolled yet."),
+        };
+    }
+}
+
+/// Every creature in the world, with its stats, energy and status effects.
+fn inspector(ui: &mut egui::Ui, game: &Game) {
+    for (id, entity) in game.entities().iter() {
+        let Some(stats) = &entity.stats else {
+            continue;
+        };
+        let title = if id == game.player() {
+            format!("{} (player)", entity.name)
+        } else {
+            entity.name.clone()
+        };
+        egui::CollapsingHeader::new(title)
+            .id_source(id)
+            .default_open(id == game.player())
+            .show(ui, |ui| {
+                egui::Grid::new(id).num_columns(2).show(ui, |ui| {
+                    ui.label("Position");
+                    ui.label(format!("{}, {}", entity.pos().x, entity.pos().y));
+                    ui.end_row();
+                    ui.label("HP");
+                    ui.label(format!("{}/{}", stats.hp, stats.max_hp));
+                    ui.end_row();
+                    ui.label("Energy");
+                    ui.label(format!(
+                        "{} (+{} per turn)",
+                        entity.energy,
+                        scheduler::speed(entity)
+                    ));
+                    ui.end_row();
+                    if let Some(ai) = entity.ai {
+                        ui.label("AI");
+                        ui.label(format!("{:?}", ai));
+                        ui.end_row();
+                    }
+                    if let Some(brain) = &entity.brain {
+                        ui.label("State");
+                        ui.label(format!("{:?}", brain.state));
+                        ui.end_row();
+                        ui.label("Target");
+                        ui.label(match brain.target {
+                            Some(target) => format!("{}, {}", target.x, target.y),
+                            None => "-".to_string(),
+                        });
+                        ui.end_row();
+                    }
+                    for effect in entity.effects.iter() {
+                        let Color { r, g, b, a } = effect.kind.color();
+                        ui.label(
+                            RichText::new(effect.kind.to_string())
+                                .color(Color32::from_rgba_unmultiplied(r, g, b, a)),
+                        );
+                        ui.label(format!(
+                            "{} turns, potency {}",
+                            effect.turns, effect.potency
+                        ));
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+}
+
+impl WfcDebugger {
+    fn ui(&mut self, ui: &mut egui::Ui, samples: &[LevelSample]) {
+        if samples.is_empty() {
+            ui.label("There are no samples in the raws.");
+            return;
+        }
+        self.sample = self.sample.min(samples.len() - 1);
+
+        let mut reset = self.wfc.is_none();
+        egui::Grid::new("wfc settings")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Sample");
+                egui::ComboBox::from_id_source("wfc sample")
+                    .selected_text(&samples[self.sample].name)
+                    .show_ui(ui, |ui| {
+                        for (index, sample) in samples.iter().enumerate() {
+                            reset |= ui
+                                .selectable_value(&mut self.sample, index, &sample.name)
+                                .changed();
+                        }
+                    });
+                ui.end_row();
+                ui.label("Seed");
+                reset |= ui.add(egui::DragValue::new(&mut self.seed)).changed();
+                ui.end_row();
+                ui.label("Size");
+                ui.horizontal(|ui| {
+                    reset |= ui
+                        .add(egui::DragValue::new(&mut self.width).clamp_range(8..=96))
+                        .changed();
+                    reset |= ui
+                        .add(egui::DragValue::new(&mut self.height).clamp_range(8..=64))
+                        .changed();
+                });
+                ui.end_row();
+            });
+
+        ui.horizontal(|ui| {
+            reset |= ui.button("Reset").clicked();
+            let step = ui.button("Step").clicked();
+            ui.toggle_value(&mut self.running, "Run");
+            ui.add(egui::Slider::new(&mut self.steps_per_frame, 1..=200).text("steps per frame"));
+            if reset {
+                let model = samples[self.sample].model.clone();
+                self.wfc = Some(Wfc::new(model, self.width, self.height, self.seed));
+                self.last_step = None;
+            }
+            let Some(wfc) = &mut self.wfc else {
+                return;
+            };
+            let steps = match (self.running, step) {
+                (true, _) => self.steps_per_frame,
+                (false, true) => 1,
+                (false, false) => 0,
+            };
+            for _ in 0..steps {
+                self.last_step = Some(wfc.step());
+                if wfc.finished().is_some() {
+                    self.running = false;
+                    break;
+                }
+            }
+        });
+        if self.running {
+            ui.ctx().request_repaint();
+        }
+
+        let Some(wfc) = &self.wfc else {
+            return;
+        };
+        ui.label(format!(
+            "{} patterns, {}/{} cells settled, {} backtracks",
+            wfc.model().pattern_count(),
+            wfc.collapsed(),
+            wfc.width() * wfc.height(),
+            wfc.backtracks()
+        ));
+        ui.label(match self.last_step {
+            None => "Not started".to_string(),
+            Some(Step::Collapsed(pos)) => format!("Settled {}, {}", pos.x, pos.y),
+            Some(Step::Backtracked(pos)) => format!("Backtracked from {}, {}", pos.x, pos.y),
+            Some(Step::Done) => "Done".to_string(),
+            Some(Step::Failed) => "Failed".to_string(),
+        });
+
+        let size = egui::vec2(wfc.width() as f32, wfc.height() as f32) * WFC_CELL_SIZE;
+        let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+        let origin = response.rect.min;
+        let cell_rect = |x: i32, y: i32| {
+            egui::Rect::from_min_size(
+                origin + egui::vec2(x as f32, y as f32) * WFC_CELL_SIZE,
+                egui::vec2(WFC_CELL_SIZE, WFC_CELL_SIZE),
+            )
+        };
+        for (index, color) in wfc.preview().into_iter().enumerate() {
+            let (x, y) = (index as i32 % wfc.width(), index as i32 / wfc.width());
+            let Color { r, g, b, a } = color;
+            painter.rect_filled(
+                cell_rect(x, y),
+                0.0,
+                Color32::from_rgba_unmultiplied(r, g, b, a),
+            );
+        }
+        if let Some(Step::Collapsed(pos) | Step::Backtracked(pos)) = self.last_step {
+            painter.rect_stroke(
+                cell_rect(pos.x, pos.y),
+                0.0,
+                egui::Stroke::new(1.0, Color32::YELLOW),
+            );
+        }
+        if let Some(hover) = response.hover_pos() {
+            let cell = (hover - origin) / WFC_CELL_SIZE;
+            let pos = glam::IVec2::new(cell.x as i32, cell.y as i32);
+            if pos.x < wfc.width() && pos.y < wfc.height() {
+                response.on_hover_text(format!(
+                    "{}, {}: {} options",
+                    pos.x,
+                    pos.y,
+                    wfc.options(pos)
+                ));
+            }
+        }
     }
 }
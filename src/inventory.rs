@@ -0,0 +1,185 @@
+use crate::raws::Raws;
+use serde::{Deserialize, Serialize};
+
+/// A number of items of one kind, named by their definition in the raws.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub(crate) struct ItemStack {
+    pub kind: String,
+    pub count: u32,
+}
+
+impl ItemStack {
+    pub(crate) fn new(kind: &str, count: u32) -> Self {
+        Self {
+            kind: kind.to_string(),
+            count,
+        }
+    }
+}
+
+/// Items carried by an entity, limited by their total weight. Items of a kind share a slot
+/// up to the stack size of the kind.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub(crate) struct Inventory {
+    /// Most weight that can be carried.
+    pub capacity: u32,
+    items: Vec<ItemStack>,
+}
+
+impl Inventory {
+    pub(crate) fn new(capacity: u32) -> Self {
+        Self {
+            capacity,
+            items: Vec::new(),
+        }
+    }
+
+    pub(crate) fn items(&self) -> &[ItemStack] {
+        &self.items
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<&ItemStack> {
+        self.items.get(index)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Total weight of everything carried.
+    pub(crate) fn weight(&self, raws: &Raws) -> u32 {
+        self.items
+            .iter()
+            .map(|stack| raws.item(&stack.kind).map_or(0, |def| def.weight) * stack.count)
+            .sum()
+    }
+
+    /// How many items of `kind` still fit within the capacity.
+    pub(crate) fn room_for(&self, kind: &str, raws: &Raws) -> u32 {
+        match raws.item(kind) {
+            Some(def) if def.weight > 0 => {
+                self.capacity.saturating_sub(self.weight(raws)) / def.weight
+            }
+            Some(_) => u32::MAX,
+            None => 0,
+        }
+    }
+
+    /// Add as many items of `stack` as fit, topping up existing stacks first. Returns what
+    /// did not fit.
+    pub(crate) fn add(&mut self, stack: ItemStack, raws: &Raws) -> Option<ItemStack> {
+        let Some(def) = raws.item(&stack.kind) else {
+            return Some(stack);
+        };
+        let fits = stack.count.min(self.room_for(&stack.kind, raws));
+        let mut left = fits;
+
+        for existing in self.items.iter_mut().filter(|s| s.kind == stack.kind) {
+            let added = left.min(def.stack.saturating_sub(existing.count));
+            existing.count += added;
+            left -= added;
+        }
+        while left > 0 {
+            let added = left.min(def.stack.max(1));
+            self.items.push(ItemStack::new(&stack.kind, added));
+            left -= added;
+        }
+
+        let rest = stack.count - fits;
+        (rest > 0).then(|| ItemStack::new(&stack.kind, rest))
+    }
+
+    /// Take up to `count` items from the slot at `index`.
+    pub(crate) fn take(&mut self, index: usize, count: u32) -> Option<ItemStack> {
+        let stack = self.items.get_mut(index)?;
+        let taken = count.min(stack.count);
+        stack.count -= taken;
+        let kind = stack.kind.clone();
+        if stack.count == 0 {
+            self.items.remove(index);
+        }
+        (taken > 0).then(|| ItemStack::new(&kind, taken))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Palette;
+
+    fn raws() -> Raws {
+        let palette = Palette::load("assets/palettes/dungeon.gpl").unwrap();
+        Raws::load("assets/raws", &palette).unwrap()
+    }
+
+    fn counts(inventory: &Inventory) -> Vec<(&str, u32)> {
+        inventory
+            .items()
+            .iter()
+            .map(|stack| (stack.kind.as_str(), stack.count))
+            .collect()
+    }
+
+    #[test]
+    fn stacks_merge_up_to_the_stack_size() {
+        let raws = raws();
+        let mut inventory = Inventory::new(100);
+        assert_eq!(
+            inventory.add(ItemStack::new("healing potion", 3), &raws),
+            None
+        );
+        assert_eq!(inventory.add(ItemStack::new("sword", 1), &raws), None);
+        // Potions stack up to 5, the rest starts a new slot.
+        assert_eq!(
+            inventory.add(ItemStack::new("healing potion", 4), &raws),
+            None
+        );
+        assert_eq!(
+            counts(&inventory),
+            [("healing potion", 5), ("sword", 1), ("healing potion", 2)]
+        );
+        // Swords do not stack at all.
+        assert_eq!(inventory.add(ItemStack::new("sword", 2), &raws), None);
+        assert_eq!(inventory.len(), 5);
+
+        assert_eq!(
+            inventory.take(0, 2),
+            Some(ItemStack::new("healing potion", 2))
+        );
+        assert_eq!(
+            inventory.take(0, 9),
+            Some(ItemStack::new("healing potion", 3))
+        );
+        assert_eq!(counts(&inventory)[0], ("sword", 1));
+        assert_eq!(inventory.take(9, 1), None);
+    }
+
+    #[test]
+    fn capacity_limits_the_weight() {
+        let raws = raws();
+        let mut inventory = Inventory::new(10);
+        // Swords weigh 3, so three fit and one is left over.
+        assert_eq!(
+            inventory.add(ItemStack::new("sword", 4), &raws),
+            Some(ItemStack::new("sword", 1))
+        );
+        assert_eq!(inventory.weight(&raws), 9);
+        assert_eq!(inventory.room_for("healing potion", &raws), 1);
+        assert_eq!(inventory.room_for("leather armour", &raws), 0);
+        assert_eq!(
+            inventory.add(ItemStack::new("leather armour", 1), &raws),
+            Some(ItemStack::new("leather armour", 1))
+        );
+        // Weightless items always fit, unknown ones never do.
+        assert_eq!(inventory.add(ItemStack::new("gold", 500), &raws), None);
+        assert_eq!(
+            inventory.add(ItemStack::new("rock", 1), &raws),
+            Some(ItemStack::new("rock", 1))
+        );
+        assert_eq!(inventory.weight(&raws), 9);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::ai::Scent;
+use crate::entity::EntityStore;
+use crate::inventory::ItemStack;
+use crate::map::{Map, TileType};
+use crate::mapgen::{self, LevelParams};
+use crate::prefab::Spawn;
+use crate::raws::Raws;
+use crate::rng::Rng;
+use glam::IVec2;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+/// Spots tried for each prefab before it is left out of the level.
+const PREFAB_ATTEMPTS: u32 = 40;
+
+/// One level of the dungeon and everything on it. Levels the player is not on are kept
+/// exactly as they were left: nothing on them moves until the player returns.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Level {
+    /// 1 for the first level, growing downwards.
+    pub depth: u32,
+    pub map: Map,
+    /// The player is only in the store of the level they are on.
+    pub entities: EntityStore,
+    /// Trail the player left on this level.
+    pub scent: Scent,
+}
+
+impl Level {
+    /// Generate level `depth` and fill it with monsters and items of that depth. Levels are
+    /// grown from one of the samples of their depth, if it succeeds, and carved into rooms
+    /// and corridors otherwise. The stairs up are in the first room and the stairs down in
+    /// the last. On the first level, the stairs up lead out of the dungeon. Prefabs of that
+    /// depth are stamped into the rock between the rooms, turned and mirrored at random.
+    pub(crate) fn generate(depth: u32, raws: &Raws, rng: &mut Rng) -> Self {
+        let params = LevelParams::for_depth(depth);
+        let samples: Vec<_> = raws.samples_at_depth(depth).collect();
+        let layout = rng
+            .pick(&samples)
+            .filter(|sample| rng.chance(sample.chance))
+            .and_then(|sample| mapgen::wave_function_collapse(&params, sample, rng))
+            .unwrap_or_else(|| mapgen::rooms_and_corridors(&params, rng));
+        let mapgen::Layout {
+            mut map,
+            rooms,
+            up,
+            down,
+        } = layout;
+        map.set(up, TileType::StairsUp);
+        map.set(down, TileType::StairsDown);
+
+        let mut entities = EntityStore::new();
+        for prefab in raws.prefabs_at_depth(depth) {
+            if !rng.chance(prefab.chance) {
+                continue;
+            }
+            let prefab = prefab.transformed(rng.below(4), rng.chance(0.5));
+            let Some(origin) = mapgen::place_prefab(&mut map, &prefab, PREFAB_ATTEMPTS, rng) else {
+                debug!("no room for the {} on level {}", prefab.name, depth);
+                continue;
+            };
+            for (pos, spawn) in prefab.spawns() {
+                let pos = origin + pos;
+                let entity = match spawn {
+                    Spawn::Monster(name) => raws.spawn_monster(name, pos),
+                    Spawn::Item(name) => raws.spawn_item(ItemStack::new(name, 1), pos),
+                };
+                if let Some(entity) = entity {
+                    entities.spawn(entity);
+                }
+            }
+        }
+
+        let monsters: Vec<_> = raws.monsters_at_depth(depth).map(|def| &def.name).collect();
+        for room in rooms.iter().skip(1) {
+            for _ in 0..rng.range(0, params.monsters_per_room) {
+                let pos = random_tile(room.tiles(), rng);
+                if entities.blocking_at(pos).is_some() || map.get(pos) != TileType::Floor {
+                    continue;
+                }
+                if let Some(monster) = rng
+                    .pick(&monsters)
+                    .and_then(|name| raws.spawn_monster(name, pos))
+                {
+                    entities.spawn(monster);
+                }
+            }
+        }
+
+        let items: Vec<_> = raws.items_at_depth(depth).map(|def| &def.name).collect();
+        for _ in 0..params.items {
+            let Some(room) = rng.pick(&rooms) else {
+                break;
+            };
+            let pos = random_tile(room.tiles(), rng);
+            if let Some(item) = rng
+                .pick(&items)
+                .filter(|_| !map.is_blocked(pos))
+                .and_then(|name| raws.spawn_item(ItemStack::new(name, 1), pos))
+            {
+                entities.spawn(item);
+            }
+        }
+
+        Self {
+            depth,
+            scent: Scent::new(map.width, map.height),
+            map,
+            entities,
+        }
+    }
+
+    /// Where the player arrives: on the stairs up when coming from above, on the stairs
+    /// down when coming from below. Beside them if someone is standing there.
+    pub(crate) fn arrival(&self, from_above: bool) -> IVec2 {
+        let stairs = if from_above {
+            TileType::StairsUp
+        } else {
+            TileType::StairsDown
+        };
+        let pos = self.map.find(stairs).unwrap_or_default();
+        self.free_tile_near(pos)
+    }
+
+    /// The closest tile to `pos` that nothing blocks, searching in growing squares.
+    fn free_tile_near(&self, pos: IVec2) -> IVec2 {
+        let is_free =
+            |tile: IVec2| !self.map.is_blocked(tile) && self.entities.blocking_at(tile).is_none();
+        let size = self.map.width.max(self.map.height);
+        (0..size)
+            .flat_map(|r| (-r..=r).flat_map(move |y| (-r..=r).map(move |x| pos + IVec2::new(x, y))))
+            .find(|&tile| is_free(tile))
+            .unwrap_or(pos)
+    }
+}
+
+fn random_tile(tiles: impl Iterator<Item = IVec2>, rng: &mut Rng) -> IVec2 {
+    let tiles: Vec<_> = tiles.collect();
+    rng.pick(&tiles).copied().unwrap_or_default()
+}
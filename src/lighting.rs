@@ -0,0 +1,184 @@
+use crate::color::Color;
+use crate::easing::Easing;
+use crate::fov::compute_fov;
+use crate::map::Map;
+use glam::IVec2;
+use serde::{Deserialize, Serialize};
+
+/// A light emitted by an entity, such as a torch or a glowing monster.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) struct Light {
+    pub color: Color,
+    /// Tiles the light reaches.
+    pub radius: i32,
+    /// Brightness at the center, 1.0 lights a tile to its full colour.
+    pub intensity: f32,
+    /// Curve mapping distance (0-1) to how much light is lost.
+    pub falloff: Easing,
+    /// How strongly the light flickers, from 0.0 (steady) to 1.0.
+    pub flicker: f32,
+}
+
+pub(crate) struct LightSource {
+    pub pos: IVec2,
+    pub light: Light,
+}
+
+/// Accumulated light per map tile, used to modulate the colour of everything drawn on it.
+pub(crate) struct LightMap {
+    width: i32,
+    height: i32,
+    /// Light every tile receives, regardless of light sources.
+    pub ambient: [f32; 3],
+    light: Vec<[f32; 3]>,
+}
+
+impl LightMap {
+    pub(crate) fn new(width: i32, height: i32, ambient: [f32; 3]) -> Self {
+        Self {
+            width,
+            height,
+            ambient,
+            light: vec![ambient; (width * height) as usize],
+        }
+    }
+
+    /// Recalculate the light map. Walls block light the same way they block sight.
+    pub(crate) fn compute(&mut self, map: &Map, sources: &[LightSource], time: f32) {
+        self.light.fill(self.ambient);
+        // The number of the last source that lit each tile, since shadowcasting can visit a
+        // tile more than once.
+        let mut lit = vec![0; self.light.len()];
+
+        for (number, source) in (1..).zip(sources) {
+            let light = &source.light;
+            let brightness = light.intensity * flicker(source.pos, light.flicker, time);
+            let color = light
+                .color
+                .to_array()
+                .map(|c| c as f32 / 255.0 * brightness);
+
+            compute_fov(
+                source.pos,
+                light.radius,
+                |pos| map.is_blocked(pos),
+                |pos| {
+                    let Some(index) = self.index(pos) else {
+                        return;
+                    };
+                    if std::mem::replace(&mut lit[index], number) == number {
+                        return;
+                    }
+
+                    let distance = (pos - source.pos).as_vec2().length() / light.radius as f32;
+                    let amount = 1.0 - light.falloff.apply(distance.clamp(0.0, 1.0));
+                    for (light, color) in self.light[index].iter_mut().zip(color) {
+                        *light += color * amount;
+                    }
+                },
+            );
+        }
+    }
+
+    fn index(&self, pos: IVec2) -> Option<usize> {
+        if pos.x >= 0 && pos.y >= 0 && pos.x < self.width && pos.y < self.height {
+            Some((pos.y * self.width + pos.x) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Light on a tile, tiles outside of the map only receive ambient light.
+    pub(crate) fn get(&self, pos: IVec2) -> [f32; 3] {
+        self.index(pos)
+            .map_or(self.ambient, |index| self.light[index])
+    }
+
+    /// Colour as it appears on the given tile.
+    pub(crate) fn modulate(&self, pos: IVec2, color: Color) -> Color {
+        color.scale_rgb(self.get(pos))
+    }
+}
+
+/// Brightness multiplier of a flickering light. The position offsets the phase so nearby
+/// lights do not flicker in sync.
+fn flicker(pos: IVec2, amount: f32, time: f32) -> f32 {
+    if amount <= 0.0 {
+        return 1.0;
+    }
+    let phase = (pos.x * 7 + pos.y * 13) as f32;
+    let noise = (time * 11.0 + phase).sin() * 0.6 + (time * 23.0 + phase * 1.7).sin() * 0.4;
+    1.0 - amount * (0.5 + 0.5 * noise)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::TileType;
+
+    const AMBIENT: [f32; 3] = [0.1, 0.1, 0.1];
+
+    fn source(pos: IVec2, falloff: Easing) -> LightSource {
+        LightSource {
+            pos,
+            light: Light {
+                color: Color::WHITE,
+                radius: 4,
+                intensity: 1.0,
+                falloff,
+                flicker: 0.0,
+            },
+        }
+    }
+
+    fn red(light_map: &LightMap, x: i32, y: i32) -> f32 {
+        light_map.get(IVec2::new(x, y))[0]
+    }
+
+    #[test]
+    fn light_falls_off_to_the_ambient_floor() {
+        let map = Map::new(12, 5, TileType::Floor);
+        let mut light_map = LightMap::new(12, 5, AMBIENT);
+        light_map.compute(&map, &[source(IVec2::new(2, 2), Easing::Linear)], 0.0);
+
+        assert!((red(&light_map, 2, 2) - 1.1).abs() < 1e-5);
+        assert!((red(&light_map, 4, 2) - 0.6).abs() < 1e-5);
+        let row: Vec<_> = (2..=6).map(|x| red(&light_map, x, 2)).collect();
+        assert!(row.windows(2).all(|pair| pair[0] > pair[1]), "{:?}", row);
+        // The edge of the radius and everything past it only has ambient light.
+        for x in 6..12 {
+            assert_eq!(light_map.get(IVec2::new(x, 2)), AMBIENT);
+        }
+        assert_eq!(light_map.get(IVec2::new(-1, 2)), AMBIENT);
+    }
+
+    #[test]
+    fn walls_cast_shadows() {
+        let mut map = Map::new(12, 5, TileType::Floor);
+        for y in 0..5 {
+            map.set(IVec2::new(4, y), TileType::Wall);
+        }
+        let mut light_map = LightMap::new(12, 5, AMBIENT);
+        light_map.compute(&map, &[source(IVec2::new(2, 2), Easing::Linear)], 0.0);
+
+        // The wall itself is lit, the floor behind it is not.
+        assert!(red(&light_map, 4, 2) > AMBIENT[0]);
+        assert_eq!(light_map.get(IVec2::new(5, 2)), AMBIENT);
+    }
+
+    #[test]
+    fn sources_add_up() {
+        let map = Map::new(12, 5, TileType::Floor);
+        let mut light_map = LightMap::new(12, 5, AMBIENT);
+        let sources = [
+            source(IVec2::new(2, 2), Easing::Linear),
+            source(IVec2::new(6, 2), Easing::Linear),
+        ];
+        light_map.compute(&map, &sources, 0.0);
+
+        // Halfway between the two, each adds half its brightness exactly once.
+        assert!((red(&light_map, 4, 2) - 1.1).abs() < 1e-5);
+        light_map.compute(&map, &sources[..1], 0.0);
+        assert!((red(&light_map, 4, 2) - 0.6).abs() < 1e-5);
+    }
+}
@@ -1,38 +1,121 @@
-use log::error;
-use std::time::Instant;
+use log::{error, warn};
+use std::path::PathBuf;
+use std::process;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use winit::dpi::LogicalSize;
 use winit::event::{Event, VirtualKeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::WindowBuilder;
 use winit_input_helper::WinitInputHelper;
 
+use crate::capture::FrameCapture;
+use crate::config::Config;
 use crate::game::*;
 use crate::gui::Framework;
+use crate::message_log::MessageCategory;
+use crate::palette::Palette;
+use crate::postfx::PostProcessor;
+use crate::raws::Raws;
 use crate::renderer::*;
+use crate::replay::{Replay, ReplayError, ReplayPlayer, ReplayRecorder};
 
+mod action;
+mod ai;
+mod animation;
+mod autotile;
+mod capture;
+mod color;
+mod combat;
+mod config;
+mod console;
+mod draw_queue;
+mod easing;
+mod entity;
+mod event;
+mod fov;
 mod game;
+mod geometry;
 mod gui;
+mod inventory;
+mod level;
+mod lighting;
+mod map;
+mod mapgen;
+mod markup;
+mod menu;
+mod message_log;
+mod palette;
+mod particles;
+mod pathfinding;
+mod postfx;
+mod prefab;
+mod raster;
+mod raws;
 mod renderer;
+mod replay;
+mod rng;
+mod save;
+mod scheduler;
+mod spatial;
 mod sprite;
+mod status;
+mod wfc;
 
-fn run_engine() {
-    env_logger::init();
+/// Seconds between actions when watching a replay.
+const REPLAY_STEP: f32 = 0.15;
+/// Messages printed at the end of a headless replay.
+const REPLAY_MESSAGES: usize = 3;
+
+/// Run the game in a window. With a replay, the recorded actions are played back first and
+/// the game can be played on from where the replay ends.
+fn run_engine(config: Config, palette: Palette, raws: Raws, replay: Option<Replay>) {
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
     let window = {
-        let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
+        let min_size = LogicalSize::new(config.width as f64, config.height as f64);
+        let size = LogicalSize::new(
+            (config.width * config.window_scale) as f64,
+            (config.height * config.window_scale) as f64,
+        );
         WindowBuilder::new()
             .with_title("Roguelike Engine")
             .with_inner_size(size)
-            .with_min_inner_size(size)
+            .with_min_inner_size(min_size)
             .build(&event_loop)
             .unwrap()
     };
 
-    let mut renderer = Renderer::new(&window);
-    let mut game = Game::new();
+    let mut renderer = Renderer::new(&window, config.width, config.height);
+    renderer.set_palette(palette);
+    // Replays always start a fresh game from their seed, and never touch the save file.
+    let is_replay = replay.is_some();
+    let mut game = match &replay {
+        Some(replay) => Game::new(config.width, config.height, replay.seed, raws),
+        None => load_game(&config, raws),
+    };
+    let mut replay_player = replay.map(ReplayPlayer::new);
+    let mut replay_timer = 0.0;
+
+    // Only games started from their seed can be replayed.
+    let mut recorder = if config.record_replays && !is_replay && game.turn() == 0 {
+        ReplayRecorder::create(&config.replay_dir, game.seed())
+            .map_err(|e| error!("Could not start recording a replay: {}", e))
+            .ok()
+    } else {
+        None
+    };
 
     let window_size = window.inner_size();
+    let mut post_processor = PostProcessor::new(
+        &renderer.pixels,
+        config.postfx,
+        renderer.palette(),
+        config.width,
+        config.height,
+        window_size.width,
+        window_size.height,
+    );
+
     let scale_factor = window.scale_factor() as f32;
     let mut framework = Framework::new(
         &event_loop,
@@ -42,6 +125,13 @@ fn run_engine() {
         &renderer.pixels,
     );
 
+    let mut capture = FrameCapture::new(
+        config.width,
+        config.height,
+        &config.capture_dir,
+        config.record_fps,
+    );
+
     let mut now = Instant::now();
     let mut dt = 0.0;
 
@@ -49,11 +139,43 @@ fn run_engine() {
         // Handle input events
         if input.update(&event) {
             // Close events
-            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+            if (input.key_pressed(VirtualKeyCode::Escape) && !game.is_menu_open()) || input.quit() {
+                // A dead player's run is over, with permadeath there is nothing to resume.
+                if !is_replay && !game.is_player_dead() {
+                    if let Err(e) = save::save(&config.save_path, &game.to_save()) {
+                        error!("Could not save to {}: {}", config.save_path.display(), e);
+                    }
+                }
+                if let Some(recorder) = recorder.take() {
+                    recorder.finish(&game);
+                }
+                capture.shutdown();
                 *control_flow = ControlFlow::Exit;
                 return;
             }
 
+            // Capture the last drawn frame
+            if input.key_pressed(VirtualKeyCode::F12) {
+                if let Some(path) = capture.screenshot(renderer.pixels.get_frame()) {
+                    game.message_log.push(
+                        &format!("Saved screenshot to {}.", path.display()),
+                        MessageCategory::Info,
+                    );
+                }
+            }
+            if input.key_pressed(VirtualKeyCode::F10) {
+                if capture.is_recording() {
+                    capture.stop_recording();
+                    game.message_log
+                        .push("Stopped recording.", MessageCategory::Info);
+                } else if let Some(path) = capture.start_recording(config.record_seconds as f32) {
+                    game.message_log.push(
+                        &format!("Recording to {}.", path.display()),
+                        MessageCategory::Info,
+                    );
+                }
+            }
+
             // Update the scale factor
             if let Some(scale_factor) = input.scale_factor() {
                 framework.scale_factor(scale_factor);
@@ -63,10 +185,43 @@ fn run_engine() {
             if let Some(size) = input.window_resized() {
                 renderer.pixels.resize_surface(size.width, size.height);
                 framework.resize(size.width, size.height);
+                post_processor.resize(&renderer.pixels, size.width, size.height);
+            }
+
+            // Map the cursor onto the frame buffer
+            let mouse = input.mouse().and_then(|pos| renderer.window_to_pixel(pos));
+
+            // Take actions from the replay while it lasts, then from the player
+            let actions = match &mut replay_player {
+                Some(player) => {
+                    replay_timer += dt;
+                    let mut actions = Vec::new();
+                    if replay_timer >= REPLAY_STEP {
+                        replay_timer = 0.0;
+                        actions.extend(player.next_action(&game));
+                    }
+                    if player.is_finished() && actions.is_empty() {
+                        match player.verify(&game) {
+                            Ok(()) => game
+                                .message_log
+                                .push("Replay finished.", MessageCategory::Info),
+                            Err(e) => game.message_log.push(
+                                &format!("Replay finished, but {}.", e),
+                                MessageCategory::Warning,
+                            ),
+                        }
+                        replay_player = None;
+                    }
+                    actions
+                }
+                None => game.read_input(&input, mouse),
+            };
+            if let Some(recorder) = &mut recorder {
+                recorder.record(game.turn(), &actions);
             }
 
             // Update internal state and request a redraw
-            game.update(&mut input, dt);
+            game.update(&actions, mouse, dt);
             window.request_redraw();
         }
 
@@ -83,20 +238,27 @@ fn run_engine() {
 
                 // Draw the world
                 game.draw(&mut renderer);
+                post_processor.apply_cpu(renderer.pixels.get_frame_mut(), game.screen_effects());
+                capture.capture_frame(renderer.pixels.get_frame(), dt);
 
                 // Prepare egui
-                framework.prepare(&window);
+                framework.prepare(&window, &game, renderer.palette());
 
                 // Render everything together
                 let render_result =
                     renderer
                         .pixels
                         .render_with(|encoder, render_target, context| {
-                            // Render the world texture
-                            context.scaling_renderer.render(encoder, render_target);
+                            // Render the world texture through the post-processing passes
+                            post_processor.render(
+                                encoder,
+                                render_target,
+                                context,
+                                game.screen_effects(),
+                            );
 
                             // Render egui
-                            //framework.render(encoder, render_target, context);
+                            framework.render(encoder, render_target, context);
 
                             Ok(())
                         });
@@ -114,7 +276,161 @@ fn run_engine() {
     });
 }
 
+/// Resume the saved game if there is one, or start a new game.
+fn load_game(config: &Config, raws: Raws) -> Game {
+    let path = &config.save_path;
+    if !path.exists() {
+        return Game::new(config.width, config.height, new_seed(config), raws);
+    }
+
+    let result = if config.permadeath {
+        save::load_and_delete(path)
+    } else {
+        save::load(path)
+    };
+    match result {
+        Ok(save) => {
+            let mut game = Game::from_save(config.width, config.height, save, raws);
+            game.message_log
+                .push("Welcome back!", MessageCategory::Info);
+            game
+        }
+        Err(e) => {
+            error!("Could not load {}: {}", path.display(), e);
+            let mut game = Game::new(config.width, config.height, new_seed(config), raws);
+            game.message_log.push(
+                &format!("Could not load the save file, {}.", e),
+                MessageCategory::Warning,
+            );
+            game
+        }
+    }
+}
+
+/// The configured palette, or an empty one if there is none.
+fn load_palette(config: &Config) -> Palette {
+    let Some(path) = &config.palette else {
+        return Palette::default();
+    };
+    Palette::load(path).unwrap_or_else(|e| {
+        warn!("{}: {}", path.display(), e);
+        Palette::default()
+    })
+}
+
+/// Seed for a new game: the configured one, or one from the clock.
+fn new_seed(config: &Config) -> u64 {
+    config.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64)
+    })
+}
+
+/// Play a replay as fast as possible without a window. Returns an error if the game did
+/// not end up where the recording did.
+fn run_headless(config: &Config, raws: Raws, replay: Replay) -> Result<(), ReplayError> {
+    let mut game = Game::new(config.width, config.height, replay.seed, raws);
+    let mut player = ReplayPlayer::new(replay);
+
+    // Every action gets its own update, like in the windowed replay.
+    while let Some(action) = player.next_action(&game) {
+        game.update(&[action], None, REPLAY_STEP);
+    }
+    player.verify(&game)?;
+
+    println!(
+        "Replayed to turn {}, state {:016x}",
+        game.turn(),
+        replay::state_checksum(&game)?
+    );
+    println!("Depth {}", game.depth());
+    if let Some(player) = game.entities().get(game.player()) {
+        let pos = player.pos();
+        print!("Player at {}, {}", pos.x, pos.y);
+        match &player.stats {
+            Some(stats) => println!(" with {}/{} HP", stats.hp, stats.max_hp),
+            None => println!(),
+        }
+    }
+    // The last few messages, as they would read in the log.
+    let messages = game.message_log.messages();
+    for message in &messages[messages.len().saturating_sub(REPLAY_MESSAGES)..] {
+        println!("Message: {}", markup::strip(&message.display_text()));
+    }
+    Ok(())
+}
+
+/// Command line options: `--replay <file>` plays back a replay, and `--headless` does so
+/// without a window.
+struct Args {
+    replay: Option<PathBuf>,
+    headless: bool,
+}
+
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut args = Args {
+            replay: None,
+            headless: false,
+        };
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--replay" => {
+                    let path = iter.next().ok_or("--replay needs a file")?;
+                    args.replay = Some(PathBuf::from(path));
+                }
+                "--headless" => args.headless = true,
+                _ => return Err(format!("unknown argument `{}`", arg)),
+            }
+        }
+        if args.headless && args.replay.is_none() {
+            return Err("--headless needs --replay <file>".to_string());
+        }
+        Ok(args)
+    }
+}
+
 // Todo: Initialization and shutdown procedures.
 fn main() {
-    run_engine();
+    env_logger::init();
+    let config = Config::load("engine.cfg");
+
+    let args = Args::parse().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        eprintln!("usage: roguelike-engine [--replay <file> [--headless]]");
+        process::exit(2);
+    });
+    let replay = args.replay.map(|path| {
+        let replay = Replay::load(&path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path.display(), e);
+            process::exit(2);
+        });
+        if replay.engine_version != replay::ENGINE_VERSION {
+            warn!(
+                "{} was recorded with version {}, this is version {}",
+                path.display(),
+                replay.engine_version,
+                replay::ENGINE_VERSION
+            );
+        }
+        replay
+    });
+
+    let palette = load_palette(&config);
+    let raws = Raws::load(&config.raws_dir, &palette).unwrap_or_else(|e| {
+        eprintln!("{}: {}", config.raws_dir.display(), e);
+        process::exit(2);
+    });
+
+    match replay {
+        Some(replay) if args.headless => {
+            if let Err(e) = run_headless(&config, raws, replay) {
+                eprintln!("Replay failed: {}", e);
+                process::exit(1);
+            }
+        }
+        replay => run_engine(config, palette, raws, replay),
+    }
 }
@@ -0,0 +1,87 @@
+use glam::IVec2;
+use serde::{Deserialize, Serialize};
+
+/// Kinds of map tile. Their appearance is defined in the raws.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub(crate) enum TileType {
+    Floor,
+    Wall,
+    /// Leads to the next level down.
+    StairsDown,
+    /// Leads back to the level above.
+    StairsUp,
+}
+
+impl TileType {
+    pub(crate) const ALL: [TileType; 4] = [
+        TileType::Floor,
+        TileType::Wall,
+        TileType::StairsDown,
+        TileType::StairsUp,
+    ];
+
+    /// Name used in messages, e.g. "You see a wall."
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            TileType::Floor => "floor",
+            TileType::Wall => "wall",
+            TileType::StairsDown => "staircase down",
+            TileType::StairsUp => "staircase up",
+        }
+    }
+
+    pub(crate) fn is_blocking(&self) -> bool {
+        matches!(self, TileType::Wall)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Map {
+    pub width: i32,
+    pub height: i32,
+    tiles: Vec<TileType>,
+}
+
+impl Map {
+    pub(crate) fn new(width: i32, height: i32, fill: TileType) -> Self {
+        Self {
+            width,
+            height,
+            tiles: vec![fill; (width * height) as usize],
+        }
+    }
+
+    pub(crate) fn in_bounds(&self, pos: IVec2) -> bool {
+        pos.x >= 0 && pos.y >= 0 && pos.x < self.width && pos.y < self.height
+    }
+
+    fn index(&self, pos: IVec2) -> usize {
+        (pos.y * self.width + pos.x) as usize
+    }
+
+    /// Tile at `pos`. Anything outside of the map counts as a wall.
+    pub(crate) fn get(&self, pos: IVec2) -> TileType {
+        if self.in_bounds(pos) {
+            self.tiles[self.index(pos)]
+        } else {
+            TileType::Wall
+        }
+    }
+
+    pub(crate) fn set(&mut self, pos: IVec2, tile: TileType) {
+        if self.in_bounds(pos) {
+            let index = self.index(pos);
+            self.tiles[index] = tile;
+        }
+    }
+
+    pub(crate) fn is_blocked(&self, pos: IVec2) -> bool {
+        self.get(pos).is_blocking()
+    }
+
+    /// First tile of the given type, row by row.
+    pub(crate) fn find(&self, tile: TileType) -> Option<IVec2> {
+        let index = self.tiles.iter().position(|&t| t == tile)? as i32;
+        Some(IVec2::new(index % self.width, index / self.width))
+    }
+}
@@ -0,0 +1,385 @@
+use crate::map::{Map, TileType};
+use crate::pathfinding::DIRECTIONS;
+use crate::prefab::{Cell, Prefab};
+use crate::rng::Rng;
+use crate::wfc::{Model, Wfc};
+use glam::IVec2;
+use log::debug;
+use std::collections::VecDeque;
+
+/// Deepest level the generation parameters keep growing to.
+const MAX_SCALED_DEPTH: i32 = 10;
+/// Width and height of the areas a level grown from a sample is split into, which stand in
+/// for its rooms.
+const SECTOR_SIZE: i32 = 8;
+/// Smallest share of a level grown from a sample that must be connected floor, in percent.
+const MIN_FLOOR_PERCENT: usize = 25;
+
+/// How a level is generated. Deeper levels are larger, with more rooms and more in them.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LevelParams {
+    pub width: i32,
+    pub height: i32,
+    /// Rooms tried. Rooms that would overlap others are skipped.
+    pub room_attempts: u32,
+    /// Smallest and largest width and height of a room, walls excluded.
+    pub room_size: (i32, i32),
+    /// Most monsters in a room. The room the player arrives in is left empty.
+    pub monsters_per_room: i32,
+    /// Items lying around the level.
+    pub items: i32,
+}
+
+impl LevelParams {
+    pub(crate) fn for_depth(depth: u32) -> Self {
+        let depth = (depth as i32).clamp(1, MAX_SCALED_DEPTH);
+        Self {
+            width: 40 + 4 * depth,
+            height: 30 + 2 * depth,
+            room_attempts: 20 + 4 * depth as u32,
+            room_size: (4, 7 + depth / 3),
+            monsters_per_room: 1 + depth / 3,
+            items: 2 + depth / 2,
+        }
+    }
+}
+
+/// A rectangular room, from `min` to `max` inclusive.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct Room {
+    pub min: IVec2,
+    pub max: IVec2,
+}
+
+impl Room {
+    pub(crate) fn center(&self) -> IVec2 {
+        (self.min + self.max) / 2
+    }
+
+    /// Whether the rooms overlap or touch, so there would be no wall between them.
+    fn touches(&self, other: &Room) -> bool {
+        self.min.cmple(other.max + 1).all() && self.max.cmpge(other.min - 1).all()
+    }
+
+    pub(crate) fn tiles(&self) -> impl Iterator<Item = IVec2> {
+        let (min, max) = (self.min, self.max);
+        (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+    }
+}
+
+/// A generated map and the rooms carved into it. The first room is where the player
+/// arrives from above, the last one holds the stairs down.
+pub(crate) struct Layout {
+    pub map: Map,
+    pub rooms: Vec<Room>,
+    /// Where the stairs up go.
+    pub up: IVec2,
+    /// Where the stairs down go.
+    pub down: IVec2,
+}
+
+/// A sample image that levels are grown from with wave function collapse.
+pub(crate) struct LevelSample {
+    pub name: String,
+    /// Dungeon levels grown from the sample, inclusive.
+    pub depth: Option<(u32, u32)>,
+    /// Chance of a level of its depth being grown from the sample.
+    pub chance: f32,
+    pub model: Model,
+    /// Tile of each colour of the model.
+    pub tiles: Vec<TileType>,
+}
+
+/// Carve rooms into solid rock and join each one to the previous with an L-shaped
+/// corridor, which keeps every room reachable.
+pub(crate) fn rooms_and_corridors(params: &LevelParams, rng: &mut Rng) -> Layout {
+    let mut map = Map::new(params.width, params.height, TileType::Wall);
+    let mut rooms: Vec<Room> = Vec::new();
+    let (min_size, max_size) = params.room_size;
+
+    for _ in 0..params.room_attempts {
+        let size = IVec2::new(rng.range(min_size, max_size), rng.range(min_size, max_size));
+        let min = IVec2::new(
+            rng.range(1, params.width - size.x - 1),
+            rng.range(1, params.height - size.y - 1),
+        );
+        let room = Room {
+            min,
+            max: min + size - 1,
+        };
+        if rooms.iter().any(|other| other.touches(&room)) {
+            continue;
+        }
+        for pos in room.tiles() {
+            map.set(pos, TileType::Floor);
+        }
+        if let Some(previous) = rooms.last() {
+            let (from, to) = (previous.center(), room.center());
+            if rng.chance(0.5) {
+                carve_horizontal(&mut map, from.x, to.x, from.y);
+                carve_vertical(&mut map, from.y, to.y, to.x);
+            } else {
+                carve_vertical(&mut map, from.y, to.y, from.x);
+                carve_horizontal(&mut map, from.x, to.x, to.y);
+            }
+        }
+        rooms.push(room);
+    }
+
+    // The placement can fail on tiny maps. One room in the middle keeps the level usable.
+    if rooms.is_empty() {
+        let center = IVec2::new(params.width, params.height) / 2;
+        let room = Room {
+            min: center - 1,
+            max: center + 1,
+        };
+        for pos in room.tiles() {
+            map.set(pos, TileType::Floor);
+        }
+        rooms.push(room);
+    }
+    let (first, last) = (rooms[0], rooms[rooms.len() - 1]);
+    Layout {
+        map,
+        rooms,
+        up: first.center(),
+        // With a single room, both stairs end up next to each other.
+        down: if first == last {
+            last.center() + IVec2::X
+        } else {
+            last.center()
+        },
+    }
+}
+
+/// Grow a level from `sample` with wave function collapse, and keep its largest connected
+/// area of floor. The stairs up go on a random tile of it and the stairs down on the tile
+/// furthest from them. Returns `None` if the collapse fails or leaves too little floor.
+pub(crate) fn wave_function_collapse(
+    params: &LevelParams,
+    sample: &LevelSample,
+    rng: &mut Rng,
+) -> Option<Layout> {
+    let (width, height) = (params.width, params.height);
+    let mut wfc = Wfc::new(sample.model.clone(), width, height, rng.next_u64());
+    let Some(cells) = wfc.run() else {
+        debug!(
+            "the {} collapse failed after {} backtracks",
+            sample.name,
+            wfc.backtracks()
+        );
+        return None;
+    };
+    let mut grown = Map::new(width, height, TileType::Wall);
+    for (index, &color) in cells.iter().enumerate() {
+        let pos = IVec2::new(index as i32 % width, index as i32 / width);
+        let inner = pos.cmpgt(IVec2::ZERO).all() && pos.x < width - 1 && pos.y < height - 1;
+        if inner {
+            grown.set(pos, sample.tiles[color]);
+        }
+    }
+
+    let mut region: Vec<IVec2> = Vec::new();
+    let mut seen = vec![false; (width * height) as usize];
+    for pos in (0..height).flat_map(|y| (0..width).map(move |x| IVec2::new(x, y))) {
+        if grown.is_blocked(pos) || seen[(pos.y * width + pos.x) as usize] {
+            continue;
+        }
+        let tiles: Vec<_> = distances(&grown, pos)
+            .iter()
+            .enumerate()
+            .filter(|(_, distance)| distance.is_some())
+            .map(|(index, _)| IVec2::new(index as i32 % width, index as i32 / width))
+            .collect();
+        for tile in &tiles {
+            seen[(tile.y * width + tile.x) as usize] = true;
+        }
+        if tiles.len() > region.len() {
+            region = tiles;
+        }
+    }
+    if region.len() * 100 < (width * height) as usize * MIN_FLOOR_PERCENT {
+        debug!("the {} collapse left too little floor", sample.name);
+        return None;
+    }
+
+    let mut map = Map::new(width, height, TileType::Wall);
+    for &pos in &region {
+        map.set(pos, TileType::Floor);
+    }
+    let up = *rng.pick(&region)?;
+    let from_up = distances(&map, up);
+    let down = region
+        .iter()
+        .copied()
+        .max_by_key(|pos| from_up[(pos.y * width + pos.x) as usize])?;
+
+    let mut rooms: Vec<Room> = (0..height)
+        .step_by(SECTOR_SIZE as usize)
+        .flat_map(|y| {
+            (0..width)
+                .step_by(SECTOR_SIZE as usize)
+                .map(move |x| IVec2::new(x, y))
+        })
+        .map(|min| Room {
+            min,
+            max: (min + SECTOR_SIZE - 1).min(IVec2::new(width - 1, height - 1)),
+        })
+        .filter(|room| room.tiles().any(|pos| !map.is_blocked(pos)))
+        .collect();
+    let contains = |room: &Room, pos: IVec2| pos.cmpge(room.min).all() && pos.cmple(room.max).all();
+    if let Some(first) = rooms.iter().position(|room| contains(room, up)) {
+        rooms.swap(0, first);
+    }
+    if let Some(last) = rooms.iter().position(|room| contains(room, down)) {
+        if last != 0 {
+            let end = rooms.len() - 1;
+            rooms.swap(last, end);
+        }
+    }
+    Some(Layout {
+        map,
+        rooms,
+        up,
+        down,
+    })
+}
+
+fn carve_horizontal(map: &mut Map, x1: i32, x2: i32, y: i32) {
+    for x in x1.min(x2)..=x1.max(x2) {
+        map.set(IVec2::new(x, y), TileType::Floor);
+    }
+}
+
+fn carve_vertical(map: &mut Map, y1: i32, y2: i32, x: i32) {
+    for y in y1.min(y2)..=y1.max(y2) {
+        map.set(IVec2::new(x, y), TileType::Floor);
+    }
+}
+
+/// Stamp `prefab` into solid rock somewhere on `map`, and dig a corridor from each of its
+/// entrances to the closest floor. Tries `attempts` random spots, and rejects those where
+/// the prefab or the stairs down would not be reachable from the stairs up afterwards.
+/// Returns where the top left corner of the prefab went.
+pub(crate) fn place_prefab(
+    map: &mut Map,
+    prefab: &Prefab,
+    attempts: u32,
+    rng: &mut Rng,
+) -> Option<IVec2> {
+    for _ in 0..attempts {
+        let origin = IVec2::new(
+            rng.range(1, map.width - prefab.size.x - 1),
+            rng.range(1, map.height - prefab.size.y - 1),
+        );
+        if !fits(map, prefab, origin) {
+            continue;
+        }
+        let mut stamped = map.clone();
+        for pos in prefab.positions() {
+            match prefab.get(pos) {
+                Cell::Outside => {}
+                Cell::Wall => stamped.set(origin + pos, TileType::Wall),
+                _ => stamped.set(origin + pos, TileType::Floor),
+            }
+        }
+        let dug = prefab
+            .entrances()
+            .all(|entrance| dig_to_floor(&mut stamped, prefab, origin, origin + entrance));
+        if dug && is_connected(&stamped, prefab, origin) {
+            *map = stamped;
+            return Some(origin);
+        }
+    }
+    None
+}
+
+/// Whether every cell of the prefab and the tiles around them are solid rock, away from the
+/// edge of the map.
+fn fits(map: &Map, prefab: &Prefab, origin: IVec2) -> bool {
+    let inner = |pos: IVec2| {
+        pos.cmpgt(IVec2::ZERO).all() && pos.x < map.width - 1 && pos.y < map.height - 1
+    };
+    prefab
+        .positions()
+        .filter(|&pos| prefab.get(pos) != &Cell::Outside)
+        .all(|pos| {
+            let pos = origin + pos;
+            (-1..=1).all(|y| {
+                (-1..=1).all(|x| {
+                    let tile = pos + IVec2::new(x, y);
+                    inner(tile) && map.get(tile) == TileType::Wall
+                })
+            })
+        })
+}
+
+/// Carve the shortest corridor from `entrance` to floor outside of the prefab. Returns
+/// false if there is none.
+fn dig_to_floor(map: &mut Map, prefab: &Prefab, origin: IVec2, entrance: IVec2) -> bool {
+    let (width, height) = (map.width, map.height);
+    let diggable = |pos: IVec2| {
+        pos.cmpgt(IVec2::ZERO).all()
+            && pos.x < width - 1
+            && pos.y < height - 1
+            && prefab.get(pos - origin) == &Cell::Outside
+    };
+    let mut came_from = vec![None; (width * height) as usize];
+    let index = |pos: IVec2| (pos.y * width + pos.x) as usize;
+    let mut open = VecDeque::from([entrance]);
+    while let Some(pos) = open.pop_front() {
+        for direction in DIRECTIONS {
+            let next = pos + direction;
+            if !diggable(next) || came_from[index(next)].is_some() {
+                continue;
+            }
+            came_from[index(next)] = Some(pos);
+            if !map.is_blocked(next) {
+                let mut tile = pos;
+                while tile != entrance {
+                    map.set(tile, TileType::Floor);
+                    tile = came_from[index(tile)].unwrap_or(entrance);
+                }
+                return true;
+            }
+            open.push_back(next);
+        }
+    }
+    false
+}
+
+/// Whether the stairs down and all of the prefab's floor can be walked to from the stairs
+/// up.
+fn is_connected(map: &Map, prefab: &Prefab, origin: IVec2) -> bool {
+    let Some(start) = map.find(TileType::StairsUp) else {
+        return false;
+    };
+    let index = |pos: IVec2| (pos.y * map.width + pos.x) as usize;
+    let reached: Vec<_> = distances(map, start).iter().map(Option::is_some).collect();
+    let stairs_reached = map
+        .find(TileType::StairsDown)
+        .is_some_and(|stairs| reached[index(stairs)]);
+    stairs_reached
+        && prefab
+            .positions()
+            .filter(|&pos| prefab.get(pos).is_walkable())
+            .all(|pos| reached[index(origin + pos)])
+}
+
+/// Steps from `start` to every tile, row by row. `None` for tiles that cannot be walked to.
+fn distances(map: &Map, start: IVec2) -> Vec<Option<u32>> {
+    let index = |pos: IVec2| (pos.y * map.width + pos.x) as usize;
+    let mut distances = vec![None; (map.width * map.height) as usize];
+    distances[index(start)] = Some(0);
+    let mut open = VecDeque::from([(start, 0)]);
+    while let Some((pos, distance)) = open.pop_front() {
+        for direction in DIRECTIONS {
+            let next = pos + direction;
+            if map.in_bounds(next) && !map.is_blocked(next) && distances[index(next)].is_none() {
+                distances[index(next)] = Some(distance + 1);
+                open.push_back((next, distance + 1));
+            }
+        }
+    }
+    distances
+}
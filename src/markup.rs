@@ -0,0 +1,137 @@
+use crate::color::Color;
+use crate::palette::Palette;
+
+/// A run of text drawn in a single colour.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct Span {
+    pub text: String,
+    pub color: Color,
+}
+
+/// Split text with colour markup into spans.
+///
+/// `[name]` switches to a palette colour, a named constant or a hex code such as `[#ff8000]`,
+/// and `[/]` goes back to the previous colour. `[[` is a literal `[`, and anything in brackets
+/// that is not a colour is kept as text. Tag colours are faded by the alpha of `base`.
+pub(crate) fn parse(text: &str, base: Color, palette: &Palette) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    let mut stack = vec![base];
+    let mut rest = text;
+
+    while let Some(open) = rest.find('[') {
+        let color = *stack.last().unwrap();
+        push_text(&mut spans, &rest[..open], color);
+        rest = &rest[open..];
+
+        if let Some(after) = rest.strip_prefix("[[") {
+            push_text(&mut spans, "[", color);
+            rest = after;
+            continue;
+        }
+
+        let tag = rest[1..].find(']').map(|close| &rest[1..close + 1]);
+        match tag {
+            Some("/") => {
+                if stack.len() > 1 {
+                    stack.pop();
+                }
+                rest = &rest[3..];
+            }
+            Some(tag) => match palette.resolve(tag) {
+                Some(tag_color) => {
+                    stack.push(tag_color.fade(base.a as f32 / 255.0));
+                    rest = &rest[tag.len() + 2..];
+                }
+                None => {
+                    push_text(&mut spans, "[", color);
+                    rest = &rest[1..];
+                }
+            },
+            None => break,
+        }
+    }
+    push_text(&mut spans, rest, *stack.last().unwrap());
+
+    spans
+}
+
+/// Append text to the last span, or start a new one when the colour changed.
+fn push_text(spans: &mut Vec<Span>, text: &str, color: Color) {
+    match spans.last_mut() {
+        Some(last) if last.color == color => last.text.push_str(text),
+        _ if text.is_empty() => {}
+        _ => spans.push(Span {
+            text: text.to_string(),
+            color,
+        }),
+    }
+}
+
+/// Text with the markup removed, e.g. for places that draw it in a single colour.
+pub(crate) fn strip(text: &str) -> String {
+    parse(text, Color::WHITE, &Palette::default())
+        .into_iter()
+        .map(|span| span.text)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str, color: Color) -> Span {
+        Span {
+            text: text.to_string(),
+            color,
+        }
+    }
+
+    fn parse(text: &str) -> Vec<Span> {
+        let mut palette = Palette::default();
+        palette.push(Color::rgb(0x12, 0x34, 0x56), Some("sea"));
+        super::parse(text, Color::WHITE, &palette)
+    }
+
+    #[test]
+    fn nesting() {
+        assert_eq!(
+            parse("a[red]b[sea]c[/]d[/]e"),
+            [
+                span("a", Color::WHITE),
+                span("b", Color::RED),
+                span("c", Color::rgb(0x12, 0x34, 0x56)),
+                span("d", Color::RED),
+                span("e", Color::WHITE),
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_tags() {
+        assert_eq!(parse("[red]abc"), [span("abc", Color::RED)]);
+        assert_eq!(parse("a[/]b[/]"), [span("ab", Color::WHITE)]);
+        assert_eq!(parse("a[red"), [span("a[red", Color::WHITE)]);
+    }
+
+    #[test]
+    fn literal_brackets() {
+        assert_eq!(parse("[[red]"), [span("[red]", Color::WHITE)]);
+        assert_eq!(parse("[nope] [1]"), [span("[nope] [1]", Color::WHITE)]);
+        assert_eq!(parse(""), []);
+    }
+
+    #[test]
+    fn tags_fade_with_base() {
+        let spans = super::parse(
+            "[#ff8000]x",
+            Color::WHITE.with_alpha(0x80),
+            &Palette::default(),
+        );
+        assert_eq!(spans, [span("x", Color::rgba(0xff, 0x80, 0x00, 0x80))]);
+    }
+
+    #[test]
+    fn strip() {
+        assert_eq!(super::strip("a [red]b[/] [[c] [#fff]d"), "a b [c] d");
+    }
+}
@@ -0,0 +1,209 @@
+use crate::color::Color;
+use crate::draw_queue::{DrawCommand, DrawQueue, Layer};
+use crate::entity::{Entity, Slot};
+use crate::raws::Raws;
+use crate::renderer::DrawParams;
+use crate::sprite::Sprite;
+use glam::{IVec2, Vec2};
+use std::collections::HashMap;
+
+const TEXT_SIZE: f32 = 8.0;
+const ROW_HEIGHT: f32 = 10.0;
+/// Rows of items shown at once. Longer lists scroll with the cursor.
+const VISIBLE_ROWS: usize = 12;
+const PANEL_POS: Vec2 = Vec2::new(16.0, 12.0);
+const PANEL_WIDTH: f32 = 288.0;
+
+const BACKGROUND: Color = Color::rgba(0x10, 0x10, 0x18, 0xe0);
+const BORDER: Color = Color::rgb(0x80, 0x80, 0x90);
+const HIGHLIGHT: Color = Color::rgba(0xff, 0xe0, 0x40, 0x40);
+const DIM: Color = Color::rgb(0x80, 0x80, 0x80);
+
+/// Screen or mode the player is in, on top of the map. Menus are not part of the saved
+/// game.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Menu {
+    /// Carried items, with the selected row.
+    Inventory { cursor: usize },
+    /// Equipment slots, with the selected slot.
+    Equipment { cursor: usize },
+    /// Picking the tile to throw an inventory item at.
+    Throw { item: usize, target: IVec2 },
+}
+
+impl Menu {
+    /// `cursor` moved by `step` rows, kept within `len` rows.
+    pub(crate) fn step_cursor(cursor: usize, step: i32, len: usize) -> usize {
+        (cursor as i32 + step).clamp(0, len.saturating_sub(1) as i32) as usize
+    }
+}
+
+/// Draws the inventory or equipment screen of `entity` on the UI layer.
+pub(crate) struct MenuView<'a> {
+    pub entity: &'a Entity,
+    pub raws: &'a Raws,
+    pub sprites: &'a HashMap<String, Sprite>,
+    /// Seconds since the start, for animated sprites.
+    pub time: f32,
+}
+
+impl<'a> MenuView<'a> {
+    pub(crate) fn draw(&self, queue: &mut DrawQueue<'a>, menu: Menu) {
+        match menu {
+            Menu::Inventory { cursor } => self.draw_inventory(queue, cursor),
+            Menu::Equipment { cursor } => self.draw_equipment(queue, cursor),
+            Menu::Throw { .. } => {
+                let pos = Vec2::new(8.0, 4.0);
+                text(
+                    queue,
+                    pos,
+                    "Throw where?  arrows: aim  enter: throw  esc: cancel",
+                    Color::WHITE,
+                );
+            }
+        }
+    }
+
+    fn draw_inventory(&self, queue: &mut DrawQueue<'a>, cursor: usize) {
+        let Some(inventory) = &self.entity.inventory else {
+            return;
+        };
+        let title = format!(
+            "Inventory  {}/{} weight",
+            inventory.weight(self.raws),
+            inventory.capacity
+        );
+        let first = cursor.saturating_sub(VISIBLE_ROWS - 1);
+        let rows = inventory.len().clamp(1, VISIBLE_ROWS);
+        let footer = "u: use  e: equip  d: drop  t: throw  esc: close";
+        // One more row below the items for the description of the selected one.
+        let mut pos = panel(queue, &title, rows + 1, footer);
+        let description_pos = pos + Vec2::new(12.0, ROW_HEIGHT * rows as f32 + 1.0);
+        if let Some(def) = inventory
+            .get(cursor)
+            .and_then(|stack| self.raws.item(&stack.kind))
+        {
+            text(queue, description_pos, &def.description, DIM);
+        }
+
+        if inventory.is_empty() {
+            text(queue, pos + Vec2::new(12.0, 1.0), "You carry nothing.", DIM);
+        }
+        for (index, stack) in inventory.items().iter().enumerate().skip(first).take(rows) {
+            let Some(def) = self.raws.item(&stack.kind) else {
+                continue;
+            };
+            if index == cursor {
+                highlight(queue, pos);
+            }
+            self.draw_icon(queue, pos, def.sprite.as_deref(), def.glyph, &def.color);
+            let weight = format!("{:>3}", def.weight * stack.count);
+            text(
+                queue,
+                pos + Vec2::new(12.0, 1.0),
+                &def.describe(stack.count),
+                Color::WHITE,
+            );
+            text(
+                queue,
+                pos + Vec2::new(PANEL_WIDTH - 28.0, 1.0),
+                &weight,
+                DIM,
+            );
+            pos.y += ROW_HEIGHT;
+        }
+    }
+
+    fn draw_equipment(&self, queue: &mut DrawQueue<'a>, cursor: usize) {
+        let footer = "enter: take off  esc: close";
+        let mut pos = panel(queue, "Equipment", Slot::COUNT, footer);
+
+        for (index, slot) in Slot::ALL.into_iter().enumerate() {
+            if index == cursor {
+                highlight(queue, pos);
+            }
+            text(queue, pos + Vec2::new(12.0, 1.0), slot.name(), DIM);
+            match self
+                .entity
+                .equipment
+                .get(slot)
+                .and_then(|name| self.raws.item(name))
+            {
+                Some(def) => {
+                    self.draw_icon(
+                        queue,
+                        pos + Vec2::new(72.0, 0.0),
+                        def.sprite.as_deref(),
+                        def.glyph,
+                        &def.color,
+                    );
+                    text(queue, pos + Vec2::new(84.0, 1.0), &def.name, Color::WHITE);
+                }
+                None => text(queue, pos + Vec2::new(84.0, 1.0), "-", DIM),
+            }
+            pos.y += ROW_HEIGHT;
+        }
+    }
+
+    /// Item sprite at half size, or its glyph for items without one.
+    fn draw_icon(
+        &self,
+        queue: &mut DrawQueue<'a>,
+        pos: Vec2,
+        sprite: Option<&str>,
+        glyph: char,
+        color: &str,
+    ) {
+        match sprite.and_then(|name| self.sprites.get(name)) {
+            Some(sprite) => queue.sprite(
+                Layer::Ui,
+                1,
+                pos + Vec2::new(1.0, 1.0),
+                sprite,
+                sprite.frame_at(self.time),
+                DrawParams {
+                    scale: 0.5,
+                    ..Default::default()
+                },
+            ),
+            None => text(
+                queue,
+                pos + Vec2::new(2.0, 1.0),
+                &glyph.to_string(),
+                self.raws.color(color),
+            ),
+        }
+    }
+}
+
+/// Background, title and footer of a panel with `rows` rows. Returns the position of the
+/// first row.
+fn panel(queue: &mut DrawQueue, title: &str, rows: usize, footer: &str) -> Vec2 {
+    let size = Vec2::new(PANEL_WIDTH, ROW_HEIGHT * (rows as f32 + 3.0) + 4.0);
+    queue.rect(Layer::Ui, -1, PANEL_POS, size, BACKGROUND);
+    queue.rect_outline(Layer::Ui, -1, PANEL_POS, size, BORDER);
+    text(queue, PANEL_POS + Vec2::new(4.0, 3.0), title, Color::WHITE);
+    let footer_pos = PANEL_POS + Vec2::new(4.0, size.y - ROW_HEIGHT);
+    text(queue, footer_pos, footer, DIM);
+    PANEL_POS + Vec2::new(2.0, ROW_HEIGHT * 1.5 + 2.0)
+}
+
+fn highlight(queue: &mut DrawQueue, pos: Vec2) {
+    let size = Vec2::new(PANEL_WIDTH - 4.0, ROW_HEIGHT);
+    queue.rect(Layer::Ui, 0, pos, size, HIGHLIGHT);
+}
+
+/// Text above the panel background and highlight.
+fn text(queue: &mut DrawQueue, pos: Vec2, text: &str, color: Color) {
+    queue.push(
+        Layer::Ui,
+        1,
+        DrawCommand::Text {
+            pos,
+            text: text.to_string(),
+            size: TEXT_SIZE,
+            spacing: TEXT_SIZE * 0.75,
+            color,
+        },
+    );
+}
@@ -0,0 +1,258 @@
+use crate::color::Color;
+use crate::draw_queue::{DrawQueue, Layer};
+use crate::easing::ease_in_quad;
+use crate::event::GameEvent;
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// Seconds a message stays fully visible in the in-game log before it starts to fade.
+const FADE_DELAY: f32 = 4.0;
+/// Seconds it takes for a message to fade out completely.
+const FADE_DURATION: f32 = 2.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub(crate) enum MessageCategory {
+    General,
+    Combat,
+    Info,
+    Warning,
+}
+
+impl MessageCategory {
+    pub(crate) fn color(&self) -> Color {
+        match self {
+            MessageCategory::General => Color::WHITE,
+            MessageCategory::Combat => Color::rgb(0xe0, 0x40, 0x40),
+            MessageCategory::Info => Color::rgb(0x60, 0xa0, 0xff),
+            MessageCategory::Warning => Color::rgb(0xff, 0xc0, 0x30),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Message {
+    pub text: String,
+    pub color: Color,
+    pub category: MessageCategory,
+    /// How many times this message was repeated in a row.
+    pub count: u32,
+    /// Seconds since the message was last pushed.
+    #[serde(skip)]
+    pub age: f32,
+}
+
+impl Message {
+    /// Text as it should be displayed, including the repeat counter.
+    pub(crate) fn display_text(&self) -> String {
+        if self.count > 1 {
+            format!("{} x{}", self.text, self.count)
+        } else {
+            self.text.clone()
+        }
+    }
+
+    /// Opacity of the message in the in-game log, from 1.0 (fresh) to 0.0 (faded out).
+    fn opacity(&self) -> f32 {
+        let t = ((self.age - FADE_DELAY) / FADE_DURATION).clamp(0.0, 1.0);
+        1.0 - ease_in_quad(t)
+    }
+}
+
+/// Scrollable history of messages shown to the player.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct MessageLog {
+    messages: Vec<Message>,
+    capacity: usize,
+    /// Number of messages scrolled back from the newest one.
+    #[serde(skip)]
+    scroll: usize,
+}
+
+impl MessageLog {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            messages: Vec::new(),
+            capacity,
+            scroll: 0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, text: &str, category: MessageCategory) {
+        self.push_colored(text, category.color(), category);
+    }
+
+    /// Push a message, stacking it onto the previous one when it is a repeat.
+    pub(crate) fn push_colored(&mut self, text: &str, color: Color, category: MessageCategory) {
+        if let Some(last) = self.messages.last_mut() {
+            if last.text == text && last.category == category && last.color == color {
+                last.count += 1;
+                last.age = 0.0;
+                return;
+            }
+        }
+
+        self.messages.push(Message {
+            text: text.to_string(),
+            color,
+            category,
+            count: 1,
+            age: 0.0,
+        });
+
+        if self.messages.len() > self.capacity {
+            let overflow = self.messages.len() - self.capacity;
+            self.messages.drain(..overflow);
+        }
+    }
+
+    pub(crate) fn handle_event(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::Message { text, category } => self.push(text, *category),
+            GameEvent::Waited => self.push("You wait.", MessageCategory::General),
+            GameEvent::Bumped { name, .. } => self.push(
+                &format!("You bump into the {}.", name),
+                MessageCategory::General,
+            ),
+            GameEvent::Attacked {
+                attacker,
+                target,
+                damage,
+                damage_type,
+                critical,
+                ranged,
+            } => {
+                let verb = attacker.verb(if *ranged { "shoot" } else { "hit" });
+                let text = if *critical {
+                    format!(
+                        "{} critically {} {} for {} {} damage!",
+                        attacker.subject(),
+                        verb,
+                        target.object(),
+                        damage,
+                        damage_type
+                    )
+                } else {
+                    format!(
+                        "{} {} {} for {} {} damage.",
+                        attacker.subject(),
+                        verb,
+                        target.object(),
+                        damage,
+                        damage_type
+                    )
+                };
+                self.push(&text, MessageCategory::Combat);
+            }
+            GameEvent::Missed {
+                attacker, target, ..
+            } => self.push(
+                &format!(
+                    "{} {} {}.",
+                    attacker.subject(),
+                    attacker.verb("miss"),
+                    target.object()
+                ),
+                MessageCategory::Combat,
+            ),
+            GameEvent::Shot { .. } => {}
+            GameEvent::Died { victim } if victim.is_player => {
+                self.push("You die...", MessageCategory::Warning)
+            }
+            GameEvent::Died { victim } => self.push(
+                &format!("{} {}.", victim.subject(), victim.verb("die")),
+                MessageCategory::Combat,
+            ),
+            GameEvent::StatusStarted { target, kind } => self.push(
+                &format!(
+                    "{} {} {}.",
+                    target.subject(),
+                    target.verb("be"),
+                    kind.adjective()
+                ),
+                MessageCategory::Info,
+            ),
+            GameEvent::StatusEnded { target, kind } => self.push(
+                &format!(
+                    "{} {} no longer {}.",
+                    target.subject(),
+                    target.verb("be"),
+                    kind.adjective()
+                ),
+                MessageCategory::Info,
+            ),
+            GameEvent::StatusDamage {
+                target,
+                damage,
+                damage_type,
+                ..
+            } => self.push(
+                &format!(
+                    "{} {} {} {} damage.",
+                    target.subject(),
+                    target.verb("take"),
+                    damage,
+                    damage_type
+                ),
+                MessageCategory::Combat,
+            ),
+        }
+    }
+
+    pub(crate) fn update(&mut self, dt: f32) {
+        for message in self.messages.iter_mut() {
+            message.age += dt;
+        }
+    }
+
+    pub(crate) fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    pub(crate) fn scroll_up(&mut self, lines: usize) {
+        self.scroll = (self.scroll + lines).min(self.messages.len().saturating_sub(1));
+    }
+
+    pub(crate) fn scroll_down(&mut self, lines: usize) {
+        self.scroll = self.scroll.saturating_sub(lines);
+    }
+
+    pub(crate) fn scroll_to_bottom(&mut self) {
+        self.scroll = 0;
+    }
+
+    pub(crate) fn is_scrolled(&self) -> bool {
+        self.scroll > 0
+    }
+
+    /// Draw the last `lines` messages, newest at the bottom. Messages fade out over time,
+    /// unless the log is scrolled back through its history.
+    pub(crate) fn draw(&self, queue: &mut DrawQueue, pos: Vec2, lines: usize, size: f32) {
+        let end = self.messages.len() - self.scroll.min(self.messages.len());
+        let start = end.saturating_sub(lines);
+
+        for (line, message) in self.messages[start..end].iter().enumerate() {
+            let opacity = if self.is_scrolled() {
+                1.0
+            } else {
+                message.opacity()
+            };
+            if opacity <= 0.0 {
+                continue;
+            }
+
+            let color = message.color.fade(opacity);
+
+            queue.text(
+                Layer::Ui,
+                Vec2 {
+                    x: pos.x,
+                    y: pos.y + line as f32 * size,
+                },
+                &message.display_text(),
+                size,
+                size * 0.75,
+                color,
+            );
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use crate::color::Color;
+use std::collections::HashMap;
+use std::path::Path;
+use std::{fmt, fs, io};
+
+/// A set of colours a game is drawn with, so it can be re-themed by swapping one file.
+///
+/// Colours can be looked up by index, or by name for palettes that name their entries.
+#[derive(Clone, Default, Debug)]
+pub(crate) struct Palette {
+    colors: Vec<Color>,
+    names: HashMap<String, usize>,
+}
+
+impl Palette {
+    /// Load a GIMP palette (`.gpl`) or a list of hex colours (`.hex`), chosen by extension.
+    pub(crate) fn load(path: impl AsRef<Path>) -> Result<Self, PaletteError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(PaletteError::Io)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gpl") => Self::parse_gpl(&contents),
+            _ => Self::parse_hex(&contents),
+        }
+    }
+
+    /// One `rrggbb` colour per line, as exported by Lospec. A name may follow the colour.
+    pub(crate) fn parse_hex(contents: &str) -> Result<Self, PaletteError> {
+        let mut palette = Self::default();
+        for (line_num, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with("//") {
+                continue;
+            }
+            let (hex, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let color = Color::from_hex(hex).map_err(|e| PaletteError::Parse {
+                line: line_num + 1,
+                message: e.to_string(),
+            })?;
+            palette.push(color, Some(name.trim()));
+        }
+        Ok(palette)
+    }
+
+    /// GIMP palette: a `GIMP Palette` header, then `r g b name` per line.
+    pub(crate) fn parse_gpl(contents: &str) -> Result<Self, PaletteError> {
+        let mut lines = contents.lines().enumerate();
+        if lines.next().map(|(_, l)| l.trim()) != Some("GIMP Palette") {
+            return Err(PaletteError::Parse {
+                line: 1,
+                message: "missing `GIMP Palette` header".to_string(),
+            });
+        }
+
+        let mut palette = Self::default();
+        for (line_num, line) in lines {
+            let line = line.trim();
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with("Name:")
+                || line.starts_with("Columns:")
+            {
+                continue;
+            }
+
+            let error = |message: &str| PaletteError::Parse {
+                line: line_num + 1,
+                message: message.to_string(),
+            };
+            let mut parts = line.split_whitespace();
+            let mut channel = || {
+                parts
+                    .next()
+                    .and_then(|c| c.parse::<u8>().ok())
+                    .ok_or_else(|| error("expected `r g b` from 0 to 255"))
+            };
+            let color = Color::rgb(channel()?, channel()?, channel()?);
+            let name = parts.collect::<Vec<_>>().join(" ");
+            palette.push(color, Some(&name));
+        }
+        Ok(palette)
+    }
+
+    /// Add a colour, returns its index. Names are case insensitive.
+    pub(crate) fn push(&mut self, color: Color, name: Option<&str>) -> usize {
+        let index = self.colors.len();
+        self.colors.push(color);
+        if let Some(name) = name.filter(|n| !n.is_empty()) {
+            self.names.entry(name.to_lowercase()).or_insert(index);
+        }
+        index
+    }
+
+    pub(crate) fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    pub(crate) fn named(&self, name: &str) -> Option<Color> {
+        self.names
+            .get(&name.to_lowercase())
+            .map(|&index| self.colors[index])
+    }
+
+    /// Resolve a colour by palette name, then constant name, then hex code.
+    pub(crate) fn resolve(&self, spec: &str) -> Option<Color> {
+        self.named(spec).or_else(|| spec.parse().ok())
+    }
+
+    /// Palette entry closest to `color`, by squared distance in RGB.
+    pub(crate) fn nearest(&self, color: Color) -> Option<Color> {
+        self.colors.iter().copied().min_by_key(|c| {
+            let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+            d(c.r, color.r) + d(c.g, color.g) + d(c.b, color.b)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum PaletteError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::Io(e) => write!(f, "could not read palette: {}", e),
+            PaletteError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_gpl() {
+        let palette = Palette::parse_gpl(
+            "GIMP Palette\nName: Test\nColumns: 2\n# Comment\n255 0 0\tBlood Red\n  0 0 255\n\n",
+        )
+        .unwrap();
+        assert_eq!(
+            palette.colors(),
+            [Color::rgb(0xff, 0x00, 0x00), Color::rgb(0x00, 0x00, 0xff)]
+        );
+        assert_eq!(palette.named("blood red"), Some(Color::rgb(0xff, 0, 0)));
+        assert_eq!(palette.resolve("Blood Red"), Some(Color::rgb(0xff, 0, 0)));
+        assert_eq!(palette.resolve("#00ff00"), Some(Color::rgb(0, 0xff, 0)));
+    }
+
+    #[test]
+    fn reject_gpl() {
+        let error = Palette::parse_gpl("255 0 0 Red\n").unwrap_err();
+        assert!(matches!(error, PaletteError::Parse { line: 1, .. }));
+        let error = Palette::parse_gpl("GIMP Palette\n255 0 0\n300 0 0\n").unwrap_err();
+        assert!(matches!(error, PaletteError::Parse { line: 3, .. }));
+        let error = Palette::parse_gpl("GIMP Palette\n255 0\n").unwrap_err();
+        assert!(matches!(error, PaletteError::Parse { line: 2, .. }));
+    }
+
+    #[test]
+    fn parse_hex() {
+        let palette = Palette::parse_hex("; Lospec\nff0000 Red\n\n// Comment\n00ff00\n").unwrap();
+        assert_eq!(
+            palette.colors(),
+            [Color::rgb(0xff, 0x00, 0x00), Color::rgb(0x00, 0xff, 0x00)]
+        );
+        assert_eq!(palette.named("RED"), Some(Color::rgb(0xff, 0, 0)));
+        let error = Palette::parse_hex("ff0000\nnope\n").unwrap_err();
+        assert!(matches!(error, PaletteError::Parse { line: 2, .. }));
+    }
+
+    #[test]
+    fn nearest() {
+        let palette = Palette::parse_hex("000000\n808080\nffffff\n").unwrap();
+        assert_eq!(
+            palette.nearest(Color::rgb(0x70, 0x90, 0x80)),
+            Some(Color::rgb(0x80, 0x80, 0x80))
+        );
+        assert_eq!(Palette::default().nearest(Color::WHITE), None);
+    }
+}
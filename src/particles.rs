@@ -0,0 +1,392 @@
+use crate::color::Color;
+use crate::draw_queue::{DrawQueue, Layer};
+use crate::easing::Easing;
+use crate::renderer::DrawParams;
+use crate::rng::Rng;
+use crate::sprite::Sprite;
+use glam::Vec2;
+use std::collections::HashMap;
+
+/// Describes the particles an emitter spawns. Ranges are `(min, max)`, picked at random
+/// per particle.
+#[derive(Clone, Copy)]
+pub(crate) struct EmitterConfig {
+    /// Seconds a particle lives.
+    pub lifetime: (f32, f32),
+    /// Pixels per second.
+    pub speed: (f32, f32),
+    /// Direction of travel in radians, 0 points right and positive angles turn clockwise.
+    pub direction: f32,
+    /// Random variation around `direction` in radians, `TAU` emits in every direction.
+    pub spread: f32,
+    /// Acceleration in pixels per second squared.
+    pub gravity: Vec2,
+    /// Fraction of the velocity lost per second.
+    pub drag: f32,
+    pub start_color: Color,
+    pub end_color: Color,
+    /// Curve used to blend from the start to the end colour.
+    pub color_curve: Easing,
+    /// Size in pixels, or the scale for sprite particles.
+    pub start_size: f32,
+    pub end_size: f32,
+    pub size_curve: Easing,
+    /// Draw the particle as this sprite instead of a square, stepping through its animation
+    /// frames over the particle's lifetime.
+    pub sprite: Option<&'static str>,
+}
+
+impl EmitterConfig {
+    pub(crate) fn blood() -> Self {
+        Self {
+            lifetime: (0.3, 0.7),
+            speed: (30.0, 90.0),
+            direction: -std::f32::consts::FRAC_PI_2,
+            spread: 2.0,
+            gravity: Vec2::new(0.0, 240.0),
+            drag: 0.5,
+            start_color: Color::rgb(0xc0, 0x10, 0x10),
+            end_color: Color::rgba(0x50, 0x00, 0x00, 0x00),
+            color_curve: Easing::InQuad,
+            start_size: 2.0,
+            end_size: 1.0,
+            size_curve: Easing::OutQuad,
+            sprite: None,
+        }
+    }
+
+    pub(crate) fn sparks() -> Self {
+        Self {
+            lifetime: (0.15, 0.4),
+            speed: (60.0, 140.0),
+            direction: 0.0,
+            spread: std::f32::consts::TAU,
+            gravity: Vec2::new(0.0, 120.0),
+            drag: 2.0,
+            start_color: Color::rgb(0xff, 0xf0, 0xa0),
+            end_color: Color::rgba(0xff, 0x60, 0x00, 0x00),
+            color_curve: Easing::OutQuad,
+            start_size: 1.0,
+            end_size: 1.0,
+            size_curve: Easing::InQuad,
+            sprite: None,
+        }
+    }
+
+    pub(crate) fn dust() -> Self {
+        Self {
+            lifetime: (0.4, 0.9),
+            speed: (5.0, 20.0),
+            direction: -std::f32::consts::FRAC_PI_2,
+            spread: 2.5,
+            gravity: Vec2::new(0.0, -10.0),
+            drag: 1.5,
+            start_color: Color::rgba(0x90, 0x88, 0x80, 0xc0),
+            end_color: Color::rgba(0x60, 0x58, 0x50, 0x00),
+            color_curve: Easing::InSine,
+            start_size: 1.0,
+            end_size: 3.0,
+            size_curve: Easing::OutCubic,
+            sprite: None,
+        }
+    }
+
+    pub(crate) fn magic_trail() -> Self {
+        Self {
+            lifetime: (0.5, 1.2),
+            speed: (2.0, 8.0),
+            direction: -std::f32::consts::FRAC_PI_2,
+            spread: 1.0,
+            gravity: Vec2::new(0.0, -15.0),
+            drag: 0.2,
+            start_color: Color::rgb(0x80, 0xff, 0xa0),
+            end_color: Color::rgba(0x20, 0x80, 0xff, 0x00),
+            color_curve: Easing::InOutSine,
+            start_size: 1.0,
+            end_size: 1.0,
+            size_curve: Easing::InQuad,
+            sprite: None,
+        }
+    }
+
+    /// Tiny slimes flung away from a hit slime.
+    pub(crate) fn slime_splat() -> Self {
+        Self {
+            lifetime: (0.3, 0.5),
+            speed: (40.0, 80.0),
+            direction: -std::f32::consts::FRAC_PI_2,
+            spread: 2.0,
+            gravity: Vec2::new(0.0, 200.0),
+            drag: 0.5,
+            start_color: Color::WHITE,
+            end_color: Color::WHITE,
+            color_curve: Easing::InQuad,
+            start_size: 0.5,
+            end_size: 0.1,
+            size_curve: Easing::InQuad,
+            sprite: Some("slime"),
+        }
+    }
+}
+
+struct Particle {
+    pos: Vec2,
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+    config: EmitterConfig,
+}
+
+impl Particle {
+    /// Fraction of the lifetime that has passed, 0.0 to 1.0.
+    fn progress(&self) -> f32 {
+        (self.age / self.lifetime).clamp(0.0, 1.0)
+    }
+}
+
+/// Spawns particles continuously, e.g. for ambience or a trail behind a projectile.
+pub(crate) struct Emitter {
+    pub config: EmitterConfig,
+    /// Position in world pixels.
+    pub pos: Vec2,
+    /// Particles per second.
+    pub rate: f32,
+    accumulator: f32,
+}
+
+impl Emitter {
+    pub(crate) fn new(config: EmitterConfig, pos: Vec2, rate: f32) -> Self {
+        Self {
+            config,
+            pos,
+            rate,
+            accumulator: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct EmitterId(usize);
+
+/// CPU simulated particles, drawn on the effects layer.
+pub(crate) struct ParticleSystem {
+    particles: Vec<Particle>,
+    emitters: Vec<Option<Emitter>>,
+    /// Most particles alive at once.
+    max_particles: usize,
+    /// Most particles spawned in one frame, so large bursts can't stall it.
+    max_spawn_per_frame: usize,
+    spawned_this_frame: usize,
+    /// Cosmetic randomness, kept separate from the gameplay streams.
+    rng: Rng,
+}
+
+impl ParticleSystem {
+    pub(crate) fn new(max_particles: usize, max_spawn_per_frame: usize, rng: Rng) -> Self {
+        Self {
+            particles: Vec::with_capacity(max_particles),
+            emitters: Vec::new(),
+            max_particles,
+            max_spawn_per_frame,
+            spawned_this_frame: 0,
+            rng,
+        }
+    }
+
+    /// Spawn `count` particles at `pos` at once, as far as the budget allows.
+    pub(crate) fn burst(&mut self, config: &EmitterConfig, pos: Vec2, count: usize) {
+        for _ in 0..count {
+            if self.particles.len() >= self.max_particles
+                || self.spawned_this_frame >= self.max_spawn_per_frame
+            {
+                break;
+            }
+
+            let angle = config.direction + (self.rng.next_f32() - 0.5) * config.spread;
+            let speed = self.rng.range_f32(config.speed.0, config.speed.1);
+            self.particles.push(Particle {
+                pos,
+                velocity: Vec2::from_angle(angle) * speed,
+                age: 0.0,
+                lifetime: self
+                    .rng
+                    .range_f32(config.lifetime.0, config.lifetime.1)
+                    .max(f32::EPSILON),
+                config: *config,
+            });
+            self.spawned_this_frame += 1;
+        }
+    }
+
+    pub(crate) fn add_emitter(&mut self, emitter: Emitter) -> EmitterId {
+        if let Some(index) = self.emitters.iter().position(|e| e.is_none()) {
+            self.emitters[index] = Some(emitter);
+            EmitterId(index)
+        } else {
+            self.emitters.push(Some(emitter));
+            EmitterId(self.emitters.len() - 1)
+        }
+    }
+
+    pub(crate) fn emitter_mut(&mut self, id: EmitterId) -> Option<&mut Emitter> {
+        self.emitters.get_mut(id.0).and_then(|e| e.as_mut())
+    }
+
+    pub(crate) fn remove_emitter(&mut self, id: EmitterId) {
+        if let Some(emitter) = self.emitters.get_mut(id.0) {
+            *emitter = None;
+        }
+    }
+
+    /// Remove every particle and emitter.
+    pub(crate) fn clear(&mut self) {
+        self.particles.clear();
+        self.emitters.clear();
+    }
+
+    /// Start a new frame's spawn budget. Call before anything bursts this frame, so bursts
+    /// and emitters share the same budget.
+    pub(crate) fn begin_frame(&mut self) {
+        self.spawned_this_frame = 0;
+    }
+
+    pub(crate) fn update(&mut self, dt: f32) {
+        for particle in self.particles.iter_mut() {
+            particle.age += dt;
+            particle.velocity += particle.config.gravity * dt;
+            particle.velocity *= (1.0 - particle.config.drag * dt).max(0.0);
+            particle.pos += particle.velocity * dt;
+        }
+        self.particles.retain(|p| p.age < p.lifetime);
+
+        let mut spawns = Vec::new();
+        for emitter in self.emitters.iter_mut().flatten() {
+            emitter.accumulator += emitter.rate * dt;
+            let count = emitter.accumulator.floor();
+            emitter.accumulator -= count;
+            spawns.push((emitter.config, emitter.pos, count as usize));
+        }
+        for (config, pos, count) in spawns {
+            self.burst(&config, pos, count);
+        }
+    }
+
+    /// Queue all particles on the effects layer. Sprite particles look up their sprite in
+    /// `sprites` and are skipped when it is missing.
+    pub(crate) fn draw<'a>(&self, queue: &mut DrawQueue<'a>, sprites: &'a HashMap<String, Sprite>) {
+        for particle in &self.particles {
+            let config = &particle.config;
+            let t = particle.progress();
+            let color = config
+                .start_color
+                .lerp(config.end_color, config.color_curve.apply(t));
+            let size = config.start_size
+                + (config.end_size - config.start_size) * config.size_curve.apply(t);
+
+            match config.sprite.and_then(|name| sprites.get(name)) {
+                Some(sprite) => {
+                    let frame = (t * sprite.frame_num as f32) as u32;
+                    let center = Vec2::new(sprite.frame_width() as f32, sprite.height as f32) / 2.0;
+                    queue.sprite(
+                        Layer::Effects,
+                        particle.pos.y as i32,
+                        particle.pos - center,
+                        sprite,
+                        frame,
+                        DrawParams {
+                            scale: size,
+                            pivot: center,
+                            tint: color,
+                            ..Default::default()
+                        },
+                    );
+                }
+                None => {
+                    queue.rect(
+                        Layer::Effects,
+                        particle.pos.y as i32,
+                        particle.pos - Vec2::splat(size / 2.0),
+                        Vec2::splat(size.max(1.0)),
+                        color,
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Particles that stand still and live exactly one second.
+    fn still() -> EmitterConfig {
+        EmitterConfig {
+            lifetime: (1.0, 1.0),
+            speed: (0.0, 0.0),
+            gravity: Vec2::ZERO,
+            drag: 0.0,
+            ..EmitterConfig::sparks()
+        }
+    }
+
+    fn system(max_particles: usize, max_spawn_per_frame: usize) -> ParticleSystem {
+        ParticleSystem::new(max_particles, max_spawn_per_frame, Rng::seed_from_u64(1))
+    }
+
+    #[test]
+    fn particles_expire_after_their_lifetime() {
+        let mut particles = system(100, 100);
+        particles.burst(&still(), Vec2::ZERO, 10);
+        particles.update(0.6);
+        assert_eq!(particles.particles.len(), 10);
+        particles.update(0.6);
+        assert!(particles.particles.is_empty());
+    }
+
+    #[test]
+    fn gravity_accelerates_particles() {
+        let mut particles = system(100, 100);
+        let config = EmitterConfig {
+            gravity: Vec2::new(0.0, 100.0),
+            ..still()
+        };
+        particles.burst(&config, Vec2::new(5.0, 5.0), 1);
+        particles.update(0.1);
+        let first = particles.particles[0].pos;
+        particles.update(0.1);
+        let second = particles.particles[0].pos;
+        assert_eq!(first.x, 5.0);
+        assert!(first.y > 5.0);
+        assert!(second.y - first.y > first.y - 5.0);
+        assert_eq!(particles.particles[0].velocity, Vec2::new(0.0, 20.0));
+    }
+
+    #[test]
+    fn spawns_are_limited_per_frame() {
+        let mut particles = system(100, 8);
+        particles.begin_frame();
+        particles.burst(&still(), Vec2::ZERO, 5);
+        particles.burst(&still(), Vec2::ZERO, 5);
+        assert_eq!(particles.particles.len(), 8);
+
+        // Emitters share the budget with bursts.
+        particles.add_emitter(Emitter::new(still(), Vec2::ZERO, 100.0));
+        particles.update(0.1);
+        assert_eq!(particles.particles.len(), 8);
+
+        particles.begin_frame();
+        particles.update(0.1);
+        assert_eq!(particles.particles.len(), 16);
+    }
+
+    #[test]
+    fn particles_are_limited_in_total() {
+        let mut particles = system(12, 8);
+        for _ in 0..3 {
+            particles.begin_frame();
+            particles.burst(&still(), Vec2::ZERO, 8);
+        }
+        assert_eq!(particles.particles.len(), 12);
+    }
+}
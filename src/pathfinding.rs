@@ -0,0 +1,158 @@
+use glam::IVec2;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Orthogonal steps, the moves entities can make.
+pub(crate) const DIRECTIONS: [IVec2; 4] = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+
+/// Shortest path from `start` to `goal` with A*, moving orthogonally.
+///
+/// `cost` gives the cost of entering a tile, `None` if it cannot be entered. The goal can
+/// always be entered, so paths may lead up to another creature. Gives up after expanding
+/// `max_nodes` tiles. Returns the tiles after `start` up to and including `goal`, which is
+/// an empty path when `start` is the goal.
+pub(crate) fn astar(
+    start: IVec2,
+    goal: IVec2,
+    max_nodes: usize,
+    cost: impl Fn(IVec2) -> Option<i32>,
+) -> Option<Vec<IVec2>> {
+    if start == goal {
+        return Some(Vec::new());
+    }
+    let heuristic = |pos: IVec2| {
+        let d = (goal - pos).abs();
+        d.x + d.y
+    };
+
+    // Ties on the estimate go to the tile closer to the goal, which keeps paths straight.
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<IVec2, IVec2> = HashMap::new();
+    let mut best: HashMap<IVec2, i32> = HashMap::new();
+    open.push(Reverse((
+        heuristic(start),
+        heuristic(start),
+        start.x,
+        start.y,
+    )));
+    best.insert(start, 0);
+
+    let mut expanded = 0;
+    while let Some(Reverse((_, _, x, y))) = open.pop() {
+        let pos = IVec2::new(x, y);
+        if pos == goal {
+            let mut path = vec![goal];
+            let mut current = goal;
+            while let Some(&previous) = came_from.get(&current) {
+                if previous == start {
+                    break;
+                }
+                path.push(previous);
+                current = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        expanded += 1;
+        if expanded > max_nodes {
+            return None;
+        }
+
+        let cost_so_far = best[&pos];
+        for direction in DIRECTIONS {
+            let next = pos + direction;
+            let step = if next == goal { Some(1) } else { cost(next) };
+            let Some(step) = step else {
+                continue;
+            };
+            let new_cost = cost_so_far + step;
+            if best.get(&next).is_some_and(|&old| old <= new_cost) {
+                continue;
+            }
+            best.insert(next, new_cost);
+            came_from.insert(next, pos);
+            let h = heuristic(next);
+            open.push(Reverse((new_cost + h, h, next.x, next.y)));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cost function of a map drawn with `#` for walls, anything else costs 1. Everything
+    /// outside of the map is wall.
+    fn grid<'a>(rows: &'a [&'a str]) -> impl Fn(IVec2) -> Option<i32> + 'a {
+        move |pos| {
+            let row = rows.get(usize::try_from(pos.y).ok()?)?;
+            match row.chars().nth(usize::try_from(pos.x).ok()?)? {
+                '#' => None,
+                _ => Some(1),
+            }
+        }
+    }
+
+    #[test]
+    fn straight() {
+        let rows = ["....."];
+        let path = astar(IVec2::new(0, 0), IVec2::new(4, 0), 100, grid(&rows)).unwrap();
+        let expected: Vec<_> = (1..=4).map(|x| IVec2::new(x, 0)).collect();
+        assert_eq!(path, expected);
+    }
+
+    #[test]
+    fn around_a_wall() {
+        let rows = [
+            "..#..", //
+            "..#..", ".....",
+        ];
+        let cost = grid(&rows);
+        let path = astar(IVec2::new(0, 0), IVec2::new(4, 0), 100, &cost).unwrap();
+        assert_eq!(path.len(), 8);
+        assert_eq!(path.last(), Some(&IVec2::new(4, 0)));
+        let mut previous = IVec2::new(0, 0);
+        for &pos in &path {
+            assert!(DIRECTIONS.contains(&(pos - previous)));
+            assert!(cost(pos).is_some());
+            previous = pos;
+        }
+    }
+
+    #[test]
+    fn unreachable() {
+        let rows = [
+            "..#..", //
+            "..#..",
+        ];
+        assert_eq!(
+            astar(IVec2::new(0, 0), IVec2::new(4, 0), 100, grid(&rows)),
+            None
+        );
+    }
+
+    #[test]
+    fn gives_up_after_max_nodes() {
+        let rows = [".........."];
+        let (start, goal) = (IVec2::new(0, 0), IVec2::new(9, 0));
+        assert_eq!(astar(start, goal, 3, grid(&rows)), None);
+        assert!(astar(start, goal, 9, grid(&rows)).is_some());
+    }
+
+    #[test]
+    fn goal_can_always_be_entered() {
+        let rows = ["..#"];
+        let path = astar(IVec2::new(0, 0), IVec2::new(2, 0), 100, grid(&rows));
+        assert_eq!(path, Some(vec![IVec2::new(1, 0), IVec2::new(2, 0)]));
+    }
+
+    #[test]
+    fn already_at_goal() {
+        let rows = ["..."];
+        let pos = IVec2::new(1, 0);
+        assert_eq!(astar(pos, pos, 100, grid(&rows)), Some(Vec::new()));
+        assert_eq!(astar(pos, pos, 0, |_| None), Some(Vec::new()));
+    }
+}
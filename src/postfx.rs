@@ -0,0 +1,597 @@
+use crate::color::Color;
+use crate::config::PostFxConfig;
+use crate::palette::Palette;
+use pixels::wgpu::util::DeviceExt;
+use pixels::{wgpu, Pixels, PixelsContext};
+
+/// How quickly the damage flash fades, in flash amount per second.
+const FLASH_FADE_SPEED: f32 = 3.0;
+
+/// Full-screen effects, applied in this order.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum PostPass {
+    Scanlines,
+    Vignette,
+    Quantize,
+    DamageFlash,
+    Desaturate,
+}
+
+impl PostPass {
+    pub(crate) const ALL: [PostPass; 5] = [
+        PostPass::Scanlines,
+        PostPass::Vignette,
+        PostPass::Quantize,
+        PostPass::DamageFlash,
+        PostPass::Desaturate,
+    ];
+
+    fn entry_point(&self) -> &'static str {
+        match self {
+            PostPass::Scanlines => "fs_scanlines",
+            PostPass::Vignette => "fs_vignette",
+            PostPass::Quantize => "fs_quantize",
+            PostPass::DamageFlash => "fs_damage_flash",
+            PostPass::Desaturate => "fs_desaturate",
+        }
+    }
+
+    /// Whether the pass is switched on and currently has a visible effect.
+    fn is_active(&self, config: &PostFxConfig, palette: &Palette, effects: &ScreenEffects) -> bool {
+        match self {
+            PostPass::Scanlines => config.scanlines,
+            PostPass::Vignette => config.vignette,
+            PostPass::Quantize => config.quantize && !palette.colors().is_empty(),
+            PostPass::DamageFlash => config.damage_flash && effects.flash_amount > 0.0,
+            PostPass::Desaturate => config.desaturate && effects.desaturation > 0.0,
+        }
+    }
+}
+
+/// Screen effects driven by the game, such as a red flash when the player is hurt.
+#[derive(Clone, Copy, Default, Debug)]
+pub(crate) struct ScreenEffects {
+    pub flash_color: Color,
+    /// Strength of the flash, from 0.0 to 1.0. Fades out by itself.
+    pub flash_amount: f32,
+    /// How much colour is removed from the screen, from 0.0 to 1.0.
+    pub desaturation: f32,
+}
+
+impl ScreenEffects {
+    pub(crate) fn flash(&mut self, color: Color, amount: f32) {
+        self.flash_color = color;
+        self.flash_amount = amount.clamp(0.0, 1.0);
+    }
+
+    pub(crate) fn update(&mut self, dt: f32) {
+        self.flash_amount = (self.flash_amount - FLASH_FADE_SPEED * dt).max(0.0);
+    }
+}
+
+/// Intermediate texture a pass renders into, with the bind group to sample it in the next.
+struct Target {
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Chain of full-screen wgpu passes run after the scaling renderer.
+pub(crate) struct PostProcessor {
+    config: PostFxConfig,
+    /// Logical resolution of the frame buffer.
+    width: u32,
+    height: u32,
+    /// Whole-number factor the frame is scaled up by.
+    scale: u32,
+    pipelines: Vec<(PostPass, wgpu::RenderPipeline)>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    /// Colours quantisation snaps to: `palette` on the CPU and `palette_buffer` on the GPU.
+    palette: Palette,
+    palette_buffer: wgpu::Buffer,
+    /// Whether the intermediate textures are sRGB, so the passes see linear colours.
+    srgb: bool,
+    targets: Vec<Target>,
+}
+
+impl PostProcessor {
+    /// `surface_width` and `surface_height` are the size of the window in physical pixels.
+    /// Quantisation snaps to the colours of `palette`.
+    pub(crate) fn new(
+        pixels: &Pixels,
+        config: PostFxConfig,
+        palette: &Palette,
+        width: u32,
+        height: u32,
+        surface_width: u32,
+        surface_height: u32,
+    ) -> Self {
+        let device = pixels.device();
+        let module = device.create_shader_module(wgpu::include_wgsl!("../shaders/postfx.wgsl"));
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("postfx_sampler"),
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("postfx_uniform_buffer"),
+            contents: &[0; 64],
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        // A storage buffer cannot be empty, so an empty palette still gets one entry.
+        let mut palette_colors: Vec<u8> = palette
+            .colors()
+            .iter()
+            .flat_map(|color| color.to_f32())
+            .flat_map(|c| c.to_ne_bytes())
+            .collect();
+        palette_colors.resize(palette_colors.len().max(16), 0);
+        let palette_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("postfx_palette_buffer"),
+            contents: &palette_colors,
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("postfx_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("postfx_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipelines = PostPass::ALL
+            .iter()
+            .map(|pass| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(pass.entry_point()),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &module,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    fragment: Some(wgpu::FragmentState {
+                        module: &module,
+                        entry_point: pass.entry_point(),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: pixels.render_texture_format(),
+                            blend: None,
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    multiview: None,
+                });
+                (*pass, pipeline)
+            })
+            .collect();
+
+        let mut post_processor = Self {
+            config,
+            width,
+            height,
+            scale: 1,
+            pipelines,
+            bind_group_layout,
+            sampler,
+            uniform_buffer,
+            palette: palette.clone(),
+            palette_buffer,
+            srgb: pixels.render_texture_format().describe().srgb,
+            targets: Vec::new(),
+        };
+        post_processor.resize(pixels, surface_width, surface_height);
+        post_processor
+    }
+
+    pub(crate) fn resize(&mut self, pixels: &Pixels, surface_width: u32, surface_height: u32) {
+        if surface_width == 0 || surface_height == 0 {
+            return;
+        }
+        self.scale = (surface_width / self.width)
+            .min(surface_height / self.height)
+            .max(1);
+
+        let device = pixels.device();
+        self.targets = (0..2)
+            .map(|_| {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("postfx_texture"),
+                    size: wgpu::Extent3d {
+                        width: surface_width,
+                        height: surface_height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: pixels.render_texture_format(),
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                });
+                let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("postfx_bind_group"),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: self.uniform_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: self.palette_buffer.as_entire_binding(),
+                        },
+                    ],
+                });
+                Target { view, bind_group }
+            })
+            .collect();
+    }
+
+    /// Run the CPU version of the enabled effects on the frame buffer, when the config asks
+    /// for it. Call this after drawing the frame and before rendering it.
+    pub(crate) fn apply_cpu(&self, frame: &mut [u8], effects: &ScreenEffects) {
+        if self.config.cpu_fallback {
+            apply_cpu(
+                frame,
+                self.width as usize,
+                &self.config,
+                &self.palette,
+                effects,
+            );
+        }
+    }
+
+    /// Scale the frame buffer to `render_target`, running the enabled passes on the way.
+    pub(crate) fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        render_target: &wgpu::TextureView,
+        context: &PixelsContext,
+        effects: &ScreenEffects,
+    ) {
+        let passes: Vec<_> = if self.config.cpu_fallback {
+            Vec::new()
+        } else {
+            self.pipelines
+                .iter()
+                .filter(|(pass, _)| pass.is_active(&self.config, &self.palette, effects))
+                .map(|(_, pipeline)| pipeline)
+                .collect()
+        };
+
+        if passes.is_empty() || self.targets.len() < 2 {
+            context.scaling_renderer.render(encoder, render_target);
+            return;
+        }
+
+        let uniforms = self.uniforms(effects, context.scaling_renderer.clip_rect());
+        context
+            .queue
+            .write_buffer(&self.uniform_buffer, 0, &uniforms);
+        context
+            .scaling_renderer
+            .render(encoder, &self.targets[0].view);
+
+        for (i, pipeline) in passes.iter().enumerate() {
+            let input = &self.targets[i % 2];
+            let output = if i == passes.len() - 1 {
+                render_target
+            } else {
+                &self.targets[(i + 1) % 2].view
+            };
+
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("postfx_render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            rpass.set_pipeline(pipeline);
+            rpass.set_bind_group(0, &input.bind_group, &[]);
+            rpass.draw(0..3, 0..1);
+        }
+    }
+
+    /// Contents of the `Params` uniform in `postfx.wgsl`. `frame` is where the scaling
+    /// renderer draws the frame: x, y, width and height in screen pixels.
+    fn uniforms(&self, effects: &ScreenEffects, frame: (u32, u32, u32, u32)) -> Vec<u8> {
+        let flash = effects.flash_color.to_f32();
+        let values: [f32; 16] = [
+            flash[0],
+            flash[1],
+            flash[2],
+            flash[3],
+            self.scale as f32,
+            self.config.scanline_strength,
+            self.config.vignette_strength,
+            self.palette.colors().len() as f32,
+            effects.flash_amount,
+            effects.desaturation,
+            if self.srgb { 1.0 } else { 0.0 },
+            0.0,
+            frame.0 as f32,
+            frame.1 as f32,
+            frame.2 as f32,
+            frame.3 as f32,
+        ];
+        values.iter().flat_map(|v| v.to_ne_bytes()).collect()
+    }
+}
+
+/// CPU equivalent of the enabled passes on an RGBA8 frame of `width` pixels per row.
+///
+/// The frame is at the logical resolution, so scanlines darken every other logical row
+/// instead of part of every scaled-up row.
+pub(crate) fn apply_cpu(
+    frame: &mut [u8],
+    width: usize,
+    config: &PostFxConfig,
+    palette: &Palette,
+    effects: &ScreenEffects,
+) {
+    let height = frame.len() / 4 / width;
+    let flash = effects.flash_color.to_array().map(|c| c as f32);
+
+    for (y, row) in frame.chunks_exact_mut(width * 4).enumerate() {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let mut color = [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32];
+
+            if config.scanlines && y % 2 == 1 {
+                color = color.map(|c| c * (1.0 - config.scanline_strength));
+            }
+            if config.vignette {
+                let u = (x as f32 + 0.5) / width as f32 - 0.5;
+                let v = (y as f32 + 0.5) / height as f32 - 0.5;
+                let distance = (u * u + v * v).sqrt() * std::f32::consts::SQRT_2;
+                let darken = config.vignette_strength * smoothstep(0.4, 1.0, distance);
+                color = color.map(|c| c * (1.0 - darken));
+            }
+            if config.quantize {
+                let [r, g, b] = color.map(|c| c.round().clamp(0.0, 255.0) as u8);
+                if let Some(nearest) = palette.nearest(Color::rgb(r, g, b)) {
+                    color = [nearest.r, nearest.g, nearest.b].map(|c| c as f32);
+                }
+            }
+            if config.damage_flash && effects.flash_amount > 0.0 {
+                for (c, flash) in color.iter_mut().zip(flash) {
+                    *c += (flash - *c) * effects.flash_amount;
+                }
+            }
+            if config.desaturate && effects.desaturation > 0.0 {
+                let luma = color[0] * 0.299 + color[1] * 0.587 + color[2] * 0.114;
+                color = color.map(|c| c + (luma - c) * effects.desaturation);
+            }
+
+            for (dest, c) in pixel.iter_mut().zip(color) {
+                *dest = c.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 5;
+    const PIXEL: [u8; 4] = [200, 100, 50, 255];
+
+    fn frame() -> Vec<u8> {
+        PIXEL.repeat(WIDTH * WIDTH)
+    }
+
+    fn pixel(frame: &[u8], x: usize, y: usize) -> [u8; 4] {
+        let i = (y * WIDTH + x) * 4;
+        frame[i..i + 4].try_into().unwrap()
+    }
+
+    fn disabled() -> PostFxConfig {
+        PostFxConfig {
+            scanlines: false,
+            vignette: false,
+            quantize: false,
+            damage_flash: false,
+            desaturate: false,
+            ..PostFxConfig::default()
+        }
+    }
+
+    #[test]
+    fn disabled_passes_leave_the_frame_alone() {
+        let effects = ScreenEffects {
+            flash_color: Color::RED,
+            flash_amount: 1.0,
+            desaturation: 1.0,
+        };
+        let mut frame = frame();
+        apply_cpu(
+            &mut frame,
+            WIDTH,
+            &disabled(),
+            &Palette::default(),
+            &effects,
+        );
+        assert_eq!(frame, self::frame());
+    }
+
+    #[test]
+    fn scanlines_darken_odd_rows() {
+        let config = PostFxConfig {
+            scanlines: true,
+            scanline_strength: 0.5,
+            ..disabled()
+        };
+        let mut frame = frame();
+        apply_cpu(
+            &mut frame,
+            WIDTH,
+            &config,
+            &Palette::default(),
+            &ScreenEffects::default(),
+        );
+        for y in 0..WIDTH {
+            let expected = if y % 2 == 1 {
+                [100, 50, 25, 255]
+            } else {
+                PIXEL
+            };
+            assert_eq!(pixel(&frame, 0, y), expected, "row {}", y);
+        }
+    }
+
+    #[test]
+    fn vignette_darkens_corners_only() {
+        let config = PostFxConfig {
+            vignette: true,
+            vignette_strength: 1.0,
+            ..disabled()
+        };
+        let mut frame = frame();
+        apply_cpu(
+            &mut frame,
+            WIDTH,
+            &config,
+            &Palette::default(),
+            &ScreenEffects::default(),
+        );
+        assert_eq!(pixel(&frame, 2, 2), PIXEL);
+        for (x, y) in [(0, 0), (4, 0), (0, 4), (4, 4)] {
+            let corner = pixel(&frame, x, y);
+            assert!(corner[..3].iter().zip(PIXEL).all(|(&c, p)| c < p));
+            assert_eq!(corner[3], 255);
+        }
+    }
+
+    #[test]
+    fn quantize_snaps_to_the_palette() {
+        let config = PostFxConfig {
+            quantize: true,
+            ..disabled()
+        };
+        let palette = Palette::parse_hex("ff0000\n0000ff\nffffff\n").unwrap();
+        let mut frame = frame();
+        let blue = (2 * WIDTH + 3) * 4;
+        frame[blue..blue + 4].copy_from_slice(&[20, 40, 180, 128]);
+        apply_cpu(
+            &mut frame,
+            WIDTH,
+            &config,
+            &palette,
+            &ScreenEffects::default(),
+        );
+        assert_eq!(pixel(&frame, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&frame, 3, 2), [0, 0, 255, 128]);
+
+        let mut frame = self::frame();
+        apply_cpu(
+            &mut frame,
+            WIDTH,
+            &config,
+            &Palette::default(),
+            &ScreenEffects::default(),
+        );
+        assert_eq!(frame, self::frame());
+    }
+
+    #[test]
+    fn flash() {
+        let config = PostFxConfig {
+            damage_flash: true,
+            ..disabled()
+        };
+        let mut effects = ScreenEffects {
+            flash_color: Color::RED,
+            flash_amount: 0.0,
+            desaturation: 0.0,
+        };
+        let mut frame = frame();
+        apply_cpu(&mut frame, WIDTH, &config, &Palette::default(), &effects);
+        assert_eq!(frame, self::frame());
+
+        effects.flash_amount = 1.0;
+        apply_cpu(&mut frame, WIDTH, &config, &Palette::default(), &effects);
+        assert_eq!(pixel(&frame, 3, 1), [0xe0, 0x40, 0x40, 255]);
+    }
+
+    #[test]
+    fn desaturation() {
+        let config = PostFxConfig {
+            desaturate: true,
+            ..disabled()
+        };
+        let mut effects = ScreenEffects::default();
+        let mut frame = frame();
+        apply_cpu(&mut frame, WIDTH, &config, &Palette::default(), &effects);
+        assert_eq!(frame, self::frame());
+
+        effects.desaturation = 1.0;
+        apply_cpu(&mut frame, WIDTH, &config, &Palette::default(), &effects);
+        assert_eq!(pixel(&frame, 1, 3), [124, 124, 124, 255]);
+    }
+}
@@ -0,0 +1,344 @@
+use crate::pathfinding::DIRECTIONS;
+use crate::raws::PrefabDef;
+use glam::IVec2;
+use serde::Deserialize;
+use std::fmt;
+
+/// What a legend character of a prefab puts on its floor.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
+pub(crate) enum Spawn {
+    Monster(String),
+    Item(String),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Cell {
+    /// Not part of the prefab. The map underneath is left alone.
+    Outside,
+    Wall,
+    Floor,
+    /// Floor on the edge that a corridor to the rest of the map is dug from.
+    Entrance,
+    /// Floor with a monster or item on it.
+    Spawn(Spawn),
+}
+
+impl Cell {
+    pub(crate) fn is_walkable(&self) -> bool {
+        matches!(self, Cell::Floor | Cell::Entrance | Cell::Spawn(_))
+    }
+}
+
+/// A hand-made map chunk, such as a vault or a boss arena, parsed from rows of map
+/// characters: `#` is wall, `.` floor, `+` an entrance and a space is outside of the
+/// prefab. Other characters are floor with a spawn from the legend.
+#[derive(Clone, Debug)]
+pub(crate) struct Prefab {
+    pub name: String,
+    /// Dungeon levels the prefab is placed on, inclusive.
+    pub depth: Option<(u32, u32)>,
+    /// Chance of trying to place the prefab on a level of its depth.
+    pub chance: f32,
+    pub size: IVec2,
+    cells: Vec<Cell>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) enum PrefabError {
+    Empty,
+    UnknownCharacter {
+        character: char,
+        pos: IVec2,
+    },
+    /// The legend redefines one of the characters with a fixed meaning.
+    ReservedCharacter(char),
+    NoEntrance,
+    /// An entrance with no way out of the prefab.
+    EnclosedEntrance(IVec2),
+    /// Floor that cannot be reached from any entrance.
+    Unreachable(IVec2),
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrefabError::Empty => write!(f, "the map is empty"),
+            PrefabError::UnknownCharacter { character, pos } => write!(
+                f,
+                "unknown character `{}` at {}, {}",
+                character, pos.x, pos.y
+            ),
+            PrefabError::ReservedCharacter(character) => {
+                write!(f, "`{}` cannot be used in the legend", character)
+            }
+            PrefabError::NoEntrance => write!(f, "needs at least one entrance `+`"),
+            PrefabError::EnclosedEntrance(pos) => {
+                write!(f, "the entrance at {}, {} is not on the edge", pos.x, pos.y)
+            }
+            PrefabError::Unreachable(pos) => write!(
+                f,
+                "the floor at {}, {} cannot be reached from an entrance",
+                pos.x, pos.y
+            ),
+        }
+    }
+}
+
+impl Prefab {
+    /// Parse and check `map`, the rows of `def`'s map or image. Rows shorter than the
+    /// longest are padded with outside cells.
+    pub(crate) fn parse(def: &PrefabDef, map: &[String]) -> Result<Self, PrefabError> {
+        if let Some(&reserved) = def
+            .legend
+            .keys()
+            .find(|c| matches!(c, '#' | '.' | '+' | ' '))
+        {
+            return Err(PrefabError::ReservedCharacter(reserved));
+        }
+        let width = map.iter().map(|row| row.chars().count()).max().unwrap_or(0);
+        if width == 0 {
+            return Err(PrefabError::Empty);
+        }
+        let size = IVec2::new(width as i32, map.len() as i32);
+        let mut cells = Vec::with_capacity(width * map.len());
+        for (y, row) in map.iter().enumerate() {
+            let mut row: Vec<_> = row.chars().collect();
+            row.resize(width, ' ');
+            for (x, character) in row.into_iter().enumerate() {
+                cells.push(match character {
+                    ' ' => Cell::Outside,
+                    '#' => Cell::Wall,
+                    '.' => Cell::Floor,
+                    '+' => Cell::Entrance,
+                    _ => match def.legend.get(&character) {
+                        Some(spawn) => Cell::Spawn(spawn.clone()),
+                        None => {
+                            let pos = IVec2::new(x as i32, y as i32);
+                            return Err(PrefabError::UnknownCharacter { character, pos });
+                        }
+                    },
+                });
+            }
+        }
+        let prefab = Self {
+            name: def.name.clone(),
+            depth: def.depth,
+            chance: def.chance,
+            size,
+            cells,
+        };
+        prefab.check_connected()?;
+        Ok(prefab)
+    }
+
+    /// Every entrance must lead outside, and all floor must be reachable from the entrances.
+    fn check_connected(&self) -> Result<(), PrefabError> {
+        let entrances: Vec<_> = self.entrances().collect();
+        if entrances.is_empty() {
+            return Err(PrefabError::NoEntrance);
+        }
+        if let Some(&enclosed) = entrances.iter().find(|&&pos| {
+            DIRECTIONS
+                .iter()
+                .all(|&direction| self.get(pos + direction) != &Cell::Outside)
+        }) {
+            return Err(PrefabError::EnclosedEntrance(enclosed));
+        }
+
+        let mut reached = vec![false; self.cells.len()];
+        let mut open = entrances;
+        while let Some(pos) = open.pop() {
+            let index = self.index(pos);
+            if reached[index] {
+                continue;
+            }
+            reached[index] = true;
+            for direction in DIRECTIONS {
+                let next = pos + direction;
+                if self.get(next).is_walkable() {
+                    open.push(next);
+                }
+            }
+        }
+        match self
+            .positions()
+            .find(|&pos| self.get(pos).is_walkable() && !reached[self.index(pos)])
+        {
+            Some(pos) => Err(PrefabError::Unreachable(pos)),
+            None => Ok(()),
+        }
+    }
+
+    fn index(&self, pos: IVec2) -> usize {
+        (pos.y * self.size.x + pos.x) as usize
+    }
+
+    /// Cell at `pos`. Anything beyond the edges is outside.
+    pub(crate) fn get(&self, pos: IVec2) -> &Cell {
+        let inside = pos.cmpge(IVec2::ZERO).all() && pos.cmplt(self.size).all();
+        if inside {
+            &self.cells[self.index(pos)]
+        } else {
+            &Cell::Outside
+        }
+    }
+
+    /// Every position within the prefab's bounds, row by row.
+    pub(crate) fn positions(&self) -> impl Iterator<Item = IVec2> {
+        let size = self.size;
+        (0..size.y).flat_map(move |y| (0..size.x).map(move |x| IVec2::new(x, y)))
+    }
+
+    pub(crate) fn entrances(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.positions()
+            .filter(|&pos| self.get(pos) == &Cell::Entrance)
+    }
+
+    /// Monsters and items to spawn, with their positions in the prefab.
+    pub(crate) fn spawns(&self) -> impl Iterator<Item = (IVec2, &Spawn)> + '_ {
+        self.positions().filter_map(|pos| match self.get(pos) {
+            Cell::Spawn(spawn) => Some((pos, spawn)),
+            _ => None,
+        })
+    }
+
+    /// The prefab turned a quarter clockwise `turns` times, then mirrored left to right if
+    /// `mirror` is set.
+    pub(crate) fn transformed(&self, turns: u32, mirror: bool) -> Prefab {
+        let mut prefab = self.clone();
+        for _ in 0..turns % 4 {
+            prefab = prefab.remap(IVec2::new(prefab.size.y, prefab.size.x), |size, pos| {
+                IVec2::new(pos.y, size.x - 1 - pos.x)
+            });
+        }
+        if mirror {
+            prefab = prefab.remap(prefab.size, |size, pos| {
+                IVec2::new(size.x - 1 - pos.x, pos.y)
+            });
+        }
+        prefab
+    }
+
+    /// A prefab of `size` whose cell at each position is this one's at `source(size, pos)`.
+    fn remap(&self, size: IVec2, source: impl Fn(IVec2, IVec2) -> IVec2) -> Prefab {
+        let cells = (0..size.y)
+            .flat_map(|y| (0..size.x).map(move |x| IVec2::new(x, y)))
+            .map(|pos| self.get(source(size, pos)).clone())
+            .collect();
+        Prefab {
+            size,
+            cells,
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn parse(map: &[&str], legend: &[(char, Spawn)]) -> Result<Prefab, PrefabError> {
+        let def = PrefabDef {
+            name: "test".to_string(),
+            depth: None,
+            chance: 1.0,
+            legend: legend.iter().cloned().collect(),
+            map: Vec::new(),
+            image: None,
+            colors: BTreeMap::new(),
+        };
+        let map: Vec<_> = map.iter().map(|row| row.to_string()).collect();
+        Prefab::parse(&def, &map)
+    }
+
+    fn goblin() -> (char, Spawn) {
+        ('g', Spawn::Monster("goblin".to_string()))
+    }
+
+    /// 7 by 5, with the entrance in the top left corner and a goblin in the bottom left.
+    fn lopsided() -> Prefab {
+        let map = [
+            "+.....#", //
+            ".#.....", ".......", "...#...", "g......",
+        ];
+        parse(&map, &[goblin()]).unwrap()
+    }
+
+    fn assert_same(a: &Prefab, b: &Prefab) {
+        assert_eq!(a.size, b.size);
+        assert_eq!(a.cells, b.cells);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse(&[], &[]).unwrap_err(), PrefabError::Empty);
+        assert_eq!(parse(&["", ""], &[]).unwrap_err(), PrefabError::Empty);
+        assert_eq!(
+            parse(&["#+#", "#x#"], &[]).unwrap_err(),
+            PrefabError::UnknownCharacter {
+                character: 'x',
+                pos: IVec2::new(1, 1)
+            }
+        );
+        let legend = [('#', Spawn::Item("gold".to_string()))];
+        assert_eq!(
+            parse(&["#+#"], &legend).unwrap_err(),
+            PrefabError::ReservedCharacter('#')
+        );
+        assert_eq!(
+            parse(&["###", "#.#", "###"], &[]).unwrap_err(),
+            PrefabError::NoEntrance
+        );
+        assert_eq!(
+            parse(&["#####", "#.+.#", "#####"], &[]).unwrap_err(),
+            PrefabError::EnclosedEntrance(IVec2::new(2, 1))
+        );
+        assert_eq!(
+            parse(&["#+###", "#.#.#", "#####"], &[]).unwrap_err(),
+            PrefabError::Unreachable(IVec2::new(3, 1))
+        );
+    }
+
+    #[test]
+    fn short_rows_are_padded() {
+        let prefab = parse(&["#+#", "#g", "#"], &[goblin()]).unwrap();
+        assert_eq!(prefab.size, IVec2::new(3, 3));
+        assert_eq!(prefab.get(IVec2::new(2, 1)), &Cell::Outside);
+        assert_eq!(prefab.get(IVec2::new(1, 2)), &Cell::Outside);
+        assert_eq!(prefab.get(IVec2::new(-1, 0)), &Cell::Outside);
+        assert_eq!(prefab.spawns().count(), 1);
+    }
+
+    #[test]
+    fn quarter_turn() {
+        let prefab = lopsided();
+        let turned = prefab.transformed(1, false);
+        assert_eq!(turned.size, IVec2::new(5, 7));
+        // Turning clockwise takes the bottom left corner to the top left, and the top left
+        // corner to the top right.
+        assert_eq!(
+            turned.get(IVec2::new(0, 0)),
+            &Cell::Spawn(Spawn::Monster("goblin".to_string()))
+        );
+        assert_eq!(turned.get(IVec2::new(4, 0)), &Cell::Entrance);
+        assert_eq!(turned.get(IVec2::new(4, 6)), &Cell::Wall);
+        assert_eq!(turned.spawns().count(), 1);
+        assert_eq!(turned.entrances().collect::<Vec<_>>(), [IVec2::new(4, 0)]);
+    }
+
+    #[test]
+    fn full_turns_and_double_mirror_restore() {
+        let prefab = lopsided();
+        let mut turned = prefab.clone();
+        for _ in 0..4 {
+            turned = turned.transformed(1, false);
+        }
+        assert_same(&turned, &prefab);
+        assert_same(&prefab.transformed(4, false), &prefab);
+
+        let mirrored = prefab.transformed(0, true);
+        assert_eq!(mirrored.get(IVec2::new(6, 0)), &Cell::Entrance);
+        assert_same(&mirrored.transformed(0, true), &prefab);
+    }
+}
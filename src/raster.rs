@@ -0,0 +1,741 @@
+//! CPU rasterisation into a tightly packed RGBA8 frame buffer.
+//!
+//! Everything here works on whole row slices: shapes are clipped against the frame once and
+//! then filled or copied row by row. With the `parallel` feature large operations split their
+//! rows over the rayon thread pool.
+
+use crate::geometry;
+use glam::{Affine2, IVec2, Vec2};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Operations touching fewer pixels than this are not worth splitting over threads.
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 128 * 128;
+
+const WHITE: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+
+/// Borrowed RGBA8 image data, such as a sprite sheet.
+#[derive(Clone, Copy)]
+pub(crate) struct ImageRef<'a> {
+    pub data: &'a [u8],
+    pub width: usize,
+    pub height: usize,
+    /// True when no pixel is transparent, which allows rows to be copied without alpha tests.
+    pub opaque: bool,
+    /// Colour every pixel is multiplied with when drawn, white leaves the image unchanged.
+    /// Tinted pixels are blended instead of copied, so a translucent tint fades the image.
+    pub tint: [u8; 4],
+}
+
+impl<'a> ImageRef<'a> {
+    pub(crate) fn new(data: &'a [u8], width: usize, height: usize) -> Self {
+        assert_eq!(data.len(), width * height * 4);
+        Self {
+            data,
+            width,
+            height,
+            opaque: data.chunks_exact(4).all(|pixel| pixel[3] > 0),
+            tint: WHITE,
+        }
+    }
+
+    pub(crate) fn with_tint(self, tint: [u8; 4]) -> Self {
+        Self { tint, ..self }
+    }
+}
+
+/// Rectangle in pixels, used to select part of an image.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+pub(crate) struct Canvas<'a> {
+    data: &'a mut [u8],
+    width: usize,
+    height: usize,
+}
+
+impl<'a> Canvas<'a> {
+    pub(crate) fn new(data: &'a mut [u8], width: usize, height: usize) -> Self {
+        assert_eq!(data.len(), width * height * 4);
+        Self {
+            data,
+            width,
+            height,
+        }
+    }
+
+    pub(crate) fn clear(&mut self, color: [u8; 4]) {
+        self.for_each_row(0, self.height, self.width, move |_, row| {
+            for pixel in row.chunks_exact_mut(4) {
+                pixel.copy_from_slice(&color);
+            }
+        });
+    }
+
+    /// Fill a rectangle, clipped against the frame. Translucent colours are blended.
+    pub(crate) fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: [u8; 4]) {
+        if let Some((x0, x1, y0, y1)) = self.clip(x, y, width, height) {
+            self.for_each_row(y0, y1, x1 - x0, move |_, row| {
+                fill(&mut row[x0 * 4..x1 * 4], color)
+            });
+        }
+    }
+
+    /// Copy `src_rect` of `image` to (`x`, `y`), enlarged by a whole `scale` factor.
+    /// Pixels with zero alpha are skipped, everything else overwrites the frame, or is
+    /// blended into it when the image is tinted.
+    pub(crate) fn blit(&mut self, x: i32, y: i32, image: ImageRef, src_rect: Rect, scale: usize) {
+        debug_assert!(
+            src_rect.x + src_rect.width <= image.width
+                && src_rect.y + src_rect.height <= image.height,
+            "source rectangle outside of the image"
+        );
+        let scale = scale.max(1);
+        let dest_width = (src_rect.width * scale) as i32;
+        let dest_height = (src_rect.height * scale) as i32;
+        let Some((x0, x1, y0, y1)) = self.clip(x, y, dest_width, dest_height) else {
+            return;
+        };
+
+        self.for_each_row(y0, y1, x1 - x0, move |dest_y, row| {
+            let src_y = if scale == 1 {
+                src_rect.y + (dest_y as i32 - y) as usize
+            } else {
+                src_rect.y + (dest_y as i32 - y) as usize / scale
+            };
+            let src_row = &image.data[src_y * image.width * 4..(src_y + 1) * image.width * 4];
+            let dest = &mut row[x0 * 4..x1 * 4];
+            let first_x = (x0 as i32 - x) as usize;
+
+            if scale == 1 {
+                let src =
+                    &src_row[(src_rect.x + first_x) * 4..(src_rect.x + first_x) * 4 + dest.len()];
+                if image.opaque && image.tint == WHITE {
+                    dest.copy_from_slice(src);
+                } else {
+                    for (dest, src) in dest.chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+                        copy_if_visible(dest, src, image.tint);
+                    }
+                }
+            } else {
+                // Step through the source with a counter instead of dividing per pixel.
+                let mut src_x = src_rect.x + first_x / scale;
+                let mut repeat = first_x % scale;
+                for dest in dest.chunks_exact_mut(4) {
+                    copy_if_visible(dest, &src_row[src_x * 4..src_x * 4 + 4], image.tint);
+                    repeat += 1;
+                    if repeat == scale {
+                        repeat = 0;
+                        src_x += 1;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Draw `src_rect` of `image` through an arbitrary affine `transform`, which maps pixel
+    /// coordinates inside `src_rect` to frame coordinates. Every covered frame pixel is mapped
+    /// back into the source and sampled with nearest neighbour filtering.
+    pub(crate) fn blit_transformed(&mut self, image: ImageRef, src_rect: Rect, transform: Affine2) {
+        debug_assert!(
+            src_rect.x + src_rect.width <= image.width
+                && src_rect.y + src_rect.height <= image.height,
+            "source rectangle outside of the image"
+        );
+        let size = Vec2::new(src_rect.width as f32, src_rect.height as f32);
+        let corners = [
+            Vec2::ZERO,
+            Vec2::new(size.x, 0.0),
+            Vec2::new(0.0, size.y),
+            size,
+        ]
+        .map(|corner| transform.transform_point2(corner));
+        let min = corners
+            .iter()
+            .fold(Vec2::splat(f32::MAX), |a, b| a.min(*b))
+            .floor();
+        let max = corners
+            .iter()
+            .fold(Vec2::splat(f32::MIN), |a, b| a.max(*b))
+            .ceil();
+
+        let Some((x0, x1, y0, y1)) = self.clip(
+            min.x as i32,
+            min.y as i32,
+            (max.x - min.x) as i32,
+            (max.y - min.y) as i32,
+        ) else {
+            return;
+        };
+
+        let inverse = transform.inverse();
+        self.for_each_row(y0, y1, x1 - x0, move |dest_y, row| {
+            for (i, dest) in row[x0 * 4..x1 * 4].chunks_exact_mut(4).enumerate() {
+                let center = Vec2::new((x0 + i) as f32 + 0.5, dest_y as f32 + 0.5);
+                let local = inverse.transform_point2(center).floor();
+                if local.x < 0.0 || local.y < 0.0 || local.x >= size.x || local.y >= size.y {
+                    continue;
+                }
+
+                let src_x = src_rect.x + local.x as usize;
+                let src_y = src_rect.y + local.y as usize;
+                let index = (src_y * image.width + src_x) * 4;
+                copy_if_visible(dest, &image.data[index..index + 4], image.tint);
+            }
+        });
+    }
+
+    /// Blend `color` into the frame using an 8-bit coverage mask, such as a rasterized glyph.
+    /// The alpha of `color` scales the coverage.
+    pub(crate) fn blend_mask(
+        &mut self,
+        x: i32,
+        y: i32,
+        mask: &[u8],
+        mask_width: usize,
+        color: [u8; 4],
+    ) {
+        if mask_width == 0 {
+            return;
+        }
+        let mask_height = mask.len() / mask_width;
+        let Some((x0, x1, y0, y1)) = self.clip(x, y, mask_width as i32, mask_height as i32) else {
+            return;
+        };
+
+        self.for_each_row(y0, y1, x1 - x0, move |dest_y, row| {
+            let mask_y = (dest_y as i32 - y) as usize;
+            let first_x = (x0 as i32 - x) as usize;
+            let mask_row = &mask[mask_y * mask_width + first_x..];
+
+            for (dest, coverage) in row[x0 * 4..x1 * 4].chunks_exact_mut(4).zip(mask_row) {
+                let alpha = div_255(*coverage as u32 * color[3] as u32);
+                if alpha > 0 {
+                    blend(dest, color, alpha);
+                }
+            }
+        });
+    }
+
+    /// Blend a single pixel, ignoring positions outside of the frame.
+    pub(crate) fn plot(&mut self, x: i32, y: i32, color: [u8; 4]) {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            let index = (y as usize * self.width + x as usize) * 4;
+            fill(&mut self.data[index..index + 4], color);
+        }
+    }
+
+    /// Blend a horizontal run of pixels from `x0` to `x1` inclusive.
+    fn span(&mut self, x0: i32, x1: i32, y: i32, color: [u8; 4]) {
+        if let Some((x0, x1, y0, _)) = self.clip(x0, y, x1 - x0 + 1, 1) {
+            let row = y0 * self.width * 4;
+            fill(&mut self.data[row + x0 * 4..row + x1 * 4], color);
+        }
+    }
+
+    pub(crate) fn line(&mut self, start: IVec2, end: IVec2, color: [u8; 4]) {
+        for point in geometry::line(start, end) {
+            self.plot(point.x, point.y, color);
+        }
+    }
+
+    /// One pixel wide rectangle outline. Corners are only drawn once, so translucent
+    /// outlines blend evenly.
+    pub(crate) fn rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: [u8; 4]) {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        let (right, bottom) = (x + width - 1, y + height - 1);
+
+        self.span(x, right, y, color);
+        if bottom > y {
+            self.span(x, right, bottom, color);
+        }
+        for y in y + 1..bottom {
+            self.plot(x, y, color);
+            if right > x {
+                self.plot(right, y, color);
+            }
+        }
+    }
+
+    /// Circle outline using the midpoint algorithm.
+    pub(crate) fn circle(&mut self, center: IVec2, radius: i32, color: [u8; 4]) {
+        if radius <= 0 {
+            self.plot(center.x, center.y, color);
+            return;
+        }
+
+        let mut points = Vec::new();
+        let (mut x, mut y, mut error) = (radius, 0, 1 - radius);
+        while x >= y {
+            for (dx, dy) in [
+                (x, y),
+                (y, x),
+                (-y, x),
+                (-x, y),
+                (-x, -y),
+                (-y, -x),
+                (y, -x),
+                (x, -y),
+            ] {
+                points.push(IVec2::new(center.x + dx, center.y + dy));
+            }
+            y += 1;
+            if error < 0 {
+                error += 2 * y + 1;
+            } else {
+                x -= 1;
+                error += 2 * (y - x) + 1;
+            }
+        }
+
+        // The octants share their end points, only plot each pixel once.
+        points.sort_by_key(|p| (p.y, p.x));
+        points.dedup();
+        for point in points {
+            self.plot(point.x, point.y, color);
+        }
+    }
+
+    pub(crate) fn fill_circle(&mut self, center: IVec2, radius: i32, color: [u8; 4]) {
+        for dy in -radius..=radius {
+            // Widened slightly so the edge matches the midpoint outline of the same radius.
+            let half_width =
+                ((radius * radius - dy * dy) as f32 + radius as f32 * 0.8).sqrt() as i32;
+            self.span(
+                center.x - half_width,
+                center.x + half_width,
+                center.y + dy,
+                color,
+            );
+        }
+    }
+
+    /// Polygon outline through `points`, closed back to the first point.
+    pub(crate) fn polygon(&mut self, points: &[IVec2], color: [u8; 4]) {
+        for (i, start) in points.iter().enumerate() {
+            let end = points[(i + 1) % points.len()];
+            // Skip the end point, it is the start of the next edge.
+            let edge: Vec<_> = geometry::line(*start, end).collect();
+            for point in &edge[..edge.len().saturating_sub(1).max(1)] {
+                self.plot(point.x, point.y, color);
+            }
+        }
+    }
+
+    /// Fill a polygon with the even-odd rule, sampling at pixel centers.
+    pub(crate) fn fill_polygon(&mut self, points: &[Vec2], color: [u8; 4]) {
+        if points.len() < 3 {
+            return;
+        }
+
+        let min_y = points.iter().map(|p| p.y).fold(f32::MAX, f32::min).floor() as i32;
+        let max_y = points.iter().map(|p| p.y).fold(f32::MIN, f32::max).ceil() as i32;
+        let mut crossings = Vec::new();
+
+        for y in min_y.max(0)..max_y.min(self.height as i32) {
+            let sample_y = y as f32 + 0.5;
+            crossings.clear();
+
+            for (i, a) in points.iter().enumerate() {
+                let b = points[(i + 1) % points.len()];
+                if (a.y <= sample_y) != (b.y <= sample_y) {
+                    crossings.push(a.x + (sample_y - a.y) / (b.y - a.y) * (b.x - a.x));
+                }
+            }
+            crossings.sort_by(|a, b| a.total_cmp(b));
+
+            for pair in crossings.chunks_exact(2) {
+                // Pixels whose centers lie between the two crossings.
+                let x0 = (pair[0] - 0.5).ceil() as i32;
+                let x1 = (pair[1] - 0.5).ceil() as i32 - 1;
+                if x1 >= x0 {
+                    self.span(x0, x1, y, color);
+                }
+            }
+        }
+    }
+
+    /// Intersect a rectangle with the frame, as pixel ranges `(x0, x1, y0, y1)`.
+    fn clip(
+        &self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    ) -> Option<(usize, usize, usize, usize)> {
+        let x0 = x.clamp(0, self.width as i32) as usize;
+        let x1 = x.saturating_add(width).clamp(0, self.width as i32) as usize;
+        let y0 = y.clamp(0, self.height as i32) as usize;
+        let y1 = y.saturating_add(height).clamp(0, self.height as i32) as usize;
+
+        if x0 < x1 && y0 < y1 {
+            Some((x0, x1, y0, y1))
+        } else {
+            None
+        }
+    }
+
+    /// Run `f` for every frame row in `y0..y1`, passing the row index and its pixels.
+    /// `columns` is the number of pixels `f` touches per row, used to decide if the work is
+    /// large enough to be split over threads.
+    fn for_each_row<F>(&mut self, y0: usize, y1: usize, columns: usize, f: F)
+    where
+        F: Fn(usize, &mut [u8]) + Send + Sync,
+    {
+        #[cfg(not(feature = "parallel"))]
+        let _ = columns;
+
+        let stride = self.width * 4;
+        let rows = &mut self.data[y0 * stride..y1 * stride];
+
+        #[cfg(feature = "parallel")]
+        if (y1 - y0) * columns >= PARALLEL_THRESHOLD {
+            rows.par_chunks_exact_mut(stride)
+                .enumerate()
+                .for_each(|(i, row)| f(y0 + i, row));
+            return;
+        }
+
+        for (i, row) in rows.chunks_exact_mut(stride).enumerate() {
+            f(y0 + i, row);
+        }
+    }
+}
+
+/// Fill a row slice with a single colour, blending it when it is translucent.
+fn fill(row: &mut [u8], color: [u8; 4]) {
+    if color[3] == 0xff {
+        for pixel in row.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    } else if color[3] > 0 {
+        for pixel in row.chunks_exact_mut(4) {
+            blend(pixel, color, color[3] as u32);
+        }
+    }
+}
+
+/// Copy a source pixel over a destination pixel unless it is fully transparent. Untinted
+/// pixels are a select on whole pixels so it compiles without a branch. Tinted pixels are
+/// multiplied by `tint` and blended with their alpha times the tint's alpha.
+#[inline(always)]
+fn copy_if_visible(dest: &mut [u8], src: &[u8], tint: [u8; 4]) {
+    if tint != WHITE {
+        let alpha = div_255(src[3] as u32 * tint[3] as u32);
+        if alpha > 0 {
+            let color = [0, 1, 2, 3]
+                .map(|channel| div_255(src[channel] as u32 * tint[channel] as u32) as u8);
+            blend(dest, color, alpha);
+        }
+        return;
+    }
+
+    let src_pixel = u32::from_ne_bytes([src[0], src[1], src[2], src[3]]);
+    let dest_pixel = u32::from_ne_bytes([dest[0], dest[1], dest[2], dest[3]]);
+    let pixel = if src[3] > 0 { src_pixel } else { dest_pixel };
+    dest.copy_from_slice(&pixel.to_ne_bytes());
+}
+
+/// Blend `color` over a pixel with the given alpha (0-255). The result is opaque.
+fn blend(pixel: &mut [u8], color: [u8; 4], alpha: u32) {
+    if alpha >= 255 {
+        pixel.copy_from_slice(&[color[0], color[1], color[2], 0xff]);
+        return;
+    }
+    let inverse = 255 - alpha;
+    for channel in 0..3 {
+        pixel[channel] =
+            div_255(color[channel] as u32 * alpha + pixel[channel] as u32 * inverse) as u8;
+    }
+    pixel[3] = 0xff;
+}
+
+/// Exact `x / 255` for `x` in `0..=255 * 255`, without a division.
+fn div_255(x: u32) -> u32 {
+    (x + 1 + (x >> 8)) >> 8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [0xff, 0, 0, 0xff];
+    const GREEN: [u8; 4] = [0, 0xff, 0, 0xff];
+    const BLACK: [u8; 4] = [0, 0, 0, 0xff];
+    const BLUE: [u8; 4] = [0, 0, 0xff, 0xff];
+    const CLEAR: [u8; 4] = [0, 0, 0, 0];
+
+    /// A 2x2 image with a distinct colour in every pixel: red, green, blue and white.
+    fn quad() -> Vec<u8> {
+        [RED, GREEN, BLUE, WHITE].concat()
+    }
+
+    fn pixel(frame: &[u8], width: usize, x: usize, y: usize) -> [u8; 4] {
+        let index = (y * width + x) * 4;
+        frame[index..index + 4].try_into().unwrap()
+    }
+
+    fn count(frame: &[u8], color: [u8; 4]) -> usize {
+        frame
+            .chunks_exact(4)
+            .filter(|pixel| *pixel == color)
+            .count()
+    }
+
+    #[test]
+    fn blit_is_clipped_to_the_frame() {
+        let image = quad();
+        let image = ImageRef::new(&image, 2, 2);
+        let full = Rect {
+            x: 0,
+            y: 0,
+            width: 2,
+            height: 2,
+        };
+        let mut frame = vec![0; 4 * 4 * 4];
+        let mut canvas = Canvas::new(&mut frame, 4, 4);
+        canvas.clear(BLACK);
+
+        // Entirely outside, on every side and far away.
+        for (x, y) in [(-6, 0), (4, 0), (0, -6), (0, 4), (i32::MIN, i32::MAX)] {
+            canvas.blit(x, y, image, full, 1);
+            canvas.blit(x, y, image, full, 3);
+        }
+        assert_eq!(count(&frame, BLACK), 16);
+
+        // Half over the top-left corner, only the white pixel lands.
+        let mut canvas = Canvas::new(&mut frame, 4, 4);
+        canvas.blit(-1, -1, image, full, 1);
+        assert_eq!(pixel(&frame, 4, 0, 0), WHITE);
+        assert_eq!(count(&frame, BLACK), 15);
+
+        // Half over the bottom-right corner, only the red pixel lands.
+        let mut canvas = Canvas::new(&mut frame, 4, 4);
+        canvas.blit(3, 3, image, full, 1);
+        assert_eq!(pixel(&frame, 4, 3, 3), RED);
+        assert_eq!(count(&frame, BLACK), 14);
+    }
+
+    #[test]
+    fn scaled_blit_repeats_pixels() {
+        let image = quad();
+        let image = ImageRef::new(&image, 2, 2);
+        let green = Rect {
+            x: 1,
+            y: 0,
+            width: 1,
+            height: 2,
+        };
+        let mut frame = vec![0; 5 * 6 * 4];
+        let mut canvas = Canvas::new(&mut frame, 5, 6);
+        canvas.clear(BLACK);
+        canvas.blit(1, 0, image, green, 3);
+
+        for y in 0..6 {
+            for x in 0..5 {
+                let expected = match (x, y) {
+                    (1..=3, 0..=2) => GREEN,
+                    (1..=3, 3..=5) => WHITE,
+                    _ => BLACK,
+                };
+                assert_eq!(pixel(&frame, 5, x, y), expected, "({}, {})", x, y);
+            }
+        }
+
+        // Clipped on the left part way through repeating the first column.
+        let mut canvas = Canvas::new(&mut frame, 5, 6);
+        canvas.clear(BLACK);
+        canvas.blit(
+            -2,
+            0,
+            image,
+            Rect {
+                x: 0,
+                width: 2,
+                ..green
+            },
+            3,
+        );
+        for x in 0..5 {
+            let expected = match x {
+                0 => [RED, BLUE],
+                1..=3 => [GREEN, WHITE],
+                _ => [BLACK, BLACK],
+            };
+            assert_eq!(pixel(&frame, 5, x, 0), expected[0], "{}", x);
+            assert_eq!(pixel(&frame, 5, x, 5), expected[1], "{}", x);
+        }
+    }
+
+    #[test]
+    fn transparent_pixels_are_skipped() {
+        let image = [RED, CLEAR].concat();
+        let image = ImageRef::new(&image, 2, 1);
+        assert!(!image.opaque);
+        let mut frame = vec![0; 2 * 4];
+        let mut canvas = Canvas::new(&mut frame, 2, 1);
+        canvas.clear(GREEN);
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            width: 2,
+            height: 1,
+        };
+        canvas.blit(0, 0, image, rect, 1);
+        assert_eq!(frame, [RED, GREEN].concat());
+    }
+
+    #[test]
+    fn transformed_blit_flips_and_turns() {
+        let image = quad();
+        let image = ImageRef::new(&image, 2, 2);
+        let full = Rect {
+            x: 0,
+            y: 0,
+            width: 2,
+            height: 2,
+        };
+        let mut frame = vec![0; 2 * 2 * 4];
+
+        let flip_x = Affine2::from_translation(Vec2::new(2.0, 0.0))
+            * Affine2::from_scale(Vec2::new(-1.0, 1.0));
+        Canvas::new(&mut frame, 2, 2).blit_transformed(image, full, flip_x);
+        assert_eq!(frame, [GREEN, RED, WHITE, BLUE].concat());
+
+        let quarter_turn = Affine2::from_translation(Vec2::new(2.0, 0.0))
+            * Affine2::from_angle(std::f32::consts::FRAC_PI_2);
+        Canvas::new(&mut frame, 2, 2).blit_transformed(image, full, quarter_turn);
+        assert_eq!(frame, [BLUE, RED, WHITE, GREEN].concat());
+    }
+
+    #[test]
+    fn transformed_blit_is_clipped_to_the_frame() {
+        let image = vec![0xff; 8 * 8 * 4];
+        let image = ImageRef::new(&image, 8, 8);
+        let full = Rect {
+            x: 0,
+            y: 0,
+            width: 8,
+            height: 8,
+        };
+        let mut frame = vec![0; 4 * 4 * 4];
+        let mut canvas = Canvas::new(&mut frame, 4, 4);
+        canvas.clear(BLACK);
+        // Centered on the frame's top-left corner and turned 45 degrees into a diamond.
+        let transform = Affine2::from_angle(std::f32::consts::FRAC_PI_4)
+            * Affine2::from_translation(Vec2::splat(-4.0));
+        canvas.blit_transformed(image, full, transform);
+        canvas.blit_transformed(image, full, Affine2::from_translation(Vec2::splat(-100.0)));
+        assert_eq!(pixel(&frame, 4, 0, 0), WHITE);
+        assert_eq!(pixel(&frame, 4, 3, 3), BLACK);
+    }
+
+    #[test]
+    fn lines_include_both_end_points() {
+        let mut frame = vec![0; 8 * 8 * 4];
+        let mut canvas = Canvas::new(&mut frame, 8, 8);
+        canvas.clear(BLACK);
+        canvas.line(IVec2::new(1, 6), IVec2::new(6, 2), RED);
+        assert_eq!(pixel(&frame, 8, 1, 6), RED);
+        assert_eq!(pixel(&frame, 8, 6, 2), RED);
+        assert_eq!(count(&frame, RED), 6);
+
+        // Lines leaving the frame are clipped per pixel.
+        let mut canvas = Canvas::new(&mut frame, 8, 8);
+        canvas.clear(BLACK);
+        canvas.line(IVec2::new(-4, 0), IVec2::new(11, 0), RED);
+        assert_eq!(count(&frame, RED), 8);
+    }
+
+    #[test]
+    fn circle_pixel_counts() {
+        let mut frame = vec![0; 16 * 16 * 4];
+        let center = IVec2::new(8, 8);
+        let mut canvas = Canvas::new(&mut frame, 16, 16);
+        canvas.clear(BLACK);
+        canvas.fill_circle(center, 0, RED);
+        assert_eq!(count(&frame, RED), 1);
+
+        let mut canvas = Canvas::new(&mut frame, 16, 16);
+        canvas.fill_circle(center, 2, RED);
+        assert_eq!(count(&frame, RED), 3 + 5 + 5 + 5 + 3);
+
+        // The outline of the same radius lies on the edge of the fill.
+        let mut canvas = Canvas::new(&mut frame, 16, 16);
+        canvas.clear(BLACK);
+        canvas.fill_circle(center, 5, RED);
+        let filled = count(&frame, RED);
+        let mut canvas = Canvas::new(&mut frame, 16, 16);
+        canvas.circle(center, 5, GREEN);
+        assert_eq!(count(&frame, RED) + count(&frame, GREEN), filled);
+        assert_eq!(count(&frame, GREEN), 28);
+    }
+
+    #[test]
+    fn shapes_blend_each_pixel_once() {
+        let half_red = [0xff, 0, 0, 0x80];
+        let once = {
+            let mut pixel = BLACK;
+            blend(&mut pixel, half_red, 0x80);
+            pixel
+        };
+
+        // Two triangles sharing a diagonal cover the square exactly once.
+        let mut frame = vec![0; 8 * 8 * 4];
+        let mut canvas = Canvas::new(&mut frame, 8, 8);
+        canvas.clear(BLACK);
+        let [a, b, c, d] = [(1.0, 1.0), (5.0, 1.0), (5.0, 5.0), (1.0, 5.0)].map(Vec2::from);
+        canvas.fill_polygon(&[a, b, c], half_red);
+        canvas.fill_polygon(&[a, c, d], half_red);
+        assert_eq!(count(&frame, once), 16);
+        assert_eq!(count(&frame, BLACK), 64 - 16);
+
+        let mut canvas = Canvas::new(&mut frame, 8, 8);
+        canvas.clear(BLACK);
+        let corners = [(1, 1), (4, 1), (4, 4), (1, 4)].map(IVec2::from);
+        canvas.polygon(&corners, half_red);
+        assert_eq!(count(&frame, once), 12);
+        let mut outline = vec![0; 8 * 8 * 4];
+        let mut canvas = Canvas::new(&mut outline, 8, 8);
+        canvas.clear(BLACK);
+        canvas.rect(1, 1, 4, 4, half_red);
+        assert_eq!(outline, frame);
+
+        let mut canvas = Canvas::new(&mut frame, 8, 8);
+        canvas.clear(BLACK);
+        canvas.circle(IVec2::new(4, 4), 3, half_red);
+        assert_eq!(count(&frame, BLACK) + count(&frame, once), 64);
+    }
+
+    #[test]
+    fn tinted_pixels_are_blended() {
+        let image = [RED, [0xff, 0xff, 0xff, 0x80], CLEAR].concat();
+        let image = ImageRef::new(&image, 3, 1);
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            width: 3,
+            height: 1,
+        };
+        let mut frame = vec![0; 3 * 4];
+        let mut canvas = Canvas::new(&mut frame, 3, 1);
+        canvas.clear(BLACK);
+        canvas.blit(0, 0, image.with_tint([0xff, 0xff, 0, 0xff]), rect, 1);
+        // Yellow takes the red through and the translucent white blends to half yellow.
+        assert_eq!(frame, [RED, [0x80, 0x80, 0, 0xff], BLACK].concat());
+
+        let mut canvas = Canvas::new(&mut frame, 3, 1);
+        canvas.clear(BLACK);
+        canvas.blit(0, 0, image.with_tint([0xff, 0xff, 0xff, 0x80]), rect, 1);
+        assert_eq!(frame[..4], [0x80, 0, 0, 0xff]);
+        assert_eq!(frame[4..8], [0x40, 0x40, 0x40, 0xff]);
+    }
+}
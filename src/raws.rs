@@ -0,0 +1,1159 @@
+use crate::ai::{self, Brain};
+use crate::autotile::Autotile;
+use crate::color::Color;
+use crate::combat::{DamageType, Inflict, Resistances};
+use crate::easing::Easing;
+use crate::entity::{AiKind, Entity, Equipment, Slot, Stats};
+use crate::inventory::{Inventory, ItemStack};
+use crate::lighting::Light;
+use crate::map::TileType;
+use crate::mapgen::LevelSample;
+use crate::palette::Palette;
+use crate::prefab::{Prefab, Spawn};
+use crate::rng::{Dice, Rng};
+use crate::scheduler::ACTION_COST;
+use crate::sprite::Sprite;
+use crate::status::{StatusEffect, StatusKind};
+use crate::wfc::{Model, Sample};
+use glam::IVec2;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// A sprite sheet. Animated sheets hold `frames` frames side by side.
+#[derive(Deserialize)]
+pub(crate) struct SpriteDef {
+    pub path: PathBuf,
+    #[serde(default = "default_frames")]
+    pub frames: u32,
+    /// Animation speed in frames per second.
+    #[serde(default = "default_fps")]
+    pub fps: f32,
+}
+
+fn default_frames() -> u32 {
+    1
+}
+
+fn default_fps() -> f32 {
+    8.0
+}
+
+/// How a tile type looks in both presentations. Colours are palette names, colour names
+/// or hex codes.
+#[derive(Deserialize)]
+pub(crate) struct TileDef {
+    pub glyph: char,
+    pub color: String,
+    pub glyph_color: String,
+    /// Tile art picked by the neighbours. Tiles without it are drawn as a square of `color`.
+    #[serde(default)]
+    pub autotile: Option<AutotileDef>,
+}
+
+/// Tile art from a sprite sheet, with a frame for every arrangement of alike neighbours.
+/// The art is tinted with the colour of the tile.
+#[derive(Deserialize)]
+pub(crate) struct AutotileDef {
+    pub mask: Autotile,
+    pub sprite: String,
+    /// Other tile types that count as alike, e.g. doors for walls.
+    #[serde(default)]
+    pub joins: Vec<TileType>,
+}
+
+/// Resolved appearance of a tile type.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TileStyle {
+    pub glyph: char,
+    /// Fill colour used by the tile presentation.
+    pub color: Color,
+    /// Glyph colour used by the ASCII presentation.
+    pub glyph_color: Color,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct StatsDef {
+    pub hp: i32,
+    #[serde(default)]
+    pub to_hit: i32,
+    /// Unarmed attack.
+    #[serde(deserialize_with = "from_str")]
+    pub attack: Dice,
+    #[serde(default = "default_damage_type")]
+    pub damage_type: DamageType,
+    #[serde(default)]
+    pub defense: i32,
+    #[serde(default)]
+    pub armour: i32,
+    /// Percentage of damage ignored by type, negative for weaknesses.
+    #[serde(default)]
+    pub resist: Resistances,
+    /// Status effect the unarmed attack causes.
+    #[serde(default)]
+    pub inflict: Option<InflictDef>,
+}
+
+/// A status effect caused by hits with some `chance`.
+#[derive(Deserialize)]
+pub(crate) struct InflictDef {
+    pub status: StatusKind,
+    pub turns: u32,
+    #[serde(default = "default_potency")]
+    pub potency: i32,
+    #[serde(default = "default_chance")]
+    pub chance: f32,
+}
+
+fn default_potency() -> i32 {
+    1
+}
+
+impl InflictDef {
+    pub(crate) fn to_inflict(&self) -> Inflict {
+        Inflict {
+            effect: StatusEffect {
+                kind: self.status,
+                turns: self.turns,
+                potency: self.potency,
+            },
+            chance: self.chance,
+        }
+    }
+}
+
+fn default_sight() -> i32 {
+    ai::DEFAULT_SIGHT
+}
+
+fn default_damage_type() -> DamageType {
+    DamageType::Blunt
+}
+
+/// Makes an item a melee weapon, or a ranged weapon with a `range`.
+#[derive(Deserialize)]
+pub(crate) struct WeaponDef {
+    #[serde(default)]
+    pub to_hit: i32,
+    #[serde(deserialize_with = "from_str")]
+    pub damage: Dice,
+    #[serde(default = "default_damage_type")]
+    pub damage_type: DamageType,
+    #[serde(default)]
+    pub range: Option<i32>,
+    #[serde(default)]
+    pub inflict: Option<InflictDef>,
+}
+
+/// Makes an item wearable armour.
+#[derive(Deserialize)]
+pub(crate) struct ArmourDef {
+    pub armour: i32,
+    #[serde(default)]
+    pub resist: Resistances,
+}
+
+/// Makes an item a ring, worn on either hand.
+#[derive(Deserialize)]
+pub(crate) struct RingDef {
+    #[serde(default)]
+    pub to_hit: i32,
+    #[serde(default)]
+    pub defense: i32,
+    #[serde(default)]
+    pub resist: Resistances,
+}
+
+/// What happens when an item is used. Used items are consumed.
+#[derive(Clone, Copy, Deserialize)]
+pub(crate) enum UseEffect {
+    /// Restore health, up to the maximum.
+    Heal(#[serde(deserialize_with = "from_str")] Dice),
+    /// Put a status effect on the user.
+    Status {
+        status: StatusKind,
+        turns: u32,
+        #[serde(default = "default_potency")]
+        potency: i32,
+    },
+    /// Crumble the walls within `radius` tiles of the target. The edge of the map holds.
+    Shatter { radius: i32 },
+}
+
+/// Starting inventory of a monster.
+#[derive(Deserialize)]
+pub(crate) struct InventoryDef {
+    /// Most weight the monster can carry.
+    pub capacity: u32,
+    #[serde(default)]
+    pub items: Vec<(String, u32)>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct LightDef {
+    pub color: String,
+    pub radius: i32,
+    pub intensity: f32,
+    #[serde(default = "default_falloff")]
+    pub falloff: Easing,
+    #[serde(default)]
+    pub flicker: f32,
+}
+
+fn default_falloff() -> Easing {
+    Easing::Linear
+}
+
+#[derive(Deserialize)]
+pub(crate) struct MonsterDef {
+    pub name: String,
+    pub glyph: char,
+    pub color: String,
+    pub sprite: String,
+    pub stats: StatsDef,
+    /// Monsters without a brain stand still. The player has none.
+    #[serde(default)]
+    pub ai: Option<AiKind>,
+    /// Tiles the monster sees the player from.
+    #[serde(default = "default_sight")]
+    pub sight: i32,
+    /// Fraction of its health below which the monster runs away from the player.
+    #[serde(default)]
+    pub flee_below: f32,
+    /// Loot table rolled when the monster dies.
+    #[serde(default)]
+    pub loot: Option<String>,
+    /// Dungeon levels the monster spawns on, inclusive. Never spawned at random without one.
+    #[serde(default)]
+    pub depth: Option<(u32, u32)>,
+    #[serde(default)]
+    pub light: Option<LightDef>,
+    /// Items the monster starts with equipped.
+    #[serde(default)]
+    pub equipment: Vec<String>,
+    /// Monsters without an inventory cannot pick up items.
+    #[serde(default)]
+    pub inventory: Option<InventoryDef>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ItemDef {
+    pub name: String,
+    pub glyph: char,
+    pub color: String,
+    /// Items without a sprite are drawn with their glyph.
+    #[serde(default)]
+    pub sprite: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub depth: Option<(u32, u32)>,
+    #[serde(default)]
+    pub weapon: Option<WeaponDef>,
+    #[serde(default)]
+    pub armour: Option<ArmourDef>,
+    #[serde(default)]
+    pub ring: Option<RingDef>,
+    #[serde(default, rename = "use")]
+    pub use_effect: Option<UseEffect>,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Most items of this kind in one inventory slot.
+    #[serde(default = "default_stack")]
+    pub stack: u32,
+    /// Defaults to the name with an "s".
+    #[serde(default)]
+    pub plural: Option<String>,
+}
+
+fn default_stack() -> u32 {
+    1
+}
+
+impl ItemDef {
+    /// Equipment slot the item goes into, if it can be equipped. Rings go into the left
+    /// hand unless it is taken.
+    pub(crate) fn slot(&self) -> Option<Slot> {
+        match (&self.weapon, &self.armour, &self.ring) {
+            (Some(weapon), _, _) if weapon.range.is_some() => Some(Slot::Ranged),
+            (Some(_), _, _) => Some(Slot::Weapon),
+            (None, Some(_), _) => Some(Slot::Armour),
+            (None, None, Some(_)) => Some(Slot::LeftRing),
+            (None, None, None) => None,
+        }
+    }
+
+    /// Name of `count` of the item, e.g. "a sword", "3 healing potions" or "5 gold".
+    pub(crate) fn describe(&self, count: u32) -> String {
+        // Uncountable items, whose plural is their name, keep the number: "1 gold".
+        if count == 1 && self.plural.as_ref() != Some(&self.name) {
+            let article = match self.name.chars().next() {
+                Some('a' | 'e' | 'i' | 'o' | 'u') => "an",
+                _ => "a",
+            };
+            format!("{} {}", article, self.name)
+        } else if count == 1 {
+            format!("1 {}", self.name)
+        } else {
+            match &self.plural {
+                Some(plural) => format!("{} {}", count, plural),
+                None => format!("{} {}s", count, self.name),
+            }
+        }
+    }
+}
+
+/// Drops with `chance`, then picks one entry by weight.
+#[derive(Deserialize)]
+pub(crate) struct LootTable {
+    #[serde(default = "default_chance")]
+    pub chance: f32,
+    pub entries: Vec<LootEntry>,
+}
+
+fn default_chance() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+pub(crate) struct LootEntry {
+    pub item: String,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// How many of the item drop.
+    #[serde(default = "default_count", deserialize_with = "from_str")]
+    pub count: Dice,
+}
+
+fn default_count() -> Dice {
+    Dice {
+        count: 0,
+        sides: 0,
+        modifier: 1,
+    }
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// A hand-made room, stamped into generated levels. Its layout is either rows of text in
+/// `map`, or an image in which each pixel colour stands for a map character through
+/// `colors`. Fully transparent pixels are outside of the prefab.
+#[derive(Deserialize)]
+pub(crate) struct PrefabDef {
+    pub name: String,
+    /// Dungeon levels the prefab is placed on, inclusive. Never placed without one.
+    #[serde(default)]
+    pub depth: Option<(u32, u32)>,
+    /// Chance of the prefab being placed on a level of its depth.
+    #[serde(default = "default_chance")]
+    pub chance: f32,
+    /// What the map characters besides `#`, `.`, `+` and space spawn.
+    #[serde(default)]
+    pub legend: BTreeMap<char, Spawn>,
+    #[serde(default)]
+    pub map: Vec<String>,
+    #[serde(default)]
+    pub image: Option<PathBuf>,
+    /// Map character of each pixel colour in `image`.
+    #[serde(default)]
+    pub colors: BTreeMap<String, char>,
+}
+
+/// A sample image that levels are grown from with wave function collapse, instead of
+/// being carved into rooms and corridors.
+#[derive(Deserialize)]
+pub(crate) struct SampleDef {
+    pub name: String,
+    /// Dungeon levels grown from the sample, inclusive. Never used without one.
+    #[serde(default)]
+    pub depth: Option<(u32, u32)>,
+    /// Chance of a level of its depth being grown from the sample.
+    #[serde(default = "default_chance")]
+    pub chance: f32,
+    pub image: PathBuf,
+    /// Width and height of the patterns learnt from the image, in pixels. Larger patterns
+    /// copy the sample more closely.
+    #[serde(default = "default_pattern_size")]
+    pub pattern_size: i32,
+    /// Also learn the rotations and mirror images of the patterns.
+    #[serde(default = "default_symmetry")]
+    pub symmetry: bool,
+    /// Tile type of each pixel colour in the image.
+    pub tiles: BTreeMap<String, TileType>,
+}
+
+fn default_pattern_size() -> i32 {
+    3
+}
+
+fn default_symmetry() -> bool {
+    true
+}
+
+/// Parse a field from its text form, e.g. dice from `"2d6+1"`.
+fn from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let text = String::deserialize(deserializer)?;
+    text.parse().map_err(serde::de::Error::custom)
+}
+
+/// Monster, item, loot, prefab, sample and tile definitions, loaded from the RON files in the raws
+/// directory. Every reference between them is checked when they are loaded.
+pub(crate) struct Raws {
+    sprites: BTreeMap<String, SpriteDef>,
+    tiles: HashMap<TileType, TileStyle>,
+    autotiles: HashMap<TileType, AutotileDef>,
+    monsters: Vec<MonsterDef>,
+    items: Vec<ItemDef>,
+    loot: BTreeMap<String, LootTable>,
+    prefabs: Vec<Prefab>,
+    samples: Vec<LevelSample>,
+    /// Resolves the colours of spawned entities.
+    palette: Palette,
+}
+
+impl Raws {
+    /// Load and validate the raws in `dir`. Colours are resolved against `palette`.
+    /// Collects every problem found instead of stopping at the first one.
+    pub(crate) fn load(dir: impl AsRef<Path>, palette: &Palette) -> Result<Self, RawsError> {
+        let dir = dir.as_ref();
+        let mut problems = Vec::new();
+
+        let sprites = read(dir, "sprites.ron", &mut problems);
+        let tiles = read(dir, "tiles.ron", &mut problems);
+        let monsters: Option<Vec<_>> = read(dir, "monsters.ron", &mut problems);
+        let items: Option<Vec<_>> = read(dir, "items.ron", &mut problems);
+        let loot = read(dir, "loot.ron", &mut problems);
+        let prefabs: Option<Vec<_>> = read(dir, "prefabs.ron", &mut problems);
+        let samples: Option<Vec<_>> = read(dir, "samples.ron", &mut problems);
+        let (
+            Some(sprites),
+            Some(tiles),
+            Some(monsters),
+            Some(items),
+            Some(loot),
+            Some(prefabs),
+            Some(samples),
+        ) = (sprites, tiles, monsters, items, loot, prefabs, samples)
+        else {
+            return Err(RawsError { problems });
+        };
+
+        let styles = validate(
+            &sprites,
+            &tiles,
+            &monsters,
+            &items,
+            &loot,
+            palette,
+            &mut problems,
+        );
+        let prefabs = validate_prefabs(&prefabs, &monsters, &items, palette, &mut problems);
+        let samples = validate_samples(&samples, palette, &mut problems);
+        if !problems.is_empty() {
+            return Err(RawsError { problems });
+        }
+        let autotiles = tiles
+            .into_iter()
+            .filter_map(|(tile, def)| Some((tile, def.autotile?)))
+            .collect();
+        Ok(Self {
+            sprites,
+            tiles: styles,
+            autotiles,
+            monsters,
+            items,
+            loot,
+            prefabs,
+            samples,
+            palette: palette.clone(),
+        })
+    }
+
+    /// Load every sprite sheet, keyed by sprite name.
+    pub(crate) fn load_sprites(&self) -> HashMap<String, Sprite> {
+        self.sprites
+            .iter()
+            .map(|(name, def)| {
+                let mut sprite = Sprite::from_image_animated(&def.path, def.frames);
+                sprite.fps = def.fps;
+                (name.clone(), sprite)
+            })
+            .collect()
+    }
+
+    pub(crate) fn tile(&self, tile: TileType) -> TileStyle {
+        self.tiles[&tile]
+    }
+
+    /// How the art of `tile` is picked, if it is autotiled.
+    pub(crate) fn autotile(&self, tile: TileType) -> Option<&AutotileDef> {
+        self.autotiles.get(&tile)
+    }
+
+    pub(crate) fn monster(&self, name: &str) -> Option<&MonsterDef> {
+        self.monsters.iter().find(|m| m.name == name)
+    }
+
+    pub(crate) fn item(&self, name: &str) -> Option<&ItemDef> {
+        self.items.iter().find(|i| i.name == name)
+    }
+
+    /// Monsters that can spawn on dungeon level `depth`.
+    pub(crate) fn monsters_at_depth(&self, depth: u32) -> impl Iterator<Item = &MonsterDef> {
+        self.monsters.iter().filter(move |m| {
+            m.depth
+                .is_some_and(|(min, max)| (min..=max).contains(&depth))
+        })
+    }
+
+    /// Items that can spawn on dungeon level `depth`.
+    pub(crate) fn items_at_depth(&self, depth: u32) -> impl Iterator<Item = &ItemDef> {
+        self.items.iter().filter(move |i| {
+            i.depth
+                .is_some_and(|(min, max)| (min..=max).contains(&depth))
+        })
+    }
+
+    /// Prefabs that can be placed on dungeon level `depth`.
+    pub(crate) fn prefabs_at_depth(&self, depth: u32) -> impl Iterator<Item = &Prefab> {
+        self.prefabs.iter().filter(move |p| {
+            p.depth
+                .is_some_and(|(min, max)| (min..=max).contains(&depth))
+        })
+    }
+
+    pub(crate) fn samples(&self) -> &[LevelSample] {
+        &self.samples
+    }
+
+    /// Samples that levels on dungeon level `depth` can be grown from.
+    pub(crate) fn samples_at_depth(&self, depth: u32) -> impl Iterator<Item = &LevelSample> {
+        self.samples.iter().filter(move |s| {
+            s.depth
+                .is_some_and(|(min, max)| (min..=max).contains(&depth))
+        })
+    }
+
+    /// Build the monster called `name` at `pos`.
+    pub(crate) fn spawn_monster(&self, name: &str, pos: IVec2) -> Option<Entity> {
+        let def = self.monster(name)?;
+        let mut entity = Entity::new(def.name.clone(), pos);
+        entity.glyph = def.glyph;
+        entity.color = self.color(&def.color);
+        entity.sprite = def.sprite.clone();
+        entity.blocks = true;
+        entity.light = def.light.as_ref().map(|light| Light {
+            color: self.color(&light.color),
+            radius: light.radius,
+            intensity: light.intensity,
+            falloff: light.falloff,
+            flicker: light.flicker,
+        });
+        entity.stats = Some(self.stats(def));
+        entity.ai = def.ai;
+        entity.brain = def.ai.map(|_| Brain::new(pos));
+        entity.loot = def.loot.clone();
+        entity.equipment = self.equipment(def);
+        entity.inventory = def.inventory.as_ref().map(|inventory| {
+            let mut items = Inventory::new(inventory.capacity);
+            for (kind, count) in &inventory.items {
+                items.add(ItemStack::new(kind, *count), self);
+            }
+            items
+        });
+        // Ready to act as soon as they are spawned.
+        entity.energy = ACTION_COST;
+        Some(entity)
+    }
+
+    /// Build `stack` lying on the floor at `pos`.
+    pub(crate) fn spawn_item(&self, stack: ItemStack, pos: IVec2) -> Option<Entity> {
+        let def = self.item(&stack.kind)?;
+        let mut entity = Entity::new(def.name.clone(), pos);
+        entity.glyph = def.glyph;
+        entity.color = self.color(&def.color);
+        entity.sprite = def.sprite.clone().unwrap_or_default();
+        entity.item = Some(stack);
+        Some(entity)
+    }
+
+    /// Equipment a monster starts with.
+    pub(crate) fn equipment(&self, def: &MonsterDef) -> Equipment {
+        let mut equipment = Equipment::default();
+        for name in &def.equipment {
+            if let Some(slot) = self.item(name).and_then(|item| item.slot()) {
+                let slot = equipment.free_slot(slot);
+                equipment.set(slot, Some(name.clone()));
+            }
+        }
+        equipment
+    }
+
+    /// Fresh stats for a monster.
+    pub(crate) fn stats(&self, def: &MonsterDef) -> Stats {
+        Stats {
+            hp: def.stats.hp,
+            max_hp: def.stats.hp,
+            to_hit: def.stats.to_hit,
+            attack: def.stats.attack,
+            damage_type: def.stats.damage_type,
+            defense: def.stats.defense,
+            armour: def.stats.armour,
+            resistances: def.stats.resist.clone(),
+        }
+    }
+
+    /// Roll the loot table `name`. Returns the dropped items, if any.
+    pub(crate) fn roll_loot(&self, name: &str, rng: &mut Rng) -> Option<ItemStack> {
+        let table = self.loot.get(name)?;
+        if !rng.chance(table.chance) {
+            return None;
+        }
+        let total: u32 = table.entries.iter().map(|e| e.weight).sum();
+        let mut pick = rng.below(total);
+        let entry = table.entries.iter().find(|e| {
+            if pick < e.weight {
+                return true;
+            }
+            pick -= e.weight;
+            false
+        })?;
+        let count = entry.count.roll(rng);
+        (count > 0).then(|| ItemStack::new(&entry.item, count as u32))
+    }
+
+    /// Colour of a definition. Specs were checked on load.
+    pub(crate) fn color(&self, spec: &str) -> Color {
+        self.palette.resolve(spec).unwrap_or(Color::MAGENTA)
+    }
+}
+
+/// Read `dir/file`, noting a problem if it is missing or malformed.
+fn read<T: DeserializeOwned>(dir: &Path, file: &str, problems: &mut Vec<String>) -> Option<T> {
+    let path = dir.join(file);
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| problems.push(format!("{}: {}", path.display(), e)))
+        .ok()?;
+    ron::from_str(&contents)
+        .map_err(|e| problems.push(format!("{}:{}", path.display(), e)))
+        .ok()
+}
+
+/// Check every reference between the definitions, and resolve the tile styles.
+fn validate(
+    sprites: &BTreeMap<String, SpriteDef>,
+    tiles: &HashMap<TileType, TileDef>,
+    monsters: &[MonsterDef],
+    items: &[ItemDef],
+    loot: &BTreeMap<String, LootTable>,
+    palette: &Palette,
+    problems: &mut Vec<String>,
+) -> HashMap<TileType, TileStyle> {
+    let mut styles = HashMap::new();
+    for tile in TileType::ALL {
+        let context = format!("tiles.ron: {:?}", tile);
+        let Some(def) = tiles.get(&tile) else {
+            problems.push(format!("{}: missing", context));
+            continue;
+        };
+        let mut color = |spec: &str| {
+            palette.resolve(spec).unwrap_or_else(|| {
+                problems.push(format!("{}: unknown colour `{}`", context, spec));
+                Color::MAGENTA
+            })
+        };
+        styles.insert(
+            tile,
+            TileStyle {
+                glyph: def.glyph,
+                color: color(&def.color),
+                glyph_color: color(&def.glyph_color),
+            },
+        );
+        if let Some(autotile) = &def.autotile {
+            match sprites.get(&autotile.sprite) {
+                None => problems.push(format!("{}: unknown sprite `{}`", context, autotile.sprite)),
+                Some(sprite) if sprite.frames != autotile.mask.frames() => problems.push(format!(
+                    "{}: {:?} autotiling needs {} frames, `{}` has {}",
+                    context,
+                    autotile.mask,
+                    autotile.mask.frames(),
+                    autotile.sprite,
+                    sprite.frames
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    for monster in monsters {
+        let context = format!("monsters.ron: {}", monster.name);
+        if !seen.insert(monster.name.as_str()) {
+            problems.push(format!("{}: defined more than once", context));
+        }
+        if palette.resolve(&monster.color).is_none() {
+            problems.push(format!("{}: unknown colour `{}`", context, monster.color));
+        }
+        if let Some(light) = &monster.light {
+            if palette.resolve(&light.color).is_none() {
+                problems.push(format!("{}: unknown colour `{}`", context, light.color));
+            }
+        }
+        if !sprites.contains_key(&monster.sprite) {
+            problems.push(format!("{}: unknown sprite `{}`", context, monster.sprite));
+        }
+        check_depth(&context, monster.depth, problems);
+        if monster.stats.hp <= 0 {
+            problems.push(format!("{}: hp must be above 0", context));
+        }
+        if let Some(inflict) = &monster.stats.inflict {
+            check_inflict(&context, inflict, problems);
+        }
+        if monster.sight <= 0 {
+            problems.push(format!("{}: sight must be above 0", context));
+        }
+        if !(0.0..=1.0).contains(&monster.flee_below) {
+            problems.push(format!("{}: flee_below must be between 0 and 1", context));
+        }
+        if let Some(table) = &monster.loot {
+            if !loot.contains_key(table) {
+                problems.push(format!("{}: unknown loot table `{}`", context, table));
+            }
+        }
+    }
+    // New games start by spawning the player from its definition.
+    if !seen.contains("player") {
+        problems.push("monsters.ron: player: missing".to_string());
+    }
+
+    let mut seen = HashSet::new();
+    for item in items {
+        let context = format!("items.ron: {}", item.name);
+        if !seen.insert(item.name.as_str()) {
+            problems.push(format!("{}: defined more than once", context));
+        }
+        if palette.resolve(&item.color).is_none() {
+            problems.push(format!("{}: unknown colour `{}`", context, item.color));
+        }
+        check_depth(&context, item.depth, problems);
+        if let Some(range) = item.weapon.as_ref().and_then(|w| w.range) {
+            if range <= 0 {
+                problems.push(format!("{}: range must be above 0", context));
+            }
+        }
+        if let Some(inflict) = item.weapon.as_ref().and_then(|w| w.inflict.as_ref()) {
+            check_inflict(&context, inflict, problems);
+        }
+        if let Some(UseEffect::Status { turns: 0, .. }) = item.use_effect {
+            problems.push(format!(
+                "{}: status effects must last at least 1 turn",
+                context
+            ));
+        }
+        if let Some(UseEffect::Shatter { radius }) = item.use_effect {
+            if radius <= 0 {
+                problems.push(format!("{}: shatter radius must be above 0", context));
+            }
+        }
+        let kinds = [
+            item.weapon.is_some(),
+            item.armour.is_some(),
+            item.ring.is_some(),
+        ];
+        if kinds.iter().filter(|&&kind| kind).count() > 1 {
+            problems.push(format!(
+                "{}: can only be one of a weapon, armour or a ring",
+                context
+            ));
+        }
+        if item.stack == 0 {
+            problems.push(format!("{}: stack must be above 0", context));
+        }
+        if let Some(sprite) = &item.sprite {
+            if !sprites.contains_key(sprite) {
+                problems.push(format!("{}: unknown sprite `{}`", context, sprite));
+            }
+        }
+    }
+
+    for monster in monsters {
+        let context = format!("monsters.ron: {}", monster.name);
+        let mut equipment = Equipment::default();
+        for name in &monster.equipment {
+            match items.iter().find(|item| &item.name == name) {
+                None => problems.push(format!("{}: unknown item `{}`", context, name)),
+                Some(item) => match item.slot().map(|slot| equipment.free_slot(slot)) {
+                    None => problems.push(format!("{}: `{}` cannot be equipped", context, name)),
+                    Some(slot) if equipment.get(slot).is_some() => problems.push(format!(
+                        "{}: `{}` goes into the occupied {} slot",
+                        context,
+                        name,
+                        slot.name().to_lowercase()
+                    )),
+                    Some(slot) => {
+                        equipment.set(slot, Some(name.clone()));
+                    }
+                },
+            }
+        }
+        let starting_items = monster.inventory.iter().flat_map(|i| &i.items);
+        for (name, count) in starting_items {
+            match items.iter().find(|item| &item.name == name) {
+                None => problems.push(format!("{}: unknown item `{}`", context, name)),
+                Some(_) if *count == 0 => {
+                    problems.push(format!("{}: needs at least one `{}`", context, name))
+                }
+                Some(_) => {}
+            }
+        }
+        if monster.ai == Some(AiKind::Kite) && equipment.get(Slot::Ranged).is_none() {
+            problems.push(format!("{}: kiting needs a ranged weapon", context));
+        }
+    }
+
+    for (name, table) in loot {
+        let context = format!("loot.ron: {}", name);
+        if !(0.0..=1.0).contains(&table.chance) {
+            problems.push(format!("{}: chance must be between 0 and 1", context));
+        }
+        if table.entries.iter().all(|e| e.weight == 0) {
+            problems.push(format!("{}: needs an entry with a weight above 0", context));
+        }
+        for entry in &table.entries {
+            if !seen.contains(entry.item.as_str()) {
+                problems.push(format!("{}: unknown item `{}`", context, entry.item));
+            }
+        }
+    }
+
+    for (name, sprite) in sprites {
+        let context = format!("sprites.ron: {}", name);
+        if sprite.frames == 0 {
+            problems.push(format!("{}: needs at least one frame", context));
+        } else {
+            match image::image_dimensions(&sprite.path) {
+                Ok((width, _)) if width % sprite.frames != 0 => problems.push(format!(
+                    "{}: {} pixels wide, which does not split into {} frames",
+                    context, width, sprite.frames
+                )),
+                Ok(_) => {}
+                Err(e) => problems.push(format!("{}: {}: {}", context, sprite.path.display(), e)),
+            }
+        }
+        if sprite.fps <= 0.0 {
+            problems.push(format!("{}: fps must be above 0", context));
+        }
+    }
+
+    styles
+}
+
+/// Check the prefab definitions and parse their maps.
+fn validate_prefabs(
+    defs: &[PrefabDef],
+    monsters: &[MonsterDef],
+    items: &[ItemDef],
+    palette: &Palette,
+    problems: &mut Vec<String>,
+) -> Vec<Prefab> {
+    let mut prefabs = Vec::new();
+    let mut seen = HashSet::new();
+    for def in defs {
+        let context = format!("prefabs.ron: {}", def.name);
+        if !seen.insert(def.name.as_str()) {
+            problems.push(format!("{}: defined more than once", context));
+        }
+        check_depth(&context, def.depth, problems);
+        if !(0.0..=1.0).contains(&def.chance) {
+            problems.push(format!("{}: chance must be between 0 and 1", context));
+        }
+        for spawn in def.legend.values() {
+            match spawn {
+                Spawn::Monster(name) if !monsters.iter().any(|m| &m.name == name) => {
+                    problems.push(format!("{}: unknown monster `{}`", context, name))
+                }
+                Spawn::Item(name) if !items.iter().any(|i| &i.name == name) => {
+                    problems.push(format!("{}: unknown item `{}`", context, name))
+                }
+                _ => {}
+            }
+        }
+
+        let map = match &def.image {
+            Some(_) if !def.map.is_empty() => {
+                problems.push(format!("{}: has both a map and an image", context));
+                continue;
+            }
+            Some(path) => match image_rows(path, &def.colors, palette) {
+                Ok(rows) => rows,
+                Err(e) => {
+                    problems.push(format!("{}: {}", context, e));
+                    continue;
+                }
+            },
+            None => def.map.clone(),
+        };
+        match Prefab::parse(def, &map) {
+            Ok(prefab) => prefabs.push(prefab),
+            Err(e) => problems.push(format!("{}: {}", context, e)),
+        }
+    }
+    prefabs
+}
+
+/// The rows of map characters a prefab image stands for.
+fn image_rows(
+    path: &Path,
+    colors: &BTreeMap<String, char>,
+    palette: &Palette,
+) -> Result<Vec<String>, String> {
+    let image = image::open(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?
+        .to_rgba8();
+    let mut characters = HashMap::new();
+    for (spec, &character) in colors {
+        let color = palette
+            .resolve(spec)
+            .ok_or_else(|| format!("unknown colour `{}`", spec))?;
+        characters.insert(color, character);
+    }
+    image
+        .rows()
+        .enumerate()
+        .map(|(y, row)| {
+            row.enumerate()
+                .map(|(x, pixel)| {
+                    let color = Color::from(pixel.0);
+                    if color.a == 0 {
+                        return Ok(' ');
+                    }
+                    characters.get(&color).copied().ok_or_else(|| {
+                        format!("no character for the colour {} at {}, {}", color, x, y)
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Check the sample definitions, and learn the patterns of their images.
+fn validate_samples(
+    defs: &[SampleDef],
+    palette: &Palette,
+    problems: &mut Vec<String>,
+) -> Vec<LevelSample> {
+    let mut samples = Vec::new();
+    let mut seen = HashSet::new();
+    for def in defs {
+        let context = format!("samples.ron: {}", def.name);
+        if !seen.insert(def.name.as_str()) {
+            problems.push(format!("{}: defined more than once", context));
+        }
+        check_depth(&context, def.depth, problems);
+        if !(0.0..=1.0).contains(&def.chance) {
+            problems.push(format!("{}: chance must be between 0 and 1", context));
+        }
+        if !(2..=4).contains(&def.pattern_size) {
+            problems.push(format!("{}: pattern_size must be from 2 to 4", context));
+            continue;
+        }
+        if !def.tiles.values().any(|&tile| tile == TileType::Floor) {
+            problems.push(format!("{}: needs a colour for floor", context));
+        }
+        // The stairs are placed after the level is grown.
+        if let Some(tile) = def
+            .tiles
+            .values()
+            .find(|tile| !matches!(tile, TileType::Floor | TileType::Wall))
+        {
+            problems.push(format!("{}: cannot grow {:?} tiles", context, tile));
+        }
+
+        let sample = match Sample::load(&def.image) {
+            Ok(sample) => sample,
+            Err(e) => {
+                problems.push(format!("{}: {}: {}", context, def.image.display(), e));
+                continue;
+            }
+        };
+        let mut tiles = HashMap::new();
+        for (spec, &tile) in &def.tiles {
+            match palette.resolve(spec) {
+                Some(color) => {
+                    tiles.insert(color, tile);
+                }
+                None => problems.push(format!("{}: unknown colour `{}`", context, spec)),
+            }
+        }
+        let model = Model::learn(&sample, def.pattern_size, def.symmetry);
+        if model.pattern_count() == 0 {
+            problems.push(format!("{}: {} is empty", context, def.image.display()));
+            continue;
+        }
+        let tiles = model
+            .colors()
+            .iter()
+            .map(|color| {
+                tiles.get(color).copied().unwrap_or_else(|| {
+                    problems.push(format!("{}: no tile for the colour {}", context, color));
+                    TileType::Wall
+                })
+            })
+            .collect();
+        samples.push(LevelSample {
+            name: def.name.clone(),
+            depth: def.depth,
+            chance: def.chance,
+            model,
+            tiles,
+        });
+    }
+    samples
+}
+
+fn check_depth(context: &str, depth: Option<(u32, u32)>, problems: &mut Vec<String>) {
+    if let Some((min, max)) = depth {
+        if min > max {
+            problems.push(format!("{}: depth {} is above {}", context, min, max));
+        }
+    }
+}
+
+fn check_inflict(context: &str, inflict: &InflictDef, problems: &mut Vec<String>) {
+    if inflict.turns == 0 {
+        problems.push(format!(
+            "{}: status effects must last at least 1 turn",
+            context
+        ));
+    }
+    if !(0.0..=1.0).contains(&inflict.chance) {
+        problems.push(format!("{}: chance must be between 0 and 1", context));
+    }
+}
+
+/// Everything wrong with a set of raws.
+#[derive(Debug)]
+pub(crate) struct RawsError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for RawsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} problem(s) in the raws", self.problems.len())?;
+        for problem in &self.problems {
+            write!(f, "\n  {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RawsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIR: &str = "assets/raws";
+
+    struct Defs {
+        sprites: BTreeMap<String, SpriteDef>,
+        tiles: HashMap<TileType, TileDef>,
+        monsters: Vec<MonsterDef>,
+        items: Vec<ItemDef>,
+        loot: BTreeMap<String, LootTable>,
+        palette: Palette,
+    }
+
+    impl Defs {
+        /// The definitions the game ships with.
+        fn load() -> Self {
+            let dir = Path::new(DIR);
+            let mut problems = Vec::new();
+            let defs = Self {
+                sprites: read(dir, "sprites.ron", &mut problems).unwrap(),
+                tiles: read(dir, "tiles.ron", &mut problems).unwrap(),
+                monsters: read(dir, "monsters.ron", &mut problems).unwrap(),
+                items: read(dir, "items.ron", &mut problems).unwrap(),
+                loot: read(dir, "loot.ron", &mut problems).unwrap(),
+                palette: Palette::load("assets/palettes/dungeon.gpl").unwrap(),
+            };
+            assert_eq!(problems, Vec::<String>::new());
+            defs
+        }
+
+        fn validate(&self) -> Vec<String> {
+            let mut problems = Vec::new();
+            validate(
+                &self.sprites,
+                &self.tiles,
+                &self.monsters,
+                &self.items,
+                &self.loot,
+                &self.palette,
+                &mut problems,
+            );
+            problems
+        }
+
+        fn monster(&mut self, name: &str) -> &mut MonsterDef {
+            self.monsters.iter_mut().find(|m| m.name == name).unwrap()
+        }
+
+        fn item(&mut self, name: &str) -> &mut ItemDef {
+            self.items.iter_mut().find(|i| i.name == name).unwrap()
+        }
+    }
+
+    #[test]
+    fn shipped_raws_are_valid() {
+        assert_eq!(Defs::load().validate(), Vec::<String>::new());
+        Raws::load(DIR, &Defs::load().palette).unwrap();
+    }
+
+    #[test]
+    fn every_problem_is_collected() {
+        let mut defs = Defs::load();
+        defs.monster("slime").sprite = "no such sprite".to_string();
+        defs.monster("goblin").loot = Some("no such table".to_string());
+        let mut copies = Defs::load();
+        defs.item("sword").ring = copies.item("ring of protection").ring.take();
+        let goblins = copies.monsters.into_iter().filter(|m| m.name == "goblin");
+        defs.monsters.extend(goblins);
+        defs.monsters.retain(|m| m.name != "player");
+        defs.monster("goblin archer").equipment.clear();
+        defs.item("scroll of shattering").use_effect = Some(UseEffect::Shatter { radius: 0 });
+        let table = defs.loot.values_mut().next().unwrap();
+        table.entries[0].item = "no such item".to_string();
+
+        let problems = defs.validate();
+        for expected in [
+            "monsters.ron: slime: unknown sprite `no such sprite`",
+            "monsters.ron: goblin: unknown loot table `no such table`",
+            "monsters.ron: goblin: defined more than once",
+            "monsters.ron: player: missing",
+            "monsters.ron: goblin archer: kiting needs a ranged weapon",
+            "items.ron: sword: can only be one of a weapon, armour or a ring",
+            "items.ron: scroll of shattering: shatter radius must be above 0",
+            "unknown item `no such item`",
+        ] {
+            assert!(
+                problems.iter().any(|problem| problem.contains(expected)),
+                "`{}` not in {:#?}",
+                expected,
+                problems
+            );
+        }
+    }
+}
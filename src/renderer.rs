@@ -1,30 +1,114 @@
 #![allow(dead_code)]
+use crate::color::Color;
+use crate::draw_queue::{DrawCommand, DrawQueue};
+use crate::markup;
+use crate::palette::Palette;
+use crate::raster::Canvas;
 use crate::sprite::Sprite;
-use fontdue::{self, Font};
-use glam::Vec2;
-use image::GenericImageView;
+use fontdue::{self, Font, Metrics};
+use glam::{Affine2, IVec2, Mat2, Vec2};
 use pixels::{Pixels, SurfaceTexture};
+use std::collections::HashMap;
 use winit::window::Window;
 
+/// How a sprite is transformed when it is drawn.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DrawParams {
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Clockwise 90 degree turns, applied in place after flipping.
+    pub quarter_turns: u8,
+    /// Uniform scale around `pivot`. Fractional values use nearest neighbour sampling.
+    pub scale: f32,
+    /// Clockwise rotation in radians around `pivot`.
+    pub rotation: f32,
+    /// Point that stays in place when scaling and rotating, in pixels from the top-left of
+    /// the flipped and quarter-turned frame.
+    pub pivot: Vec2,
+    /// Colour the sprite is multiplied with, e.g. the light on its tile.
+    pub tint: Color,
+}
+
+impl Default for DrawParams {
+    fn default() -> Self {
+        Self {
+            flip_x: false,
+            flip_y: false,
+            quarter_turns: 0,
+            scale: 1.0,
+            rotation: 0.0,
+            pivot: Vec2::ZERO,
+            tint: Color::WHITE,
+        }
+    }
+}
+
+impl DrawParams {
+    /// True when the only transform is a whole-number scale.
+    fn is_integer_scale_only(&self) -> bool {
+        !self.flip_x
+            && !self.flip_y
+            && self.quarter_turns.is_multiple_of(4)
+            && self.rotation == 0.0
+            && self.scale >= 1.0
+            && self.scale.fract() == 0.0
+    }
+
+    /// Map from pixel coordinates in a frame of `size` to frame buffer coordinates.
+    fn transform(&self, pos: Vec2, size: Vec2) -> Affine2 {
+        let mut local = Affine2::IDENTITY;
+        if self.flip_x {
+            local = Affine2::from_translation(Vec2::new(size.x, 0.0))
+                * Affine2::from_scale(Vec2::new(-1.0, 1.0))
+                * local;
+        }
+        if self.flip_y {
+            local = Affine2::from_translation(Vec2::new(0.0, size.y))
+                * Affine2::from_scale(Vec2::new(1.0, -1.0))
+                * local;
+        }
+
+        // Each quarter turn maps (x, y) to (height - y, x) and swaps the frame dimensions.
+        let mut size = size;
+        for _ in 0..self.quarter_turns % 4 {
+            let turn = Mat2::from_cols(Vec2::new(0.0, 1.0), Vec2::new(-1.0, 0.0));
+            local = Affine2::from_mat2_translation(turn, Vec2::new(size.y, 0.0)) * local;
+            size = Vec2::new(size.y, size.x);
+        }
+
+        Affine2::from_translation(pos + self.pivot)
+            * Affine2::from_angle(self.rotation)
+            * Affine2::from_scale(Vec2::splat(self.scale))
+            * Affine2::from_translation(-self.pivot)
+            * local
+    }
+}
+
 pub(crate) struct Renderer {
     pub pixels: Pixels,
     width: u32,
-    _height: u32,
+    height: u32,
     offset: Vec2,
     font: Font,
+    /// Rasterized glyphs, keyed by character and font size bits.
+    glyph_cache: HashMap<(char, u32), (Metrics, Vec<u8>)>,
+    /// Colours that text markup and the game can look up by name.
+    palette: Palette,
 }
 
 impl Renderer {
-    pub(crate) fn new(window: &Window) -> Self {
+    /// Create a renderer with a frame buffer of `width` x `height` logical pixels. The frame is
+    /// scaled up to the window by the largest whole factor that fits, and letterboxed.
+    pub(crate) fn new(window: &Window, width: u32, height: u32) -> Self {
         let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        let pixels = Pixels::new(window_size.width, window_size.height, surface_texture)
-            .expect("Error while creating buffer");
+        let pixels =
+            Pixels::new(width, height, surface_texture).expect("Error while creating buffer");
 
         Self {
             pixels,
-            width: window_size.width as u32,
-            _height: window_size.height as u32,
+            width,
+            height,
             offset: Vec2::ZERO,
             font: {
                 // Read the font data.
@@ -32,132 +116,378 @@ impl Renderer {
                 // Parse it into the font type.
                 fontdue::Font::from_bytes(font, fontdue::FontSettings::default()).unwrap()
             },
+            glyph_cache: HashMap::new(),
+            palette: Palette::default(),
         }
     }
 
-    pub(crate) fn clear_frame(&mut self, color: [u8; 4]) {
-        for pixel in self.pixels.get_frame_mut().chunks_exact_mut(4) {
-            pixel.copy_from_slice(&color);
-        }
+    pub(crate) fn clear_frame(&mut self, color: Color) {
+        self.canvas().clear(color.to_array());
+    }
+
+    pub(crate) fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Re-theme everything drawn from now on.
+    pub(crate) fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub(crate) fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub(crate) fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Map a physical window position (e.g. the mouse cursor) to a frame buffer pixel.
+    /// Returns `None` when the position lies in the letterbox area.
+    pub(crate) fn window_to_pixel(&self, physical_pos: (f32, f32)) -> Option<Vec2> {
+        self.pixels
+            .window_pos_to_pixel(physical_pos)
+            .ok()
+            .map(|(x, y)| Vec2::new(x as f32, y as f32))
     }
 
     pub(crate) fn set_offset(&mut self, offset: Vec2) {
         self.offset = offset;
     }
 
-    pub(crate) fn draw_square(&mut self, pos: Vec2, size: Vec2, color: [u8; 4]) {
-        let pos = pos + self.offset;
-        for (i, pixel) in self.pixels.get_frame_mut().chunks_exact_mut(4).enumerate() {
-            let x = (i % self.width as usize) as i16;
-            let y = (i / self.width as usize) as i16;
+    /// Frame buffer of the `Pixels` instance as a canvas to rasterize into.
+    fn canvas(&mut self) -> Canvas<'_> {
+        Canvas::new(
+            self.pixels.get_frame_mut(),
+            self.width as usize,
+            self.height as usize,
+        )
+    }
 
-            let inside_the_box = x >= pos.x as i16
-                && x < pos.x as i16 + size.x as i16
-                && y >= pos.y as i16
-                && y < pos.y as i16 + size.y as i16;
+    pub(crate) fn draw_square(&mut self, pos: Vec2, size: Vec2, color: Color) {
+        let pos = (pos + self.offset).floor();
+        self.canvas().fill_rect(
+            pos.x as i32,
+            pos.y as i32,
+            size.x as i32,
+            size.y as i32,
+            color.to_array(),
+        );
+    }
 
-            if inside_the_box {
-                pixel.copy_from_slice(&color);
-            }
-        }
+    /// Outline of a rectangle, one pixel wide.
+    pub(crate) fn draw_rect(&mut self, pos: Vec2, size: Vec2, color: Color) {
+        let pos = (pos + self.offset).floor();
+        self.canvas().rect(
+            pos.x as i32,
+            pos.y as i32,
+            size.x as i32,
+            size.y as i32,
+            color.to_array(),
+        );
     }
 
-    pub(crate) fn draw_sprite(&mut self, pos: Vec2, sprite: &Sprite) {
-        let pos = pos + self.offset;
+    pub(crate) fn draw_line(&mut self, start: Vec2, end: Vec2, color: Color) {
+        let start = self.to_pixel(start);
+        let end = self.to_pixel(end);
+        self.canvas().line(start, end, color.to_array());
+    }
 
-        for y in 0..sprite.height as usize * sprite.scale as usize {
-            let i = pos.x as usize * 4
-                + pos.y as usize * self.width as usize * 4
-                + y * self.width as usize * 4;
-
-            for (sprite_index, chunk) in self.pixels.get_frame_mut()
-                [i..i + sprite.width as usize * 4 * sprite.scale as usize]
-                .chunks_mut(4)
-                .enumerate()
-            {
-                let x = sprite_index as u32 / sprite.scale as u32;
-                let data = &sprite.image.get_pixel(x, y as u32 / sprite.scale as u32).0;
-
-                if data[3] > 0 {
-                    for (col, pixel) in chunk.iter_mut().enumerate() {
-                        *pixel = data[col];
-                    }
-                }
-            }
-        }
+    pub(crate) fn draw_circle(&mut self, center: Vec2, radius: f32, color: Color) {
+        let center = self.to_pixel(center);
+        self.canvas()
+            .circle(center, radius as i32, color.to_array());
+    }
+
+    pub(crate) fn fill_circle(&mut self, center: Vec2, radius: f32, color: Color) {
+        let center = self.to_pixel(center);
+        self.canvas()
+            .fill_circle(center, radius as i32, color.to_array());
+    }
+
+    /// Outline of a closed polygon.
+    pub(crate) fn draw_polygon(&mut self, points: &[Vec2], color: Color) {
+        let points: Vec<_> = points.iter().map(|p| self.to_pixel(*p)).collect();
+        self.canvas().polygon(&points, color.to_array());
+    }
+
+    pub(crate) fn fill_polygon(&mut self, points: &[Vec2], color: Color) {
+        let offset = self.offset;
+        let points: Vec<_> = points.iter().map(|p| *p + offset).collect();
+        self.canvas().fill_polygon(&points, color.to_array());
+    }
+
+    /// Pixel a position lands on after applying the offset.
+    fn to_pixel(&self, pos: Vec2) -> IVec2 {
+        (pos + self.offset).floor().as_ivec2()
+    }
+
+    pub(crate) fn draw_sprite(&mut self, pos: Vec2, sprite: &Sprite) {
+        self.draw_sprite_animated(pos, sprite, 0);
     }
 
     pub(crate) fn draw_sprite_animated(&mut self, pos: Vec2, sprite: &Sprite, frame: u32) {
-        let size_x = sprite.width as u32;
-        let size_y = sprite.height as u32;
+        self.draw_sprite_ex(pos, sprite, frame, &DrawParams::default());
+    }
+
+    /// Draw a sprite frame with flipping, rotation and scaling applied. Without rotation,
+    /// `pos` is where the top-left corner of the (flipped and quarter-turned) frame lands.
+    pub(crate) fn draw_sprite_ex(
+        &mut self,
+        pos: Vec2,
+        sprite: &Sprite,
+        frame: u32,
+        params: &DrawParams,
+    ) {
         let pos = pos + self.offset;
+        let src_rect = sprite.frame_rect(frame);
 
-        let size_x = size_x / sprite.frame_num;
-
-        for y in 0..size_y as usize * sprite.scale as usize {
-            let i = pos.x as usize * 4
-                + pos.y as usize * self.width as usize * 4
-                + y * self.width as usize * 4;
-
-            for (sprite_index, chunk) in self.pixels.get_frame_mut()
-                [i..i + size_x as usize * 4 * sprite.scale as usize]
-                .chunks_mut(4)
-                .enumerate()
-            {
-                let frame_offset = size_x * frame;
-                let x = frame_offset + sprite_index as u32 / sprite.scale as u32;
-                let data = &sprite.image.get_pixel(x, y as u32 / sprite.scale as u32).0;
-
-                if data[3] > 0 {
-                    for (col, pixel) in chunk.iter_mut().enumerate() {
-                        *pixel = data[col];
-                    }
-                }
-            }
+        if params.is_integer_scale_only() {
+            // Plain row copies are much faster than mapping every pixel through the transform.
+            let top_left = (pos + params.pivot * (1.0 - params.scale)).floor();
+            self.canvas().blit(
+                top_left.x as i32,
+                top_left.y as i32,
+                sprite.image_ref().with_tint(params.tint.to_array()),
+                src_rect,
+                params.scale as usize,
+            );
+        } else {
+            let size = Vec2::new(src_rect.width as f32, src_rect.height as f32);
+            let transform = params.transform(pos, size);
+            self.canvas().blit_transformed(
+                sprite.image_ref().with_tint(params.tint.to_array()),
+                src_rect,
+                transform,
+            );
         }
     }
 
-    pub(crate) fn draw_char(&mut self, pos: Vec2, char: char, size: f32, color: [u8; 4]) {
-        let (metrics, bitmap) = self.font.rasterize(char, size);
-        let pos = pos + self.offset;
+    pub(crate) fn draw_char(&mut self, pos: Vec2, char: char, size: f32, color: Color) {
+        let pos = (pos + self.offset).floor();
+        let font = &self.font;
+        let (metrics, bitmap) = self
+            .glyph_cache
+            .entry((char, size.to_bits()))
+            .or_insert_with(|| font.rasterize(char, size));
 
-        for y in 0..metrics.height {
-            let i = pos.x as usize * 4
-                + pos.y as usize * self.width as usize * 4
-                + y * self.width as usize * 4;
-
-            for (bitmap_index, chunk) in self.pixels.get_frame_mut()[i..i + metrics.width * 4]
-                .chunks_mut(4)
-                .enumerate()
-            {
-                let bitmap_index = bitmap_index + y * metrics.width;
-                if bitmap[bitmap_index] > 0 {
-                    for (col, pixel) in chunk.iter_mut().enumerate() {
-                        *pixel = color[col];
-                    }
-                }
-            }
-        }
+        Canvas::new(
+            self.pixels.get_frame_mut(),
+            self.width as usize,
+            self.height as usize,
+        )
+        .blend_mask(
+            pos.x as i32,
+            pos.y as i32,
+            bitmap,
+            metrics.width,
+            color.to_array(),
+        );
     }
 
+    /// Draw a single glyph centered horizontally in a square cell, aligned to the font baseline.
+    pub(crate) fn draw_glyph(&mut self, cell_pos: Vec2, cell_size: f32, char: char, color: Color) {
+        let metrics = self.font.metrics(char, cell_size);
+        let ascent = self
+            .font
+            .horizontal_line_metrics(cell_size)
+            .map_or(cell_size, |line| line.ascent);
+
+        let pos = Vec2 {
+            x: cell_pos.x + ((cell_size - metrics.width as f32) / 2.0).floor(),
+            y: cell_pos.y + (ascent - metrics.height as f32 - metrics.ymin as f32).floor(),
+        };
+        self.draw_char(pos, char, cell_size, color);
+    }
+
+    /// Draw a line of text with colour markup, see [`markup::parse`]. Colour names are
+    /// looked up in the palette first.
     pub(crate) fn draw_text(
         &mut self,
         pos: Vec2,
         text: &str,
         size: f32,
         spacing: f32,
-        color: [u8; 4],
+        color: Color,
     ) {
-        for (i, char) in text.chars().enumerate() {
-            self.draw_char(
-                Vec2 {
-                    x: pos.x + spacing * i as f32,
-                    y: pos.y,
-                },
-                char,
-                size,
-                color,
+        let mut i = 0;
+        for span in markup::parse(text, color, &self.palette) {
+            for char in span.text.chars() {
+                self.draw_char(
+                    Vec2 {
+                        x: pos.x + spacing * i as f32,
+                        y: pos.y,
+                    },
+                    char,
+                    size,
+                    span.color,
+                );
+                i += 1;
+            }
+        }
+    }
+
+    /// Draw everything submitted to `queue` in layer and sort key order, leaving it empty.
+    pub(crate) fn flush(&mut self, queue: &mut DrawQueue) {
+        let previous_offset = self.offset;
+        let layers = *queue.layers();
+
+        for entry in queue.drain_sorted() {
+            let settings = &layers[entry.layer as usize];
+            if !settings.visible {
+                continue;
+            }
+            self.offset = previous_offset + settings.offset;
+
+            match entry.command {
+                DrawCommand::Sprite {
+                    pos,
+                    sprite,
+                    frame,
+                    params,
+                } => self.draw_sprite_ex(pos, sprite, frame, &params),
+                DrawCommand::Text {
+                    pos,
+                    text,
+                    size,
+                    spacing,
+                    color,
+                } => self.draw_text(pos, &text, size, spacing, color),
+                DrawCommand::Rect { pos, size, color } => self.draw_square(pos, size, color),
+                DrawCommand::RectOutline { pos, size, color } => self.draw_rect(pos, size, color),
+                DrawCommand::Line { start, end, color } => self.draw_line(start, end, color),
+                DrawCommand::Circle {
+                    center,
+                    radius,
+                    color,
+                    filled,
+                } => {
+                    if filled {
+                        self.fill_circle(center, radius, color)
+                    } else {
+                        self.draw_circle(center, radius, color)
+                    }
+                }
+                DrawCommand::Polygon { points, color } => self.draw_polygon(&points, color),
+                DrawCommand::FillPolygon { points, color } => self.fill_polygon(&points, color),
+            }
+        }
+
+        self.offset = previous_offset;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, SQRT_2};
+
+    /// Where the corners of a `size` frame drawn at `pos` land: top-left, top-right,
+    /// bottom-left and bottom-right.
+    fn corners(params: DrawParams, pos: Vec2, size: Vec2) -> [Vec2; 4] {
+        let transform = params.transform(pos, size);
+        [
+            Vec2::ZERO,
+            Vec2::new(size.x, 0.0),
+            Vec2::new(0.0, size.y),
+            size,
+        ]
+        .map(|corner| transform.transform_point2(corner))
+    }
+
+    fn assert_near(actual: [Vec2; 4], expected: [Vec2; 4]) {
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!(
+                actual.abs_diff_eq(expected, 1e-4),
+                "{:?} != {:?}",
+                actual,
+                expected
             );
         }
     }
+
+    #[test]
+    fn quarter_turns_map_corners_clockwise() {
+        let pos = Vec2::new(10.0, 20.0);
+        let size = Vec2::new(2.0, 3.0);
+        let turned = |quarter_turns| {
+            let params = DrawParams {
+                quarter_turns,
+                ..Default::default()
+            };
+            corners(params, pos, size).map(|corner| corner - pos)
+        };
+
+        assert_eq!(
+            turned(0),
+            [Vec2::ZERO, Vec2::new(2.0, 0.0), Vec2::new(0.0, 3.0), size]
+        );
+        // The frame becomes 3 x 2, with its top-left corner ending up top-right.
+        let one = [
+            Vec2::new(3.0, 0.0),
+            Vec2::new(3.0, 2.0),
+            Vec2::ZERO,
+            Vec2::new(0.0, 2.0),
+        ];
+        assert_near(turned(1), one);
+        assert_near(
+            turned(2),
+            [size, Vec2::new(0.0, 3.0), Vec2::new(2.0, 0.0), Vec2::ZERO],
+        );
+        assert_near(turned(4), turned(0));
+        assert_near(turned(5), one);
+    }
+
+    #[test]
+    fn flips_are_applied_before_turning() {
+        let size = Vec2::new(2.0, 3.0);
+        let params = DrawParams {
+            flip_x: true,
+            quarter_turns: 1,
+            ..Default::default()
+        };
+        // Mirroring then turning clockwise reflects the frame in its other diagonal.
+        assert_near(
+            corners(params, Vec2::ZERO, size),
+            [
+                Vec2::new(3.0, 2.0),
+                Vec2::new(3.0, 0.0),
+                Vec2::new(0.0, 2.0),
+                Vec2::ZERO,
+            ],
+        );
+    }
+
+    #[test]
+    fn rotation_turns_around_the_pivot() {
+        let pos = Vec2::new(10.0, 20.0);
+        let size = Vec2::splat(4.0);
+        let pivot = Vec2::splat(2.0);
+
+        // A right angle about the center matches a quarter turn.
+        let rotated = DrawParams {
+            rotation: FRAC_PI_2,
+            pivot,
+            ..Default::default()
+        };
+        let turned = DrawParams {
+            quarter_turns: 1,
+            ..Default::default()
+        };
+        assert_near(corners(rotated, pos, size), corners(turned, pos, size));
+
+        // At 45 degrees the top-left corner swings up above the pivot, which stays put.
+        let params = DrawParams {
+            rotation: FRAC_PI_4,
+            pivot,
+            scale: 2.0,
+            ..Default::default()
+        };
+        let transform = params.transform(pos, size);
+        assert!(transform
+            .transform_point2(pivot)
+            .abs_diff_eq(pos + pivot, 1e-4));
+        let top_left = transform.transform_point2(Vec2::ZERO);
+        assert!(top_left.abs_diff_eq(pos + pivot - Vec2::new(0.0, 4.0 * SQRT_2), 1e-4));
+    }
 }
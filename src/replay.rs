@@ -0,0 +1,250 @@
+use crate::action::Action;
+use crate::capture;
+use crate::game::Game;
+use crate::save;
+use log::warn;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::{fmt, mem};
+
+/// First line of every replay file.
+const REPLAY_MAGIC: &str = "# roguelike-engine replay";
+/// Version of the engine, recorded so a replay can be matched to the build it came from.
+pub(crate) const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// An action and the turn it was taken on.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct ReplayEntry {
+    pub turn: u64,
+    pub action: Action,
+}
+
+/// A recorded game: the seed it started from and every action taken.
+///
+/// Replays are plain text, one entry per line, so they can be attached to bug reports:
+///
+/// ```text
+/// # roguelike-engine replay
+/// version 0.1.0
+/// seed 12345
+/// 0 move 1 0
+/// 1 look 4 2
+/// 1 wait
+/// end 2 9f3c2a07b1d5e864
+/// ```
+///
+/// The `end` line holds the final turn and a checksum of the game state. It is missing
+/// when the game crashed while recording.
+#[derive(Clone, Debug)]
+pub(crate) struct Replay {
+    pub engine_version: String,
+    pub seed: u64,
+    pub entries: Vec<ReplayEntry>,
+    pub end: Option<(u64, u64)>,
+}
+
+impl Replay {
+    pub(crate) fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        Self::parse(&fs::read_to_string(path).map_err(ReplayError::Io)?)
+    }
+
+    pub(crate) fn parse(text: &str) -> Result<Self, ReplayError> {
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, l)| l.trim()) != Some(REPLAY_MAGIC) {
+            return Err(ReplayError::NotAReplay);
+        }
+
+        let mut engine_version = None;
+        let mut seed = None;
+        let mut entries = Vec::new();
+        let mut end = None;
+
+        for (line_num, line) in lines {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || ReplayError::Parse {
+                line: line_num + 1,
+                text: line.to_string(),
+            };
+            let (key, rest) = line.split_once(' ').ok_or_else(error)?;
+
+            match key {
+                "version" => engine_version = Some(rest.to_string()),
+                "seed" => seed = Some(rest.parse().map_err(|_| error())?),
+                "end" => {
+                    let (turn, checksum) = rest.split_once(' ').ok_or_else(error)?;
+                    end = Some((
+                        turn.parse().map_err(|_| error())?,
+                        u64::from_str_radix(checksum, 16).map_err(|_| error())?,
+                    ));
+                }
+                turn => entries.push(ReplayEntry {
+                    turn: turn.parse().map_err(|_| error())?,
+                    action: rest.parse().map_err(|_| error())?,
+                }),
+            }
+        }
+
+        Ok(Self {
+            engine_version: engine_version.unwrap_or_default(),
+            seed: seed.ok_or(ReplayError::MissingSeed)?,
+            entries,
+            end,
+        })
+    }
+}
+
+/// Writes a replay while the game is played. Every line is flushed right away, so the
+/// replay survives a crash.
+pub(crate) struct ReplayRecorder {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl ReplayRecorder {
+    /// Start a timestamped replay in `dir` for a game started from `seed`.
+    pub(crate) fn create(dir: impl AsRef<Path>, seed: u64) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("replay_{}.txt", capture::timestamp()));
+
+        let mut recorder = Self {
+            writer: BufWriter::new(File::create(&path)?),
+            path,
+        };
+        recorder.write_line(&format!(
+            "{}\nversion {}\nseed {}",
+            REPLAY_MAGIC, ENGINE_VERSION, seed
+        ));
+        Ok(recorder)
+    }
+
+    /// Record actions about to be taken on `turn`.
+    pub(crate) fn record(&mut self, turn: u64, actions: &[Action]) {
+        for action in actions {
+            self.write_line(&format!("{} {}", turn, action));
+        }
+    }
+
+    /// Mark the replay as complete, with the final state of the game.
+    pub(crate) fn finish(mut self, game: &Game) {
+        match state_checksum(game) {
+            Ok(checksum) => self.write_line(&format!("end {} {:016x}", game.turn(), checksum)),
+            Err(e) => warn!("Could not finish replay {}: {}", self.path.display(), e),
+        }
+    }
+
+    fn write_line(&mut self, line: &str) {
+        let result = writeln!(self.writer, "{}", line).and_then(|_| self.writer.flush());
+        if let Err(e) = result {
+            warn!("Could not write replay {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Feeds the entries of a replay back to the game, checking that every action is taken
+/// on the same turn as when it was recorded.
+pub(crate) struct ReplayPlayer {
+    entries: std::vec::IntoIter<ReplayEntry>,
+    end: Option<(u64, u64)>,
+    desynced: bool,
+}
+
+impl ReplayPlayer {
+    pub(crate) fn new(replay: Replay) -> Self {
+        Self {
+            entries: replay.entries.into_iter(),
+            end: replay.end,
+            desynced: false,
+        }
+    }
+
+    /// The next action, or `None` when the replay is over.
+    pub(crate) fn next_action(&mut self, game: &Game) -> Option<Action> {
+        let entry = self.entries.next()?;
+        if entry.turn != game.turn() && !mem::replace(&mut self.desynced, true) {
+            warn!(
+                "Replay desynced: `{}` was recorded on turn {}, but the game is on turn {}",
+                entry.action,
+                entry.turn,
+                game.turn()
+            );
+        }
+        Some(entry.action)
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.entries.len() == 0
+    }
+
+    /// Compare the finished game with the end of the recording.
+    pub(crate) fn verify(&self, game: &Game) -> Result<(), ReplayError> {
+        if self.desynced {
+            return Err(ReplayError::Desync);
+        }
+        let Some((turn, checksum)) = self.end else {
+            return Ok(());
+        };
+        let actual = state_checksum(game)?;
+        if game.turn() != turn || actual != checksum {
+            return Err(ReplayError::StateMismatch {
+                expected: (turn, checksum),
+                actual: (game.turn(), actual),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Checksum of everything that would be saved.
+pub(crate) fn state_checksum(game: &Game) -> Result<u64, save::SaveError> {
+    Ok(save::checksum(&save::encode(&game.to_save())?))
+}
+
+#[derive(Debug)]
+pub(crate) enum ReplayError {
+    Io(io::Error),
+    NotAReplay,
+    MissingSeed,
+    Parse {
+        line: usize,
+        text: String,
+    },
+    /// An action was replayed on a different turn than it was recorded on.
+    Desync,
+    /// The game ended up in a different state than the recording.
+    StateMismatch {
+        expected: (u64, u64),
+        actual: (u64, u64),
+    },
+    Save(save::SaveError),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "could not read the replay: {}", e),
+            ReplayError::NotAReplay => write!(f, "the file is not a replay"),
+            ReplayError::MissingSeed => write!(f, "the replay has no seed"),
+            ReplayError::Parse { line, text } => write!(f, "line {}: invalid entry `{}`", line, text),
+            ReplayError::Desync => write!(f, "actions were replayed on different turns"),
+            ReplayError::StateMismatch { expected, actual } => write!(
+                f,
+                "expected to end on turn {} with state {:016x}, but ended on turn {} with state {:016x}",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            ReplayError::Save(e) => write!(f, "could not encode the game state: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<save::SaveError> for ReplayError {
+    fn from(e: save::SaveError) -> Self {
+        ReplayError::Save(e)
+    }
+}
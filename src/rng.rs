@@ -0,0 +1,385 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Stream for dungeon generation.
+pub(crate) const MAPGEN: &str = "mapgen";
+/// Stream for attack and damage rolls.
+pub(crate) const COMBAT: &str = "combat";
+/// Stream for item drops.
+pub(crate) const LOOT: &str = "loot";
+/// Stream for monster decisions.
+pub(crate) const AI: &str = "ai";
+/// Stream for the chances of status effects, such as stumbling while confused.
+pub(crate) const STATUS: &str = "status";
+
+/// A small, fast generator (xoshiro256**). Not suitable for cryptography.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub(crate) struct Rng {
+    state: [u64; 4],
+}
+
+impl Rng {
+    pub(crate) fn seed_from_u64(seed: u64) -> Self {
+        // Expand the seed with SplitMix64, which never produces an all-zero state.
+        let mut x = seed;
+        let mut next = || {
+            x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+        Self {
+            state: [next(), next(), next(), next()],
+        }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    pub(crate) fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Uniform in `0.0..1.0`.
+    pub(crate) fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniform in `0..bound`, without modulo bias. Returns 0 when `bound` is 0.
+    pub(crate) fn below(&mut self, bound: u32) -> u32 {
+        if bound == 0 {
+            return 0;
+        }
+        // Lemire's multiply and reject method.
+        let threshold = bound.wrapping_neg() % bound;
+        loop {
+            let product = self.next_u32() as u64 * bound as u64;
+            if (product as u32) >= threshold {
+                return (product >> 32) as u32;
+            }
+        }
+    }
+
+    /// Uniform in `min..=max`.
+    pub(crate) fn range(&mut self, min: i32, max: i32) -> i32 {
+        if max <= min {
+            return min;
+        }
+        let span = (max as i64 - min as i64 + 1) as u64;
+        if span > u32::MAX as u64 {
+            return (min as i64 + (self.next_u64() % span) as i64) as i32;
+        }
+        (min as i64 + self.below(span as u32) as i64) as i32
+    }
+
+    /// Uniform in `min..max`.
+    pub(crate) fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    /// True with the given probability, from 0.0 to 1.0.
+    pub(crate) fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+
+    pub(crate) fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            None
+        } else {
+            items.get(self.below(items.len() as u32) as usize)
+        }
+    }
+
+    /// Roll dice in notation such as `"2d6+1"`, see [`Dice`].
+    pub(crate) fn roll(&mut self, dice: &str) -> Result<i32, DiceError> {
+        Ok(dice.parse::<Dice>()?.roll(self))
+    }
+}
+
+/// The engine's random number generator, split into independent named streams.
+///
+/// Every stream is seeded from the game seed and its name, so a stream produces the same
+/// numbers no matter how much the others are used: an extra combat roll does not change
+/// the dungeon layout.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct GameRng {
+    seed: u64,
+    streams: BTreeMap<String, Rng>,
+}
+
+impl GameRng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            streams: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The stream with the given name, created on first use.
+    pub(crate) fn stream(&mut self, name: &str) -> &mut Rng {
+        let seed = self.seed;
+        self.streams
+            .entry(name.to_string())
+            .or_insert_with(|| Rng::seed_from_u64(stream_seed(seed, name)))
+    }
+
+    /// A new generator for `name` that is not part of the saved state, for randomness that
+    /// does not affect the game, such as particles.
+    pub(crate) fn fork(&self, name: &str) -> Rng {
+        Rng::seed_from_u64(stream_seed(self.seed, name))
+    }
+
+    /// Roll dice on a stream, e.g. `rng.roll(rng::COMBAT, "1d20+2")`.
+    pub(crate) fn roll(&mut self, stream: &str, dice: &str) -> Result<i32, DiceError> {
+        self.stream(stream).roll(dice)
+    }
+}
+
+/// Mix the stream name into the game seed with FNV-1a.
+fn stream_seed(seed: u64, name: &str) -> u64 {
+    name.bytes()
+        .fold(seed ^ 0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+/// Dice notation: `NdS+M`, where the count and modifier are optional, e.g. `d20`, `2d6+1`,
+/// `3d4-2`. A plain number is a constant.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub(crate) struct Dice {
+    pub count: u32,
+    pub sides: u32,
+    pub modifier: i32,
+}
+
+impl Dice {
+    pub(crate) fn roll(&self, rng: &mut Rng) -> i32 {
+        (0..self.count).fold(self.modifier, |total, _| {
+            total.saturating_add(rng.range(1, self.sides as i32))
+        })
+    }
+}
+
+impl FromStr for Dice {
+    type Err = DiceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || DiceError(s.to_string());
+        let text: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        let text = text.to_lowercase();
+
+        // Split off the modifier at the last sign after the dice.
+        let (dice, modifier) = match text.rfind(['+', '-']) {
+            Some(at) if at > 0 => (&text[..at], text[at..].parse().map_err(|_| error())?),
+            _ => (text.as_str(), 0),
+        };
+
+        let Some((count, sides)) = dice.split_once('d') else {
+            let constant = dice.parse::<i32>().map_err(|_| error())?;
+            return Ok(Dice {
+                count: 0,
+                sides: 0,
+                modifier: constant.checked_add(modifier).ok_or_else(error)?,
+            });
+        };
+
+        let count = if count.is_empty() {
+            1
+        } else {
+            count.parse().map_err(|_| error())?
+        };
+        let sides: u32 = sides.parse().map_err(|_| error())?;
+        if sides == 0 || sides > i32::MAX as u32 || count > 1000 {
+            return Err(error());
+        }
+        Ok(Dice {
+            count,
+            sides,
+            modifier,
+        })
+    }
+}
+
+impl fmt::Display for Dice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.count == 0 {
+            return write!(f, "{}", self.modifier);
+        }
+        write!(f, "{}d{}", self.count, self.sides)?;
+        match self.modifier {
+            0 => Ok(()),
+            m if m > 0 => write!(f, "+{}", m),
+            m => write!(f, "{}", m),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct DiceError(String);
+
+impl fmt::Display for DiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` is not valid dice notation", self.0)
+    }
+}
+
+impl std::error::Error for DiceError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dice(count: u32, sides: u32, modifier: i32) -> Dice {
+        Dice {
+            count,
+            sides,
+            modifier,
+        }
+    }
+
+    #[test]
+    fn parse_dice() {
+        assert_eq!("2d6+1".parse(), Ok(dice(2, 6, 1)));
+        assert_eq!("d20".parse(), Ok(dice(1, 20, 0)));
+        assert_eq!("3d4-2".parse(), Ok(dice(3, 4, -2)));
+        assert_eq!("5".parse(), Ok(dice(0, 0, 5)));
+        assert_eq!("-5".parse(), Ok(dice(0, 0, -5)));
+        assert_eq!(" 2 D6 + 1 ".parse(), Ok(dice(2, 6, 1)));
+    }
+
+    #[test]
+    fn reject_dice() {
+        for text in ["2d0", "2d", "1001d6", "2d6+", "", "d", "2x6", "2d6+1d4"] {
+            assert_eq!(
+                text.parse::<Dice>(),
+                Err(DiceError(text.to_string())),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for text in ["2d6+1", "1d20", "3d4-2", "5", "-5", "0"] {
+            let parsed: Dice = text.parse().unwrap();
+            assert_eq!(parsed.to_string(), text);
+            assert_eq!(parsed.to_string().parse(), Ok(parsed));
+        }
+        assert_eq!("d20".parse::<Dice>().unwrap().to_string(), "1d20");
+    }
+
+    #[test]
+    fn dice_rolls_stay_in_range() {
+        let mut rng = Rng::seed_from_u64(1);
+        let dice = dice(3, 4, -2);
+        let rolls: Vec<_> = (0..1000).map(|_| dice.roll(&mut rng)).collect();
+        assert_eq!(rolls.iter().min(), Some(&1));
+        assert_eq!(rolls.iter().max(), Some(&10));
+    }
+
+    #[test]
+    fn roll_on_a_stream() {
+        let mut rng = GameRng::new(5);
+        for _ in 0..1000 {
+            let roll = rng.roll(COMBAT, "2d6+1").unwrap();
+            assert!((3..=13).contains(&roll));
+        }
+        assert_eq!(rng.roll(COMBAT, "2d6+"), Err(DiceError("2d6+".to_string())));
+        assert_eq!(rng.roll(COMBAT, "7"), Ok(7));
+    }
+
+    #[test]
+    fn rolls_do_not_disturb_other_streams() {
+        let mut quiet = GameRng::new(9);
+        let mut busy = GameRng::new(9);
+        for _ in 0..100 {
+            busy.roll(COMBAT, "1d20").unwrap();
+        }
+        let quiet: Vec<_> = (0..16).map(|_| quiet.roll(LOOT, "3d6").unwrap()).collect();
+        let busy: Vec<_> = (0..16).map(|_| busy.roll(LOOT, "3d6").unwrap()).collect();
+        assert_eq!(quiet, busy);
+    }
+
+    #[test]
+    fn below_bounds() {
+        let mut rng = Rng::seed_from_u64(2);
+        assert_eq!(rng.below(0), 0);
+        assert_eq!(rng.below(1), 0);
+        let mut seen = [false; 6];
+        for _ in 0..1000 {
+            seen[rng.below(6) as usize] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen));
+        for _ in 0..1000 {
+            assert!(rng.below(u32::MAX) < u32::MAX);
+        }
+    }
+
+    #[test]
+    fn range_bounds() {
+        let mut rng = Rng::seed_from_u64(3);
+        assert_eq!(rng.range(4, 4), 4);
+        assert_eq!(rng.range(5, 1), 5);
+        let mut seen = [false; 7];
+        for _ in 0..1000 {
+            let value = rng.range(-3, 3);
+            assert!((-3..=3).contains(&value));
+            seen[(value + 3) as usize] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen));
+
+        let values: Vec<_> = (0..1000).map(|_| rng.range(i32::MIN, i32::MAX)).collect();
+        assert!(values.iter().any(|&v| v < 0) && values.iter().any(|&v| v > 0));
+        assert!((0..1000).all(|_| rng.range(i32::MAX - 1, i32::MAX) >= i32::MAX - 1));
+        assert!((0..1000).all(|_| rng.range(i32::MIN, i32::MIN + 1) <= i32::MIN + 1));
+    }
+
+    #[test]
+    fn same_seed_same_streams() {
+        let mut a = GameRng::new(42);
+        let mut b = GameRng::new(42);
+        for stream in [MAPGEN, COMBAT, LOOT] {
+            let a: Vec<_> = (0..16).map(|_| a.stream(stream).next_u64()).collect();
+            let b: Vec<_> = (0..16).map(|_| b.stream(stream).next_u64()).collect();
+            assert_eq!(a, b);
+        }
+        assert_ne!(
+            GameRng::new(42).stream(MAPGEN).next_u64(),
+            GameRng::new(43).stream(MAPGEN).next_u64()
+        );
+    }
+
+    #[test]
+    fn streams_are_independent() {
+        let mut quiet = GameRng::new(7);
+        let mut busy = GameRng::new(7);
+        for _ in 0..100 {
+            busy.stream(COMBAT).next_u64();
+        }
+        let quiet: Vec<_> = (0..16).map(|_| quiet.stream(MAPGEN).next_u64()).collect();
+        let busy: Vec<_> = (0..16).map(|_| busy.stream(MAPGEN).next_u64()).collect();
+        assert_eq!(quiet, busy);
+        assert_ne!(
+            GameRng::new(7).stream(MAPGEN).next_u64(),
+            GameRng::new(7).stream(COMBAT).next_u64()
+        );
+    }
+}
@@ -0,0 +1,1181 @@
+use crate::ai::{Brain, Scent};
+use crate::combat::{DamageType, Resistances};
+use crate::entity::{Entity, EntityId, EntityStore, Equipment, Slot, Stats};
+use crate::level::Level;
+use crate::message_log::MessageLog;
+use crate::rng::GameRng;
+use crate::scheduler::ACTION_COST;
+use crate::status::StatusEffects;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::{fmt, fs, io};
+
+/// Identifies a file as a save of this game.
+const MAGIC: [u8; 4] = *b"RLSV";
+/// Magic, version, payload length and checksum.
+const HEADER_LEN: usize = 4 + 4 + 8 + 8;
+
+/// Version written by this build. Bump it whenever `SaveData` changes, and add a migration
+/// from the previous version to `MIGRATIONS`.
+pub(crate) const SAVE_VERSION: u32 = 8;
+
+/// Upgrades the payload of a save by one version. A migration deserializes the payload with
+/// the structs of the old version and serializes it again in the new layout. The old
+/// structs only copy what changed since: the fields they share with the current version,
+/// such as maps and stats, use the live types. The fixtures in `tests/saves` catch changes
+/// to those types that would break old saves, which then need a copy of the old layout.
+type Migration = fn(&[u8]) -> Result<Vec<u8>, SaveError>;
+
+/// `MIGRATIONS[0]` turns a version 1 payload into a version 2 payload, and so on.
+const MIGRATIONS: [Migration; SAVE_VERSION as usize - 1] = [
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+    migrate_v5_to_v6,
+    migrate_v6_to_v7,
+    migrate_v7_to_v8,
+];
+
+/// Everything about a game in progress that is kept between sessions.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SaveData {
+    /// The player, in the entities of the current level.
+    pub player: EntityId,
+    /// Player turns taken since the start of the game.
+    pub turn: u64,
+    pub message_log: MessageLog,
+    /// Added in version 2. Entities gained stats, brains and loot in version 3, equipment
+    /// in version 4, inventories in version 5, status effects and energy in version 6, and
+    /// monster memory in version 7.
+    pub rng: GameRng,
+    /// The level the player is on. Versions before 8 had a single level.
+    pub level: Level,
+    /// Levels the player has left.
+    pub levels: Vec<Level>,
+}
+
+/// Saves as written by version 1.
+mod v1 {
+    use crate::color::Color;
+    use crate::entity::EntityId;
+    use crate::lighting::Light;
+    use crate::map::Map;
+    use crate::message_log::MessageLog;
+    use glam::IVec2;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Deserialize)]
+    pub(super) struct SaveData {
+        pub map: Map,
+        pub entities: EntityStore,
+        pub player: EntityId,
+        pub turn: u64,
+        pub message_log: MessageLog,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct EntityStore {
+        pub entities: Vec<Option<Entity>>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct Entity {
+        pub name: String,
+        pub pos: IVec2,
+        pub glyph: char,
+        pub color: Color,
+        pub sprite: String,
+        pub facing_left: bool,
+        pub blocks: bool,
+        pub light: Option<Light>,
+    }
+}
+
+/// Saves as written by version 2.
+mod v2 {
+    use super::v1::EntityStore;
+    use crate::entity::EntityId;
+    use crate::map::Map;
+    use crate::message_log::MessageLog;
+    use crate::rng::GameRng;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct SaveData {
+        pub map: Map,
+        pub entities: EntityStore,
+        pub player: EntityId,
+        pub turn: u64,
+        pub message_log: MessageLog,
+        pub rng: GameRng,
+    }
+}
+
+/// Saves as written by version 3.
+mod v3 {
+    use crate::color::Color;
+    use crate::entity::{AiKind, EntityId};
+    use crate::lighting::Light;
+    use crate::map::Map;
+    use crate::message_log::MessageLog;
+    use crate::rng::{Dice, GameRng};
+    use glam::IVec2;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct SaveData {
+        pub map: Map,
+        pub entities: EntityStore,
+        pub player: EntityId,
+        pub turn: u64,
+        pub message_log: MessageLog,
+        pub rng: GameRng,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct EntityStore {
+        pub entities: Vec<Option<Entity>>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct Entity {
+        pub name: String,
+        pub pos: IVec2,
+        pub glyph: char,
+        pub color: Color,
+        pub sprite: String,
+        pub facing_left: bool,
+        pub blocks: bool,
+        pub light: Option<Light>,
+        pub stats: Option<Stats>,
+        pub ai: Option<AiKind>,
+        pub loot: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct Stats {
+        pub hp: i32,
+        pub max_hp: i32,
+        pub attack: Dice,
+        pub defense: i32,
+    }
+}
+
+/// Saves as written by version 4.
+mod v4 {
+    use crate::color::Color;
+    use crate::entity::{AiKind, EntityId, Stats};
+    use crate::lighting::Light;
+    use crate::map::Map;
+    use crate::message_log::MessageLog;
+    use crate::rng::GameRng;
+    use glam::IVec2;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct SaveData {
+        pub map: Map,
+        pub entities: EntityStore,
+        pub player: EntityId,
+        pub turn: u64,
+        pub message_log: MessageLog,
+        pub rng: GameRng,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct EntityStore {
+        pub entities: Vec<Option<Entity>>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct Entity {
+        pub name: String,
+        pub pos: IVec2,
+        pub glyph: char,
+        pub color: Color,
+        pub sprite: String,
+        pub facing_left: bool,
+        pub blocks: bool,
+        pub light: Option<Light>,
+        pub stats: Option<Stats>,
+        pub ai: Option<AiKind>,
+        pub loot: Option<String>,
+        pub equipment: Equipment,
+    }
+
+    #[derive(Default, Serialize, Deserialize)]
+    pub(super) struct Equipment {
+        pub weapon: Option<String>,
+        pub ranged: Option<String>,
+        pub armour: Option<String>,
+    }
+}
+
+/// Saves as written by version 5.
+mod v5 {
+    use crate::color::Color;
+    use crate::entity::{AiKind, EntityId, Equipment, Stats};
+    use crate::inventory::{Inventory, ItemStack};
+    use crate::lighting::Light;
+    use crate::map::Map;
+    use crate::message_log::MessageLog;
+    use crate::rng::GameRng;
+    use glam::IVec2;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct SaveData {
+        pub map: Map,
+        pub entities: EntityStore,
+        pub player: EntityId,
+        pub turn: u64,
+        pub message_log: MessageLog,
+        pub rng: GameRng,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct EntityStore {
+        pub entities: Vec<Option<Entity>>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct Entity {
+        pub name: String,
+        pub pos: IVec2,
+        pub glyph: char,
+        pub color: Color,
+        pub sprite: String,
+        pub facing_left: bool,
+        pub blocks: bool,
+        pub light: Option<Light>,
+        pub stats: Option<Stats>,
+        pub ai: Option<AiKind>,
+        pub loot: Option<String>,
+        pub equipment: Equipment,
+        pub inventory: Option<Inventory>,
+        pub item: Option<ItemStack>,
+    }
+}
+
+/// Saves as written by version 6.
+mod v6 {
+    use crate::color::Color;
+    use crate::entity::{AiKind, EntityId, Equipment, Stats};
+    use crate::inventory::{Inventory, ItemStack};
+    use crate::lighting::Light;
+    use crate::map::Map;
+    use crate::message_log::MessageLog;
+    use crate::rng::GameRng;
+    use crate::status::StatusEffects;
+    use glam::IVec2;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct SaveData {
+        pub map: Map,
+        pub entities: EntityStore,
+        pub player: EntityId,
+        pub turn: u64,
+        pub message_log: MessageLog,
+        pub rng: GameRng,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct EntityStore {
+        pub entities: Vec<Option<Entity>>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct Entity {
+        pub name: String,
+        pub pos: IVec2,
+        pub glyph: char,
+        pub color: Color,
+        pub sprite: String,
+        pub facing_left: bool,
+        pub blocks: bool,
+        pub light: Option<Light>,
+        pub stats: Option<Stats>,
+        pub ai: Option<AiKind>,
+        pub loot: Option<String>,
+        pub equipment: Equipment,
+        pub inventory: Option<Inventory>,
+        pub item: Option<ItemStack>,
+        pub effects: StatusEffects,
+        pub energy: i32,
+    }
+}
+
+/// Saves as written by version 7, the last with a single level.
+mod v7 {
+    use crate::ai::Scent;
+    use crate::entity::{EntityId, EntityStore};
+    use crate::map::Map;
+    use crate::message_log::MessageLog;
+    use crate::rng::GameRng;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct SaveData {
+        pub map: Map,
+        pub entities: EntityStore,
+        pub player: EntityId,
+        pub turn: u64,
+        pub message_log: MessageLog,
+        pub rng: GameRng,
+        pub scent: Scent,
+    }
+}
+
+/// Version 1 had no RNG state. The new seed is derived from the save itself, so loading
+/// the same old save always continues the same way.
+fn migrate_v1_to_v2(payload: &[u8]) -> Result<Vec<u8>, SaveError> {
+    let old: v1::SaveData = bincode_options(payload.len() as u64).deserialize(payload)?;
+    let new = v2::SaveData {
+        map: old.map,
+        entities: old.entities,
+        player: old.player,
+        turn: old.turn,
+        message_log: old.message_log,
+        rng: GameRng::new(checksum(payload)),
+    };
+    Ok(bincode_options(u64::MAX).serialize(&new)?)
+}
+
+/// Version 2 entities had no stats, brain or loot. They are left empty here and filled in
+/// from the raws when the game is resumed.
+fn migrate_v2_to_v3(payload: &[u8]) -> Result<Vec<u8>, SaveError> {
+    let old: v2::SaveData = bincode_options(payload.len() as u64).deserialize(payload)?;
+    let entities = old.entities.entities.into_iter().map(|slot| {
+        slot.map(|e| v3::Entity {
+            name: e.name,
+            pos: e.pos,
+            glyph: e.glyph,
+            color: e.color,
+            sprite: e.sprite,
+            facing_left: e.facing_left,
+            blocks: e.blocks,
+            light: e.light,
+            stats: None,
+            ai: None,
+            loot: None,
+        })
+    });
+    let new = v3::SaveData {
+        map: old.map,
+        entities: v3::EntityStore {
+            entities: entities.collect(),
+        },
+        player: old.player,
+        turn: old.turn,
+        message_log: old.message_log,
+        rng: old.rng,
+    };
+    Ok(bincode_options(u64::MAX).serialize(&new)?)
+}
+
+/// Version 3 stats had no damage types, armour or resistances, and entities had no
+/// equipment. Entities keep their health and fight unarmed.
+fn migrate_v3_to_v4(payload: &[u8]) -> Result<Vec<u8>, SaveError> {
+    let old: v3::SaveData = bincode_options(payload.len() as u64).deserialize(payload)?;
+    let entities = old.entities.entities.into_iter().map(|slot| {
+        slot.map(|e| v4::Entity {
+            name: e.name,
+            pos: e.pos,
+            glyph: e.glyph,
+            color: e.color,
+            sprite: e.sprite,
+            facing_left: e.facing_left,
+            blocks: e.blocks,
+            light: e.light,
+            stats: e.stats.map(|stats| Stats {
+                hp: stats.hp,
+                max_hp: stats.max_hp,
+                to_hit: 0,
+                attack: stats.attack,
+                damage_type: DamageType::Blunt,
+                defense: stats.defense,
+                armour: 0,
+                resistances: Resistances::new(),
+            }),
+            ai: e.ai,
+            loot: e.loot,
+            equipment: v4::Equipment::default(),
+        })
+    });
+    let new = v4::SaveData {
+        map: old.map,
+        entities: v4::EntityStore {
+            entities: entities.collect(),
+        },
+        player: old.player,
+        turn: old.turn,
+        message_log: old.message_log,
+        rng: old.rng,
+    };
+    Ok(bincode_options(u64::MAX).serialize(&new)?)
+}
+
+/// Version 4 had no inventories, items on the floor or ring slots. Entities keep their
+/// equipment, and get empty inventories from the raws when the game is resumed.
+fn migrate_v4_to_v5(payload: &[u8]) -> Result<Vec<u8>, SaveError> {
+    let old: v4::SaveData = bincode_options(payload.len() as u64).deserialize(payload)?;
+    let slots = old.entities.entities.into_iter().map(|slot| {
+        slot.map(|e| {
+            let mut equipment = Equipment::default();
+            equipment.set(Slot::Weapon, e.equipment.weapon);
+            equipment.set(Slot::Ranged, e.equipment.ranged);
+            equipment.set(Slot::Armour, e.equipment.armour);
+            v5::Entity {
+                name: e.name,
+                pos: e.pos,
+                glyph: e.glyph,
+                color: e.color,
+                sprite: e.sprite,
+                facing_left: e.facing_left,
+                blocks: e.blocks,
+                light: e.light,
+                stats: e.stats,
+                ai: e.ai,
+                loot: e.loot,
+                equipment,
+                inventory: None,
+                item: None,
+            }
+        })
+    });
+    let new = v5::SaveData {
+        map: old.map,
+        entities: v5::EntityStore {
+            entities: slots.collect(),
+        },
+        player: old.player,
+        turn: old.turn,
+        message_log: old.message_log,
+        rng: old.rng,
+    };
+    Ok(bincode_options(u64::MAX).serialize(&new)?)
+}
+
+/// Version 5 entities had no status effects or energy. Everything starts out unaffected
+/// and ready to act.
+fn migrate_v5_to_v6(payload: &[u8]) -> Result<Vec<u8>, SaveError> {
+    let old: v5::SaveData = bincode_options(payload.len() as u64).deserialize(payload)?;
+    let slots = old.entities.entities.into_iter().map(|slot| {
+        slot.map(|e| v6::Entity {
+            name: e.name,
+            pos: e.pos,
+            glyph: e.glyph,
+            color: e.color,
+            sprite: e.sprite,
+            facing_left: e.facing_left,
+            blocks: e.blocks,
+            light: e.light,
+            stats: e.stats,
+            ai: e.ai,
+            loot: e.loot,
+            equipment: e.equipment,
+            inventory: e.inventory,
+            item: e.item,
+            effects: StatusEffects::default(),
+            energy: ACTION_COST,
+        })
+    });
+    let new = v6::SaveData {
+        map: old.map,
+        entities: v6::EntityStore {
+            entities: slots.collect(),
+        },
+        player: old.player,
+        turn: old.turn,
+        message_log: old.message_log,
+        rng: old.rng,
+    };
+    Ok(bincode_options(u64::MAX).serialize(&new)?)
+}
+
+/// Version 6 monsters had no memory. They start out idle at their current position, and
+/// the player leaves no scent yet.
+fn migrate_v6_to_v7(payload: &[u8]) -> Result<Vec<u8>, SaveError> {
+    let old: v6::SaveData = bincode_options(payload.len() as u64).deserialize(payload)?;
+    let slots = old.entities.entities.into_iter().map(|slot| {
+        slot.map(|e| {
+            let mut entity = Entity::new(e.name, e.pos);
+            entity.glyph = e.glyph;
+            entity.color = e.color;
+            entity.sprite = e.sprite;
+            entity.facing_left = e.facing_left;
+            entity.blocks = e.blocks;
+            entity.light = e.light;
+            entity.stats = e.stats;
+            entity.ai = e.ai;
+            entity.brain = e.ai.map(|_| Brain::new(e.pos));
+            entity.loot = e.loot;
+            entity.equipment = e.equipment;
+            entity.inventory = e.inventory;
+            entity.item = e.item;
+            entity.effects = e.effects;
+            entity.energy = e.energy;
+            entity
+        })
+    });
+    let new = v7::SaveData {
+        scent: Scent::new(old.map.width, old.map.height),
+        map: old.map,
+        entities: EntityStore::from_slots(slots.collect()),
+        player: old.player,
+        turn: old.turn,
+        message_log: old.message_log,
+        rng: old.rng,
+    };
+    Ok(bincode_options(u64::MAX).serialize(&new)?)
+}
+
+/// Version 7 games took place on a single level, which becomes the first one.
+fn migrate_v7_to_v8(payload: &[u8]) -> Result<Vec<u8>, SaveError> {
+    let old: v7::SaveData = bincode_options(payload.len() as u64).deserialize(payload)?;
+    let new = SaveData {
+        player: old.player,
+        turn: old.turn,
+        message_log: old.message_log,
+        rng: old.rng,
+        level: Level {
+            depth: 1,
+            map: old.map,
+            entities: old.entities,
+            scent: old.scent,
+        },
+        levels: Vec::new(),
+    };
+    Ok(bincode_options(u64::MAX).serialize(&new)?)
+}
+
+#[derive(Debug)]
+pub(crate) enum SaveError {
+    Io(io::Error),
+    /// The file does not start with the save file magic.
+    NotASave,
+    /// The file ends before the header or the payload does.
+    Truncated,
+    /// The save was written by a newer version of the game.
+    TooNew {
+        version: u32,
+    },
+    /// The payload does not match its checksum.
+    ChecksumMismatch,
+    /// The payload could not be encoded or decoded.
+    Serialization(bincode::Error),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "could not access the save file: {}", e),
+            SaveError::NotASave => write!(f, "the file is not a save file"),
+            SaveError::Truncated => write!(f, "the save file is truncated"),
+            SaveError::TooNew { version } => write!(
+                f,
+                "the save file is version {}, but this build only reads up to version {}",
+                version, SAVE_VERSION
+            ),
+            SaveError::ChecksumMismatch => write!(f, "the save file is corrupted"),
+            SaveError::Serialization(e) => write!(f, "the save data is invalid: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SaveError::Io(e) => Some(e),
+            SaveError::Serialization(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for SaveError {
+    fn from(e: bincode::Error) -> Self {
+        SaveError::Serialization(e)
+    }
+}
+
+/// Bincode with variable length integers. Decoding may not read more than `limit` bytes,
+/// so a corrupted length cannot make it allocate huge buffers.
+fn bincode_options(limit: u64) -> impl Options {
+    bincode::DefaultOptions::new().with_limit(limit)
+}
+
+/// Serialize `data` with the save header.
+pub(crate) fn encode(data: &SaveData) -> Result<Vec<u8>, SaveError> {
+    let payload = bincode_options(u64::MAX).serialize(data)?;
+    Ok(encode_payload(SAVE_VERSION, &payload))
+}
+
+fn encode_payload(version: u32, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&version.to_le_bytes());
+    bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&checksum(payload).to_le_bytes());
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Check the header, migrate the payload to the current version and deserialize it.
+pub(crate) fn decode(bytes: &[u8]) -> Result<SaveData, SaveError> {
+    if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+        return Err(SaveError::NotASave);
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err(SaveError::Truncated);
+    }
+
+    let read_u64 = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+    let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+    let len = read_u64(8);
+    let expected_checksum = read_u64(16);
+
+    let payload = &bytes[HEADER_LEN..];
+    if (payload.len() as u64) < len {
+        return Err(SaveError::Truncated);
+    }
+    let payload = &payload[..len as usize];
+    if checksum(payload) != expected_checksum {
+        return Err(SaveError::ChecksumMismatch);
+    }
+    if version == 0 {
+        return Err(SaveError::NotASave);
+    }
+    if version > SAVE_VERSION {
+        return Err(SaveError::TooNew { version });
+    }
+
+    let mut payload = payload.to_vec();
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        payload = migration(&payload)?;
+    }
+    Ok(bincode_options(payload.len() as u64).deserialize(&payload)?)
+}
+
+/// Write a save file. The data goes to a temporary file first, so a crash while saving
+/// does not destroy the previous save.
+pub(crate) fn save(path: impl AsRef<Path>, data: &SaveData) -> Result<(), SaveError> {
+    let path = path.as_ref();
+    let bytes = encode(data)?;
+
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, bytes)?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+pub(crate) fn load(path: impl AsRef<Path>) -> Result<SaveData, SaveError> {
+    decode(&fs::read(path)?)
+}
+
+/// Load a save and delete it, so a run cannot be resumed from the same save twice.
+/// A save that cannot be decoded is left in place.
+pub(crate) fn load_and_delete(path: impl AsRef<Path>) -> Result<SaveData, SaveError> {
+    let path = path.as_ref();
+    let data = decode(&fs::read(path)?)?;
+    fs::remove_file(path)?;
+    Ok(data)
+}
+
+/// 64-bit FNV-1a hash, enough to catch truncated or damaged files.
+pub(crate) fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::easing::Easing;
+    use crate::entity::AiKind;
+    use crate::inventory::{Inventory, ItemStack};
+    use crate::lighting::Light;
+    use crate::map::{Map, TileType};
+    use crate::message_log::MessageCategory;
+    use crate::palette::Palette;
+    use crate::raws::Raws;
+    use crate::status::{StatusEffect, StatusKind};
+    use glam::IVec2;
+
+    /// Where the fixture of a version is kept.
+    fn fixture_path(version: u32) -> String {
+        format!("tests/saves/v{}.sav", version)
+    }
+
+    /// A version 1 payload with one entity, the player. Bincode writes structs as tuples,
+    /// so a tuple of the fields stands in for `v1::SaveData`, which only deserializes.
+    fn v1_payload() -> Vec<u8> {
+        let player = v1::Entity {
+            name: "player".to_string(),
+            pos: IVec2::new(3, 4),
+            glyph: '@',
+            color: Color::WHITE,
+            sprite: "player".to_string(),
+            facing_left: false,
+            blocks: true,
+            light: None,
+        };
+        let data = (
+            Map::new(10, 8, TileType::Floor),
+            v1::EntityStore {
+                entities: vec![Some(player)],
+            },
+            0usize,
+            42u64,
+            MessageLog::new(10),
+        );
+        bincode_options(u64::MAX).serialize(&data).unwrap()
+    }
+
+    const PLAYER_POS: IVec2 = IVec2::new(3, 4);
+    const GOBLIN_POS: IVec2 = IVec2::new(6, 2);
+
+    fn map() -> Map {
+        let mut map = Map::new(10, 8, TileType::Floor);
+        map.set(IVec2::new(5, 5), TileType::Wall);
+        map
+    }
+
+    fn message_log() -> MessageLog {
+        let mut log = MessageLog::new(10);
+        log.push("Welcome.", MessageCategory::General);
+        log
+    }
+
+    /// The id of the first entity in a store, the player in every fixture.
+    fn first_id() -> EntityId {
+        EntityStore::new().spawn(Entity::new(String::new(), IVec2::ZERO))
+    }
+
+    fn torch() -> Option<Light> {
+        Some(Light {
+            color: Color::rgb(0xff, 0xc0, 0x80),
+            radius: 7,
+            intensity: 1.2,
+            falloff: Easing::InQuad,
+            flicker: 0.15,
+        })
+    }
+
+    fn stats() -> Stats {
+        Stats {
+            hp: 9,
+            max_hp: 12,
+            to_hit: 1,
+            attack: "1d6+1".parse().unwrap(),
+            damage_type: DamageType::Slashing,
+            defense: 1,
+            armour: 1,
+            resistances: [(DamageType::Fire, -50)].into_iter().collect(),
+        }
+    }
+
+    fn equipment() -> Equipment {
+        let mut equipment = Equipment::default();
+        equipment.set(Slot::Weapon, Some("sword".to_string()));
+        equipment
+    }
+
+    fn inventory() -> Option<Inventory> {
+        let palette = Palette::load("assets/palettes/dungeon.gpl").unwrap();
+        let raws = Raws::load("assets/raws", &palette).unwrap();
+        let mut inventory = Inventory::new(30);
+        inventory.add(ItemStack::new("healing potion", 2), &raws);
+        Some(inventory)
+    }
+
+    fn effects() -> StatusEffects {
+        let mut effects = StatusEffects::default();
+        effects.apply(StatusEffect {
+            kind: StatusKind::Poison,
+            turns: 3,
+            potency: 2,
+        });
+        effects
+    }
+
+    fn v1_entities() -> v1::EntityStore {
+        let entity = |name: &str, pos, glyph, light| v1::Entity {
+            name: name.to_string(),
+            pos,
+            glyph,
+            color: Color::WHITE,
+            sprite: name.to_string(),
+            facing_left: false,
+            blocks: true,
+            light,
+        };
+        v1::EntityStore {
+            entities: vec![
+                Some(entity("player", PLAYER_POS, '@', torch())),
+                None,
+                Some(entity("goblin", GOBLIN_POS, 'g', None)),
+            ],
+        }
+    }
+
+    /// The entities of a fixture in the current layout.
+    fn entity_slots() -> Vec<Option<Entity>> {
+        let mut player = Entity::new("player".to_string(), PLAYER_POS);
+        player.light = torch();
+        player.equipment = equipment();
+        player.inventory = inventory();
+        let mut goblin = Entity::new("goblin".to_string(), GOBLIN_POS);
+        goblin.stats = Some(stats());
+        goblin.ai = Some(AiKind::Melee);
+        goblin.loot = Some("goblin".to_string());
+        goblin.brain = Some(Brain::new(GOBLIN_POS));
+        goblin.effects = effects();
+        goblin.energy = 50;
+        vec![Some(player), None, Some(goblin)]
+    }
+
+    fn entities() -> EntityStore {
+        EntityStore::from_slots(entity_slots())
+    }
+
+    /// The entities of a fixture in the layout of an older version.
+    fn old_entities<T>(convert: impl Fn(&Entity) -> T) -> Vec<Option<T>> {
+        entity_slots()
+            .iter()
+            .map(|slot| slot.as_ref().map(&convert))
+            .collect()
+    }
+
+    /// The payload of a fixture, written in the layout of `version`. Every version has the
+    /// player and a goblin, with whatever the version can store about them.
+    fn fixture_payload(version: u32) -> Vec<u8> {
+        let options = bincode_options(u64::MAX);
+        let rng = GameRng::new(7);
+        match version {
+            1 => v1_payload(),
+            2 => options
+                .serialize(&v2::SaveData {
+                    map: map(),
+                    entities: v1_entities(),
+                    player: first_id(),
+                    turn: 42,
+                    message_log: message_log(),
+                    rng,
+                })
+                .unwrap(),
+            3 => {
+                let entities = v1_entities().entities.into_iter().map(|slot| {
+                    slot.map(|e| v3::Entity {
+                        stats: (e.name == "goblin").then(|| v3::Stats {
+                            hp: 9,
+                            max_hp: 12,
+                            attack: "1d6+1".parse().unwrap(),
+                            defense: 1,
+                        }),
+                        ai: (e.name == "goblin").then_some(AiKind::Melee),
+                        loot: (e.name == "goblin").then(|| "goblin".to_string()),
+                        name: e.name,
+                        pos: e.pos,
+                        glyph: e.glyph,
+                        color: e.color,
+                        sprite: e.sprite,
+                        facing_left: e.facing_left,
+                        blocks: e.blocks,
+                        light: e.light,
+                    })
+                });
+                options
+                    .serialize(&v3::SaveData {
+                        map: map(),
+                        entities: v3::EntityStore {
+                            entities: entities.collect(),
+                        },
+                        player: first_id(),
+                        turn: 42,
+                        message_log: message_log(),
+                        rng,
+                    })
+                    .unwrap()
+            }
+            4 => {
+                let entities = old_entities(|e| v4::Entity {
+                    name: e.name.clone(),
+                    pos: e.pos(),
+                    glyph: e.glyph,
+                    color: e.color,
+                    sprite: e.sprite.clone(),
+                    facing_left: e.facing_left,
+                    blocks: e.blocks,
+                    light: e.light,
+                    stats: e.stats.clone(),
+                    ai: e.ai,
+                    loot: e.loot.clone(),
+                    equipment: v4::Equipment {
+                        weapon: e.equipment.get(Slot::Weapon).map(str::to_string),
+                        ranged: None,
+                        armour: None,
+                    },
+                });
+                options
+                    .serialize(&v4::SaveData {
+                        map: map(),
+                        entities: v4::EntityStore { entities },
+                        player: first_id(),
+                        turn: 42,
+                        message_log: message_log(),
+                        rng,
+                    })
+                    .unwrap()
+            }
+            5 => {
+                let entities = old_entities(|e| v5::Entity {
+                    name: e.name.clone(),
+                    pos: e.pos(),
+                    glyph: e.glyph,
+                    color: e.color,
+                    sprite: e.sprite.clone(),
+                    facing_left: e.facing_left,
+                    blocks: e.blocks,
+                    light: e.light,
+                    stats: e.stats.clone(),
+                    ai: e.ai,
+                    loot: e.loot.clone(),
+                    equipment: e.equipment.clone(),
+                    inventory: e.inventory.clone(),
+                    item: e.item.clone(),
+                });
+                options
+                    .serialize(&v5::SaveData {
+                        map: map(),
+                        entities: v5::EntityStore { entities },
+                        player: first_id(),
+                        turn: 42,
+                        message_log: message_log(),
+                        rng,
+                    })
+                    .unwrap()
+            }
+            6 => {
+                let entities = old_entities(|e| v6::Entity {
+                    name: e.name.clone(),
+                    pos: e.pos(),
+                    glyph: e.glyph,
+                    color: e.color,
+                    sprite: e.sprite.clone(),
+                    facing_left: e.facing_left,
+                    blocks: e.blocks,
+                    light: e.light,
+                    stats: e.stats.clone(),
+                    ai: e.ai,
+                    loot: e.loot.clone(),
+                    equipment: e.equipment.clone(),
+                    inventory: e.inventory.clone(),
+                    item: e.item.clone(),
+                    effects: e.effects.clone(),
+                    energy: e.energy,
+                });
+                options
+                    .serialize(&v6::SaveData {
+                        map: map(),
+                        entities: v6::EntityStore { entities },
+                        player: first_id(),
+                        turn: 42,
+                        message_log: message_log(),
+                        rng,
+                    })
+                    .unwrap()
+            }
+            7 => {
+                let mut scent = Scent::new(10, 8);
+                scent.update(PLAYER_POS);
+                options
+                    .serialize(&v7::SaveData {
+                        map: map(),
+                        entities: entities(),
+                        player: first_id(),
+                        turn: 42,
+                        message_log: message_log(),
+                        rng,
+                        scent,
+                    })
+                    .unwrap()
+            }
+            _ => {
+                let level = |depth| Level {
+                    depth,
+                    map: map(),
+                    entities: entities(),
+                    scent: Scent::new(10, 8),
+                };
+                options
+                    .serialize(&SaveData {
+                        player: first_id(),
+                        turn: 42,
+                        message_log: message_log(),
+                        rng,
+                        level: level(2),
+                        levels: vec![level(1)],
+                    })
+                    .unwrap()
+            }
+        }
+    }
+
+    fn current_save() -> Vec<u8> {
+        let data = decode(&encode_payload(1, &v1_payload())).unwrap();
+        encode(&data).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = current_save();
+        let data = decode(&bytes).unwrap();
+        assert_eq!(data.turn, 42);
+        assert_eq!(encode(&data).unwrap(), bytes);
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut bytes = current_save();
+        bytes[0] = b'X';
+        assert!(matches!(decode(&bytes), Err(SaveError::NotASave)));
+        assert!(matches!(decode(b"RL"), Err(SaveError::NotASave)));
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = current_save();
+        for len in [HEADER_LEN - 1, HEADER_LEN, bytes.len() - 1] {
+            assert!(matches!(decode(&bytes[..len]), Err(SaveError::Truncated)));
+        }
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut bytes = current_save();
+        bytes[16] ^= 0x01;
+        assert!(matches!(decode(&bytes), Err(SaveError::ChecksumMismatch)));
+    }
+
+    #[test]
+    fn too_new() {
+        let bytes = encode_payload(SAVE_VERSION + 1, &[]);
+        assert!(matches!(
+            decode(&bytes),
+            Err(SaveError::TooNew { version }) if version == SAVE_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn migrate_from_v1() {
+        let payload = v1_payload();
+        let data = decode(&encode_payload(1, &payload)).unwrap();
+        assert_eq!(data.turn, 42);
+        assert_eq!(data.level.depth, 1);
+        assert!(data.levels.is_empty());
+        assert_eq!(data.level.map.width, 10);
+
+        let player = data.level.entities.get(data.player).unwrap();
+        assert_eq!(player.name, "player");
+        assert_eq!(player.pos(), IVec2::new(3, 4));
+        assert!(player.stats.is_none());
+        assert!(player.brain.is_none());
+        assert_eq!(data.level.entities.at(IVec2::new(3, 4)), vec![data.player]);
+
+        // The new RNG is seeded from the old save, so the migration is repeatable.
+        let again = decode(&encode_payload(1, &payload)).unwrap();
+        assert_eq!(encode(&again).unwrap(), encode(&data).unwrap());
+    }
+
+    /// Writes the fixtures of versions that do not have one yet. Run it with
+    /// `cargo test write_fixtures -- --ignored` after bumping `SAVE_VERSION`. The fixtures of
+    /// older versions must never change.
+    #[test]
+    #[ignore]
+    fn write_fixtures() {
+        for version in 1..=SAVE_VERSION {
+            let path = fixture_path(version);
+            if !Path::new(&path).exists() {
+                fs::write(path, encode_payload(version, &fixture_payload(version))).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn fixtures_of_every_version_load() {
+        for version in 1..=SAVE_VERSION {
+            let bytes = fs::read(fixture_path(version))
+                .unwrap_or_else(|e| panic!("no fixture for version {}: {}", version, e));
+            // A type shared with the current version changed how it is serialized. Give
+            // the old version a copy of the old layout instead.
+            assert!(
+                bytes == encode_payload(version, &fixture_payload(version)),
+                "the layout of version {} changed",
+                version
+            );
+
+            let data = decode(&bytes).unwrap();
+            assert_eq!(data.turn, 42);
+            assert_eq!(data.level.depth, if version >= 8 { 2 } else { 1 });
+            assert_eq!(data.levels.len(), usize::from(version >= 8));
+            let entities = &data.level.entities;
+            let player = entities.get(data.player).unwrap();
+            assert_eq!(player.name, "player");
+            assert_eq!(player.pos(), PLAYER_POS);
+            if version == 1 {
+                continue;
+            }
+
+            assert!(player.light.is_some());
+            let goblin = entities.at(GOBLIN_POS);
+            let goblin = entities.get(goblin[0]).unwrap();
+            assert_eq!(goblin.name, "goblin");
+            if version >= 3 {
+                assert_eq!(goblin.stats.as_ref().unwrap().hp, 9);
+                assert_eq!(goblin.ai, Some(AiKind::Melee));
+                assert!(goblin.brain.is_some());
+                assert_eq!(goblin.loot.as_deref(), Some("goblin"));
+            }
+            if version >= 4 {
+                assert_eq!(player.equipment.get(Slot::Weapon), Some("sword"));
+            }
+            if version >= 5 {
+                let inventory = player.inventory.as_ref().unwrap();
+                assert_eq!(inventory.items(), [ItemStack::new("healing potion", 2)]);
+            }
+            if version >= 6 {
+                assert!(goblin.effects.has(StatusKind::Poison));
+            }
+        }
+    }
+
+    #[test]
+    fn load_and_delete_keeps_invalid_saves() {
+        let dir = std::env::temp_dir().join(format!("save-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let broken = dir.join("broken.sav");
+        fs::write(&broken, b"not a save").unwrap();
+        assert!(matches!(load_and_delete(&broken), Err(SaveError::NotASave)));
+        assert!(broken.exists());
+
+        let valid = dir.join("valid.sav");
+        fs::write(&valid, current_save()).unwrap();
+        assert_eq!(load_and_delete(&valid).unwrap().turn, 42);
+        assert!(!valid.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
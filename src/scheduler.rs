@@ -0,0 +1,91 @@
+use crate::entity::Entity;
+use crate::status::StatusKind;
+
+/// Energy an action costs. Entities act whenever they have this much energy.
+pub(crate) const ACTION_COST: i32 = 100;
+/// Energy gained per game turn at normal speed, enough for one action.
+pub(crate) const NORMAL_SPEED: i32 = 100;
+
+/// Energy `entity` gains every game turn. Haste doubles it and slow halves it.
+pub(crate) fn speed(entity: &Entity) -> i32 {
+    let mut speed = NORMAL_SPEED;
+    if entity.effects.has(StatusKind::Haste) {
+        speed *= 2;
+    }
+    if entity.effects.has(StatusKind::Slow) {
+        speed /= 2;
+    }
+    speed
+}
+
+/// Whether `entity` has the energy to act.
+pub(crate) fn is_ready(entity: &Entity) -> bool {
+    entity.energy >= ACTION_COST
+}
+
+/// Spend the energy of one action.
+pub(crate) fn spend(entity: &mut Entity) {
+    entity.energy -= ACTION_COST;
+}
+
+/// Give `entity` its energy for a game turn.
+pub(crate) fn recharge(entity: &mut Entity) {
+    entity.energy += speed(entity);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::StatusEffect;
+    use glam::IVec2;
+
+    /// Actions `entity` takes over `turns` game turns.
+    fn actions(entity: &mut Entity, turns: u32) -> u32 {
+        let mut actions = 0;
+        for _ in 0..turns {
+            recharge(entity);
+            while is_ready(entity) {
+                spend(entity);
+                actions += 1;
+            }
+        }
+        actions
+    }
+
+    fn with(kind: StatusKind) -> Entity {
+        let mut entity = Entity::new("test".to_string(), IVec2::ZERO);
+        entity.effects.apply(StatusEffect {
+            kind,
+            turns: 100,
+            potency: 0,
+        });
+        entity
+    }
+
+    #[test]
+    fn entities_act_once_they_have_the_energy() {
+        let mut entity = Entity::new("test".to_string(), IVec2::ZERO);
+        assert!(!is_ready(&entity));
+        entity.energy = ACTION_COST - 1;
+        assert!(!is_ready(&entity));
+        entity.energy = ACTION_COST;
+        assert!(is_ready(&entity));
+        spend(&mut entity);
+        assert_eq!(entity.energy, 0);
+    }
+
+    #[test]
+    fn haste_doubles_and_slow_halves_the_speed() {
+        let mut normal = Entity::new("test".to_string(), IVec2::ZERO);
+        assert_eq!(speed(&normal), ACTION_COST);
+        assert_eq!(actions(&mut normal, 10), 10);
+
+        let mut hasted = with(StatusKind::Haste);
+        assert_eq!(speed(&hasted), 2 * ACTION_COST);
+        assert_eq!(actions(&mut hasted, 10), 20);
+
+        let mut slowed = with(StatusKind::Slow);
+        assert_eq!(speed(&slowed), ACTION_COST / 2);
+        assert_eq!(actions(&mut slowed, 10), 5);
+    }
+}
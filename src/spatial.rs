@@ -0,0 +1,227 @@
+use glam::IVec2;
+use std::collections::HashMap;
+
+/// Width and height of a bucket in tiles. Queries visit every bucket they overlap, so this
+/// trades the cost of small queries against that of large ones.
+const BUCKET_SIZE: i32 = 8;
+
+/// Spatial hash of values on the tile grid, for finding what is on a tile or near it without
+/// looking at everything.
+///
+/// Values are grouped into square buckets of tiles. The index does not know where values
+/// are on its own: whoever moves one must tell it with `update`.
+#[derive(Clone, Debug)]
+pub(crate) struct SpatialIndex<T> {
+    buckets: HashMap<IVec2, Vec<(T, IVec2)>>,
+    len: usize,
+}
+
+impl<T> Default for SpatialIndex<T> {
+    fn default() -> Self {
+        Self {
+            buckets: HashMap::new(),
+            len: 0,
+        }
+    }
+}
+
+fn bucket(pos: IVec2) -> IVec2 {
+    IVec2::new(pos.x.div_euclid(BUCKET_SIZE), pos.y.div_euclid(BUCKET_SIZE))
+}
+
+impl<T: Copy + PartialEq> SpatialIndex<T> {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn insert(&mut self, value: T, pos: IVec2) {
+        self.buckets
+            .entry(bucket(pos))
+            .or_default()
+            .push((value, pos));
+        self.len += 1;
+    }
+
+    /// Remove `value` from `pos`. Returns whether it was there.
+    pub(crate) fn remove(&mut self, value: T, pos: IVec2) -> bool {
+        let key = bucket(pos);
+        let Some(entries) = self.buckets.get_mut(&key) else {
+            return false;
+        };
+        let Some(index) = entries.iter().position(|&(v, p)| v == value && p == pos) else {
+            return false;
+        };
+        entries.swap_remove(index);
+        if entries.is_empty() {
+            self.buckets.remove(&key);
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// Move `value` from `from` to `to`, inserting it if it was not at `from`.
+    pub(crate) fn update(&mut self, value: T, from: IVec2, to: IVec2) {
+        if bucket(from) == bucket(to) {
+            let entry = self
+                .buckets
+                .get_mut(&bucket(from))
+                .and_then(|entries| entries.iter_mut().find(|(v, p)| *v == value && *p == from));
+            if let Some(entry) = entry {
+                entry.1 = to;
+                return;
+            }
+        } else {
+            self.remove(value, from);
+        }
+        self.insert(value, to);
+    }
+
+    /// Values on the tile `pos`, in no particular order.
+    pub(crate) fn at(&self, pos: IVec2) -> impl Iterator<Item = T> + '_ {
+        self.buckets
+            .get(&bucket(pos))
+            .into_iter()
+            .flatten()
+            .filter(move |&&(_, p)| p == pos)
+            .map(|&(v, _)| v)
+    }
+
+    /// Values on tiles from `min` to `max`, both inclusive, with their positions.
+    pub(crate) fn in_rect(&self, min: IVec2, max: IVec2) -> impl Iterator<Item = (T, IVec2)> + '_ {
+        let (first, last) = (bucket(min), bucket(max));
+        (first.y..=last.y)
+            .flat_map(move |y| (first.x..=last.x).map(move |x| IVec2::new(x, y)))
+            .filter_map(|key| self.buckets.get(&key))
+            .flatten()
+            .filter(move |&&(_, p)| p.cmpge(min).all() && p.cmple(max).all())
+            .copied()
+    }
+
+    /// Values within `radius` tiles of `center`, measured in a straight line, with their
+    /// positions.
+    pub(crate) fn in_radius(
+        &self,
+        center: IVec2,
+        radius: i32,
+    ) -> impl Iterator<Item = (T, IVec2)> + '_ {
+        let extent = IVec2::splat(radius);
+        self.in_rect(center - extent, center + extent)
+            .filter(move |&(_, p)| {
+                let d = p - center;
+                d.x * d.x + d.y * d.y <= radius * radius
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(values: impl Iterator<Item = (u32, IVec2)>) -> Vec<u32> {
+        let mut values: Vec<_> = values.map(|(v, _)| v).collect();
+        values.sort();
+        values
+    }
+
+    #[test]
+    fn update_across_buckets() {
+        let mut index = SpatialIndex::new();
+        index.insert(1, IVec2::new(7, 3));
+        index.update(1, IVec2::new(7, 3), IVec2::new(8, 3));
+        assert_eq!(index.at(IVec2::new(7, 3)).count(), 0);
+        assert_eq!(index.at(IVec2::new(8, 3)).collect::<Vec<_>>(), [1]);
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.buckets.len(), 1);
+    }
+
+    #[test]
+    fn update_within_bucket() {
+        let mut index = SpatialIndex::new();
+        index.insert(1, IVec2::new(1, 1));
+        index.insert(2, IVec2::new(1, 1));
+        index.update(1, IVec2::new(1, 1), IVec2::new(2, 2));
+        assert_eq!(index.at(IVec2::new(1, 1)).collect::<Vec<_>>(), [2]);
+        assert_eq!(index.at(IVec2::new(2, 2)).collect::<Vec<_>>(), [1]);
+        assert_eq!(index.len(), 2);
+    }
+
+    #[test]
+    fn update_of_missing_value_inserts() {
+        let mut index = SpatialIndex::new();
+        index.update(1, IVec2::new(1, 1), IVec2::new(2, 2));
+        assert_eq!(index.at(IVec2::new(2, 2)).collect::<Vec<_>>(), [1]);
+        assert_eq!(index.len(), 1);
+    }
+
+    #[test]
+    fn remove_missing() {
+        let mut index = SpatialIndex::new();
+        index.insert(1, IVec2::new(1, 1));
+        assert!(!index.remove(2, IVec2::new(1, 1)));
+        assert!(!index.remove(1, IVec2::new(1, 2)));
+        assert!(!index.remove(1, IVec2::new(100, 100)));
+        assert_eq!(index.len(), 1);
+        assert!(index.remove(1, IVec2::new(1, 1)));
+        assert!(index.is_empty());
+        assert!(index.buckets.is_empty());
+    }
+
+    #[test]
+    fn negative_coordinates() {
+        let mut index = SpatialIndex::new();
+        index.insert(1, IVec2::new(-1, -1));
+        index.insert(2, IVec2::new(0, 0));
+        assert_ne!(bucket(IVec2::new(-1, -1)), bucket(IVec2::new(0, 0)));
+        assert_eq!(bucket(IVec2::new(-8, -8)), IVec2::new(-1, -1));
+        assert_eq!(bucket(IVec2::new(-9, 0)), IVec2::new(-2, 0));
+        assert_eq!(index.at(IVec2::new(-1, -1)).collect::<Vec<_>>(), [1]);
+        assert_eq!(
+            sorted(index.in_rect(IVec2::new(-1, -1), IVec2::new(0, 0))),
+            [1, 2]
+        );
+        index.update(1, IVec2::new(-1, -1), IVec2::new(-9, -9));
+        assert_eq!(index.at(IVec2::new(-9, -9)).collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn in_rect_is_inclusive() {
+        let mut index = SpatialIndex::new();
+        for (value, pos) in [
+            (1, (2, 2)),
+            (2, (5, 5)),
+            (3, (1, 3)),
+            (4, (6, 5)),
+            (5, (3, 6)),
+        ] {
+            index.insert(value, IVec2::new(pos.0, pos.1));
+        }
+        assert_eq!(
+            sorted(index.in_rect(IVec2::new(2, 2), IVec2::new(5, 5))),
+            [1, 2]
+        );
+    }
+
+    #[test]
+    fn in_radius_boundary() {
+        let mut index = SpatialIndex::new();
+        let center = IVec2::new(10, 10);
+        // Exactly 5 away, at 3-4-5 offsets, and just outside.
+        index.insert(1, center + IVec2::new(5, 0));
+        index.insert(2, center + IVec2::new(3, 4));
+        index.insert(3, center + IVec2::new(-4, -3));
+        index.insert(4, center + IVec2::new(4, 4));
+        index.insert(5, center + IVec2::new(6, 0));
+        assert_eq!(sorted(index.in_radius(center, 5)), [1, 2, 3]);
+        assert_eq!(sorted(index.in_radius(center, 0)), []);
+    }
+}
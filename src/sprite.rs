@@ -1,34 +1,62 @@
-use image::{DynamicImage, GenericImageView};
+use crate::raster::{ImageRef, Rect};
+use image::GenericImageView;
+use std::path::Path;
 
 pub(crate) struct Sprite {
     pub width: u32,
     pub height: u32,
-    pub scale: f32,
-    pub image: DynamicImage,
+    /// Pixels converted to tightly packed RGBA8 rows when the sprite is loaded.
+    pub data: Vec<u8>,
     pub frame_num: u32,
+    /// Animation speed in frames per second.
+    pub fps: f32,
+    /// True when the sprite has no transparent pixels.
+    opaque: bool,
 }
 
 impl Sprite {
-    pub(crate) fn from_image(path: &str, scale: Option<f32>) -> Self {
+    pub(crate) fn from_image_animated(path: impl AsRef<Path>, frame_count: u32) -> Self {
         let image = image::open(path).unwrap();
         let (width, height) = image.dimensions();
+        let data = image.to_rgba8().into_raw();
+        let opaque = ImageRef::new(&data, width as usize, height as usize).opaque;
         Self {
             width,
             height,
-            scale: scale.unwrap_or(4.0),
-            image,
-            frame_num: 1,
+            data,
+            frame_num: frame_count,
+            fps: 8.0,
+            opaque,
         }
     }
-    pub(crate) fn from_image_animated(path: &str, frame_count: u32, scale: Option<f32>) -> Self {
-        let image = image::open(path).unwrap();
-        let (width, height) = image.dimensions();
-        Self {
-            width,
-            height,
-            scale: scale.unwrap_or(4.0),
-            image,
-            frame_num: frame_count,
+
+    /// Animation frame to show `time` seconds in.
+    pub(crate) fn frame_at(&self, time: f32) -> u32 {
+        (time * self.fps) as u32 % self.frame_num
+    }
+
+    /// Width of a single animation frame.
+    pub(crate) fn frame_width(&self) -> u32 {
+        self.width / self.frame_num
+    }
+
+    pub(crate) fn image_ref(&self) -> ImageRef<'_> {
+        ImageRef {
+            data: &self.data,
+            width: self.width as usize,
+            height: self.height as usize,
+            opaque: self.opaque,
+            tint: [0xff, 0xff, 0xff, 0xff],
+        }
+    }
+
+    /// Area of the sprite sheet holding `frame`.
+    pub(crate) fn frame_rect(&self, frame: u32) -> Rect {
+        Rect {
+            x: (self.frame_width() * (frame % self.frame_num)) as usize,
+            y: 0,
+            width: self.frame_width() as usize,
+            height: self.height as usize,
         }
     }
 }
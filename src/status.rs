@@ -0,0 +1,403 @@
+use crate::color::Color;
+use crate::combat::{self, DamageType};
+use crate::entity::{EntityId, EntityStore};
+use crate::event::{Combatant, GameEvent};
+use crate::raws::Raws;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub(crate) enum StatusKind {
+    /// Loses `potency` health every turn.
+    Poison,
+    /// Takes `potency` fire damage every turn.
+    Burning,
+    /// Acts twice as often.
+    Haste,
+    /// Acts half as often.
+    Slow,
+    /// Stumbles in random directions.
+    Confusion,
+    /// Harder to hit, and not drawn for others.
+    Invisibility,
+}
+
+/// What happens when an effect is applied to an entity that already has it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Stacking {
+    /// Potencies add up, the longer duration is kept.
+    Intensify,
+    /// The stronger potency and the longer duration are kept.
+    Refresh,
+    /// Durations add up.
+    Extend,
+}
+
+impl StatusKind {
+    pub(crate) fn stacking(&self) -> Stacking {
+        match self {
+            StatusKind::Poison => Stacking::Intensify,
+            StatusKind::Confusion => Stacking::Extend,
+            StatusKind::Burning
+            | StatusKind::Haste
+            | StatusKind::Slow
+            | StatusKind::Invisibility => Stacking::Refresh,
+        }
+    }
+
+    /// Effect that cancels this one out instead of both applying.
+    pub(crate) fn opposite(&self) -> Option<StatusKind> {
+        match self {
+            StatusKind::Haste => Some(StatusKind::Slow),
+            StatusKind::Slow => Some(StatusKind::Haste),
+            _ => None,
+        }
+    }
+
+    /// Damage type of the harm the effect does. Entities immune to it are immune to the
+    /// effect.
+    pub(crate) fn damage_type(&self) -> Option<DamageType> {
+        match self {
+            StatusKind::Poison => Some(DamageType::Poison),
+            StatusKind::Burning => Some(DamageType::Fire),
+            _ => None,
+        }
+    }
+
+    /// Describes an entity with the effect, e.g. "You are poisoned".
+    pub(crate) fn adjective(&self) -> &'static str {
+        match self {
+            StatusKind::Poison => "poisoned",
+            StatusKind::Burning => "burning",
+            StatusKind::Haste => "hasted",
+            StatusKind::Slow => "slowed",
+            StatusKind::Confusion => "confused",
+            StatusKind::Invisibility => "invisible",
+        }
+    }
+
+    /// Colour of the indicator drawn above affected entities.
+    pub(crate) fn color(&self) -> Color {
+        match self {
+            StatusKind::Poison => Color::GREEN,
+            StatusKind::Burning => Color::ORANGE,
+            StatusKind::Haste => Color::YELLOW,
+            StatusKind::Slow => Color::BLUE,
+            StatusKind::Confusion => Color::MAGENTA,
+            StatusKind::Invisibility => Color::CYAN,
+        }
+    }
+}
+
+impl fmt::Display for StatusKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            StatusKind::Poison => "poison",
+            StatusKind::Burning => "burning",
+            StatusKind::Haste => "haste",
+            StatusKind::Slow => "slow",
+            StatusKind::Confusion => "confusion",
+            StatusKind::Invisibility => "invisibility",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub(crate) struct StatusEffect {
+    pub kind: StatusKind,
+    /// Game turns left.
+    pub turns: u32,
+    /// Strength of the effect, e.g. damage per turn.
+    pub potency: i32,
+}
+
+/// Effects an entity is under, at most one of each kind.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub(crate) struct StatusEffects {
+    effects: Vec<StatusEffect>,
+}
+
+/// Change from applying an effect.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Applied {
+    /// The entity did not have the effect before.
+    Started,
+    /// An effect of the same kind was stacked onto.
+    Stacked,
+    /// The effect cancelled out an opposite one.
+    Cancelled(StatusKind),
+}
+
+impl StatusEffects {
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &StatusEffect> {
+        self.effects.iter()
+    }
+
+    pub(crate) fn get(&self, kind: StatusKind) -> Option<&StatusEffect> {
+        self.effects.iter().find(|effect| effect.kind == kind)
+    }
+
+    pub(crate) fn has(&self, kind: StatusKind) -> bool {
+        self.get(kind).is_some()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Add `effect`, following the stacking rules of its kind.
+    pub(crate) fn apply(&mut self, effect: StatusEffect) -> Applied {
+        if let Some(opposite) = effect.kind.opposite().filter(|&kind| self.has(kind)) {
+            self.effects.retain(|e| e.kind != opposite);
+            return Applied::Cancelled(opposite);
+        }
+        let Some(existing) = self.effects.iter_mut().find(|e| e.kind == effect.kind) else {
+            self.effects.push(effect);
+            return Applied::Started;
+        };
+        match effect.kind.stacking() {
+            Stacking::Intensify => {
+                existing.potency += effect.potency;
+                existing.turns = existing.turns.max(effect.turns);
+            }
+            Stacking::Refresh => {
+                existing.potency = existing.potency.max(effect.potency);
+                existing.turns = existing.turns.max(effect.turns);
+            }
+            Stacking::Extend => existing.turns += effect.turns,
+        }
+        Applied::Stacked
+    }
+
+    pub(crate) fn remove(&mut self, kind: StatusKind) -> Option<StatusEffect> {
+        let index = self.effects.iter().position(|e| e.kind == kind)?;
+        Some(self.effects.remove(index))
+    }
+}
+
+/// Apply `effect` to `target`, unless its resistances make it immune. Returns the events to
+/// report.
+pub(crate) fn apply(
+    entities: &mut EntityStore,
+    raws: &Raws,
+    player: EntityId,
+    target: EntityId,
+    effect: StatusEffect,
+) -> Vec<GameEvent> {
+    let Some(entity) = entities.get_mut(target) else {
+        return Vec::new();
+    };
+    if let Some(damage_type) = effect.kind.damage_type() {
+        let defense = combat::defense(entity, raws);
+        if combat::resist(1, damage_type, &defense) == 0 {
+            return Vec::new();
+        }
+    }
+    let applied = entity.effects.apply(effect);
+    let target = Combatant::new(entity, target, player);
+    match applied {
+        Applied::Started => vec![GameEvent::StatusStarted {
+            target,
+            kind: effect.kind,
+        }],
+        Applied::Stacked => Vec::new(),
+        Applied::Cancelled(kind) => vec![GameEvent::StatusEnded { target, kind }],
+    }
+}
+
+/// Advance the effects on `id` by one game turn: harmful effects deal their damage, and
+/// effects that run out are removed.
+pub(crate) fn tick(
+    entities: &mut EntityStore,
+    raws: &Raws,
+    player: EntityId,
+    id: EntityId,
+) -> Vec<GameEvent> {
+    let mut events = Vec::new();
+    let Some(entity) = entities.get(id) else {
+        return events;
+    };
+    let defense = combat::defense(entity, raws);
+    let target = Combatant::new(entity, id, player);
+
+    let effects: Vec<_> = entity.effects.iter().copied().collect();
+    for effect in effects {
+        if let Some(damage_type) = effect.kind.damage_type() {
+            let damage = combat::resist(effect.potency, damage_type, &defense);
+            let Some(stats) = entities.get_mut(id).and_then(|e| e.stats.as_mut()) else {
+                continue;
+            };
+            if damage > 0 && !stats.is_dead() {
+                stats.hp -= damage;
+                events.push(GameEvent::StatusDamage {
+                    target: target.clone(),
+                    kind: effect.kind,
+                    damage,
+                    damage_type,
+                });
+                if stats.is_dead() {
+                    events.push(GameEvent::Died {
+                        victim: target.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    let Some(entity) = entities.get_mut(id) else {
+        return events;
+    };
+    for effect in entity.effects.effects.iter_mut() {
+        effect.turns = effect.turns.saturating_sub(1);
+    }
+    let expired: Vec<_> = entity
+        .effects
+        .iter()
+        .filter(|effect| effect.turns == 0)
+        .map(|effect| effect.kind)
+        .collect();
+    for kind in expired {
+        entity.effects.remove(kind);
+        events.push(GameEvent::StatusEnded {
+            target: target.clone(),
+            kind,
+        });
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Palette;
+    use glam::IVec2;
+
+    fn effect(kind: StatusKind, turns: u32, potency: i32) -> StatusEffect {
+        StatusEffect {
+            kind,
+            turns,
+            potency,
+        }
+    }
+
+    fn raws() -> Raws {
+        let palette = Palette::load("assets/palettes/dungeon.gpl").unwrap();
+        Raws::load("assets/raws", &palette).unwrap()
+    }
+
+    #[test]
+    fn stacking_rules() {
+        let mut effects = StatusEffects::default();
+        let poison = effect(StatusKind::Poison, 4, 1);
+        assert_eq!(effects.apply(poison), Applied::Started);
+        assert_eq!(
+            effects.apply(effect(StatusKind::Poison, 2, 2)),
+            Applied::Stacked
+        );
+        // Intensify adds the potencies and keeps the longer duration.
+        assert_eq!(
+            effects.get(StatusKind::Poison),
+            Some(&effect(StatusKind::Poison, 4, 3))
+        );
+
+        effects.apply(effect(StatusKind::Burning, 2, 3));
+        effects.apply(effect(StatusKind::Burning, 5, 1));
+        // Refresh keeps the stronger potency and the longer duration.
+        assert_eq!(
+            effects.get(StatusKind::Burning),
+            Some(&effect(StatusKind::Burning, 5, 3))
+        );
+
+        effects.apply(effect(StatusKind::Confusion, 3, 0));
+        effects.apply(effect(StatusKind::Confusion, 4, 0));
+        // Extend adds the durations.
+        assert_eq!(effects.get(StatusKind::Confusion).unwrap().turns, 7);
+        assert_eq!(effects.iter().count(), 3);
+    }
+
+    #[test]
+    fn haste_and_slow_cancel_out() {
+        let mut effects = StatusEffects::default();
+        effects.apply(effect(StatusKind::Haste, 10, 0));
+        assert_eq!(
+            effects.apply(effect(StatusKind::Slow, 3, 0)),
+            Applied::Cancelled(StatusKind::Haste)
+        );
+        assert!(effects.is_empty());
+        effects.apply(effect(StatusKind::Slow, 3, 0));
+        assert_eq!(
+            effects.apply(effect(StatusKind::Haste, 10, 0)),
+            Applied::Cancelled(StatusKind::Slow)
+        );
+        assert!(effects.is_empty());
+    }
+
+    #[test]
+    fn effects_tick_down_and_expire() {
+        let raws = raws();
+        let mut entities = EntityStore::new();
+        let goblin = entities.spawn(raws.spawn_monster("goblin", IVec2::ZERO).unwrap());
+        let player = goblin;
+        apply(
+            &mut entities,
+            &raws,
+            player,
+            goblin,
+            effect(StatusKind::Poison, 2, 3),
+        );
+        apply(
+            &mut entities,
+            &raws,
+            player,
+            goblin,
+            effect(StatusKind::Haste, 3, 0),
+        );
+        let hp = |entities: &EntityStore| entities.get(goblin).unwrap().stats.as_ref().unwrap().hp;
+        let start = hp(&entities);
+
+        let events = tick(&mut entities, &raws, player, goblin);
+        assert!(matches!(
+            events.as_slice(),
+            [GameEvent::StatusDamage {
+                kind: StatusKind::Poison,
+                damage: 3,
+                ..
+            }]
+        ));
+        assert_eq!(hp(&entities), start - 3);
+
+        // Poison runs out after its second turn, haste after its third.
+        let events = tick(&mut entities, &raws, player, goblin);
+        assert!(matches!(
+            events.as_slice(),
+            [
+                GameEvent::StatusDamage { .. },
+                GameEvent::StatusEnded {
+                    kind: StatusKind::Poison,
+                    ..
+                }
+            ]
+        ));
+        let events = tick(&mut entities, &raws, player, goblin);
+        assert!(matches!(
+            events.as_slice(),
+            [GameEvent::StatusEnded {
+                kind: StatusKind::Haste,
+                ..
+            }]
+        ));
+        assert_eq!(hp(&entities), start - 6);
+        assert!(entities.get(goblin).unwrap().effects.is_empty());
+    }
+
+    #[test]
+    fn immune_entities_are_unaffected() {
+        let raws = raws();
+        let mut entities = EntityStore::new();
+        let slime = entities.spawn(raws.spawn_monster("slime", IVec2::ZERO).unwrap());
+        let poison = effect(StatusKind::Poison, 4, 2);
+        assert!(apply(&mut entities, &raws, slime, slime, poison).is_empty());
+        assert!(entities.get(slime).unwrap().effects.is_empty());
+    }
+}
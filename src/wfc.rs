@@ -0,0 +1,535 @@
+use crate::color::Color;
+use crate::pathfinding::DIRECTIONS;
+use crate::rng::Rng;
+use glam::IVec2;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Choices undone before the collapse gives up.
+const MAX_BACKTRACKS: u32 = 2000;
+
+/// A small image that the wave function collapse learns from. It wraps around at the
+/// edges, so patterns can run off one side and continue on the other.
+#[derive(Clone, Debug)]
+pub(crate) struct Sample {
+    pub width: i32,
+    pub height: i32,
+    pub pixels: Vec<Color>,
+}
+
+impl Sample {
+    pub(crate) fn load(path: impl AsRef<Path>) -> Result<Self, image::ImageError> {
+        let image = image::open(path)?.to_rgba8();
+        Ok(Self {
+            width: image.width() as i32,
+            height: image.height() as i32,
+            pixels: image.pixels().map(|pixel| Color::from(pixel.0)).collect(),
+        })
+    }
+}
+
+/// A set of pattern indices.
+type Bits = Vec<u64>;
+
+/// The overlapping model: every square of `size` by `size` pixels in a sample, with how often
+/// it appears and which patterns can overlap each other one pixel apart.
+#[derive(Clone, Debug)]
+pub(crate) struct Model {
+    /// Distinct colours of the sample.
+    colors: Vec<Color>,
+    /// Colour indices of each pattern, row by row.
+    patterns: Vec<Vec<usize>>,
+    weights: Vec<f32>,
+    /// For each of `DIRECTIONS` and each pattern, the patterns that can sit next to it in
+    /// that direction.
+    compatible: [Vec<Bits>; 4],
+}
+
+impl Model {
+    /// Learn the patterns of `sample`. With `symmetry`, their rotations and mirror images are
+    /// learnt too.
+    pub(crate) fn learn(sample: &Sample, size: i32, symmetry: bool) -> Self {
+        let mut colors = Vec::new();
+        let indices: Vec<usize> = sample
+            .pixels
+            .iter()
+            .map(|color| match colors.iter().position(|c| c == color) {
+                Some(index) => index,
+                None => {
+                    colors.push(*color);
+                    colors.len() - 1
+                }
+            })
+            .collect();
+
+        let mut patterns = Vec::new();
+        let mut weights = Vec::new();
+        let mut seen = HashMap::new();
+        for y in 0..sample.height {
+            for x in 0..sample.width {
+                let pattern = square(size)
+                    .map(|pos| {
+                        let pixel = IVec2::new(
+                            (x + pos.x).rem_euclid(sample.width),
+                            (y + pos.y).rem_euclid(sample.height),
+                        );
+                        indices[(pixel.y * sample.width + pixel.x) as usize]
+                    })
+                    .collect();
+                for variant in variants(pattern, size, symmetry) {
+                    match seen.get(&variant) {
+                        Some(&index) => weights[index] += 1.0,
+                        None => {
+                            seen.insert(variant.clone(), patterns.len());
+                            patterns.push(variant);
+                            weights.push(1.0);
+                        }
+                    }
+                }
+            }
+        }
+
+        let compatible = DIRECTIONS.map(|direction| {
+            patterns
+                .iter()
+                .map(|p| {
+                    let mut bits = vec![0; patterns.len().div_ceil(64)];
+                    for (index, q) in patterns.iter().enumerate() {
+                        if agrees(p, q, direction, size) {
+                            bits[index / 64] |= 1 << (index % 64);
+                        }
+                    }
+                    bits
+                })
+                .collect()
+        });
+        Self {
+            colors,
+            patterns,
+            weights,
+            compatible,
+        }
+    }
+
+    /// Distinct colours of the sample. Solved cells are indices into these.
+    pub(crate) fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    pub(crate) fn pattern_count(&self) -> usize {
+        self.patterns.len()
+    }
+}
+
+/// Positions in a square of `size`, row by row.
+fn square(size: i32) -> impl Iterator<Item = IVec2> {
+    (0..size).flat_map(move |y| (0..size).map(move |x| IVec2::new(x, y)))
+}
+
+/// `pattern`, and with `symmetry` its rotations and their mirror images.
+fn variants(pattern: Vec<usize>, size: i32, symmetry: bool) -> Vec<Vec<usize>> {
+    if !symmetry {
+        return vec![pattern];
+    }
+    let remap = |pattern: &[usize], source: fn(IVec2, i32) -> IVec2| -> Vec<usize> {
+        square(size)
+            .map(|pos| {
+                let from = source(pos, size);
+                pattern[(from.y * size + from.x) as usize]
+            })
+            .collect()
+    };
+    let mut variants = Vec::with_capacity(8);
+    let mut turned = pattern;
+    for _ in 0..4 {
+        let next = remap(&turned, |pos, size| IVec2::new(pos.y, size - 1 - pos.x));
+        variants.push(remap(&turned, |pos, size| {
+            IVec2::new(size - 1 - pos.x, pos.y)
+        }));
+        variants.push(turned);
+        turned = next;
+    }
+    variants
+}
+
+/// Whether `q` can sit one pixel in `direction` from `p`, with the pixels they share matching.
+fn agrees(p: &[usize], q: &[usize], direction: IVec2, size: i32) -> bool {
+    square(size)
+        .filter(|&pos| {
+            let shifted = pos - direction;
+            shifted.cmpge(IVec2::ZERO).all() && shifted.cmplt(IVec2::splat(size)).all()
+        })
+        .all(|pos| {
+            let shifted = pos - direction;
+            p[(pos.y * size + pos.x) as usize] == q[(shifted.y * size + shifted.x) as usize]
+        })
+}
+
+/// What one step of the collapse did.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Step {
+    /// Settled the cell on one pattern.
+    Collapsed(IVec2),
+    /// Settling the cell left another without options, so earlier choices were undone.
+    Backtracked(IVec2),
+    /// Every cell is settled.
+    Done,
+    /// Ran out of choices to undo, or undid too many.
+    Failed,
+}
+
+/// A choice made by the collapse, and where its changes to the wave start in the trail.
+struct Choice {
+    trail: usize,
+    cell: usize,
+    pattern: usize,
+}
+
+/// The wave function collapse of a model over a grid that wraps around at the edges.
+///
+/// Every cell starts out able to hold any pattern. Each step settles the cell with the
+/// fewest options left on one of them, picked by how often it appears in the sample, and
+/// rules out the patterns around it that no longer fit. When a cell runs out of options,
+/// the latest choice is undone and ruled out instead.
+pub(crate) struct Wfc {
+    model: Model,
+    width: i32,
+    height: i32,
+    /// Words of `wave` per cell.
+    words: usize,
+    /// Patterns each cell can still hold.
+    wave: Vec<u64>,
+    options: Vec<u32>,
+    /// Previous values of the wave words changed since the first choice, for backtracking.
+    trail: Vec<(usize, u64)>,
+    choices: Vec<Choice>,
+    backtracks: u32,
+    rng: Rng,
+    finished: Option<Step>,
+}
+
+impl Wfc {
+    pub(crate) fn new(model: Model, width: i32, height: i32, seed: u64) -> Self {
+        let words = model.patterns.len().div_ceil(64);
+        let cells = (width * height) as usize;
+        let mut all = vec![0; words];
+        for pattern in 0..model.patterns.len() {
+            all[pattern / 64] |= 1 << (pattern % 64);
+        }
+        let mut wfc = Self {
+            wave: all.repeat(cells),
+            options: vec![model.patterns.len() as u32; cells],
+            model,
+            width,
+            height,
+            words,
+            trail: Vec::new(),
+            choices: Vec::new(),
+            backtracks: 0,
+            rng: Rng::seed_from_u64(seed),
+            finished: None,
+        };
+        // Patterns that only fit beside others in some directions are ruled out at the
+        // edges of what they can reach.
+        if !wfc.propagate((0..cells).collect()) {
+            wfc.finished = Some(Step::Failed);
+        }
+        wfc
+    }
+
+    pub(crate) fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub(crate) fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub(crate) fn model(&self) -> &Model {
+        &self.model
+    }
+
+    /// Patterns the cell at `pos` can still hold.
+    pub(crate) fn options(&self, pos: IVec2) -> u32 {
+        self.options[self.index(pos)]
+    }
+
+    /// Choices undone so far.
+    pub(crate) fn backtracks(&self) -> u32 {
+        self.backtracks
+    }
+
+    /// Cells settled on one pattern.
+    pub(crate) fn collapsed(&self) -> usize {
+        self.options.iter().filter(|&&options| options == 1).count()
+    }
+
+    /// How the collapse ended, if it has.
+    pub(crate) fn finished(&self) -> Option<Step> {
+        self.finished
+    }
+
+    /// Settle one more cell.
+    pub(crate) fn step(&mut self) -> Step {
+        if let Some(step) = self.finished {
+            return step;
+        }
+        let Some(cell) = self.observe() else {
+            self.finished = Some(Step::Done);
+            return Step::Done;
+        };
+        let pos = self.pos(cell);
+        if self.collapse(cell) {
+            Step::Collapsed(pos)
+        } else if self.backtrack() {
+            Step::Backtracked(pos)
+        } else {
+            self.finished = Some(Step::Failed);
+            Step::Failed
+        }
+    }
+
+    /// Collapse every cell. Returns the colour index of each cell, row by row, unless the
+    /// collapse failed.
+    pub(crate) fn run(&mut self) -> Option<Vec<usize>> {
+        loop {
+            match self.step() {
+                Step::Done => return Some(self.result()),
+                Step::Failed => return None,
+                _ => {}
+            }
+        }
+    }
+
+    /// Colour of each cell, row by row: that of its pattern when settled, the average of
+    /// what it could still become otherwise. Cells without options are magenta.
+    pub(crate) fn preview(&self) -> Vec<Color> {
+        (0..self.options.len())
+            .map(|cell| {
+                let patterns: Vec<_> = self.patterns(cell).collect();
+                if patterns.is_empty() {
+                    return Color::MAGENTA;
+                }
+                let mut sum = [0u32; 4];
+                for pattern in &patterns {
+                    let color = self.model.colors[self.model.patterns[*pattern][0]];
+                    for (total, channel) in sum.iter_mut().zip(color.to_array()) {
+                        *total += channel as u32;
+                    }
+                }
+                Color::from(sum.map(|total| (total / patterns.len() as u32) as u8))
+            })
+            .collect()
+    }
+
+    fn result(&self) -> Vec<usize> {
+        (0..self.options.len())
+            .map(|cell| {
+                let pattern = self.patterns(cell).next().unwrap_or(0);
+                self.model.patterns[pattern][0]
+            })
+            .collect()
+    }
+
+    fn index(&self, pos: IVec2) -> usize {
+        (pos.y * self.width + pos.x) as usize
+    }
+
+    fn pos(&self, cell: usize) -> IVec2 {
+        IVec2::new(cell as i32 % self.width, cell as i32 / self.width)
+    }
+
+    fn patterns(&self, cell: usize) -> impl Iterator<Item = usize> + '_ {
+        let bits = &self.wave[cell * self.words..(cell + 1) * self.words];
+        (0..self.model.patterns.len()).filter(move |&p| bits[p / 64] & (1 << (p % 64)) != 0)
+    }
+
+    /// The unsettled cell with the fewest options, picking at random among ties.
+    fn observe(&mut self) -> Option<usize> {
+        let mut best = None;
+        let mut ties = 0;
+        for (cell, &options) in self.options.iter().enumerate() {
+            if options <= 1 {
+                continue;
+            }
+            match best {
+                Some((_, fewest)) if options > fewest => {}
+                Some((_, fewest)) if options == fewest => {
+                    ties += 1;
+                    if self.rng.below(ties + 1) == 0 {
+                        best = Some((cell, options));
+                    }
+                }
+                _ => {
+                    best = Some((cell, options));
+                    ties = 0;
+                }
+            }
+        }
+        best.map(|(cell, _)| cell)
+    }
+
+    /// Settle `cell` on one of its patterns. Returns false on a contradiction.
+    fn collapse(&mut self, cell: usize) -> bool {
+        let patterns: Vec<_> = self.patterns(cell).collect();
+        let total: f32 = patterns.iter().map(|&p| self.model.weights[p]).sum();
+        let mut pick = self.rng.next_f32() * total;
+        let pattern = patterns
+            .iter()
+            .copied()
+            .find(|&p| {
+                pick -= self.model.weights[p];
+                pick < 0.0
+            })
+            .unwrap_or(patterns[patterns.len() - 1]);
+
+        self.choices.push(Choice {
+            trail: self.trail.len(),
+            cell,
+            pattern,
+        });
+        let mut only = vec![0; self.words];
+        only[pattern / 64] |= 1 << (pattern % 64);
+        self.restrict(cell, &only);
+        self.propagate(vec![cell])
+    }
+
+    /// Undo choices until ruling out the latest one leaves no contradiction. Returns false
+    /// if there are no choices left, or too many were undone.
+    fn backtrack(&mut self) -> bool {
+        while let Some(choice) = self.choices.pop() {
+            for (word, previous) in self.trail.drain(choice.trail..).rev() {
+                let cell = word / self.words;
+                self.options[cell] += previous.count_ones() - self.wave[word].count_ones();
+                self.wave[word] = previous;
+            }
+            self.backtracks += 1;
+            if self.backtracks > MAX_BACKTRACKS {
+                return false;
+            }
+            let mut without = vec![u64::MAX; self.words];
+            without[choice.pattern / 64] &= !(1 << (choice.pattern % 64));
+            self.restrict(choice.cell, &without);
+            if self.options[choice.cell] > 0 && self.propagate(vec![choice.cell]) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Rule out the patterns of `cell` that are not in `allowed`. Returns whether any were.
+    fn restrict(&mut self, cell: usize, allowed: &[u64]) -> bool {
+        let mut changed = false;
+        for (i, &bits) in allowed.iter().enumerate() {
+            let word = cell * self.words + i;
+            let previous = self.wave[word];
+            let next = previous & bits;
+            if next != previous {
+                // Changes before the first choice are never undone.
+                if !self.choices.is_empty() {
+                    self.trail.push((word, previous));
+                }
+                self.wave[word] = next;
+                self.options[cell] -= previous.count_ones() - next.count_ones();
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Rule out the patterns that no longer fit beside the cells in `open`, spreading to
+    /// every cell that changes. Returns false if a cell runs out of options.
+    fn propagate(&mut self, mut open: Vec<usize>) -> bool {
+        let mut allowed = vec![0; self.words];
+        while let Some(cell) = open.pop() {
+            let pos = self.pos(cell);
+            for (d, direction) in DIRECTIONS.iter().enumerate() {
+                allowed.fill(0);
+                for pattern in self.patterns(cell) {
+                    for (bits, compatible) in
+                        allowed.iter_mut().zip(&self.model.compatible[d][pattern])
+                    {
+                        *bits |= compatible;
+                    }
+                }
+                let next = pos + *direction;
+                let next = IVec2::new(
+                    next.x.rem_euclid(self.width),
+                    next.y.rem_euclid(self.height),
+                );
+                let neighbour = self.index(next);
+                if self.restrict(neighbour, &allowed) {
+                    if self.options[neighbour] == 0 {
+                        return false;
+                    }
+                    open.push(neighbour);
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: i32 = 2;
+
+    /// Blocks of two by two pixels in a checkerboard, with a dot in one of them.
+    fn sample() -> Sample {
+        let (a, b, c) = (Color::BLACK, Color::WHITE, Color::RED);
+        Sample {
+            width: 4,
+            height: 4,
+            #[rustfmt::skip]
+            pixels: vec![
+                a, a, b, b,
+                a, c, b, b,
+                b, b, a, a,
+                b, b, a, a,
+            ],
+        }
+    }
+
+    #[test]
+    fn learn() {
+        let model = Model::learn(&sample(), SIZE, false);
+        assert_eq!(model.colors(), [Color::BLACK, Color::WHITE, Color::RED]);
+        // Squares within the plain blocks repeat, so 16 positions give 12 patterns.
+        assert_eq!(model.pattern_count(), 12);
+        assert_eq!(model.weights.iter().sum::<f32>(), 16.0);
+        assert!(Model::learn(&sample(), SIZE, true).pattern_count() > 16);
+    }
+
+    #[test]
+    fn collapse_agrees_with_neighbours() {
+        let mut wfc = Wfc::new(Model::learn(&sample(), SIZE, true), 12, 10, 3);
+        let result = wfc.run().expect("the collapse failed");
+        assert_eq!(result.len(), 12 * 10);
+        assert_eq!(wfc.collapsed(), 12 * 10);
+
+        for (cell, &color) in result.iter().enumerate() {
+            let patterns: Vec<_> = wfc.patterns(cell).collect();
+            assert_eq!(patterns.len(), 1);
+            let p = &wfc.model.patterns[patterns[0]];
+            assert_eq!(color, p[0]);
+            let pos = wfc.pos(cell);
+            for direction in DIRECTIONS {
+                let next = pos + direction;
+                let next = IVec2::new(next.x.rem_euclid(12), next.y.rem_euclid(10));
+                let q = wfc.patterns(wfc.index(next)).next().unwrap();
+                assert!(agrees(p, &wfc.model.patterns[q], direction, SIZE));
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_result() {
+        let model = Model::learn(&sample(), SIZE, true);
+        let first = Wfc::new(model.clone(), 16, 16, 11).run();
+        let second = Wfc::new(model, 16, 16, 11).run();
+        assert!(first.is_some());
+        assert_eq!(first, second);
+    }
+}
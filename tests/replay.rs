@@ -0,0 +1,75 @@
+//! Plays back recorded games without a window and checks where they end: the turn, depth,
+//! the player's position and health, and the last messages. Each replay is also played twice
+//! to catch changes that break determinism.
+//!
+//! The replays have no `end` checksum, which covers the whole game state and would change
+//! with any gameplay tweak. When a change really does alter how a replay ends, run it with
+//! `cargo run -- --replay tests/replays/<name>.txt --headless`, check the new ending makes
+//! sense, update the expected lines below and explain the difference in the commit.
+
+use std::path::Path;
+use std::process::Command;
+
+/// Output of a headless replay.
+fn replay(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/replays")
+        .join(name);
+    let output = Command::new(env!("CARGO_BIN_EXE_roguelike-engine"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("--replay")
+        .arg(&path)
+        .arg("--headless")
+        .output()
+        .expect("failed to run the engine");
+
+    assert!(
+        output.status.success(),
+        "replay {} failed:\n{}{}",
+        name,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).expect("replay output is not UTF-8")
+}
+
+/// Play `name` twice and check that it ends on `turn` with `expected`, the output after the
+/// first line.
+fn check(name: &str, turn: u64, expected: &[&str]) {
+    let output = replay(name);
+    assert_eq!(output, replay(name), "replay {} is not deterministic", name);
+    let prefix = format!("Replayed to turn {},", turn);
+    assert!(output.starts_with(&prefix), "replay {}: {}", name, output);
+    let ending: Vec<_> = output.lines().skip(1).collect();
+    assert_eq!(ending, expected, "replay {} ended differently", name);
+}
+
+#[test]
+fn arena() {
+    check(
+        "arena.txt",
+        42,
+        &[
+            "Depth 1",
+            "Player at 8, 13 with 17/30 HP",
+            "Message: You hit the goblin for 4 slashing damage.",
+            "Message: The goblin dies.",
+            "Message: You wait. x2",
+        ],
+    );
+}
+
+#[test]
+fn stairs() {
+    check(
+        "stairs.txt",
+        15,
+        &[
+            "Depth 2",
+            "Player at 4, 9 with 30/30 HP",
+            "Message: You wait. x2",
+            "Message: You descend to level 2.",
+            "Message: You wait. x2",
+        ],
+    );
+}